@@ -1,7 +1,7 @@
-use std::{fs::File, io, path::PathBuf, sync::Arc};
+use std::{env, fs::File, io, path::PathBuf, sync::Arc};
 
 use anyhow::Result;
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use log::debug;
 use pako_core::{
     plugins::PluginsFactory, Analyzer, Plugin, ThreadedAnalyzer, PLUGIN_FLOW_DEL, PLUGIN_FLOW_NEW,
@@ -41,12 +41,19 @@ fn main() -> Result<()> {
     let factory = PluginsFactory::default();
     let registry = factory.build_plugins(&Config::default())?;
 
-    let config = if let Some(path) = cli.config {
+    let mut config = if let Some(path) = cli.config {
         load_config(path)?
     } else {
         Config::default()
     };
 
+    // TLS secrets: the configuration file takes precedence over the environment
+    if config.get("ssl_keylog_file").is_none() {
+        if let Ok(path) = env::var("SSLKEYLOGFILE") {
+            config.set("ssl_keylog_file", path);
+        }
+    }
+
     // determine number of worker threads
     let num_threads = config.get_usize("num_threads").unwrap_or(1);
 
@@ -1,12 +1,21 @@
-use std::io;
+use std::{io, path::Path};
 
 mod info;
 mod interface;
+mod repair;
 
 pub use info::{FileType, Options, PcapInfo, SectionInfo};
 pub use interface::InterfaceInfo;
+pub use repair::RepairReport;
 
 /// Display information about the input file (which must be pcap or pcap-ng)
 pub fn pcap_info(name: &str, options: &info::Options) -> Result<(i32, PcapInfo), io::Error> {
     info::process_file(name, options)
 }
+
+/// Read the input file in recovery mode, skipping corrupted data
+///
+/// If `output` is set, valid blocks are copied to a repaired file.
+pub fn pcap_repair(name: &str, output: Option<&Path>) -> Result<RepairReport, io::Error> {
+    repair::repair_file(name, output)
+}
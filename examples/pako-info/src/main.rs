@@ -3,6 +3,7 @@
 use std::{
     fs, io,
     net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
     process, str,
 };
 
@@ -18,6 +19,14 @@ struct Cli {
     #[arg(short, long, default_value_t = false)]
     skip: bool,
 
+    /// Skip corrupted data, and show a repair report
+    #[arg(short, long, default_value_t = false)]
+    repair: bool,
+
+    /// Write valid blocks to a repaired file (implies --repair)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
     /// Input file
     file: String,
 }
@@ -26,6 +35,13 @@ fn main() -> Result<(), io::Error> {
     let cli = Cli::parse();
 
     let input_filename = cli.file;
+
+    if cli.repair || cli.output.is_some() {
+        let report = pcap_repair(input_filename.as_ref(), cli.output.as_deref())?;
+        display_repair_report(input_filename.as_ref(), &report);
+        process::exit(if report.skipped.is_empty() { 0 } else { 1 });
+    }
+
     let options = Options {
         check_file: cli.skip,
     };
@@ -101,6 +117,33 @@ fn display_pcap_info(name: &str, info: &PcapInfo) {
     }
 }
 
+fn display_repair_report(name: &str, report: &RepairReport) {
+    println!("File name: {name}");
+    println!("{:<20}: {} bytes", "File size", report.file_bytes);
+    println!("{:<20}: {}", "Valid blocks", report.block_index);
+    println!("{:<20}: {}", "Valid packets", report.packet_index);
+    println!(
+        "{:<20}: {} ({} bytes)",
+        "Skipped ranges",
+        report.skipped.len(),
+        report.skipped_bytes()
+    );
+    for range in &report.skipped {
+        println!(
+            "    offset {} (0x{:x}): {} bytes ({})",
+            range.offset, range.offset, range.len, range.reason
+        );
+    }
+    if let Some((path, size)) = &report.output {
+        println!(
+            "{:<20}: {} ({} bytes)",
+            "Repaired file",
+            path.display(),
+            size
+        );
+    }
+}
+
 fn display_section_info(info: &SectionInfo) {
     let native_s = if info.native_endian {
         "Native"
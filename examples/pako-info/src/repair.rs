@@ -0,0 +1,97 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use pako_pcap::{Block, Format, LegacyPcapWriter, PcapNgReader, PcapNgWriter, SkippedRange};
+
+use crate::info::open_file;
+
+/// Result of the recovery of a (possibly corrupted) capture file
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Number of bytes read from the input
+    pub file_bytes: usize,
+    /// Number of valid blocks
+    pub block_index: usize,
+    /// Number of valid packets
+    pub packet_index: usize,
+    /// Byte ranges skipped because they could not be parsed
+    pub skipped: Vec<SkippedRange>,
+    /// Repaired file, and its size
+    pub output: Option<(PathBuf, usize)>,
+}
+
+impl RepairReport {
+    /// Total number of skipped bytes
+    pub fn skipped_bytes(&self) -> usize {
+        self.skipped.iter().map(|range| range.len).sum()
+    }
+}
+
+fn to_io_error<I>(e: pako_pcap::Error<I>) -> io::Error {
+    match e {
+        pako_pcap::Error::Io(e) => e,
+        e => Error::new(ErrorKind::InvalidData, e.into_static().to_string()),
+    }
+}
+
+/// Read all blocks of the input in recovery mode, and copy the valid blocks to `output`
+pub(crate) fn repair_file(name: &str, output: Option<&Path>) -> Result<RepairReport, io::Error> {
+    let file = open_file(name)?;
+    // the file header may be damaged too
+    let mut reader = PcapNgReader::with_recovery(file).map_err(to_io_error)?;
+
+    let mut writer = output
+        .map(|path| File::create(path).map(BufWriter::new))
+        .transpose()?;
+    let mut pcapng_writer = match reader.format() {
+        Format::PcapNg => writer.take().map(PcapNgWriter::new),
+        Format::Legacy => None,
+    };
+    // the legacy writer needs the file header
+    let mut legacy_writer = None;
+
+    let mut report = RepairReport::default();
+    loop {
+        // byte order of the current section, for blocks other than SHB
+        let endianness = reader.endianness();
+        let Some(block) = reader.read_block().map_err(to_io_error)? else {
+            break;
+        };
+        report.block_index += 1;
+        match block {
+            Block::EnhancedPacket(_) | Block::SimplePacket(_) | Block::LegacyPacket(_) => {
+                report.packet_index += 1;
+            }
+            Block::LegacyHeader(ref header) => {
+                if let Some(w) = writer.take() {
+                    let w = LegacyPcapWriter::new(w, header.clone()).map_err(to_io_error)?;
+                    legacy_writer = Some(w);
+                }
+                continue;
+            }
+            _ => (),
+        }
+        if let Some(w) = pcapng_writer.as_mut() {
+            w.write_block(&block, endianness).map_err(to_io_error)?;
+        }
+        if let Some(w) = legacy_writer.as_mut() {
+            w.write_block(&block).map_err(to_io_error)?;
+        }
+    }
+    report.file_bytes = reader.consumed();
+    report.skipped = reader.skipped().to_vec();
+
+    let written = match (pcapng_writer, legacy_writer) {
+        (Some(w), _) => Some((w.written(), w.into_inner().map_err(to_io_error)?)),
+        (_, Some(w)) => Some((w.written(), w.into_inner().map_err(to_io_error)?)),
+        _ => None,
+    };
+    if let (Some((size, mut w)), Some(path)) = (written, output) {
+        w.flush()?;
+        report.output = Some((path.to_path_buf(), size));
+    }
+    Ok(report)
+}
@@ -0,0 +1,52 @@
+use std::{env, fs, path::PathBuf};
+
+use pako_info::{pcap_repair, RepairReport};
+use pako_pcap::PcapNgReader;
+
+const ASSETS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../pako-pcap/assets/");
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("pako-info-{}-{}", std::process::id(), name))
+}
+
+/// Repair `input`, and return the number of blocks of the repaired file
+fn repair(input: &[u8], name: &str) -> (RepairReport, usize) {
+    let input_path = temp_path(name);
+    let output_path = temp_path(&format!("{name}.repaired"));
+    fs::write(&input_path, input).unwrap();
+    let report = pcap_repair(input_path.to_str().unwrap(), Some(&output_path)).unwrap();
+    let output = fs::read(&output_path).unwrap();
+    let mut reader = PcapNgReader::new(output.as_slice()).unwrap();
+    let mut count = 0;
+    while reader.read_block().unwrap().is_some() {
+        count += 1;
+    }
+    fs::remove_file(input_path).unwrap();
+    fs::remove_file(output_path).unwrap();
+    (report, count)
+}
+
+#[test]
+fn test_repair_damaged_file_header() {
+    let blocks = fs::read(format!("{ASSETS}blocks_le.pcapng")).unwrap();
+    let legacy = fs::read(format!("{ASSETS}legacy_le_us.pcap")).unwrap();
+
+    // garbage before the section header
+    let input = [&[0u8; 13][..], &blocks].concat();
+    let (report, count) = repair(&input, "garbage.pcapng");
+    assert_eq!(report.skipped_bytes(), 13);
+    assert_eq!(report.skipped[0].offset, 0);
+    assert_eq!(report.block_index, 12);
+    assert_eq!(report.packet_index, 5);
+    assert_eq!(count, 12);
+
+    // damaged magic of the legacy file header, followed by a valid file
+    let mut input = legacy.clone();
+    input[0] = 0;
+    input.extend_from_slice(&legacy);
+    let (report, count) = repair(&input, "legacy.pcap");
+    assert_eq!(report.skipped_bytes(), legacy.len());
+    assert_eq!(report.packet_index, 4);
+    // file header and packets
+    assert_eq!(count, 5);
+}
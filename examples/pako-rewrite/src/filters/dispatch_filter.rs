@@ -108,7 +108,7 @@ impl DispatchFilterBuilder {
         match filtering_key {
             FilteringKey::SrcIpaddr => {
                 let ipaddr_container = IpAddrC::of_file_path(Path::new(key_file_path))
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
 
                 let keep: KeepFn<IpAddrC, IpAddr> = match filtering_action {
                     FilteringAction::Keep => Box::new(|c: &IpAddrC, ipaddr| Ok(c.contains(ipaddr))),
@@ -126,7 +126,7 @@ impl DispatchFilterBuilder {
             }
             FilteringKey::DstIpaddr => {
                 let ipaddr_container = IpAddrC::of_file_path(Path::new(key_file_path))
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
 
                 let keep: KeepFn<IpAddrC, IpAddr> = match filtering_action {
                     FilteringAction::Keep => Box::new(|c: &IpAddrC, ipaddr| Ok(c.contains(ipaddr))),
@@ -144,7 +144,7 @@ impl DispatchFilterBuilder {
             }
             FilteringKey::SrcDstIpaddr => {
                 let ipaddr_container = IpAddrC::of_file_path(Path::new(key_file_path))
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
 
                 let keep: KeepFn<IpAddrC, (IpAddr, IpAddr)> = match filtering_action {
                     FilteringAction::Keep => Box::new(|c, ipaddr_tuple| {
@@ -165,7 +165,7 @@ impl DispatchFilterBuilder {
             FilteringKey::SrcIpaddrProtoDstPort => {
                 let ipaddr_proto_port_container =
                     IpAddrProtoPortC::of_file_path(Path::new(key_file_path))
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                        .map_err(|e| io::Error::other(e.to_string()))?;
 
                 let keep: KeepFn<IpAddrProtoPortC, (IpAddr, IpNextHeaderProtocol, u16)> =
                     match filtering_action {
@@ -186,7 +186,7 @@ impl DispatchFilterBuilder {
             }
             FilteringKey::SrcDstIpaddrProtoSrcDstPort => {
                 let five_tuple_container = FiveTupleC::of_file_path(Path::new(key_file_path))
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
 
                 let keep: KeepFn<FiveTupleC, FiveTuple> = match filtering_action {
                     FilteringAction::Keep => Box::new(|c, five_tuple| Ok(c.contains(five_tuple))),
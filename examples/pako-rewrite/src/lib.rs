@@ -57,7 +57,7 @@ pub fn pcap_rewrite_file<S1: AsRef<str>, S2: AsRef<str>>(
         if input_filename == "-" {
             const MSG: &str = "Plugins with pre-analysis pass cannot be run on stdin";
             error!("{}", MSG);
-            return Err(io::Error::new(io::ErrorKind::Other, MSG));
+            return Err(io::Error::other(MSG));
         }
         info!("Running pre-analysis pass");
         engine.data_analyzer_mut().set_run_pre_analysis(true);
@@ -80,9 +80,8 @@ fn get_reader(input_filename: &str) -> io::Result<Box<dyn Read>> {
         Box::new(io::stdin())
     } else {
         let path = Path::new(&input_filename);
-        let file = File::open(path).map_err(|e| {
+        let file = File::open(path).inspect_err(|_| {
             error!("Could not open input file '{}'", input_filename);
-            e
         })?;
         if input_filename.ends_with(".gz") {
             Box::new(GzDecoder::new(file))
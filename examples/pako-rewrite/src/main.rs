@@ -44,9 +44,8 @@ path: path to a csv formatted file without header that contains filtering keys
 fn load_config(config: &mut Config, filename: &str) -> Result<(), io::Error> {
     debug!("Loading configuration {}", filename);
     let path = Path::new(&filename);
-    let file = File::open(path).map_err(|e| {
+    let file = File::open(path).inspect_err(|_| {
         error!("Could not open config file '{}'", filename);
-        e
     })?;
     config.load_config(file)
 }
@@ -113,10 +112,9 @@ fn main() -> io::Result<()> {
                 let dispatch_data = args[1];
                 let args: Vec<_> = dispatch_data.split('%').collect();
                 assert_eq!(args.len(), 3);
-                let filtering_key = FilteringKey::of_string(args[0])
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                let filtering_action = FilteringAction::of_string(args[1])
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let filtering_key = FilteringKey::of_string(args[0]).map_err(io::Error::other)?;
+                let filtering_action =
+                    FilteringAction::of_string(args[1]).map_err(io::Error::other)?;
                 let key_file_path = args[2];
 
                 let f = DispatchFilterBuilder::from_args(
@@ -131,15 +129,13 @@ fn main() -> io::Result<()> {
                 let dispatch_data = args[1];
                 let args: Vec<_> = dispatch_data.split('%').collect();
                 if args.len() != 2 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
+                    return Err(io::Error::other(
                         "More than two arguments provided to fragmentation filter.".to_string(),
                     ));
                 };
-                let filtering_key = FilteringKey::of_string(args[0])
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                let filtering_action = FilteringAction::of_string(args[1])
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let filtering_key = FilteringKey::of_string(args[0]).map_err(io::Error::other)?;
+                let filtering_action =
+                    FilteringAction::of_string(args[1]).map_err(io::Error::other)?;
 
                 let f = FragmentationFilterBuilder::from_args(filtering_key, filtering_action)?;
                 filters.push(f);
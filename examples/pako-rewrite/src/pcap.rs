@@ -1,4 +1,4 @@
-use std::io::{self, Error, ErrorKind, Write};
+use std::io::{self, Error, Write};
 
 use log::debug;
 use pako_tools::Packet;
@@ -26,25 +26,22 @@ impl<W: Write> Writer for PcapWriter<W> {
         hdr.snaplen = snaplen as u32;
         hdr.network = linktype;
         #[allow(clippy::or_fun_call)]
-        let s = hdr.to_vec().or(Err(Error::new(
-            ErrorKind::Other,
-            "Pcap header serialization failed",
-        )))?;
+        let s = hdr
+            .to_vec()
+            .or(Err(Error::other("Pcap header serialization failed")))?;
         self.w.write(&s)
     }
 
     fn write_block(&mut self, block: &PcapBlockOwned) -> Result<usize, io::Error> {
         match block {
-            PcapBlockOwned::LegacyHeader(_) => Err(Error::new(
-                ErrorKind::Other,
+            PcapBlockOwned::LegacyHeader(_) => Err(Error::other(
                 "PcapWriter::write_block called twice for header",
             )),
             PcapBlockOwned::Legacy(b) => {
                 #[allow(clippy::or_fun_call)]
-                let v = b.to_vec_raw().or(Err(Error::new(
-                    ErrorKind::Other,
-                    "Pcap block serialization failed",
-                )))?;
+                let v = b
+                    .to_vec_raw()
+                    .or(Err(Error::other("Pcap block serialization failed")))?;
                 self.w.write(&v)
             }
             PcapBlockOwned::NG(b) => {
@@ -59,8 +56,9 @@ impl<W: Write> Writer for PcapWriter<W> {
 
     fn write_packet(&mut self, packet: &Packet, data: &[u8]) -> Result<usize, io::Error> {
         let record = LegacyPcapBlock {
-            ts_sec: packet.ts.secs,
-            ts_usec: packet.ts.micros,
+            // legacy pcap timestamps are 32-bit
+            ts_sec: packet.ts.secs() as u32,
+            ts_usec: packet.ts.subsec_micros(),
             caplen: data.len() as u32,  // packet.header.caplen,
             origlen: data.len() as u32, // packet.header.len,
             data,
@@ -68,10 +66,9 @@ impl<W: Write> Writer for PcapWriter<W> {
         // debug!("rec_hdr: {:?}", rec_hdr);
         // debug!("data (len={}): {}", data.len(), data.to_hex(16));
         #[allow(clippy::or_fun_call)]
-        let s = record.to_vec_raw().or(Err(Error::new(
-            ErrorKind::Other,
-            "Pcap block serialization failed",
-        )))?;
+        let s = record
+            .to_vec_raw()
+            .or(Err(Error::other("Pcap block serialization failed")))?;
         self.w.write(&s)
     }
 }
@@ -0,0 +1,49 @@
+//!
+//! State of an analysis, shared by the analyzer, its workers and plugins
+//!
+
+use crate::{
+    capture_stats::CaptureStats, checksum::ChecksumStats, error_stats::ErrorStats,
+    name_table::NameTable, tls_keylog::TlsKeyLog,
+};
+
+/// Shared state of an analysis
+///
+/// The same instance is used by all workers of a `ThreadedAnalyzer`, and given to plugins
+/// in `Plugin::set_context`. All members are updated while packets are processed, and can
+/// be read at any time, for ex. in `post_process` or after the analysis.
+#[derive(Debug, Default)]
+pub struct AnalysisContext {
+    pub(crate) checksum_stats: ChecksumStats,
+    pub(crate) error_stats: ErrorStats,
+    pub(crate) tls_keylog: TlsKeyLog,
+    pub(crate) capture_stats: CaptureStats,
+    pub(crate) name_table: NameTable,
+}
+
+impl AnalysisContext {
+    /// Checksum validation counters
+    pub fn checksum_stats(&self) -> &ChecksumStats {
+        &self.checksum_stats
+    }
+
+    /// Decoding error counters
+    pub fn error_stats(&self) -> &ErrorStats {
+        &self.error_stats
+    }
+
+    /// TLS secrets, read from the `ssl_keylog_file` file and from the capture
+    pub fn tls_keylog(&self) -> &TlsKeyLog {
+        &self.tls_keylog
+    }
+
+    /// Capture completeness counters (interface statistics and drops)
+    pub fn capture_stats(&self) -> &CaptureStats {
+        &self.capture_stats
+    }
+
+    /// Address to name table, filled from Name Resolution Blocks
+    pub fn name_table(&self) -> &NameTable {
+        &self.name_table
+    }
+}
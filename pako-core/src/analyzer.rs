@@ -1,10 +1,15 @@
-use std::{cmp::min, net::IpAddr, ops::DerefMut, sync::Arc};
+use std::{
+    cmp::min,
+    net::{IpAddr, Ipv4Addr},
+    ops::DerefMut,
+    sync::Arc,
+};
 
 use log::{debug, trace, warn};
 use pako_tools::*;
 use pcap_parser::{
     data::{get_packetdata_raw, PacketData},
-    Linktype,
+    Block, DecryptionSecretsBlock, Linktype, SecretsType,
 };
 use pnet_packet::{
     ethernet::{EtherType, EtherTypes, EthernetPacket},
@@ -13,25 +18,33 @@ use pnet_packet::{
     icmpv6::Icmpv6Packet,
     ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
     ipv4::{Ipv4Flags, Ipv4Packet},
-    ipv6::{ExtensionPacket, FragmentPacket, Ipv6Packet},
+    ipv6::Ipv6Packet,
     tcp::TcpPacket,
     udp::UdpPacket,
     vlan::VlanPacket,
-    Packet as PnetPacket, PacketSize,
+    Packet as PnetPacket,
 };
 
 use crate::{
+    analysis_context::AnalysisContext,
+    checksum::{verify_l4_checksum, ChecksumStatus},
     erspan::ERSPANPacket,
+    flow_event::FlowEvent,
     flow_map::FlowMap,
     geneve::*,
+    icmp_error::IcmpError,
     ip_defrag::{DefragEngine, Fragment, IPDefragEngine},
+    ipv6_ext::Ipv6ExtensionChain,
     layers::LinkLayerType,
     mpls::*,
+    neighbor::{parse_arp, parse_ndp, NeighborMessage},
     packet_info::PacketInfo,
     plugin::*,
     plugin_registry::*,
     ppp::{PppPacket, PppProtocolTypes},
     pppoe::PppoeSessionPacket,
+    sctp::SctpPacket,
+    sctp_reassembly::{finalize_sctp_associations, SctpAssociations, SctpMessage},
     tcp_reassembly::{finalize_tcp_streams, TcpStreamError, TcpStreamReassembly},
     vxlan::*,
 };
@@ -41,6 +54,8 @@ pub struct L3Info {
     /// Layer 4 protocol (e.g TCP, UDP, ICMP)
     pub l4_proto: u8,
     pub three_tuple: ThreeTuple,
+    /// IPv6 extension headers (empty for IPv4)
+    pub ipv6_extensions: Ipv6ExtensionChain,
 }
 
 /// Pcap/Pcap-ng analyzer
@@ -64,9 +79,15 @@ pub struct Analyzer {
     ipv4_defrag: Box<dyn DefragEngine>,
     ipv6_defrag: Box<dyn DefragEngine>,
     pub(crate) tcp_defrag: TcpStreamReassembly,
+    pub(crate) sctp_defrag: SctpAssociations,
 
     defrag_count: usize,
     do_checksums: bool,
+    /// Counters, TLS secrets and names (shared between workers and plugins)
+    pub(crate) context: Arc<AnalysisContext>,
+    /// If true, stop analysis on the first error
+    pub(crate) strict: bool,
+    keylog_file: Option<String>,
     skip_index: usize,
     output_dir: Option<String>,
 }
@@ -79,24 +100,116 @@ impl Analyzer {
             debug!("Will skip to index {}", skip_index);
         }
         let output_dir = config.get("output_dir").map(|s| s.to_owned());
+        let strict = config.get_bool("strict").unwrap_or(false);
+        let keylog_file = config.get("ssl_keylog_file").map(|s| s.to_owned());
         Analyzer {
             registry,
             flows: FlowMap::default(),
             ipv4_defrag: Box::new(IPDefragEngine::new()),
             ipv6_defrag: Box::new(IPDefragEngine::new()),
             tcp_defrag: TcpStreamReassembly::default(),
+            sctp_defrag: SctpAssociations::default(),
             defrag_count: 0,
             do_checksums,
+            context: Arc::new(AnalysisContext::default()),
+            strict,
+            keylog_file,
             skip_index,
             output_dir,
         }
     }
 
+    /// Expire all remaining flows
+    ///
+    /// Pending TCP streams and SCTP associations are finalized, then `flow_destroyed`
+    /// is called for each flow and the flow table is cleared.
+    pub(crate) fn finalize_flows(&mut self) {
+        // expire all TCP connections in reassembly engine
+        finalize_tcp_streams(self);
+        finalize_sctp_associations(self);
+        // expire remaining flows
+        let flows = &self.flows;
+        trace!("{} flows remaining in table", flows.len());
+        self.registry.run_plugins(
+            |p| p.plugin_type() & PLUGIN_FLOW_DEL != 0,
+            |p| {
+                flows.values().for_each(|flow| {
+                    p.flow_destroyed(flow);
+                });
+            },
+        );
+        self.flows.clear();
+    }
+
+    /// Count an error for the current packet
+    ///
+    /// The error is returned in strict mode (analysis will stop), and ignored otherwise.
+    pub(crate) fn handle_error(&self, ctx: &ParseContext, e: Error) -> Result<(), Error> {
+        self.context.error_stats.add_failure(&e);
+        if self.strict {
+            return Err(e);
+        }
+        warn!(
+            "Error while handling packet: {} (idx={})",
+            e, ctx.pcap_index
+        );
+        Ok(())
+    }
+
+    /// Record an anomaly, which does not stop the decoding of the packet
+    pub(crate) fn record_anomaly(&self, e: Error) {
+        self.context.error_stats.record(&e);
+    }
+
+    /// Return the number of packets for which an error occurred
+    pub fn num_errors(&self) -> usize {
+        self.context.error_stats.failed() as usize
+    }
+
+    /// Get the shared state of the analysis (counters, TLS secrets, names)
+    ///
+    /// The returned object is shared, and is updated while packets are processed.
+    pub fn context(&self) -> Arc<AnalysisContext> {
+        self.context.clone()
+    }
+
+    pub(crate) fn output_dir(&self) -> Option<&str> {
+        self.output_dir.as_deref()
+    }
+
     /// Get a reference to plugin registry
     pub fn registry(&self) -> &PluginRegistry {
         &self.registry
     }
 
+    /// Give a plugin access to the state shared by the analyzer
+    pub(crate) fn share_state(&self, p: &mut dyn Plugin) {
+        p.set_context(self.context.clone());
+    }
+
+    /// Load the TLS key log file (if configured), and share the analyzer state with plugins
+    pub(crate) fn init_shared_state(&self) -> Result<(), Error> {
+        if let Some(path) = &self.keylog_file {
+            let count = self.context.tls_keylog.load_file(path)?;
+            debug!("Loaded {} TLS secrets from {}", count, path);
+        }
+        self.registry.run_plugins(|_| true, |p| self.share_state(p));
+        Ok(())
+    }
+
+    /// Add the secrets of a Decryption Secrets Block
+    pub(crate) fn handle_decryption_secrets(&self, dsb: &DecryptionSecretsBlock) {
+        if dsb.secrets_type == SecretsType::TlsKeyLog {
+            let count = self.context.tls_keylog.parse(dsb.data);
+            debug!("Read {} TLS secrets from Decryption Secrets Block", count);
+        } else {
+            debug!(
+                "Ignoring Decryption Secrets Block (type 0x{:x})",
+                dsb.secrets_type.0
+            );
+        }
+    }
+
     #[inline]
     fn handle_l2(&mut self, packet: &Packet, ctx: &ParseContext, data: &[u8]) -> Result<(), Error> {
         handle_l2(packet, ctx, data, self)
@@ -104,9 +217,9 @@ impl Analyzer {
 
     /// Use deterministic values for random numbers (for ex. flow IDs)
     ///
-    /// This option is intended for use in testing
-    pub fn with_deterministic_rng(mut self) -> Self {
-        self.flows = self.flows.with_rng_seed(0);
+    /// Flow IDs are now always deterministic, so this function has no effect.
+    #[deprecated(note = "flow IDs are deterministic")]
+    pub fn with_deterministic_rng(self) -> Self {
         self
     }
 }
@@ -163,6 +276,7 @@ pub(crate) fn handle_l2(
                     let payload = eth.payload();
                     if payload.len() < 3 {
                         warn!("Incomplete 802.3 frame (idx={})", ctx.pcap_index);
+                        analyzer.record_anomaly(Error::truncated(Layer::Link, "802.3"));
                         return Ok(());
                     }
                     // if payload[1] == 0xAA {
@@ -215,8 +329,7 @@ pub(crate) fn handle_l3(
         EtherTypes::Ipv4 => handle_l3_ipv4(packet, ctx, data, analyzer),
         EtherTypes::Ipv6 => handle_l3_ipv6(packet, ctx, data, analyzer),
         EtherTypes::Vlan => handle_l3_vlan_801q(packet, ctx, data, analyzer),
-        // ignore ARP packets
-        EtherTypes::Arp => Ok(()),
+        EtherTypes::Arp => handle_l3_arp(packet, ctx, data, analyzer),
         // 0x880b: PPP (rfc7042)
         EtherType(0x880b) => handle_l3_ppp(packet, ctx, data, analyzer),
         // 0x8847: MPLS (RFC5332)
@@ -232,6 +345,7 @@ pub(crate) fn handle_l3(
                 e.0,
                 ctx.pcap_index
             );
+            analyzer.record_anomaly(Error::unsupported(Layer::Link, "ethertype"));
             Ok(())
         }
     }
@@ -244,7 +358,7 @@ fn handle_l3_ipv4(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_ipv4 (idx={})", ctx.pcap_index);
-    let ipv4 = Ipv4Packet::new(data).ok_or("Could not build IPv4 packet from data")?;
+    let ipv4 = Ipv4Packet::new(data).ok_or(Error::truncated(Layer::Network, "IPv4"))?;
     // eprintln!("ABORT pkt {:?}", ipv4);
     let orig_len = data.len();
 
@@ -254,7 +368,7 @@ fn handle_l3_ipv4(
     let (data, ipv4) = {
         if ip_len < data.len() && ip_len > 0 {
             let d = &data[..ip_len];
-            let ipv4 = Ipv4Packet::new(d).ok_or("Could not build IPv4 packet from data")?;
+            let ipv4 = Ipv4Packet::new(d).ok_or(Error::truncated(Layer::Network, "IPv4"))?;
             (d, ipv4)
         } else {
             (data, ipv4)
@@ -270,9 +384,20 @@ fn handle_l3_ipv4(
 
     if analyzer.do_checksums {
         let cksum = ::pnet_packet::ipv4::checksum(&ipv4);
-        if cksum != ipv4.get_checksum() {
-            warn!("IPv4: invalid checksum");
-        }
+        let status = if cksum == ipv4.get_checksum() {
+            ChecksumStatus::Valid
+        } else if ipv4.get_checksum() == 0 {
+            // checksum offload
+            ChecksumStatus::NotChecked
+        } else {
+            warn!("IPv4: invalid checksum (idx={})", ctx.pcap_index);
+            analyzer.record_anomaly(Error::checksum(Layer::Network, "IPv4"));
+            ChecksumStatus::Invalid
+        };
+        analyzer
+            .context
+            .checksum_stats
+            .record(IpNextHeaderProtocols::Ipv4, status);
     }
 
     // if get_total_length is 0, assume TSO offloading and no padding
@@ -285,7 +410,7 @@ fn handle_l3_ipv4(
         let start = ipv4.get_header_length() as usize * 4;
         if start > data.len() {
             warn!("IPv4: ip_len == 0 and ipv4.get_header_length is invalid!");
-            return Ok(());
+            return Err(Error::malformed(Layer::Network, "IPv4"));
         }
         &data[start..]
     } else {
@@ -316,6 +441,7 @@ fn handle_l3_ipv4(
         }
         Fragment::Error => {
             warn!("IPv4 defragmentation error");
+            analyzer.record_anomaly(Error::defrag(Layer::Network, "IPv4"));
             return Ok(());
         }
     };
@@ -328,23 +454,11 @@ fn handle_l3_ipv4(
     let l3_info = L3Info {
         three_tuple: t3,
         l4_proto,
+        ipv6_extensions: Ipv6ExtensionChain::default(),
     };
     handle_l3_common(packet, ctx, payload, &l3_info, analyzer)
 }
 
-fn is_ipv6_opt(opt: IpNextHeaderProtocol) -> bool {
-    matches!(
-        opt,
-        IpNextHeaderProtocols::Hopopt
-            | IpNextHeaderProtocols::Ipv6Opts
-            | IpNextHeaderProtocols::Ipv6Route
-            | IpNextHeaderProtocols::Ipv6Frag
-            | IpNextHeaderProtocols::Esp
-            | IpNextHeaderProtocols::Ah
-            | IpNextHeaderProtocols::MobilityHeader
-    )
-}
-
 fn handle_l3_ipv6(
     packet: &Packet,
     ctx: &ParseContext,
@@ -352,10 +466,9 @@ fn handle_l3_ipv6(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_ipv6 (idx={})", ctx.pcap_index);
-    let ipv6 = Ipv6Packet::new(data).ok_or("Could not build IPv6 packet from data")?;
+    let ipv6 = Ipv6Packet::new(data).ok_or(Error::truncated(Layer::Network, "IPv6"))?;
 
     let mut payload = ipv6.payload();
-    let mut l4_proto = ipv6.get_next_header();
 
     if payload.is_empty() {
         // jumbogram ? (rfc2675)
@@ -373,41 +486,9 @@ fn handle_l3_ipv6(
 
     // XXX remove padding ?
 
-    let mut extensions = Vec::new();
-    let mut frag_ext = None;
-
-    // skip all extensions (keep them ?)
-    while is_ipv6_opt(l4_proto) {
-        let ext = ExtensionPacket::new(payload)
-            .ok_or("Could not build IPv6 Extension packet from payload")?;
-        let next_header = ext.get_next_header();
-        trace!("option header: {}", l4_proto);
-        if l4_proto == IpNextHeaderProtocols::Ipv6Frag {
-            if frag_ext.is_some() {
-                warn!("multiple IPv6Frag extensions idx={}", ctx.pcap_index);
-                return Ok(());
-            }
-            frag_ext = FragmentPacket::new(payload);
-        }
-        // XXX fixup wrong extension size calculation in pnet
-        let offset = if l4_proto != IpNextHeaderProtocols::Ah {
-            ext.packet_size()
-        } else {
-            // https://en.wikipedia.org/wiki/IPsec#Authentication_Header
-            // The length of this Authentication Header in 4-octet units, minus 2. For example, an
-            // AH value of 4 equals 3×(32-bit fixed-length AH fields) + 3×(32-bit ICV fields) − 2
-            // and thus an AH value of 4 means 24 octets. Although the size is measured in 4-octet
-            // units, the length of this header needs to be a multiple of 8 octets if carried in an
-            // IPv6 packet. This restriction does not apply to an Authentication Header carried in
-            // an IPv4 packet.
-            let l1 = (payload[1] - 1) as usize;
-            let val = l1 * 4 + l1 * 4 - 2;
-            (val + 7) & (!7)
-        };
-        extensions.push((l4_proto, ext));
-        l4_proto = next_header;
-        payload = &payload[offset..];
-    }
+    let mut ipv6_extensions = Ipv6ExtensionChain::default();
+    let (l4_proto, payload) = ipv6_extensions.parse(ipv6.get_next_header(), payload)?;
+    trace!("IPv6 extension headers: {:?}", ipv6_extensions.headers);
 
     let t3 = ThreeTuple {
         src: IpAddr::V6(ipv6.get_source()),
@@ -433,17 +514,33 @@ fn handle_l3_ipv6(
     let l3_info = L3Info {
         three_tuple: t3,
         l4_proto: l4_proto.0,
+        ipv6_extensions,
     };
 
-    if let Some(frag_info) = frag_ext {
-        handle_l4_ipv6frag(
-            packet, ctx, &frag_info, payload, &l3_info, l4_proto, analyzer,
-        )
+    if l3_info.ipv6_extensions.fragment().is_some() {
+        handle_l4_ipv6frag(packet, ctx, payload, l3_info, analyzer)
     } else {
         handle_l3_common(packet, ctx, payload, &l3_info, analyzer)
     }
 }
 
+fn handle_l3_arp(
+    packet: &Packet,
+    ctx: &ParseContext,
+    data: &[u8],
+    analyzer: &mut Analyzer,
+) -> Result<(), Error> {
+    trace!("handle_l3_arp (idx={})", ctx.pcap_index);
+    match parse_arp(data) {
+        Some(msg) => {
+            trace!("    ARP: {:?}", msg);
+            gen_event_neighbor(packet, &msg, &analyzer.registry);
+        }
+        None => debug!("Unsupported or invalid ARP packet (idx={})", ctx.pcap_index),
+    }
+    Ok(())
+}
+
 fn handle_l3_vlan_801q(
     packet: &Packet,
     ctx: &ParseContext,
@@ -451,7 +548,7 @@ fn handle_l3_vlan_801q(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_vlan_801q (idx={})", ctx.pcap_index);
-    let vlan = VlanPacket::new(data).ok_or("Could not build 802.1Q Vlan packet from data")?;
+    let vlan = VlanPacket::new(data).ok_or(Error::truncated(Layer::Link, "802.1Q"))?;
     let next_ethertype = vlan.get_ethertype();
     trace!("    802.1q: VLAN id={}", vlan.get_vlan_identifier());
 
@@ -465,7 +562,7 @@ fn handle_l3_erspan(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_erspan (idx={})", ctx.pcap_index);
-    let erspan = ERSPANPacket::new(data).ok_or("Could not build Erspan packet from data")?;
+    let erspan = ERSPANPacket::new(data).ok_or(Error::truncated(Layer::Link, "ERSPAN"))?;
     trace!(
         "    erspan: VLAN id={} span ID={}",
         erspan.get_vlan(),
@@ -481,7 +578,7 @@ fn handle_l3_mpls(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l2_mpls (idx={})", ctx.pcap_index);
-    let mpls = MPLSPacket::new(data).ok_or("Could not build MPLS packet from data")?;
+    let mpls = MPLSPacket::new(data).ok_or(Error::truncated(Layer::Link, "MPLS"))?;
 
     let payload = mpls.payload();
     trace!("    MPLS # labels: {}", mpls.get_num_labels());
@@ -509,8 +606,7 @@ fn handle_l3_pppoesession(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_pppoesession (idx={})", ctx.pcap_index);
-    let session =
-        PppoeSessionPacket::new(data).ok_or("Could not build PppoeSession packet from data")?;
+    let session = PppoeSessionPacket::new(data).ok_or(Error::truncated(Layer::Link, "PPPoE"))?;
     trace!(
         "    pppoesession: version={} type={} code={}",
         session.get_version(),
@@ -528,7 +624,7 @@ fn handle_l3_ppp(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_ppp (idx={})", ctx.pcap_index);
-    let ppp = PppPacket::new(data).ok_or("Could not build Ppp packet from data")?;
+    let ppp = PppPacket::new(data).ok_or(Error::truncated(Layer::Link, "PPP"))?;
     let proto = ppp.get_protocol();
     let payload = ppp.payload();
     trace!("    ppp: protocol=0x{:02x}", proto.0,);
@@ -537,11 +633,50 @@ fn handle_l3_ppp(
         PppProtocolTypes::Ipv6 => handle_l3_ipv6(packet, ctx, payload, analyzer),
         _ => {
             warn!("Unsupported PPP protocol 0x{:02x}", proto.0);
+            analyzer.record_anomaly(Error::unsupported(Layer::Network, "PPP protocol"));
             Ok(())
         }
     }
 }
 
+/// Convert packet data for link types not decoded by the parser
+pub(crate) fn fixup_packet_data<'a>(packet: &Packet<'a>) -> PacketData<'a> {
+    match packet.data {
+        PacketData::Unsupported(raw) if packet.link_type == Linktype(12) => {
+            // defined as DLT_RAW in libpcap/dlt.h
+            match get_packetdata_raw(raw, packet.caplen as usize) {
+                Some(data @ PacketData::L3(_, _)) => data,
+                _ => PacketData::Unsupported(raw),
+            }
+        }
+        ref data => data.clone(),
+    }
+}
+
+/// Handle data starting at the transport layer
+///
+/// There is no network layer, so addresses are unknown (unspecified).
+pub(crate) fn handle_l4(
+    packet: &Packet,
+    ctx: &ParseContext,
+    data: &[u8],
+    l4_proto: u8,
+    analyzer: &mut Analyzer,
+) -> Result<(), Error> {
+    trace!("handle_l4 (idx={})", ctx.pcap_index);
+    let unspecified = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    let l3_info = L3Info {
+        l4_proto,
+        three_tuple: ThreeTuple {
+            src: unspecified,
+            dst: unspecified,
+            l4_proto,
+        },
+        ..L3Info::default()
+    };
+    handle_l3_common(packet, ctx, data, &l3_info, analyzer)
+}
+
 fn handle_l3_common(
     packet: &Packet,
     ctx: &ParseContext,
@@ -552,6 +687,7 @@ fn handle_l3_common(
     match IpNextHeaderProtocol(l3_info.l4_proto) {
         IpNextHeaderProtocols::Tcp => handle_l4_tcp(packet, ctx, data, l3_info, analyzer),
         IpNextHeaderProtocols::Udp => handle_l4_udp(packet, ctx, data, l3_info, analyzer),
+        IpNextHeaderProtocols::Sctp => handle_l4_sctp(packet, ctx, data, l3_info, analyzer),
         IpNextHeaderProtocols::Icmp => handle_l4_icmp(packet, ctx, data, l3_info, analyzer),
         IpNextHeaderProtocols::Icmpv6 => handle_l4_icmpv6(packet, ctx, data, l3_info, analyzer),
         IpNextHeaderProtocols::Esp => handle_l4_generic(packet, ctx, data, l3_info, analyzer),
@@ -560,6 +696,7 @@ fn handle_l3_common(
         IpNextHeaderProtocols::Ipv6 => handle_l3(packet, ctx, data, EtherTypes::Ipv6, analyzer),
         p => {
             warn!("Unsupported L4 proto {} (idx={})", p, ctx.pcap_index);
+            analyzer.record_anomaly(Error::unsupported(Layer::Transport, "IP protocol"));
             handle_l4_generic(packet, ctx, data, l3_info, analyzer)
         }
     }
@@ -574,48 +711,22 @@ fn handle_l4_tcp(
 ) -> Result<(), Error> {
     trace!("handle_l4_tcp (idx={})", ctx.pcap_index);
     trace!("    l4_data len: {}", l4_data.len());
-    let tcp = TcpPacket::new(l4_data).ok_or("Could not build TCP packet from data")?;
+    let tcp = TcpPacket::new(l4_data).ok_or(Error::truncated(Layer::Transport, "TCP"))?;
 
     let src_port = tcp.get_source();
     let dst_port = tcp.get_destination();
 
-    // XXX begin copy/paste of handle_l4_common
+    // result is only counted: data is sent to plugins after reassembly
+    let _ = check_l4_checksum(packet, ctx, l4_data, l3_info, analyzer);
+
     let five_tuple = FiveTuple::from_three_tuple(&l3_info.three_tuple, src_port, dst_port);
     trace!("5-t: {}", five_tuple);
     let now = packet.ts;
 
-    let flow_id = {
-        // flows modification section
-        let flows = &mut analyzer.flows;
-        // lookup flow
-        let flow_id = match flows.lookup_flow(&five_tuple) {
-            Some(id) => id,
-            None => {
-                let flow = Flow::new(&five_tuple, packet.ts.secs, packet.ts.micros);
-                gen_event_new_flow(&flow, &analyzer.registry);
-                flows.insert_flow(five_tuple.clone(), flow)
-            }
-        };
-
-        // update flow
-        flows.entry(flow_id).and_modify(|flow| {
-            flow.flow_id = flow_id;
-            flow.last_seen = now;
-        });
-        flow_id
-    };
-
-    // get a read-only reference to flow
-    let flow = analyzer
-        .flows
-        .get_flow(flow_id)
-        .expect("could not get flow from ID")
-        .clone();
-
+    let flow = update_flow(packet, &five_tuple, analyzer);
+    let flow_id = flow.flow_id;
     let to_server = flow.five_tuple == five_tuple;
 
-    // XXX end copy/paste
-
     let res = analyzer
         .tcp_defrag
         .update(&flow, &tcp, to_server, ctx.pcap_index);
@@ -663,10 +774,14 @@ fn handle_l4_tcp(
                 five_tuple: &t5,
                 to_server: !to_server,
                 l3_type: l3_info.three_tuple.l3_proto(),
+                ipv6_extensions: &l3_info.ipv6_extensions,
                 l4_data: &[], // reassembled, so no L4 data
                 l4_type: t5.proto,
                 l4_payload: Some(l4_payload),
                 flow: Some(&flow),
+                sctp: None,
+                icmp_error: None,
+                l4_checksum: ChecksumStatus::NotChecked,
                 pcap_index,
             };
             // let start = ::std::time::Instant::now();
@@ -682,6 +797,7 @@ fn handle_l4_tcp(
         }
         Err(e) => {
             warn!("Tcp steam reassembly error: {:?}", e);
+            analyzer.record_anomaly(Error::defrag(Layer::Transport, "TCP"));
         }
     }
 
@@ -700,6 +816,104 @@ fn handle_l4_tcp(
     Ok(())
 }
 
+fn handle_l4_sctp(
+    packet: &Packet,
+    ctx: &ParseContext,
+    l4_data: &[u8],
+    l3_info: &L3Info,
+    analyzer: &mut Analyzer,
+) -> Result<(), Error> {
+    trace!("handle_l4_sctp (idx={})", ctx.pcap_index);
+    trace!("    l4_data len: {}", l4_data.len());
+    let sctp = SctpPacket::new(l4_data).ok_or(Error::truncated(Layer::Transport, "SCTP"))?;
+
+    let src_port = sctp.get_source();
+    let dst_port = sctp.get_destination();
+
+    let five_tuple = FiveTuple::from_three_tuple(&l3_info.three_tuple, src_port, dst_port);
+    trace!("5-t: {}", five_tuple);
+
+    let flow = update_flow(packet, &five_tuple, analyzer);
+    let to_server = flow.five_tuple == five_tuple;
+
+    let update = analyzer
+        .sctp_defrag
+        .update(&flow, &sctp, to_server, ctx.pcap_index);
+    for event in update.events {
+        gen_event_flow(&flow, &FlowEvent::Sctp(event), &analyzer.registry);
+    }
+    send_sctp_messages(
+        packet,
+        ctx,
+        &flow,
+        &l3_info.ipv6_extensions,
+        l4_data,
+        update.messages,
+        analyzer,
+    )
+}
+
+/// Send complete (or partial) SCTP user messages to upper layer and call plugins
+///
+/// `packet` is the packet which completed the messages, or a dummy packet if the
+/// association is finalized.
+pub(crate) fn send_sctp_messages(
+    packet: &Packet,
+    ctx: &ParseContext,
+    flow: &Flow,
+    ipv6_extensions: &Ipv6ExtensionChain,
+    l4_data: &[u8],
+    messages: Vec<SctpMessage>,
+    analyzer: &mut Analyzer,
+) -> Result<(), Error> {
+    for msg in messages {
+        trace!(
+            "Sending SCTP message stream={} ssn={} ppid={} (len={}, first pcap_index={})",
+            msg.info.stream_id,
+            msg.info.ssn,
+            msg.info.ppid,
+            msg.data.len(),
+            msg.pcap_index,
+        );
+        let five_tuple = if msg.to_server {
+            flow.five_tuple.clone()
+        } else {
+            flow.five_tuple.get_reverse()
+        };
+        let l3_type = match five_tuple.src {
+            IpAddr::V4(_) => 0x0800,
+            IpAddr::V6(_) => 0x86DD,
+        };
+        // XXX build a dummy packet
+        let dummy_packet = Packet {
+            interface: packet.interface,
+            caplen: 0,
+            origlen: 0,
+            ts: packet.ts,
+            link_type: packet.link_type,
+            data: PacketData::L4(five_tuple.proto, &[]),
+            pcap_index: msg.pcap_index,
+        };
+        let pinfo = PacketInfo {
+            five_tuple: &five_tuple,
+            to_server: msg.to_server,
+            l3_type,
+            ipv6_extensions,
+            l4_data,
+            l4_type: five_tuple.proto,
+            l4_payload: Some(&msg.data),
+            flow: Some(flow),
+            sctp: Some(msg.info),
+            icmp_error: None,
+            l4_checksum: ChecksumStatus::NotChecked,
+            pcap_index: msg.pcap_index,
+        };
+        run_plugins_v2_transport(&dummy_packet, ctx, &pinfo, analyzer)?;
+    }
+
+    Ok(())
+}
+
 fn handle_l4_udp(
     packet: &Packet,
     ctx: &ParseContext,
@@ -709,12 +923,14 @@ fn handle_l4_udp(
 ) -> Result<(), Error> {
     trace!("handle_l4_udp (idx={})", ctx.pcap_index);
     trace!("    l4_data len: {}", data.len());
-    let udp = UdpPacket::new(data).ok_or("Could not build UDP packet from data")?;
+    let udp = UdpPacket::new(data).ok_or(Error::truncated(Layer::Transport, "UDP"))?;
 
     let l4_payload = Some(udp.payload());
     let src_port = udp.get_source();
     let dst_port = udp.get_destination();
 
+    let l4_checksum = check_l4_checksum(packet, ctx, data, l3_info, analyzer);
+
     // if sport/dport == 4789, this could be VXLAN
     // XXX l4 plugins will not be called
     if src_port == 4789 || dst_port == 4789 {
@@ -728,7 +944,15 @@ fn handle_l4_udp(
     }
 
     handle_l4_common(
-        packet, ctx, data, l3_info, src_port, dst_port, l4_payload, analyzer,
+        packet,
+        ctx,
+        data,
+        l3_info,
+        src_port,
+        dst_port,
+        l4_payload,
+        l4_checksum,
+        analyzer,
     )
 }
 
@@ -740,7 +964,7 @@ fn handle_l4_icmp(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l4_icmp (idx={})", ctx.pcap_index);
-    let icmp = IcmpPacket::new(data).ok_or("Could not build ICMP packet from data")?;
+    let icmp = IcmpPacket::new(data).ok_or(Error::truncated(Layer::Transport, "ICMP"))?;
     trace!(
         "ICMP type={:?} code={:?}",
         icmp.get_icmp_type(),
@@ -751,15 +975,38 @@ fn handle_l4_icmp(
     let src_port = u16::from(icmp.get_icmp_type().0);
     let dst_port = u16::from(icmp.get_icmp_code().0);
 
-    if analyzer.do_checksums {
-        let cksum = ::pnet_packet::icmp::checksum(&icmp);
-        if cksum != icmp.get_checksum() {
-            warn!("ICMP: invalid checksum");
-        }
+    let l4_checksum = check_l4_checksum(packet, ctx, data, l3_info, analyzer);
+
+    if let Some(error) = IcmpError::from_icmp(
+        icmp.get_icmp_type().0,
+        icmp.get_icmp_code().0,
+        l3_info.three_tuple.src,
+        icmp.payload(),
+    ) {
+        return handle_l4_icmp_error(
+            packet,
+            ctx,
+            data,
+            l3_info,
+            src_port,
+            dst_port,
+            l4_payload,
+            error,
+            l4_checksum,
+            analyzer,
+        );
     }
 
     handle_l4_common(
-        packet, ctx, data, l3_info, src_port, dst_port, l4_payload, analyzer,
+        packet,
+        ctx,
+        data,
+        l3_info,
+        src_port,
+        dst_port,
+        l4_payload,
+        l4_checksum,
+        analyzer,
     )
 }
 
@@ -771,7 +1018,7 @@ fn handle_l4_icmpv6(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l4_icmpv6 (idx={})", ctx.pcap_index);
-    let icmpv6 = Icmpv6Packet::new(data).ok_or("Could not build ICMPv6 packet from data")?;
+    let icmpv6 = Icmpv6Packet::new(data).ok_or(Error::truncated(Layer::Transport, "ICMPv6"))?;
     trace!(
         "ICMPv6 type={:?} code={:?}",
         icmpv6.get_icmpv6_type(),
@@ -782,18 +1029,152 @@ fn handle_l4_icmpv6(
     let src_port = 0;
     let dst_port = 0;
 
-    if let (IpAddr::V6(src), IpAddr::V6(dst)) = (l3_info.three_tuple.src, l3_info.three_tuple.dst) {
-        let cksum = ::pnet_packet::icmpv6::checksum(&icmpv6, &src, &dst);
-        if cksum != icmpv6.get_checksum() {
-            warn!("ICMPv6: invalid checksum");
+    let l4_checksum = check_l4_checksum(packet, ctx, data, l3_info, analyzer);
+
+    if let IpAddr::V6(src) = l3_info.three_tuple.src {
+        if let Some(msg) = parse_ndp(&src, data) {
+            trace!("    NDP: {:?}", msg);
+            gen_event_neighbor(packet, &msg, &analyzer.registry);
         }
     }
 
+    if let Some(error) = IcmpError::from_icmpv6(
+        icmpv6.get_icmpv6_type().0,
+        icmpv6.get_icmpv6_code().0,
+        l3_info.three_tuple.src,
+        icmpv6.payload(),
+    ) {
+        return handle_l4_icmp_error(
+            packet,
+            ctx,
+            data,
+            l3_info,
+            src_port,
+            dst_port,
+            l4_payload,
+            error,
+            l4_checksum,
+            analyzer,
+        );
+    }
+
     handle_l4_common(
-        packet, ctx, data, l3_info, src_port, dst_port, l4_payload, analyzer,
+        packet,
+        ctx,
+        data,
+        l3_info,
+        src_port,
+        dst_port,
+        l4_payload,
+        l4_checksum,
+        analyzer,
     )
 }
 
+/// Verify the transport layer checksum, if enabled, and update counters
+///
+/// Checksum is not verified if the packet was truncated during capture.
+fn check_l4_checksum(
+    packet: &Packet,
+    ctx: &ParseContext,
+    l4_data: &[u8],
+    l3_info: &L3Info,
+    analyzer: &Analyzer,
+) -> ChecksumStatus {
+    if !analyzer.do_checksums {
+        return ChecksumStatus::NotChecked;
+    }
+    let proto = IpNextHeaderProtocol(l3_info.l4_proto);
+    // without network layer, the pseudo-header is unknown
+    let status = if packet.caplen < packet.origlen || matches!(packet.data, PacketData::L4(_, _)) {
+        ChecksumStatus::NotChecked
+    } else {
+        let t3 = &l3_info.three_tuple;
+        verify_l4_checksum(proto, &t3.src, &t3.dst, l4_data)
+    };
+    if status == ChecksumStatus::Invalid {
+        warn!("{}: invalid checksum (idx={})", proto, ctx.pcap_index);
+        let name = match proto {
+            IpNextHeaderProtocols::Tcp => "TCP",
+            IpNextHeaderProtocols::Udp => "UDP",
+            IpNextHeaderProtocols::Icmp => "ICMP",
+            IpNextHeaderProtocols::Icmpv6 => "ICMPv6",
+            _ => "other",
+        };
+        analyzer.record_anomaly(Error::checksum(Layer::Transport, name));
+    }
+    analyzer.context.checksum_stats.record(proto, status);
+    status
+}
+
+/// Handle ICMP and ICMPv6 error messages
+///
+/// If the flow of the quoted packet is known, the error is attached to this flow as an
+/// event, and the transport plugins receive the original flow. Otherwise, the message
+/// is handled as a regular ICMP flow.
+#[allow(clippy::too_many_arguments)]
+fn handle_l4_icmp_error(
+    packet: &Packet,
+    ctx: &ParseContext,
+    l4_data: &[u8],
+    l3_info: &L3Info,
+    src_port: u16,
+    dst_port: u16,
+    l4_payload: Option<&[u8]>,
+    error: IcmpError,
+    l4_checksum: ChecksumStatus,
+    analyzer: &mut Analyzer,
+) -> Result<(), Error> {
+    trace!(
+        "ICMP error type={} code={} for {} (idx={})",
+        error.icmp_type,
+        error.icmp_code,
+        error.quoted,
+        ctx.pcap_index
+    );
+    let five_tuple = FiveTuple::from_three_tuple(&l3_info.three_tuple, src_port, dst_port);
+
+    let flow_id = analyzer
+        .flows
+        .lookup_flow(&error.quoted)
+        .or_else(|| analyzer.flows.lookup_flow(&error.quoted.get_reverse()));
+    let orig_flow = flow_id.and_then(|id| analyzer.flows.get_flow(id)).cloned();
+    let (flow, to_server) = match orig_flow {
+        Some(flow) => {
+            gen_event_flow(
+                &flow,
+                &FlowEvent::IcmpError(error.clone()),
+                &analyzer.registry,
+            );
+            // the error is sent back to the sender of the quoted packet
+            let to_server = flow.five_tuple != error.quoted;
+            (flow, to_server)
+        }
+        None => {
+            debug!("No flow found for ICMP error (quoted: {})", error.quoted);
+            let flow = update_flow(packet, &five_tuple, analyzer);
+            let to_server = flow.five_tuple == five_tuple;
+            (flow, to_server)
+        }
+    };
+
+    let pinfo = PacketInfo {
+        five_tuple: &five_tuple,
+        to_server,
+        l3_type: l3_info.three_tuple.l3_proto(),
+        ipv6_extensions: &l3_info.ipv6_extensions,
+        l4_data,
+        l4_type: five_tuple.proto,
+        l4_payload,
+        flow: Some(&flow),
+        sctp: None,
+        icmp_error: Some(&error),
+        l4_checksum,
+        pcap_index: ctx.pcap_index,
+    };
+    run_plugins_v2_transport(packet, ctx, &pinfo, analyzer)
+}
+
 // Geneve: Generic Network Virtualization Encapsulation
 // https://tools.ietf.org/html/draft-ietf-nvo3-geneve-16
 fn handle_l4_geneve(
@@ -805,7 +1186,8 @@ fn handle_l4_geneve(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l4_geneve (idx={})", ctx.pcap_index);
-    let geneve = GENEVEPacket::new(l4_data).ok_or("Could not build GENEVE packet from data")?;
+    let geneve =
+        GENEVEPacket::new(l4_data).ok_or(Error::truncated(Layer::Application, "Geneve"))?;
     let payload = geneve.payload();
     let next_proto = geneve.get_protocol_type();
 
@@ -833,7 +1215,7 @@ fn handle_l4_gre(
     trace!("handle_l4_gre (idx={})", ctx.pcap_index);
     let l3_data = data;
 
-    let gre = GrePacket::new(l3_data).ok_or("Could not build GRE packet from data")?;
+    let gre = GrePacket::new(l3_data).ok_or(Error::truncated(Layer::Transport, "GRE"))?;
 
     let next_proto = gre.get_protocol_type();
     // XXX can panic: 'Source routed GRE packets not supported' in gre_routing_length()
@@ -871,7 +1253,7 @@ fn handle_l4_vxlan(
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l4_vxlan (idx={})", ctx.pcap_index);
-    let vxlan = VxlanPacket::new(l4_data).ok_or("Could not build Vxlan packet from data")?;
+    let vxlan = VxlanPacket::new(l4_data).ok_or(Error::truncated(Layer::Application, "VXLAN"))?;
     let payload = vxlan.payload();
 
     trace!("    Vxlan: VLAN id={}", vxlan.get_vlan_identifier());
@@ -882,29 +1264,27 @@ fn handle_l4_vxlan(
 fn handle_l4_ipv6frag(
     packet: &Packet,
     ctx: &ParseContext,
-    frag_info: &FragmentPacket,
     data: &[u8],
-    l3_info: &L3Info,
-    l4_proto: IpNextHeaderProtocol,
+    mut l3_info: L3Info,
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     trace!("handle_l3_ipv6frag (idx={})", ctx.pcap_index);
-    let frag_offset = frag_info.get_fragment_offset() as usize;
-    let frag_id = frag_info.get_id();
-    let last_fragment = frag_info.is_last_fragment();
+    let (frag_offset, more_fragments, frag_id) = l3_info
+        .ipv6_extensions
+        .fragment()
+        .ok_or(Error::malformed(Layer::Network, "IPv6 fragment"))?;
     trace!(
-        "IPv6 Fragment frag_offset={} id={} last_fragment={}",
+        "IPv6 Fragment frag_offset={} id={} more_fragments={}",
         frag_offset,
         frag_id,
-        last_fragment
+        more_fragments
     );
 
     let defrag = {
         // check IP fragmentation before calling handle_l4
-        let more_fragments = !last_fragment;
         analyzer
             .ipv6_defrag
-            .update(frag_id, frag_offset, more_fragments, data)
+            .update(frag_id, frag_offset as usize, more_fragments, data)
     };
     let data = match defrag {
         Fragment::NoFrag(d) => d,
@@ -921,19 +1301,34 @@ fn handle_l4_ipv6frag(
         }
         Fragment::Error => {
             warn!("IPv6Fragment defragmentation error");
+            analyzer.record_anomaly(Error::defrag(Layer::Network, "IPv6"));
             return Ok(());
         }
     };
 
-    match l4_proto {
-        IpNextHeaderProtocols::Tcp => handle_l4_tcp(packet, ctx, data, l3_info, analyzer),
-        IpNextHeaderProtocols::Udp => handle_l4_udp(packet, ctx, data, l3_info, analyzer),
-        IpNextHeaderProtocols::Icmp => handle_l4_icmp(packet, ctx, data, l3_info, analyzer),
-        _ => {
-            warn!("IPv6Fragment: Unsupported L4 proto {}", l4_proto);
-            handle_l4_generic(packet, ctx, data, l3_info, analyzer)
-        }
+    // extension headers following the fragment header are in the fragmentable part
+    let nb_headers = l3_info.ipv6_extensions.headers.len();
+    let (l4_proto, data) = l3_info
+        .ipv6_extensions
+        .parse(IpNextHeaderProtocol(l3_info.l4_proto), data)?;
+    if l3_info.ipv6_extensions.fragment_count() > 1 {
+        warn!("multiple IPv6Frag extensions idx={}", ctx.pcap_index);
+        return Err(Error::malformed(Layer::Network, "IPv6 fragment"));
+    }
+    if l3_info.ipv6_extensions.headers.len() > nb_headers {
+        trace!(
+            "IPv6 extension headers after reassembly: {:?}",
+            &l3_info.ipv6_extensions.headers[nb_headers..]
+        );
     }
+    if l4_proto == IpNextHeaderProtocols::Ipv6NoNxt {
+        trace!("No next header");
+        return Ok(());
+    }
+    l3_info.l4_proto = l4_proto.0;
+    l3_info.three_tuple.l4_proto = l4_proto.0;
+
+    handle_l3_common(packet, ctx, data, &l3_info, analyzer)
 }
 
 fn handle_l4_generic(
@@ -952,9 +1347,18 @@ fn handle_l4_generic(
     let l4_payload = None;
     let src_port = 0;
     let dst_port = 0;
+    let l4_checksum = ChecksumStatus::NotChecked;
 
     handle_l4_common(
-        packet, ctx, data, l3_info, src_port, dst_port, l4_payload, analyzer,
+        packet,
+        ctx,
+        data,
+        l3_info,
+        src_port,
+        dst_port,
+        l4_payload,
+        l4_checksum,
+        analyzer,
     )
 }
 
@@ -967,50 +1371,27 @@ fn handle_l4_common(
     src_port: u16,
     dst_port: u16,
     l4_payload: Option<&[u8]>,
+    l4_checksum: ChecksumStatus,
     analyzer: &mut Analyzer,
 ) -> Result<(), Error> {
     let five_tuple = FiveTuple::from_three_tuple(&l3_info.three_tuple, src_port, dst_port);
     trace!("5-t: {}", five_tuple);
-    let now = packet.ts;
-
-    let flow_id = {
-        // flows modification section
-        let flows = &mut analyzer.flows;
-        // lookup flow
-        let flow_id = match flows.lookup_flow(&five_tuple) {
-            Some(id) => id,
-            None => {
-                let flow = Flow::new(&five_tuple, packet.ts.secs, packet.ts.micros);
-                gen_event_new_flow(&flow, &analyzer.registry);
-                flows.insert_flow(five_tuple.clone(), flow)
-            }
-        };
-
-        // update flow
-        flows.entry(flow_id).and_modify(|flow| {
-            flow.flow_id = flow_id;
-            flow.last_seen = now;
-        });
-        flow_id
-    };
-
-    // get a read-only reference to flow
-    let flow = analyzer
-        .flows
-        .get_flow(flow_id)
-        .expect("could not get flow from ID")
-        .clone(); // clone because run_plugins_v2_transport borrows analyzer
 
+    let flow = update_flow(packet, &five_tuple, analyzer);
     let to_server = flow.five_tuple == five_tuple;
 
     let pinfo = PacketInfo {
         five_tuple: &five_tuple,
         to_server,
         l3_type: l3_info.three_tuple.l3_proto(),
+        ipv6_extensions: &l3_info.ipv6_extensions,
         l4_data,
         l4_type: five_tuple.proto,
         l4_payload,
         flow: Some(&flow),
+        sctp: None,
+        icmp_error: None,
+        l4_checksum,
         pcap_index: ctx.pcap_index,
     };
     // let start = ::std::time::Instant::now();
@@ -1035,6 +1416,41 @@ fn handle_l4_common(
     Ok(())
 }
 
+/// Lookup flow (or create it if not found), update timestamps, and return a copy
+fn update_flow(packet: &Packet, five_tuple: &FiveTuple, analyzer: &mut Analyzer) -> Flow {
+    let now = packet.ts;
+    let flow_id = {
+        // flows modification section
+        let flows = &mut analyzer.flows;
+        // lookup flow
+        let flow_id = match flows.lookup_flow(five_tuple) {
+            Some(id) => id,
+            None => {
+                let flow = Flow::new(five_tuple, packet.ts);
+                let flow_id = flows.insert_flow(five_tuple.clone(), flow);
+                if let Some(flow) = flows.get_flow(flow_id) {
+                    gen_event_new_flow(flow, &analyzer.registry);
+                }
+                flow_id
+            }
+        };
+
+        // update flow
+        flows.entry(flow_id).and_modify(|flow| {
+            flow.flow_id = flow_id;
+            flow.last_seen = now;
+        });
+        flow_id
+    };
+
+    // clone because plugin callbacks borrow analyzer
+    analyzer
+        .flows
+        .get_flow(flow_id)
+        .expect("could not get flow from ID")
+        .clone()
+}
+
 fn run_plugins_v2<'i, F>(
     packet: &Packet,
     ctx: &ParseContext,
@@ -1076,6 +1492,7 @@ where
             PluginResult::Error(e) => {
                 // XXX ignore error in plugins ? just log ?
                 warn!("Plugin returned error {:?}", e);
+                analyzer.record_anomaly(e);
                 continue;
             }
             PluginResult::L2(e, payload) => {
@@ -1092,6 +1509,7 @@ where
                     t5.src_port,
                     t5.dst_port,
                     Some(payload),
+                    ChecksumStatus::NotChecked,
                     analyzer,
                 )?;
             }
@@ -1164,79 +1582,101 @@ pub(crate) fn gen_event_new_flow(flow: &Flow, registry: &PluginRegistry) {
     // debug!("Time to run flow_created: {}.{}", elapsed.as_secs(), elapsed.as_millis());
 }
 
+pub(crate) fn gen_event_neighbor(
+    packet: &Packet,
+    msg: &NeighborMessage,
+    registry: &PluginRegistry,
+) {
+    registry.run_plugins(
+        |p| p.plugin_type() & PLUGIN_NEIGHBOR != 0,
+        |p| p.neighbor_message(packet, msg),
+    );
+}
+
+pub(crate) fn gen_event_flow(flow: &Flow, event: &FlowEvent, registry: &PluginRegistry) {
+    registry.run_plugins(
+        |p| p.plugin_type() & PLUGIN_FLOW_EVENT != 0,
+        |p| p.flow_event(flow, event),
+    );
+}
+
+/// Run `post_process` for all plugins, then save results if `output_dir` is set
+pub(crate) fn finish_plugins(registry: &PluginRegistry, output_dir: Option<&str>) {
+    registry.run_plugins(|_| true, |p| p.post_process());
+
+    if let Some(output_dir) = output_dir {
+        registry.run_plugins(
+            |_| true,
+            |p| {
+                let res = p.save_results(output_dir);
+                if let Err(e) = res {
+                    warn!("error while saving results for {}: {}", p.name(), e);
+                }
+            },
+        );
+    }
+}
+
 impl PcapAnalyzer for Analyzer {
     /// Initialize all plugins
     fn init(&mut self) -> Result<(), Error> {
+        self.init_shared_state()?;
         self.registry.run_plugins(|_| true, |p| p.pre_process());
         Ok(())
     }
 
+    fn handle_block(
+        &mut self,
+        block: &PcapBlockOwned,
+        _block_ctx: &ParseBlockContext,
+    ) -> Result<(), Error> {
+        self.context.capture_stats.handle_block(block);
+        match block {
+            PcapBlockOwned::NG(Block::DecryptionSecrets(dsb)) => {
+                self.handle_decryption_secrets(dsb);
+            }
+            PcapBlockOwned::NG(Block::NameResolution(nrb)) => {
+                let count = self.context.name_table.handle_nrb(nrb);
+                debug!("Read {} names from Name Resolution Block", count);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
     /// Dispatch function: given a packet, use link type to get the real data, and
     /// call the matching handling function (some pcap blocks encode ethernet, or IPv4 etc.)
     fn handle_packet(&mut self, packet: &Packet, ctx: &ParseContext) -> Result<(), Error> {
         if ctx.pcap_index < self.skip_index {
             return Ok(());
         }
-        match packet.data {
+        self.context.error_stats.add_packet();
+        let res = match fixup_packet_data(packet) {
             PacketData::L2(data) => self.handle_l2(packet, ctx, data),
             PacketData::L3(ethertype, data) => {
                 handle_l3(packet, ctx, data, EtherType(ethertype), self)
             }
-            PacketData::L4(_, _) => unimplemented!(), // XXX
-            PacketData::Unsupported(raw) => {
-                // fixups
-                if packet.link_type == Linktype(12) {
-                    // defined as DLT_RAW in libpcap/dlt.h
-                    if let Some(PacketData::L3(ethertype, packet_data)) =
-                        get_packetdata_raw(raw, packet.caplen as usize)
-                    {
-                        return handle_l3(packet, ctx, packet_data, EtherType(ethertype), self);
-                    }
-                }
+            PacketData::L4(l4_proto, data) => handle_l4(packet, ctx, data, l4_proto, self),
+            PacketData::Unsupported(_) => {
                 warn!(
                     "Unsupported data format (unknown linktype {}) idx={}",
                     packet.link_type, ctx.pcap_index
                 );
-                Ok(())
+                Err(Error::unsupported(Layer::Link, "link type"))
             }
-        }
+        };
+        res.or_else(|e| self.handle_error(ctx, e))
     }
 
     /// Finalize analysis and notify plugins
     fn teardown(&mut self) {
-        {
-            // expire all TCP connections in reassembly engine
-            finalize_tcp_streams(self);
-            // expire remaining flows
-            let flows = &self.flows;
-            trace!("{} flows remaining in table", flows.len());
-            // let start = ::std::time::Instant::now();
-            self.registry.run_plugins(
-                |p| p.plugin_type() & PLUGIN_FLOW_DEL != 0,
-                |p| {
-                    flows.values().for_each(|flow| {
-                        p.flow_destroyed(flow);
-                    });
-                },
-            );
-            // let elapsed = start.elapsed();
-            // debug!("Time to run flow_destroyed {}.{}", elapsed.as_secs(), elapsed.as_millis());
-            self.flows.clear();
-
-            self.registry.run_plugins(|_| true, |p| p.post_process());
-
-            if let Some(output_dir) = &self.output_dir {
-                self.registry.run_plugins(
-                    |_| true,
-                    |p| {
-                        let res = p.save_results(output_dir);
-                        if let Err(e) = res {
-                            warn!("error while saving results for {}: {}", p.name(), e);
-                        }
-                    },
-                );
-            }
-        };
+        self.finalize_flows();
+        debug!("Checksums: {}", self.context.checksum_stats);
+        if self.num_errors() > 0 {
+            warn!("{} packets could not be handled", self.num_errors());
+        }
+        debug!("Errors: {}", self.context.error_stats);
+        finish_plugins(&self.registry, self.output_dir.as_deref());
     }
 }
 
@@ -0,0 +1,75 @@
+//!
+//! Pool of reusable packet buffers
+//!
+
+use std::ops::Deref;
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+/// A pool of byte buffers, shared between threads
+///
+/// Buffers are returned to the pool when dropped, so allocations are reused
+/// once the pool is warmed up.
+#[derive(Clone)]
+pub struct BufferPool {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Create a pool keeping at most `capacity` free buffers
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = bounded(capacity);
+        BufferPool { sender, receiver }
+    }
+
+    /// Get a buffer from the pool (or allocate a new one), and fill it with a copy of `data`
+    pub fn copy_from_slice(&self, data: &[u8]) -> PooledBuffer {
+        let mut buf = self.receiver.try_recv().unwrap_or_default();
+        buf.clear();
+        buf.extend_from_slice(data);
+        PooledBuffer {
+            buf,
+            pool: self.sender.clone(),
+        }
+    }
+}
+
+/// An owned byte buffer, returned to its `BufferPool` when dropped
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Sender<Vec<u8>>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let buf = std::mem::take(&mut self.buf);
+        // if pool is full, buffer is simply deallocated
+        let _ = self.pool.try_send(buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferPool;
+
+    #[test]
+    fn buffer_pool_reuse() {
+        let pool = BufferPool::new(2);
+        let b1 = pool.copy_from_slice(b"abcdef");
+        assert_eq!(&*b1, b"abcdef");
+        let ptr = b1.as_ptr();
+        drop(b1);
+        let b2 = pool.copy_from_slice(b"xyz");
+        assert_eq!(&*b2, b"xyz");
+        assert_eq!(b2.as_ptr(), ptr);
+    }
+}
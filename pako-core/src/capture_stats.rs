@@ -0,0 +1,274 @@
+//!
+//! Capture completeness: interface statistics and packet drops
+//!
+
+use std::sync::Mutex;
+
+use log::warn;
+use pako_pcap::{BlockOption, BlockType, Endianness, EpbOption, IsbOption};
+use pako_tools::pcap_parser::{Block, PcapBlockOwned, PcapNGOption};
+use serde::Serialize;
+
+/// Statistics of a capture interface
+///
+/// ISB counters are cumulative: the values of the last Interface Statistics Block of the
+/// interface are kept. Counters absent from the capture are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InterfaceStats {
+    /// Packets received by the interface (`isb_ifrecv`)
+    pub if_recv: Option<u64>,
+    /// Packets dropped by the interface, for ex. for lack of resources (`isb_ifdrop`)
+    pub if_drop: Option<u64>,
+    /// Packets accepted by the capture filter (`isb_filteraccept`)
+    pub filter_accept: Option<u64>,
+    /// Packets dropped by the operating system (`isb_osdrop`)
+    pub os_drop: Option<u64>,
+    /// Packets delivered to the capture application (`isb_usrdeliv`)
+    pub user_deliv: Option<u64>,
+    /// Sum of the drop counts of the packets of the interface (`epb_dropcount`)
+    pub epb_drops: Option<u64>,
+    /// Number of packets of the interface in the capture
+    pub packets: u64,
+}
+
+impl InterfaceStats {
+    /// Number of packets lost by the capture
+    ///
+    /// The ISB drop counters are used if present, otherwise the sum of the EPB drop counts.
+    pub fn dropped(&self) -> Option<u64> {
+        match (self.if_drop, self.os_drop) {
+            (None, None) => self.epb_drops,
+            (if_drop, os_drop) => Some(if_drop.unwrap_or(0).saturating_add(os_drop.unwrap_or(0))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Interfaces of each section
+    sections: Vec<Vec<InterfaceStats>>,
+    /// Byte order of the current section
+    endianness: Option<Endianness>,
+}
+
+impl State {
+    fn interface(&mut self, if_id: u32) -> Option<&mut InterfaceStats> {
+        self.sections.last_mut()?.get_mut(if_id as usize)
+    }
+}
+
+/// Decode the options of a block, in the byte order of the section
+fn decode_options<'a>(
+    block_type: BlockType,
+    endianness: Endianness,
+    options: &'a [PcapNGOption],
+) -> impl Iterator<Item = BlockOption<'a>> {
+    options
+        .iter()
+        .map(move |opt| BlockOption::decode(block_type, endianness, opt.code.0, &opt.value))
+}
+
+/// Capture completeness counters
+///
+/// The analyzer updates the counters from the blocks of the capture: number of packets
+/// of each interface, drop counts of Enhanced Packet Blocks, and counters of Interface
+/// Statistics Blocks. Comparing them tells how complete the capture is.
+///
+/// The same instance is shared by all workers of a `ThreadedAnalyzer` and by plugins
+/// (see `Plugin::set_context`), and can be read after the analysis.
+#[derive(Debug, Default)]
+pub struct CaptureStats {
+    state: Mutex<State>,
+}
+
+impl CaptureStats {
+    /// Update counters from a block of the capture
+    pub(crate) fn handle_block(&self, block: &PcapBlockOwned) {
+        let mut state = self.state.lock().expect("capture stats lock poisoned");
+        let endianness = state.endianness.unwrap_or(Endianness::Little);
+        match block {
+            PcapBlockOwned::NG(Block::SectionHeader(shb)) => {
+                state.sections.push(Vec::new());
+                state.endianness = Some(if shb.big_endian() {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                });
+            }
+            PcapBlockOwned::NG(Block::InterfaceDescription(_)) => {
+                if state.sections.is_empty() {
+                    state.sections.push(Vec::new());
+                }
+                if let Some(section) = state.sections.last_mut() {
+                    section.push(InterfaceStats::default());
+                }
+            }
+            PcapBlockOwned::LegacyHeader(_) => {
+                state.sections.push(vec![InterfaceStats::default()]);
+            }
+            PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                if let Some(stats) = state.interface(epb.if_id) {
+                    stats.packets += 1;
+                    let drops = decode_options(BlockType::EnhancedPacket, endianness, &epb.options)
+                        .find_map(|opt| match opt {
+                            BlockOption::Epb(EpbOption::DropCount(drops)) => Some(drops),
+                            _ => None,
+                        });
+                    if let Some(drops) = drops {
+                        stats.epb_drops = Some(stats.epb_drops.unwrap_or(0).saturating_add(drops));
+                    }
+                }
+            }
+            PcapBlockOwned::NG(Block::SimplePacket(_)) | PcapBlockOwned::Legacy(_) => {
+                if let Some(stats) = state.interface(0) {
+                    stats.packets += 1;
+                }
+            }
+            PcapBlockOwned::NG(Block::InterfaceStatistics(isb)) => {
+                let Some(stats) = state.interface(isb.if_id) else {
+                    warn!("Interface statistics for unknown interface {}", isb.if_id);
+                    return;
+                };
+                for opt in decode_options(BlockType::InterfaceStatistic, endianness, &isb.options) {
+                    let (counter, value) = match opt {
+                        BlockOption::Isb(IsbOption::IfRecv(v)) => (&mut stats.if_recv, v),
+                        BlockOption::Isb(IsbOption::IfDrop(v)) => (&mut stats.if_drop, v),
+                        BlockOption::Isb(IsbOption::FilterAccept(v)) => {
+                            (&mut stats.filter_accept, v)
+                        }
+                        BlockOption::Isb(IsbOption::OsDrop(v)) => (&mut stats.os_drop, v),
+                        BlockOption::Isb(IsbOption::UsrDeliv(v)) => (&mut stats.user_deliv, v),
+                        _ => continue,
+                    };
+                    *counter = Some(value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Statistics of the interfaces of each section
+    pub fn sections(&self) -> Vec<Vec<InterfaceStats>> {
+        let state = self.state.lock().expect("capture stats lock poisoned");
+        state.sections.clone()
+    }
+
+    /// Number of packets in the capture
+    pub fn packets(&self) -> u64 {
+        let state = self.state.lock().expect("capture stats lock poisoned");
+        state.sections.iter().flatten().map(|s| s.packets).sum()
+    }
+
+    /// Number of packets lost by the capture, or `None` if no interface reports drops
+    pub fn dropped(&self) -> Option<u64> {
+        let state = self.state.lock().expect("capture stats lock poisoned");
+        state
+            .sections
+            .iter()
+            .flatten()
+            .filter_map(InterfaceStats::dropped)
+            .reduce(u64::saturating_add)
+    }
+
+    /// Fraction of the packets which were captured (between 0 and 1), or `None` if unknown
+    pub fn completeness(&self) -> Option<f64> {
+        let dropped = self.dropped()?;
+        let packets = self.packets();
+        match packets.saturating_add(dropped) {
+            0 => None,
+            total => Some(packets as f64 / total as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pako_pcap::{BlockOption, Endianness, EpbOption, IsbOption, PcapNgWriter};
+    use pako_tools::pcap_parser::{
+        pcapng::{parse_block_be, parse_block_le},
+        PcapBlockOwned,
+    };
+
+    use super::{CaptureStats, InterfaceStats};
+
+    #[test]
+    fn capture_stats_options() {
+        for endianness in [Endianness::Little, Endianness::Big] {
+            let mut writer = PcapNgWriter::new(Vec::new());
+            writer.write_section_header(endianness, &[]).unwrap();
+            let if_id = writer.write_interface_description(1, 65535, &[]).unwrap();
+            for drops in [2, 3] {
+                let options = [BlockOption::Epb(EpbOption::DropCount(drops))];
+                writer
+                    .write_enhanced_packet(if_id, 0, 4, &[0; 4], &options)
+                    .unwrap();
+            }
+            let options = [
+                BlockOption::Isb(IsbOption::IfRecv(20)),
+                BlockOption::Isb(IsbOption::IfDrop(u64::MAX)),
+                BlockOption::Isb(IsbOption::OsDrop(1)),
+            ];
+            writer
+                .write_interface_statistics(if_id, 0, &options)
+                .unwrap();
+            let data = writer.into_inner().unwrap();
+
+            let stats = CaptureStats::default();
+            let mut rem = &data[..];
+            while !rem.is_empty() {
+                let (i, block) = match endianness {
+                    Endianness::Little => parse_block_le(rem),
+                    Endianness::Big => parse_block_be(rem),
+                }
+                .expect("invalid block");
+                stats.handle_block(&PcapBlockOwned::NG(block));
+                rem = i;
+            }
+            let sections = stats.sections();
+            assert_eq!(sections.len(), 1);
+            let interface = &sections[0][0];
+            assert_eq!(interface.packets, 2);
+            assert_eq!(interface.epb_drops, Some(5));
+            assert_eq!(interface.if_recv, Some(20));
+            assert_eq!(interface.os_drop, Some(1));
+            assert_eq!(interface.user_deliv, None);
+            // counters saturate instead of overflowing
+            assert_eq!(stats.dropped(), Some(u64::MAX));
+            assert_eq!(stats.completeness(), Some(2.0 / u64::MAX as f64));
+        }
+    }
+
+    #[test]
+    fn capture_stats_dropped() {
+        let stats = CaptureStats::default();
+        assert_eq!(stats.dropped(), None);
+        assert_eq!(stats.completeness(), None);
+
+        let epb_only = InterfaceStats {
+            epb_drops: Some(5),
+            packets: 15,
+            ..InterfaceStats::default()
+        };
+        assert_eq!(epb_only.dropped(), Some(5));
+        // ISB counters take precedence over EPB drop counts
+        let isb = InterfaceStats {
+            if_drop: Some(10),
+            os_drop: Some(5),
+            epb_drops: Some(3),
+            packets: 30,
+            ..InterfaceStats::default()
+        };
+        assert_eq!(isb.dropped(), Some(15));
+        assert_eq!(InterfaceStats::default().dropped(), None);
+
+        stats
+            .state
+            .lock()
+            .unwrap()
+            .sections
+            .push(vec![epb_only, isb, InterfaceStats::default()]);
+        assert_eq!(stats.packets(), 45);
+        assert_eq!(stats.dropped(), Some(20));
+        assert_eq!(stats.completeness(), Some(45.0 / 65.0));
+    }
+}
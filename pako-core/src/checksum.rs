@@ -0,0 +1,287 @@
+//!
+//! Checksum validation for IPv4, TCP, UDP, ICMP and ICMPv6
+//!
+
+use std::{
+    fmt,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use pnet_packet::{
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    util,
+};
+
+/// Result of a checksum validation
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChecksumStatus {
+    /// Checksum was not verified (disabled, unsupported protocol, truncated capture,
+    /// checksum offload or no checksum present)
+    #[default]
+    NotChecked,
+    Valid,
+    Invalid,
+}
+
+/// Counters for a single protocol
+#[derive(Debug, Default)]
+pub struct ChecksumCounter {
+    valid: AtomicU64,
+    invalid: AtomicU64,
+    skipped: AtomicU64,
+}
+
+impl ChecksumCounter {
+    /// Number of packets with a valid checksum
+    pub fn valid(&self) -> u64 {
+        self.valid.load(Ordering::Relaxed)
+    }
+
+    /// Number of packets with an invalid checksum
+    pub fn invalid(&self) -> u64 {
+        self.invalid.load(Ordering::Relaxed)
+    }
+
+    /// Number of packets for which checksum could not be verified
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, status: ChecksumStatus) {
+        let counter = match status {
+            ChecksumStatus::Valid => &self.valid,
+            ChecksumStatus::Invalid => &self.invalid,
+            ChecksumStatus::NotChecked => &self.skipped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Checksum validation counters
+///
+/// Counters are atomic, so the same instance can be shared by all workers of a
+/// `ThreadedAnalyzer`, and read by plugins (see `AnalysisContext`) or reports while or
+/// after packets are processed.
+#[derive(Debug, Default)]
+pub struct ChecksumStats {
+    pub ipv4: ChecksumCounter,
+    pub tcp: ChecksumCounter,
+    pub udp: ChecksumCounter,
+    pub icmp: ChecksumCounter,
+    pub icmpv6: ChecksumCounter,
+}
+
+impl ChecksumStats {
+    /// Record the result of a checksum validation for the given protocol
+    ///
+    /// The IPv4 header checksum is recorded using `IpNextHeaderProtocols::Ipv4`.
+    pub fn record(&self, proto: IpNextHeaderProtocol, status: ChecksumStatus) {
+        let counter = match proto {
+            IpNextHeaderProtocols::Ipv4 => &self.ipv4,
+            IpNextHeaderProtocols::Tcp => &self.tcp,
+            IpNextHeaderProtocols::Udp => &self.udp,
+            IpNextHeaderProtocols::Icmp => &self.icmp,
+            IpNextHeaderProtocols::Icmpv6 => &self.icmpv6,
+            _ => return,
+        };
+        counter.add(status);
+    }
+
+    /// Total number of packets with an invalid checksum
+    pub fn invalid(&self) -> u64 {
+        [&self.ipv4, &self.tcp, &self.udp, &self.icmp, &self.icmpv6]
+            .iter()
+            .map(|c| c.invalid())
+            .sum()
+    }
+}
+
+impl fmt::Display for ChecksumStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counters = [
+            ("IPv4", &self.ipv4),
+            ("TCP", &self.tcp),
+            ("UDP", &self.udp),
+            ("ICMP", &self.icmp),
+            ("ICMPv6", &self.icmpv6),
+        ];
+        for (i, (name, c)) in counters.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "{}: valid={} invalid={} skipped={}",
+                name,
+                c.valid(),
+                c.invalid(),
+                c.skipped()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Sum of the pseudo-header, folded but not complemented
+///
+/// This is the value stored in the checksum field by the operating system when the
+/// computation is offloaded to the network card.
+fn pseudo_header_sum(src: &IpAddr, dst: &IpAddr, proto: u8, len: usize) -> u16 {
+    let words = |ip: &IpAddr| -> u32 {
+        match ip {
+            IpAddr::V4(ip) => ip
+                .octets()
+                .chunks(2)
+                .map(|c| u32::from(u16::from_be_bytes([c[0], c[1]])))
+                .sum(),
+            IpAddr::V6(ip) => ip.segments().iter().map(|&s| u32::from(s)).sum(),
+        }
+    };
+    let mut sum = words(src) + words(dst) + u32::from(proto) + len as u32;
+    while sum >> 16 != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    sum as u16
+}
+
+/// Verify the checksum of a transport layer packet (TCP, UDP, ICMP or ICMPv6)
+///
+/// `l4_data` must be the complete transport layer data (header and payload).
+/// UDP over IPv4 packets with a null checksum (no checksum), and packets with only the
+/// pseudo-header sum (partial checksum offload), are reported as `NotChecked`. A null
+/// checksum is invalid for UDP over IPv6 (RFC 8200 section 8.1).
+pub fn verify_l4_checksum(
+    proto: IpNextHeaderProtocol,
+    src: &IpAddr,
+    dst: &IpAddr,
+    l4_data: &[u8],
+) -> ChecksumStatus {
+    // offset of the checksum field, in 16-bit words
+    let (skipword, min_len) = match proto {
+        IpNextHeaderProtocols::Tcp => (8, 20),
+        IpNextHeaderProtocols::Udp => (3, 8),
+        IpNextHeaderProtocols::Icmp => (1, 4),
+        IpNextHeaderProtocols::Icmpv6 => (1, 4),
+        _ => return ChecksumStatus::NotChecked,
+    };
+    if l4_data.len() < min_len {
+        return ChecksumStatus::NotChecked;
+    }
+    let stored = u16::from_be_bytes([l4_data[skipword * 2], l4_data[skipword * 2 + 1]]);
+    if stored == 0 && proto == IpNextHeaderProtocols::Udp {
+        return match src {
+            // no checksum
+            IpAddr::V4(_) => ChecksumStatus::NotChecked,
+            IpAddr::V6(_) => ChecksumStatus::Invalid,
+        };
+    }
+    let computed = match (proto, src, dst) {
+        (IpNextHeaderProtocols::Icmp, _, _) => util::checksum(l4_data, skipword),
+        (_, IpAddr::V4(src), IpAddr::V4(dst)) => {
+            util::ipv4_checksum(l4_data, skipword, &[], src, dst, proto)
+        }
+        (_, IpAddr::V6(src), IpAddr::V6(dst)) => {
+            util::ipv6_checksum(l4_data, skipword, &[], src, dst, proto)
+        }
+        _ => return ChecksumStatus::NotChecked,
+    };
+    // UDP transmits a computed checksum of 0 as all ones
+    let computed = if proto == IpNextHeaderProtocols::Udp && computed == 0 {
+        0xffff
+    } else {
+        computed
+    };
+    if computed == stored {
+        ChecksumStatus::Valid
+    } else if proto != IpNextHeaderProtocols::Icmp
+        && stored == pseudo_header_sum(src, dst, proto.0, l4_data.len())
+    {
+        // partial checksum offload
+        ChecksumStatus::NotChecked
+    } else {
+        ChecksumStatus::Invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use pnet_packet::ip::IpNextHeaderProtocols;
+
+    use super::{pseudo_header_sum, verify_l4_checksum, ChecksumStatus};
+
+    #[test]
+    fn udp_checksum() {
+        let src = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let dst = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let mut udp = *b"\x30\x39\x00\x35\x00\x0c\x00\x00abcd";
+        // no checksum
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Udp, &src, &dst, &udp),
+            ChecksumStatus::NotChecked
+        );
+        let cksum = pnet_packet::util::ipv4_checksum(
+            &udp,
+            3,
+            &[],
+            &Ipv4Addr::new(192, 168, 1, 1),
+            &Ipv4Addr::new(192, 168, 1, 2),
+            IpNextHeaderProtocols::Udp,
+        );
+        udp[6..8].copy_from_slice(&cksum.to_be_bytes());
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Udp, &src, &dst, &udp),
+            ChecksumStatus::Valid
+        );
+        udp[8] = b'x';
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Udp, &src, &dst, &udp),
+            ChecksumStatus::Invalid
+        );
+        // partial offload: only the pseudo-header sum is stored
+        let partial = pseudo_header_sum(&src, &dst, 17, udp.len());
+        udp[6..8].copy_from_slice(&partial.to_be_bytes());
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Udp, &src, &dst, &udp),
+            ChecksumStatus::NotChecked
+        );
+    }
+
+    #[test]
+    fn zero_checksum() {
+        let src = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let dst = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        // TCP has no optional checksum: a null checksum is verified
+        let mut tcp = [0u8; 20];
+        tcp[..4].copy_from_slice(b"\x30\x39\x00\x50");
+        tcp[12] = 0x50;
+        tcp[13] = 0x02;
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Tcp, &src, &dst, &tcp),
+            ChecksumStatus::Invalid
+        );
+        let cksum = pnet_packet::util::ipv4_checksum(
+            &tcp,
+            8,
+            &[],
+            &Ipv4Addr::new(192, 168, 1, 1),
+            &Ipv4Addr::new(192, 168, 1, 2),
+            IpNextHeaderProtocols::Tcp,
+        );
+        tcp[16..18].copy_from_slice(&cksum.to_be_bytes());
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Tcp, &src, &dst, &tcp),
+            ChecksumStatus::Valid
+        );
+        // the UDP checksum is mandatory over IPv6
+        let src = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let dst = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2));
+        let udp = *b"\x30\x39\x00\x35\x00\x0c\x00\x00abcd";
+        assert_eq!(
+            verify_l4_checksum(IpNextHeaderProtocols::Udp, &src, &dst, &udp),
+            ChecksumStatus::Invalid
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//!
+//! Counters of decoding errors and anomalies
+//!
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use pako_tools::{DecodeError, DecodeErrorKind, Error, Layer};
+
+/// Decoding error counters
+///
+/// Errors are counted by kind, layer and protocol. This includes errors stopping the
+/// decoding of a packet, and anomalies which are only reported (for ex. invalid checksums
+/// or defragmentation errors).
+///
+/// The same instance is shared by all workers of a `ThreadedAnalyzer` and by plugins (see
+/// `AnalysisContext`), and can be read after the analysis to report how much of a capture
+/// was actually decoded.
+#[derive(Debug, Default)]
+pub struct ErrorStats {
+    packets: AtomicU64,
+    failed: AtomicU64,
+    other: AtomicU64,
+    counters: Mutex<BTreeMap<DecodeError, u64>>,
+}
+
+impl ErrorStats {
+    /// Count a packet submitted to the analyzer
+    pub(crate) fn add_packet(&self) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a packet which could not be decoded, because of error `e`
+    pub(crate) fn add_failure(&self, e: &Error) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.record(e);
+    }
+
+    /// Record an error or anomaly
+    ///
+    /// Errors which are not decoding errors are only counted in `other()`.
+    pub fn record(&self, e: &Error) {
+        match e.decode_error() {
+            Some(d) => {
+                let mut counters = self.counters.lock().unwrap();
+                *counters.entry(*d).or_default() += 1;
+            }
+            None => {
+                self.other.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Number of packets submitted to the analyzer
+    pub fn packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// Number of packets for which decoding stopped because of an error
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Number of errors which are not decoding errors
+    pub fn other(&self) -> u64 {
+        self.other.load(Ordering::Relaxed)
+    }
+
+    /// Ratio of packets decoded without a fatal error (1.0 if no packet was seen)
+    pub fn decoded_ratio(&self) -> f64 {
+        match self.packets() {
+            0 => 1.0,
+            n => n.saturating_sub(self.failed()) as f64 / n as f64,
+        }
+    }
+
+    /// Return a copy of all counters, sorted by kind, layer and protocol
+    pub fn counters(&self) -> Vec<(DecodeError, u64)> {
+        let counters = self.counters.lock().unwrap();
+        counters.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Total count of errors of the given kind
+    pub fn count_kind(&self, kind: DecodeErrorKind) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters
+            .iter()
+            .filter(|(k, _)| k.kind == kind)
+            .map(|(_, v)| v)
+            .sum()
+    }
+
+    /// Total count of errors at the given layer
+    pub fn count_layer(&self, layer: Layer) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters
+            .iter()
+            .filter(|(k, _)| k.layer == layer)
+            .map(|(_, v)| v)
+            .sum()
+    }
+}
+
+impl fmt::Display for ErrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "packets={} failed={} decoded={:.2}%",
+            self.packets(),
+            self.failed(),
+            self.decoded_ratio() * 100.0
+        )?;
+        for (e, count) in self.counters() {
+            write!(f, ", {}: {}", e, count)?;
+        }
+        if self.other() > 0 {
+            write!(f, ", other: {}", self.other())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pako_tools::{DecodeErrorKind, Error, Layer};
+
+    use super::ErrorStats;
+
+    #[test]
+    fn error_stats_counters() {
+        let stats = ErrorStats::default();
+        for _ in 0..4 {
+            stats.add_packet();
+        }
+        stats.add_failure(&Error::truncated(Layer::Transport, "TCP"));
+        stats.record(&Error::checksum(Layer::Network, "IPv4"));
+        stats.record(&Error::checksum(Layer::Network, "IPv4"));
+        stats.record(&Error::Generic("other"));
+        assert_eq!(stats.failed(), 1);
+        assert_eq!(stats.count_kind(DecodeErrorKind::Checksum), 2);
+        assert_eq!(stats.count_layer(Layer::Transport), 1);
+        assert_eq!(stats.other(), 1);
+        assert!((stats.decoded_ratio() - 0.75).abs() < f64::EPSILON);
+        assert_eq!(stats.counters().len(), 2);
+    }
+}
@@ -17,7 +17,7 @@ impl<'a> ERSPANPacket<'a> {
     /// Constructs a new ErspanPacket. If the provided buffer is less than the minimum required
     /// packet size, this will return None.
     #[inline]
-    pub fn new(packet: &[u8]) -> Option<ERSPANPacket> {
+    pub fn new(packet: &[u8]) -> Option<ERSPANPacket<'_>> {
         if packet.len() >= ERSPANPacket::minimum_packet_size() {
             use ::pnet_macros_support::packet::PacketData;
             Some(ERSPANPacket {
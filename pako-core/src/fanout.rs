@@ -0,0 +1,512 @@
+//!
+//! Selection of the worker thread for a packet (`ThreadedAnalyzer`)
+//!
+//! The hash is symmetric (both directions of a flow go to the same worker), and
+//! is computed on the innermost headers, after decapsulation of known tunnels
+//! (VXLAN, Geneve, GRE, IP in IP). IP fragments are sent to the worker of the
+//! first fragment, which is the only one containing the transport header.
+//! ICMP and ICMPv6 errors are sent to the worker of the flow of the quoted packet.
+
+use std::{collections::HashMap, net::IpAddr};
+
+use pako_tools::{Config, FiveTuple, Ipv6ExtensionWalker};
+use pnet_packet::ethernet::{EtherType, EtherTypes};
+
+use crate::{
+    icmp_error::{is_icmp_error, is_icmpv6_error, quoted_five_tuple},
+    threaded_analyzer::{JobLayer, PacketJob},
+};
+
+/// Maximum number of encapsulation levels to follow
+const MAX_DEPTH: usize = 8;
+/// Delay (in seconds) after which fragment information is considered stale
+const FRAGMENT_TIMEOUT: u64 = 30;
+/// Number of tracked fragmented datagrams after which stale entries are removed
+const MAX_FRAGMENTS: usize = 4096;
+
+/// Key identifying the fragments of a single datagram
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct FragmentKey {
+    src: IpAddr,
+    dst: IpAddr,
+    proto: u8,
+    id: u32,
+}
+
+/// Result of the lightweight dissection of a packet
+#[derive(Default)]
+struct Dissection<'a> {
+    /// Addresses of the selected layer (IP, or ethernet if there is no IP layer)
+    addresses: Option<(&'a [u8], &'a [u8])>,
+    proto: u8,
+    ports: Option<(u16, u16)>,
+    /// First fragmented IP layer, and true if this is the first fragment
+    fragment: Option<(FragmentKey, bool)>,
+    /// 5-tuple of the packet quoted in an ICMP or ICMPv6 error
+    quoted: Option<FiveTuple>,
+}
+
+impl<'a> Dissection<'a> {
+    /// Compute a symmetric hash of the selected layer
+    ///
+    /// For ICMP errors, the hash of the quoted packet is returned, so the error is
+    /// handled by the worker of the flow it refers to.
+    fn hash(&self, with_ports: bool) -> u64 {
+        match self.quoted {
+            Some(ref quoted) => {
+                let (src, dst) = (ip_octets(&quoted.src), ip_octets(&quoted.dst));
+                // same ports as `transport_ports`, for the packets of the quoted flow
+                let ports = has_ports(quoted.proto).then_some((quoted.src_port, quoted.dst_port));
+                symmetric_hash(Some((&src, &dst)), quoted.proto, ports, with_ports)
+            }
+            None => symmetric_hash(self.addresses, self.proto, self.ports, with_ports),
+        }
+    }
+}
+
+fn symmetric_hash(
+    addresses: Option<(&[u8], &[u8])>,
+    proto: u8,
+    ports: Option<(u16, u16)>,
+    with_ports: bool,
+) -> u64 {
+    let mut buf = [0u8; 40];
+    let mut sz = 0;
+    if let Some((a, b)) = addresses {
+        let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+        buf[..lo.len()].copy_from_slice(lo);
+        buf[lo.len()..lo.len() + hi.len()].copy_from_slice(hi);
+        sz = lo.len() + hi.len();
+    }
+    buf[sz] = proto;
+    sz += 1;
+    if let (true, Some((p1, p2))) = (with_ports, ports) {
+        let (lo, hi) = if p1 <= p2 { (p1, p2) } else { (p2, p1) };
+        buf[sz..sz + 2].copy_from_slice(&lo.to_be_bytes());
+        buf[sz + 2..sz + 4].copy_from_slice(&hi.to_be_bytes());
+        sz += 4;
+    }
+    seahash::hash(&buf[..sz])
+}
+
+fn ip_octets(addr: &IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    }
+}
+
+fn ip_addr(data: &[u8]) -> IpAddr {
+    match data.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(data).unwrap_or_default()),
+        _ => IpAddr::from(<[u8; 16]>::try_from(data).unwrap_or_default()),
+    }
+}
+
+/// Lightweight dissector, following encapsulation if `tunnels` is true
+///
+/// Only the fields needed to select a worker are read, and errors are ignored: the
+/// dissection simply stops at the last valid layer.
+///
+/// `ethertype` is the type of `data`, or `None` for an ethernet frame.
+fn dissect(data: &[u8], ethertype: Option<EtherType>, tunnels: bool) -> Dissection<'_> {
+    let mut d = Dissection::default();
+    let mut data = data;
+    let mut ethertype = match ethertype {
+        Some(ethertype) => ethertype,
+        None => match l2_payload(data) {
+            Some((et, payload, macs)) => {
+                d.addresses = Some(macs);
+                data = payload;
+                et
+            }
+            None => return d,
+        },
+    };
+    for _ in 0..MAX_DEPTH {
+        // read IP header
+        let (src, dst, proto, l4, fragment) = match ethertype {
+            EtherTypes::Ipv4 => match ipv4_header(data) {
+                Some(h) => h,
+                None => return d,
+            },
+            EtherTypes::Ipv6 => match ipv6_header(data) {
+                Some(h) => h,
+                None => return d,
+            },
+            _ => return d,
+        };
+        d.addresses = Some((src, dst));
+        d.proto = proto;
+        d.ports = None;
+        if let Some((key, first)) = fragment {
+            if d.fragment.is_none() {
+                d.fragment = Some((key, first));
+            }
+            if !first {
+                // no transport header
+                return d;
+            }
+        }
+        d.ports = transport_ports(proto, l4);
+        // ICMP error: type, code, checksum and 4 bytes before the quoted packet
+        let icmp_error = match proto {
+            1 => l4.first().is_some_and(|&t| is_icmp_error(t)),
+            58 => l4.first().is_some_and(|&t| is_icmpv6_error(t)),
+            _ => false,
+        };
+        if icmp_error {
+            d.quoted = l4.get(8..).and_then(quoted_five_tuple);
+            return d;
+        }
+        if !tunnels {
+            return d;
+        }
+        // follow encapsulation
+        let (next_ethertype, next) = match (proto, d.ports) {
+            // VXLAN
+            (17, Some((sport, dport))) if sport == 4789 || dport == 4789 => {
+                match l4.get(16..).and_then(l2_payload) {
+                    Some((et, payload, _)) => (et, payload),
+                    None => return d,
+                }
+            }
+            // Geneve
+            (17, Some((sport, dport))) if sport == 6081 || dport == 6081 => {
+                match geneve_payload(&l4[8..]) {
+                    Some(r) => r,
+                    None => return d,
+                }
+            }
+            // GRE
+            (47, _) => match gre_payload(l4) {
+                Some(r) => r,
+                None => return d,
+            },
+            (4, _) => (EtherTypes::Ipv4, l4),
+            (41, _) => (EtherTypes::Ipv6, l4),
+            _ => return d,
+        };
+        ethertype = next_ethertype;
+        data = next;
+        // transparent ethernet bridging
+        if ethertype == EtherType(0x6558) {
+            match l2_payload(data) {
+                Some((et, payload, _)) => {
+                    ethertype = et;
+                    data = payload;
+                }
+                None => return d,
+            }
+        }
+    }
+    d
+}
+
+/// Dissect the data of a job
+fn dissect_job(job: &PacketJob, tunnels: bool) -> Dissection<'_> {
+    match job.layer() {
+        JobLayer::L2 => dissect(job.data(), None, tunnels),
+        JobLayer::L3(ethertype) => dissect(job.data(), Some(ethertype), tunnels),
+        // no network layer, only the transport header is available
+        JobLayer::L4(proto) => Dissection {
+            proto,
+            ports: transport_ports(proto, job.data()),
+            ..Dissection::default()
+        },
+    }
+}
+
+/// Return true for TCP, UDP and SCTP
+fn has_ports(proto: u8) -> bool {
+    matches!(proto, 6 | 17 | 132)
+}
+
+/// Return source and destination ports, for protocols having ports
+fn transport_ports(proto: u8, l4: &[u8]) -> Option<(u16, u16)> {
+    if has_ports(proto) && l4.len() >= 4 {
+        let sport = u16::from_be_bytes([l4[0], l4[1]]);
+        let dport = u16::from_be_bytes([l4[2], l4[3]]);
+        Some((sport, dport))
+    } else {
+        None
+    }
+}
+
+type L2Payload<'a> = (EtherType, &'a [u8], (&'a [u8], &'a [u8]));
+
+/// Return ethertype, payload and MAC addresses of an ethernet frame, skipping VLAN tags
+fn l2_payload(data: &[u8]) -> Option<L2Payload<'_>> {
+    if data.len() < 14 {
+        return None;
+    }
+    let macs = (&data[0..6], &data[6..12]);
+    let mut ethertype = EtherType(u16::from_be_bytes([data[12], data[13]]));
+    let mut payload = &data[14..];
+    while ethertype == EtherTypes::Vlan || ethertype == EtherTypes::QinQ {
+        if payload.len() < 4 {
+            return None;
+        }
+        ethertype = EtherType(u16::from_be_bytes([payload[2], payload[3]]));
+        payload = &payload[4..];
+    }
+    Some((ethertype, payload, macs))
+}
+
+type IpHeader<'a> = (
+    &'a [u8],
+    &'a [u8],
+    u8,
+    &'a [u8],
+    Option<(FragmentKey, bool)>,
+);
+
+fn ipv4_header(data: &[u8]) -> Option<IpHeader<'_>> {
+    if data.len() < 20 || data[0] >> 4 != 4 {
+        return None;
+    }
+    let ihl = (data[0] & 0x0f) as usize * 4;
+    let src = &data[12..16];
+    let dst = &data[16..20];
+    let proto = data[9];
+    let flags_offset = u16::from_be_bytes([data[6], data[7]]);
+    let more_fragments = flags_offset & 0x2000 != 0;
+    let offset = flags_offset & 0x1fff;
+    let fragment = if more_fragments || offset != 0 {
+        let key = FragmentKey {
+            src: ip_addr(src),
+            dst: ip_addr(dst),
+            proto,
+            id: u32::from(u16::from_be_bytes([data[4], data[5]])),
+        };
+        Some((key, offset == 0))
+    } else {
+        None
+    };
+    let l4 = data.get(ihl..).unwrap_or_default();
+    Some((src, dst, proto, l4, fragment))
+}
+
+fn ipv6_header(data: &[u8]) -> Option<IpHeader<'_>> {
+    if data.len() < 40 || data[0] >> 4 != 6 {
+        return None;
+    }
+    let src = &data[8..24];
+    let dst = &data[24..40];
+    let mut walker = Ipv6ExtensionWalker::new(data[6], &data[40..]);
+    let mut fragment = None;
+    for res in walker.by_ref() {
+        let (header_type, ext) = res.ok()?;
+        // fragment
+        if header_type == 44 {
+            if fragment.is_some() {
+                return None;
+            }
+            let offset = Ipv6ExtensionWalker::fragment_offset(ext);
+            let key = FragmentKey {
+                src: ip_addr(src),
+                dst: ip_addr(dst),
+                proto: 0,
+                id: u32::from_be_bytes([ext[4], ext[5], ext[6], ext[7]]),
+            };
+            fragment = Some((key, offset == 0));
+            if offset != 0 {
+                break;
+            }
+        }
+    }
+    Some((src, dst, walker.next_header(), walker.data(), fragment))
+}
+
+fn geneve_payload(data: &[u8]) -> Option<(EtherType, &[u8])> {
+    if data.len() < 8 {
+        return None;
+    }
+    let opt_len = (data[0] & 0x3f) as usize * 4;
+    let ethertype = EtherType(u16::from_be_bytes([data[2], data[3]]));
+    Some((ethertype, data.get(8 + opt_len..)?))
+}
+
+fn gre_payload(data: &[u8]) -> Option<(EtherType, &[u8])> {
+    if data.len() < 4 {
+        return None;
+    }
+    // only version 0 (RFC 2784 / RFC 2890)
+    if data[1] & 0x07 != 0 {
+        return None;
+    }
+    let mut len = 4;
+    // checksum, key and sequence number present bits
+    for mask in [0x80, 0x20, 0x10] {
+        if data[0] & mask != 0 {
+            len += 4;
+        }
+    }
+    let ethertype = EtherType(u16::from_be_bytes([data[2], data[3]]));
+    Some((ethertype, data.get(len..)?))
+}
+
+/// Worker selection for `ThreadedAnalyzer`
+///
+/// Configuration:
+///   - `fanout.tunnels` (default: true): hash inner headers of known tunnels
+///   - `fanout.ports` (default: true): include transport ports in the hash
+pub(crate) struct FanOut {
+    n_workers: usize,
+    tunnels: bool,
+    ports: bool,
+    /// Worker assigned to fragmented datagrams, and timestamp (secs) of last fragment
+    fragments: HashMap<FragmentKey, (usize, u64)>,
+    /// Fragments received before the first fragment of their datagram
+    pending: HashMap<FragmentKey, (u64, Vec<PacketJob>)>,
+}
+
+impl FanOut {
+    pub(crate) fn new(n_workers: usize, config: &Config) -> Self {
+        FanOut {
+            n_workers,
+            tunnels: config.get_bool("fanout.tunnels").unwrap_or(true),
+            ports: config.get_bool("fanout.ports").unwrap_or(true),
+            fragments: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Select the worker for this packet
+    ///
+    /// Jobs ready to be dispatched are pushed to `out`, with the index of the worker.
+    /// This can be empty (fragment waiting for the first fragment of its datagram), or
+    /// contain previously queued fragments.
+    pub(crate) fn assign(&mut self, job: PacketJob, out: &mut Vec<(usize, PacketJob)>) {
+        let now = job.ts().secs();
+        let d = dissect_job(&job, self.tunnels);
+        let worker = (d.hash(self.ports) % self.n_workers as u64) as usize;
+        match d.fragment {
+            None => out.push((worker, job)),
+            Some((key, true)) => {
+                self.expire_fragments(now, out);
+                self.fragments.insert(key.clone(), (worker, now));
+                out.push((worker, job));
+                if let Some((_, jobs)) = self.pending.remove(&key) {
+                    out.extend(jobs.into_iter().map(|j| (worker, j)));
+                }
+            }
+            Some((key, false)) => {
+                if let Some(entry) = self.fragments.get_mut(&key) {
+                    entry.1 = now;
+                    out.push((entry.0, job));
+                    return;
+                }
+                self.expire_fragments(now, out);
+                let entry = self.pending.entry(key).or_insert_with(|| (now, Vec::new()));
+                entry.1.push(job);
+            }
+        }
+    }
+
+    /// Remove stale fragment information, and dispatch fragments for which the first
+    /// fragment was not received (they are sent to the worker of their own addresses)
+    fn expire_fragments(&mut self, now: u64, out: &mut Vec<(usize, PacketJob)>) {
+        if self.fragments.len() >= MAX_FRAGMENTS {
+            self.fragments
+                .retain(|_, (_, ts)| now.saturating_sub(*ts) <= FRAGMENT_TIMEOUT);
+            if self.fragments.len() >= MAX_FRAGMENTS {
+                self.fragments.clear();
+            }
+        }
+        if self.pending.len() >= MAX_FRAGMENTS {
+            let stale: Vec<_> = self
+                .pending
+                .iter()
+                .filter(|(_, (ts, _))| now.saturating_sub(*ts) > FRAGMENT_TIMEOUT)
+                .map(|(k, _)| k.clone())
+                .collect();
+            for key in stale {
+                self.release(&key, out);
+            }
+            if self.pending.len() >= MAX_FRAGMENTS {
+                self.flush(out);
+            }
+        }
+    }
+
+    fn release(&mut self, key: &FragmentKey, out: &mut Vec<(usize, PacketJob)>) {
+        if let Some((_, jobs)) = self.pending.remove(key) {
+            for job in jobs {
+                let d = dissect_job(&job, self.tunnels);
+                let worker = (d.hash(false) % self.n_workers as u64) as usize;
+                out.push((worker, job));
+            }
+        }
+    }
+
+    /// Dispatch all pending fragments
+    pub(crate) fn flush(&mut self, out: &mut Vec<(usize, PacketJob)>) {
+        let keys: Vec<_> = self.pending.keys().cloned().collect();
+        for key in keys {
+            self.release(&key, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pnet_packet::ethernet::EtherTypes;
+
+    use super::dissect;
+
+    fn udp_ipv4(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, payload: &[u8]) -> Vec<u8> {
+        let mut v = vec![0x45, 0, 0, 0, 0, 1, 0, 0, 64, 17, 0, 0];
+        v.extend_from_slice(&src);
+        v.extend_from_slice(&dst);
+        v.extend_from_slice(&sport.to_be_bytes());
+        v.extend_from_slice(&dport.to_be_bytes());
+        v.extend_from_slice(&[0, 0, 0, 0]);
+        v.extend_from_slice(payload);
+        v
+    }
+
+    #[test]
+    fn fanout_symmetric_vxlan() {
+        let inner1 = udp_ipv4([10, 0, 0, 1], [10, 0, 0, 2], 1234, 53, b"");
+        let inner2 = udp_ipv4([10, 0, 0, 2], [10, 0, 0, 1], 53, 1234, b"");
+        let inner3 = udp_ipv4([10, 0, 0, 1], [10, 0, 0, 2], 1235, 53, b"");
+        let vxlan = |inner: &[u8]| {
+            // VXLAN header, then ethernet header
+            let mut p = vec![0x08, 0, 0, 0, 0, 0, 1, 0];
+            p.extend_from_slice(&[0; 12]);
+            p.extend_from_slice(&[0x08, 0x00]);
+            p.extend_from_slice(inner);
+            udp_ipv4([192, 168, 0, 1], [192, 168, 0, 2], 50000, 4789, &p)
+        };
+        let (p1, p2, p3) = (vxlan(&inner1), vxlan(&inner2), vxlan(&inner3));
+        let d1 = dissect(&p1, Some(EtherTypes::Ipv4), true);
+        let d2 = dissect(&p2, Some(EtherTypes::Ipv4), true);
+        let d3 = dissect(&p3, Some(EtherTypes::Ipv4), true);
+        assert_eq!(d1.ports, Some((1234, 53)));
+        assert_eq!(d1.hash(true), d2.hash(true));
+        assert_ne!(d1.hash(true), d3.hash(true));
+        // without tunnel decapsulation, only outer header is used
+        let o1 = dissect(&p1, Some(EtherTypes::Ipv4), false);
+        let o3 = dissect(&p3, Some(EtherTypes::Ipv4), false);
+        assert_eq!(o1.hash(true), o3.hash(true));
+    }
+
+    #[test]
+    fn fanout_icmp_error_quoted_flow() {
+        let udp = udp_ipv4([10, 0, 0, 1], [10, 0, 0, 2], 12345, 53, b"");
+        // port unreachable, sent by 10.0.0.2, then by a router
+        for reporter in [[10, 0, 0, 2], [192, 168, 0, 1]] {
+            let mut icmp = vec![0x45, 0, 0, 0, 0, 1, 0, 0, 64, 1, 0, 0];
+            icmp.extend_from_slice(&reporter);
+            icmp.extend_from_slice(&[10, 0, 0, 1]);
+            icmp.extend_from_slice(&[3, 3, 0, 0, 0, 0, 0, 0]);
+            icmp.extend_from_slice(&udp);
+            let d = dissect(&icmp, Some(EtherTypes::Ipv4), true);
+            let flow = dissect(&udp, Some(EtherTypes::Ipv4), true);
+            assert_eq!(d.quoted.as_ref().map(|q| q.dst_port), Some(53));
+            assert_eq!(d.hash(true), flow.hash(true));
+            assert_eq!(d.hash(false), flow.hash(false));
+        }
+    }
+}
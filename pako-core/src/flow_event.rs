@@ -0,0 +1,12 @@
+use crate::{icmp_error::IcmpError, sctp_reassembly::SctpAssociationEvent};
+
+/// Event attached to a `Flow` during its lifetime
+///
+/// Events are sent to plugins registered for `PLUGIN_FLOW_EVENT`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FlowEvent {
+    /// SCTP association lifecycle event
+    Sctp(SctpAssociationEvent),
+    /// ICMP or ICMPv6 error message quoting a packet of the flow
+    IcmpError(IcmpError),
+}
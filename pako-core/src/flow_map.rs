@@ -4,40 +4,31 @@ use std::collections::{
 };
 
 use fnv::FnvHashMap;
-use log::trace;
+use log::{trace, warn};
+pub use pako_tools::compute_flow_id;
 use pako_tools::{FiveTuple, Flow, FlowID};
-use rand::prelude::*;
-use rand_chacha::*;
 
 /// Storage for flows
 ///
 /// A `Flow` is identified by a `FlowID`.
 /// Multiple `FlowID` may point to the same flow (direct and reverse flow, for ex.).
+///
+/// Flow IDs are derived from the 5-tuple of the first packet and its timestamp (see
+/// `compute_flow_id`), so they do not depend on the order of creation of flows, and are
+/// identical between runs, and between single and multi-threaded analyzers.
+///
+/// Collisions (two flows with the same ID) are not resolved: a tie-break would depend on
+/// the other flows of the map, which differ between the workers of a threaded analyzer.
+/// IDs are 64-bit hashes, so a collision is very unlikely (probability of about 3e-8 for a
+/// capture of one million flows). If it happens, a warning is logged and the second flow is
+/// merged into the first one.
+#[derive(Default)]
 pub struct FlowMap {
-    trng: ChaChaRng,
     flows: FnvHashMap<FlowID, Flow>,
     flows_id: HashMap<FiveTuple, FlowID>,
 }
 
-impl Default for FlowMap {
-    fn default() -> Self {
-        FlowMap {
-            trng: ChaChaRng::from_rng(rand::thread_rng()).unwrap(),
-            flows: FnvHashMap::default(),
-            flows_id: HashMap::new(),
-        }
-    }
-}
-
 impl FlowMap {
-    /// Use provided seed for the random number generator (flow IDs)
-    ///
-    /// This option is intended for use in testing
-    pub fn with_rng_seed(self, seed: u64) -> Self {
-        let trng = <ChaChaRng as SeedableRng>::seed_from_u64(seed);
-        FlowMap { trng, ..self }
-    }
-
     pub fn lookup_flow(&self, five_t: &FiveTuple) -> Option<FlowID> {
         self.flows_id.get(five_t).copied()
     }
@@ -56,7 +47,10 @@ impl FlowMap {
 
     /// Insert a flow in the hash tables.
     /// Takes ownership of five_t and flow
-    pub fn insert_flow(&mut self, five_t: FiveTuple, flow: Flow) -> FlowID {
+    ///
+    /// If the flow is new, its `flow_id` field is set. If its ID is already used by another
+    /// flow (hash collision), the 5-tuple is attached to the existing flow.
+    pub fn insert_flow(&mut self, five_t: FiveTuple, mut flow: Flow) -> FlowID {
         let rev_id = self.flows_id.get(&five_t.get_reverse()).copied();
         if let Some(id) = rev_id {
             // insert reverse flow ID
@@ -64,11 +58,21 @@ impl FlowMap {
             self.flows_id.insert(five_t, id);
             return id;
         }
-        // get a new flow index (XXX currently: random number)
-        let id = self.trng.gen();
-        trace!("Inserting new flow (id=0x{:x})", id);
-        trace!("    flow: {:?}", flow);
-        self.flows.insert(id, flow);
+        let id = compute_flow_id(&five_t, flow.first_seen);
+        match self.flows.entry(id) {
+            Entry::Occupied(_) => {
+                warn!(
+                    "Flow ID collision (id=0x{:x}), merging {} into flow",
+                    id, five_t
+                );
+            }
+            Entry::Vacant(entry) => {
+                flow.flow_id = id;
+                trace!("Inserting new flow (id=0x{:x})", id);
+                trace!("    flow: {:?}", flow);
+                entry.insert(flow);
+            }
+        }
         self.flows_id.insert(five_t, id);
         id
     }
@@ -87,13 +91,13 @@ impl FlowMap {
 
     /// An iterator visiting all flows in arbitrary order.
     #[inline]
-    pub fn values(&self) -> Values<FlowID, Flow> {
+    pub fn values(&self) -> Values<'_, FlowID, Flow> {
         self.flows.values()
     }
 
     /// Gets the given key's corresponding entry in the map for in-place manipulation.
     #[inline]
-    pub fn entry(&mut self, flow_id: FlowID) -> Entry<FlowID, Flow> {
+    pub fn entry(&mut self, flow_id: FlowID) -> Entry<'_, FlowID, Flow> {
         self.flows.entry(flow_id)
     }
 
@@ -103,3 +107,96 @@ impl FlowMap {
         self.flows_id.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+    };
+
+    use pako_tools::{
+        Config, Duration, FiveTuple, Flow, MappedCapture, PacketIndex, PcapDataEngine, PcapEngine,
+    };
+
+    use super::FlowMap;
+    use crate::{
+        plugin::{Plugin, PLUGIN_FLOW_DEL},
+        plugin_registry::PluginRegistry,
+        Analyzer,
+    };
+
+    #[test]
+    fn flow_id_reproducible() {
+        let t5 = FiveTuple {
+            proto: 6,
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            src_port: 1234,
+            dst_port: 80,
+        };
+        let ts = Duration::from_nanos_parts(1_600_000_000, 42_000);
+        let mut m1 = FlowMap::default();
+        let mut m2 = FlowMap::default();
+        let id1 = m1.insert_flow(t5.clone(), Flow::new(&t5, ts));
+        let id2 = m2.insert_flow(t5.clone(), Flow::new(&t5, ts));
+        assert_eq!(id1, id2);
+        assert_eq!(m1.get_flow(id1).map(|f| f.flow_id), Some(id1));
+        // reverse flow shares the same ID
+        assert_eq!(m1.insert_flow(t5.get_reverse(), Flow::default()), id1);
+        // same 5-tuple, different start time
+        let t = ts + Duration::from_nanos(1);
+        let mut m3 = FlowMap::default();
+        assert_ne!(m3.insert_flow(t5.clone(), Flow::new(&t5, t)), id1);
+    }
+
+    /// Record the flows of the analyzer, when they are destroyed
+    #[derive(Default)]
+    struct FlowRecorder {
+        flows: Vec<Flow>,
+    }
+
+    impl Plugin for FlowRecorder {
+        fn name(&self) -> &'static str {
+            "FlowRecorder"
+        }
+
+        fn plugin_type(&self) -> u16 {
+            PLUGIN_FLOW_DEL
+        }
+
+        fn flow_destroyed(&mut self, flow: &Flow) {
+            self.flows.push(flow.clone());
+        }
+    }
+
+    #[test]
+    fn flow_id_matches_index() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../pako-pcap/assets/legacy_le_us.pcap"
+        );
+        let recorder = Arc::new(Mutex::new(FlowRecorder::default()));
+        let mut registry = PluginRegistry::new();
+        registry.add_plugin(recorder.clone());
+        let config = Config::default();
+        let analyzer = Analyzer::new(Arc::new(registry), &config);
+        let mut engine = PcapDataEngine::new(analyzer, &config);
+        engine
+            .run(&mut File::open(path).expect("open"))
+            .expect("run analyzer");
+
+        let index = PacketIndex::build(&MappedCapture::open(path).expect("map")).expect("index");
+        let flows = &recorder.lock().unwrap().flows;
+        assert_eq!(flows.len(), 1);
+        for flow in flows {
+            assert_eq!(index.lookup_flow(&flow.five_tuple), Some(flow.flow_id));
+            assert_eq!(
+                index.flow(flow.flow_id).map(|f| f.first_seen),
+                Some(flow.first_seen)
+            );
+            assert_eq!(index.packets_of_flow(flow), vec![0, 1, 2, 3]);
+        }
+    }
+}
@@ -13,7 +13,7 @@ pub struct GENEVEPacket<'p> {
 impl<'a> GENEVEPacket<'a> {
     /// Constructs a new GENEVE packet. If the provided buffer is less than the minimum required
     /// packet size, this will return None.
-    pub fn new(packet: &[u8]) -> Option<GENEVEPacket> {
+    pub fn new(packet: &[u8]) -> Option<GENEVEPacket<'_>> {
         if packet.len() >= GENEVEPacket::minimum_packet_size() {
             use ::pnet_macros_support::packet::PacketData;
             Some(GENEVEPacket {
@@ -94,7 +94,7 @@ impl<'a> GENEVEPacket<'a> {
     }
 
     /// Get the value of the options field as iterator
-    pub fn get_options_iter(&self) -> GENEVEOptionIterable {
+    pub fn get_options_iter(&self) -> GENEVEOptionIterable<'_> {
         let buf = self.get_options_raw();
         GENEVEOptionIterable { buf }
     }
@@ -0,0 +1,179 @@
+//!
+//! Parsing of the packet quoted in ICMP and ICMPv6 error messages
+//!
+
+use std::net::IpAddr;
+
+use pako_tools::FiveTuple;
+
+/// Information extracted from an ICMP or ICMPv6 error message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IcmpError {
+    /// ICMP (or ICMPv6) type
+    pub icmp_type: u8,
+    /// ICMP (or ICMPv6) code
+    pub icmp_code: u8,
+    /// Address of the host reporting the error (router or destination host)
+    pub reporter: IpAddr,
+    /// The 5-tuple of the packet which triggered the error, as quoted in the message
+    pub quoted: FiveTuple,
+    /// Next-hop MTU, for ICMP "fragmentation needed" and ICMPv6 "packet too big"
+    pub mtu: Option<u32>,
+}
+
+/// Return true if the ICMP type is an error message quoting the original datagram
+pub fn is_icmp_error(icmp_type: u8) -> bool {
+    // destination unreachable, source quench, redirect, time exceeded, parameter problem
+    matches!(icmp_type, 3 | 4 | 5 | 11 | 12)
+}
+
+/// Return true if the ICMPv6 type is an error message (RFC 4443)
+pub fn is_icmpv6_error(icmpv6_type: u8) -> bool {
+    icmpv6_type < 128
+}
+
+impl IcmpError {
+    /// Parse an ICMP error message
+    ///
+    /// `data` is the ICMP payload after the type, code and checksum fields.
+    pub fn from_icmp(icmp_type: u8, icmp_code: u8, reporter: IpAddr, data: &[u8]) -> Option<Self> {
+        if !is_icmp_error(icmp_type) || data.len() < 4 {
+            return None;
+        }
+        // RFC 1191: next-hop MTU in the low-order 16 bits of the second word
+        let mtu = if icmp_type == 3 && icmp_code == 4 {
+            Some(u32::from(u16::from_be_bytes([data[2], data[3]])))
+        } else {
+            None
+        };
+        let quoted = quoted_five_tuple(&data[4..])?;
+        Some(IcmpError {
+            icmp_type,
+            icmp_code,
+            reporter,
+            quoted,
+            mtu,
+        })
+    }
+
+    /// Parse an ICMPv6 error message
+    ///
+    /// `data` is the ICMPv6 payload after the type, code and checksum fields.
+    pub fn from_icmpv6(
+        icmpv6_type: u8,
+        icmpv6_code: u8,
+        reporter: IpAddr,
+        data: &[u8],
+    ) -> Option<Self> {
+        if !is_icmpv6_error(icmpv6_type) || data.len() < 4 {
+            return None;
+        }
+        // packet too big
+        let mtu = if icmpv6_type == 2 {
+            Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+        } else {
+            None
+        };
+        let quoted = quoted_five_tuple(&data[4..])?;
+        Some(IcmpError {
+            icmp_type: icmpv6_type,
+            icmp_code: icmpv6_code,
+            reporter,
+            quoted,
+            mtu,
+        })
+    }
+}
+
+/// Build the 5-tuple of the quoted IP packet
+///
+/// Ports are extracted as in the analyzer: real ports for TCP, UDP and SCTP,
+/// type and code for ICMP, and 0 for other protocols or non-first fragments.
+pub(crate) fn quoted_five_tuple(data: &[u8]) -> Option<FiveTuple> {
+    FiveTuple::from_ip_packet(data).map(|(five_tuple, _)| five_tuple)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::IcmpError;
+
+    #[test]
+    fn icmp_frag_needed() {
+        // unused, MTU 1400, then IPv4 header (UDP 10.0.0.1 -> 10.0.0.2) and 8 bytes of UDP
+        let data = b"\x00\x00\x05\x78\
+            \x45\x00\x05\xdc\x00\x01\x40\x00\x40\x11\x00\x00\x0a\x00\x00\x01\x0a\x00\x00\x02\
+            \x30\x39\x00\x35\x05\xc8\x00\x00";
+        let reporter = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let err = IcmpError::from_icmp(3, 4, reporter, data).expect("ICMP error");
+        assert_eq!(err.mtu, Some(1400));
+        assert_eq!(err.quoted.proto, 17);
+        assert_eq!(err.quoted.src, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(err.quoted.dst, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(err.quoted.src_port, 12345);
+        assert_eq!(err.quoted.dst_port, 53);
+        // echo request is not an error
+        assert!(IcmpError::from_icmp(8, 0, reporter, data).is_none());
+    }
+
+    /// IPv6 header (2001:db8::1 -> 2001:db8::2), with a payload of `len` bytes
+    fn ipv6_header(next_header: u8, len: u16) -> Vec<u8> {
+        let mut data = vec![0x60, 0, 0, 0];
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(&[next_header, 64]);
+        data.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).octets());
+        data.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2).octets());
+        data
+    }
+
+    #[test]
+    fn icmpv6_packet_too_big() {
+        // MTU 1280, then IPv6 header and TCP ports (quote truncated after the ports)
+        let mut data = b"\x00\x00\x05\x00".to_vec();
+        data.extend(ipv6_header(6, 1440));
+        data.extend_from_slice(b"\xc0\x00\x01\xbb");
+        let reporter = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1));
+        let err = IcmpError::from_icmpv6(2, 0, reporter, &data).expect("ICMPv6 error");
+        assert_eq!(err.mtu, Some(1280));
+        assert_eq!(err.quoted.proto, 6);
+        assert_eq!(
+            err.quoted.src,
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))
+        );
+        assert_eq!(err.quoted.src_port, 49152);
+        assert_eq!(err.quoted.dst_port, 443);
+        // echo request is not an error
+        assert!(IcmpError::from_icmpv6(128, 0, reporter, &data).is_none());
+    }
+
+    #[test]
+    fn icmpv6_quoted_extension_headers() {
+        // time exceeded, then IPv6 header with Hop-by-Hop, Fragment (first fragment)
+        // and UDP headers
+        let mut quoted = ipv6_header(0, 1240);
+        quoted.extend_from_slice(b"\x2c\x00\x05\x02\x00\x00\x01\x00");
+        quoted.extend_from_slice(b"\x11\x00\x00\x01\x00\x00\x12\x34");
+        quoted.extend_from_slice(b"\x30\x39\x00\x35\x04\xd0\x00\x00");
+        let mut data = vec![0; 4];
+        data.extend_from_slice(&quoted);
+        let reporter = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1));
+        let err = IcmpError::from_icmpv6(3, 0, reporter, &data).expect("ICMPv6 error");
+        assert_eq!(err.mtu, None);
+        assert_eq!(err.quoted.proto, 17);
+        assert_eq!(err.quoted.src_port, 12345);
+        assert_eq!(err.quoted.dst_port, 53);
+
+        // other fragments have no transport header
+        data[4 + 40 + 8 + 3] = 0xb9;
+        let err = IcmpError::from_icmpv6(3, 0, reporter, &data).expect("ICMPv6 error");
+        assert_eq!(err.quoted.proto, 17);
+        assert_eq!((err.quoted.src_port, err.quoted.dst_port), (0, 0));
+
+        // quote truncated in the Fragment header
+        let err =
+            IcmpError::from_icmpv6(3, 0, reporter, &data[..4 + 40 + 8 + 4]).expect("ICMPv6 error");
+        assert_eq!(err.quoted.proto, 17);
+        assert_eq!((err.quoted.src_port, err.quoted.dst_port), (0, 0));
+    }
+}
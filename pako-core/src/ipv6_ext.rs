@@ -0,0 +1,350 @@
+//!
+//! Parsing of IPv6 extension header chains (RFC 8200)
+//!
+
+use std::net::Ipv6Addr;
+
+use pako_tools::{Error, Ipv6ExtensionWalker, Layer};
+use pnet_packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+
+/// An option, from an Hop-by-Hop or Destination Options header, or a SRH TLV
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ipv6Option {
+    pub option_type: u8,
+    /// Option data (empty for Pad1)
+    pub data: Vec<u8>,
+}
+
+/// Type-specific data of a Routing header
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ipv6RoutingData {
+    /// Type 0 (deprecated by RFC 5095)
+    Type0 { segments: Vec<Ipv6Addr> },
+    /// Type 2 (Mobile IPv6, RFC 6275)
+    Type2 { home_address: Ipv6Addr },
+    /// Type 4: Segment Routing Header (SRv6, RFC 8754)
+    ///
+    /// Segments are stored as in the header, i.e. in reverse order (the final segment first).
+    Srh {
+        last_entry: u8,
+        flags: u8,
+        tag: u16,
+        segments: Vec<Ipv6Addr>,
+        tlvs: Vec<Ipv6Option>,
+    },
+    /// Other routing types, raw type-specific data
+    Other(Vec<u8>),
+}
+
+/// IPv6 Routing header
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ipv6RoutingHeader {
+    pub routing_type: u8,
+    pub segments_left: u8,
+    pub data: Ipv6RoutingData,
+}
+
+/// An IPv6 extension header
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ipv6ExtensionHeader {
+    HopByHop(Vec<Ipv6Option>),
+    DestinationOptions(Vec<Ipv6Option>),
+    Routing(Ipv6RoutingHeader),
+    Fragment {
+        /// Fragment offset, in bytes
+        offset: u16,
+        more_fragments: bool,
+        id: u32,
+    },
+    /// Authentication Header
+    Ah {
+        spi: u32,
+        seq: u32,
+    },
+    /// Encapsulating Security Payload. The rest of the packet is encrypted, so this is
+    /// always the last header of the chain.
+    Esp {
+        spi: u32,
+        seq: u32,
+    },
+    Mobility {
+        mh_type: u8,
+        data: Vec<u8>,
+    },
+    /// Other extension header using the generic format (RFC 7045), e.g HIP or Shim6
+    Other {
+        next_header: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// The chain of extension headers of an IPv6 packet
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Ipv6ExtensionChain {
+    /// Extension headers, in order of appearance
+    pub headers: Vec<Ipv6ExtensionHeader>,
+}
+
+/// Return true if the protocol is an IPv6 extension header
+pub fn is_ipv6_extension(proto: IpNextHeaderProtocol) -> bool {
+    Ipv6ExtensionWalker::is_extension(proto.0)
+}
+
+impl Ipv6ExtensionChain {
+    /// Walk the extension headers starting with `next_header`, and append them to the chain
+    ///
+    /// Returns the protocol and data following the last parsed header.
+    ///
+    /// Parsing stops after a Fragment header, since the following headers are only present
+    /// in the first fragment: once the packet is reassembled, this function should be called
+    /// again on the reassembled data to complete the chain. Parsing also stops after an ESP
+    /// header, the returned protocol being ESP.
+    pub fn parse<'a>(
+        &mut self,
+        next_header: IpNextHeaderProtocol,
+        data: &'a [u8],
+    ) -> Result<(IpNextHeaderProtocol, &'a [u8]), Error> {
+        let mut walker = Ipv6ExtensionWalker::new(next_header.0, data);
+        for res in walker.by_ref() {
+            let (header_type, ext) = res?;
+            let header = match IpNextHeaderProtocol(header_type) {
+                IpNextHeaderProtocols::Hopopt => {
+                    Ipv6ExtensionHeader::HopByHop(parse_options(&ext[2..])?)
+                }
+                IpNextHeaderProtocols::Ipv6Opts => {
+                    Ipv6ExtensionHeader::DestinationOptions(parse_options(&ext[2..])?)
+                }
+                IpNextHeaderProtocols::Ipv6Route => {
+                    Ipv6ExtensionHeader::Routing(parse_routing_header(ext)?)
+                }
+                IpNextHeaderProtocols::Ipv6Frag => Ipv6ExtensionHeader::Fragment {
+                    offset: Ipv6ExtensionWalker::fragment_offset(ext),
+                    more_fragments: ext[3] & 1 != 0,
+                    id: be_u32(&ext[4..8]),
+                },
+                IpNextHeaderProtocols::Ah => Ipv6ExtensionHeader::Ah {
+                    spi: be_u32(&ext[4..8]),
+                    seq: be_u32(&ext[8..12]),
+                },
+                IpNextHeaderProtocols::MobilityHeader => Ipv6ExtensionHeader::Mobility {
+                    mh_type: ext[2],
+                    data: ext[4..].to_vec(),
+                },
+                _ => Ipv6ExtensionHeader::Other {
+                    next_header: header_type,
+                    data: ext[2..].to_vec(),
+                },
+            };
+            let is_fragment = matches!(header, Ipv6ExtensionHeader::Fragment { .. });
+            self.headers.push(header);
+            if is_fragment {
+                return Ok((IpNextHeaderProtocol(walker.next_header()), walker.data()));
+            }
+        }
+        let (next_header, data) = (IpNextHeaderProtocol(walker.next_header()), walker.data());
+        if next_header == IpNextHeaderProtocols::Esp {
+            if data.len() < 8 {
+                return Err(Error::truncated(Layer::Network, "IPv6 ESP"));
+            }
+            self.headers.push(Ipv6ExtensionHeader::Esp {
+                spi: be_u32(&data[0..4]),
+                seq: be_u32(&data[4..8]),
+            });
+        }
+        Ok((next_header, data))
+    }
+
+    /// Return true if the chain has no extension header
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Return the Fragment header information (offset, more_fragments, id), if present
+    pub fn fragment(&self) -> Option<(u16, bool, u32)> {
+        self.headers.iter().find_map(|h| match *h {
+            Ipv6ExtensionHeader::Fragment {
+                offset,
+                more_fragments,
+                id,
+            } => Some((offset, more_fragments, id)),
+            _ => None,
+        })
+    }
+
+    /// Return the number of Fragment headers in the chain
+    pub fn fragment_count(&self) -> usize {
+        self.headers
+            .iter()
+            .filter(|h| matches!(h, Ipv6ExtensionHeader::Fragment { .. }))
+            .count()
+    }
+
+    /// Return the Routing headers of the chain
+    pub fn routing_headers(&self) -> impl Iterator<Item = &Ipv6RoutingHeader> {
+        self.headers.iter().filter_map(|h| match h {
+            Ipv6ExtensionHeader::Routing(r) => Some(r),
+            _ => None,
+        })
+    }
+}
+
+#[inline]
+fn be_u32(i: &[u8]) -> u32 {
+    u32::from_be_bytes([i[0], i[1], i[2], i[3]])
+}
+
+/// Parse a list of TLV-encoded options (Pad1 is a single zero byte)
+fn parse_options(mut i: &[u8]) -> Result<Vec<Ipv6Option>, Error> {
+    let mut options = Vec::new();
+    while let Some(&option_type) = i.first() {
+        if option_type == 0 {
+            options.push(Ipv6Option {
+                option_type,
+                data: Vec::new(),
+            });
+            i = &i[1..];
+            continue;
+        }
+        let len = *i
+            .get(1)
+            .ok_or(Error::truncated(Layer::Network, "IPv6 option"))? as usize;
+        if i.len() < 2 + len {
+            return Err(Error::truncated(Layer::Network, "IPv6 option"));
+        }
+        options.push(Ipv6Option {
+            option_type,
+            data: i[2..2 + len].to_vec(),
+        });
+        i = &i[2 + len..];
+    }
+    Ok(options)
+}
+
+fn parse_addresses(i: &[u8], count: usize) -> Result<Vec<Ipv6Addr>, Error> {
+    if i.len() < count * 16 {
+        return Err(Error::truncated(Layer::Network, "IPv6 Routing header"));
+    }
+    let v = i
+        .chunks_exact(16)
+        .take(count)
+        .map(|c| {
+            let a: [u8; 16] = c.try_into().unwrap_or_default();
+            Ipv6Addr::from(a)
+        })
+        .collect();
+    Ok(v)
+}
+
+/// Parse a complete Routing header (including next header and length fields)
+fn parse_routing_header(ext: &[u8]) -> Result<Ipv6RoutingHeader, Error> {
+    let routing_type = ext[2];
+    let segments_left = ext[3];
+    let data = match routing_type {
+        0 => Ipv6RoutingData::Type0 {
+            segments: parse_addresses(&ext[8..], (ext.len() - 8) / 16)?,
+        },
+        2 => {
+            let v = parse_addresses(&ext[8..], 1)?;
+            Ipv6RoutingData::Type2 { home_address: v[0] }
+        }
+        4 => {
+            let last_entry = ext[4];
+            let n = last_entry as usize + 1;
+            let segments = parse_addresses(&ext[8..], n)?;
+            let tlvs = parse_options(&ext[8 + n * 16..])?;
+            Ipv6RoutingData::Srh {
+                last_entry,
+                flags: ext[5],
+                tag: u16::from_be_bytes([ext[6], ext[7]]),
+                segments,
+                tlvs,
+            }
+        }
+        _ => Ipv6RoutingData::Other(ext[4..].to_vec()),
+    };
+    Ok(Ipv6RoutingHeader {
+        routing_type,
+        segments_left,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use pako_tools::DecodeErrorKind;
+    use pnet_packet::ip::IpNextHeaderProtocols;
+
+    use super::*;
+
+    #[test]
+    fn ipv6_ext_srh_fragment() {
+        let mut data = Vec::new();
+        // Hop-by-Hop: next=Routing, len=0, Router Alert (type 5, len 2), PadN (len 0)
+        data.extend_from_slice(b"\x2b\x00\x05\x02\x00\x00\x01\x00");
+        // SRH: next=Fragment, len=4 (2 segments), type 4, segments left 1, last entry 1
+        data.extend_from_slice(b"\x2c\x04\x04\x01\x01\x00\x00\x00");
+        data.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2).octets());
+        data.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).octets());
+        // Fragment: next=Destination Options, offset 0, more fragments, id 0x1234
+        data.extend_from_slice(b"\x3c\x00\x00\x01\x00\x00\x12\x34");
+        // Destination Options: next=ICMPv6, len=0, PadN (len 4)
+        data.extend_from_slice(b"\x3a\x00\x01\x04\x00\x00\x00\x00");
+        data.extend_from_slice(b"\x80\x00\x00\x00");
+
+        let mut chain = Ipv6ExtensionChain::default();
+        let (proto, rem) = chain
+            .parse(IpNextHeaderProtocols::Hopopt, &data)
+            .expect("parse chain");
+        // parsing stops after the fragment header
+        assert_eq!(proto, IpNextHeaderProtocols::Ipv6Opts);
+        assert_eq!(rem.len(), 12);
+        assert_eq!(chain.headers.len(), 3);
+        assert_eq!(chain.fragment(), Some((0, true, 0x1234)));
+        match &chain.headers[0] {
+            Ipv6ExtensionHeader::HopByHop(opts) => {
+                assert_eq!(opts.len(), 2);
+                assert_eq!(opts[0].option_type, 5);
+                assert_eq!(opts[0].data, vec![0, 0]);
+            }
+            h => panic!("unexpected header {:?}", h),
+        }
+        let srh = chain.routing_headers().next().expect("routing header");
+        assert_eq!(srh.routing_type, 4);
+        assert_eq!(srh.segments_left, 1);
+        match &srh.data {
+            Ipv6RoutingData::Srh { segments, tlvs, .. } => {
+                assert_eq!(segments.len(), 2);
+                assert_eq!(segments[1], Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+                assert!(tlvs.is_empty());
+            }
+            d => panic!("unexpected routing data {:?}", d),
+        }
+        // complete the chain, as done after reassembly
+        let (proto, rem) = chain.parse(proto, rem).expect("parse after fragment");
+        assert_eq!(proto, IpNextHeaderProtocols::Icmpv6);
+        assert_eq!(rem, b"\x80\x00\x00\x00");
+        assert_eq!(chain.headers.len(), 4);
+    }
+
+    #[test]
+    fn ipv6_ext_errors() {
+        // Hop-by-Hop header with a length larger than data
+        let data = b"\x3a\x01\x01\x04\x00\x00\x00\x00";
+        let mut chain = Ipv6ExtensionChain::default();
+        match chain.parse(IpNextHeaderProtocols::Hopopt, data) {
+            Err(Error::Decode(e)) => {
+                assert_eq!(e.kind, DecodeErrorKind::Truncated);
+                assert_eq!(e.layer, Layer::Network);
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        // AH header with an invalid length
+        let data = b"\x3a\x00\x00\x00\x00\x00\x00\x00";
+        match chain.parse(IpNextHeaderProtocols::Ah, data) {
+            Err(Error::Decode(e)) => assert_eq!(e.kind, DecodeErrorKind::Malformed),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -19,4 +19,5 @@ pub enum TransportLayerType {
     Icmp = IpNextHeaderProtocols::Icmp.0 as u16,
     Tcp = IpNextHeaderProtocols::Tcp.0 as u16,
     Udp = IpNextHeaderProtocols::Udp.0 as u16,
+    Sctp = IpNextHeaderProtocols::Sctp.0 as u16,
 }
@@ -1,37 +1,63 @@
 #![warn(clippy::all)]
 #![allow(clippy::upper_case_acronyms)]
 
+mod analysis_context;
 mod analyzer;
+mod buffer_pool;
+mod capture_stats;
+mod checksum;
+mod error_stats;
 mod erspan;
+mod fanout;
+mod flow_event;
 mod flow_map;
 mod geneve;
+mod icmp_error;
 mod ip_defrag;
+mod ipv6_ext;
 mod layers;
 mod mpls;
+mod name_table;
+mod neighbor;
 mod packet_info;
 mod plugin;
 mod plugin_registry;
 mod ppp;
 mod pppoe;
+mod sctp;
+mod sctp_reassembly;
 mod tcp_reassembly;
 mod threaded_analyzer;
+mod tls_keylog;
 mod vxlan;
 
 pub mod output;
 pub mod plugins;
 
+pub use analysis_context::AnalysisContext;
 pub use analyzer::*;
+pub use capture_stats::{CaptureStats, InterfaceStats};
+pub use checksum::*;
+pub use error_stats::ErrorStats;
 pub use erspan::*;
-pub use flow_map::FlowMap;
+pub use flow_event::*;
+pub use flow_map::{compute_flow_id, FlowMap};
 pub use geneve::*;
+pub use icmp_error::*;
+pub use ipv6_ext::*;
 pub use layers::*;
 pub use mpls::*;
+pub use name_table::NameTable;
+pub use neighbor::*;
 pub use packet_info::*;
 pub use plugin::*;
 pub use plugin_registry::*;
 pub use ppp::*;
 pub use pppoe::*;
+pub use sctp::*;
+pub use sctp_reassembly::{SctpAssociationEvent, SctpDataInfo};
 pub use threaded_analyzer::*;
+pub use tls_keylog::*;
 pub use vxlan::*;
 
 #[derive(Debug, PartialEq)]
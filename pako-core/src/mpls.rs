@@ -47,7 +47,7 @@ impl<'a> MPLSPacket<'a> {
     /// Constructs a new PPP packet. If the provided buffer is less than the minimum required
     /// packet size, this will return None.
     #[inline]
-    pub fn new(packet: &[u8]) -> Option<MPLSPacket> {
+    pub fn new(packet: &[u8]) -> Option<MPLSPacket<'_>> {
         if packet.len() >= MPLSPacket::minimum_packet_size() {
             let mut stack_size = 0;
             let mut ptr = packet;
@@ -0,0 +1,139 @@
+//!
+//! Address to name table, filled from the Name Resolution Blocks of the capture
+//!
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str,
+    sync::RwLock,
+};
+
+use log::{debug, warn};
+use pako_tools::pcap_parser::{NameRecord, NameRecordType, NameResolutionBlock};
+
+/// Names of IP addresses, as resolved by the capture application
+///
+/// An address can have several names, kept in the order of the capture. The same instance
+/// is shared by all workers of a `ThreadedAnalyzer` and by plugins (see
+/// `Plugin::set_context`), so reports can show hostnames instead of addresses.
+#[derive(Debug, Default)]
+pub struct NameTable {
+    names: RwLock<HashMap<IpAddr, Vec<String>>>,
+}
+
+/// Return the address and names of a name resolution record
+///
+/// The record value is an address, followed by one or more zero-terminated names.
+fn parse_record<'a>(record: &NameRecord<'a>) -> Option<(IpAddr, Vec<&'a str>)> {
+    let (addr, rem) = match record.record_type {
+        NameRecordType::Ipv4 if record.record_value.len() > 4 => {
+            let (addr, rem) = record.record_value.split_at(4);
+            let addr: [u8; 4] = addr.try_into().ok()?;
+            (IpAddr::V4(Ipv4Addr::from(addr)), rem)
+        }
+        NameRecordType::Ipv6 if record.record_value.len() > 16 => {
+            let (addr, rem) = record.record_value.split_at(16);
+            let addr: [u8; 16] = addr.try_into().ok()?;
+            (IpAddr::V6(Ipv6Addr::from(addr)), rem)
+        }
+        _ => return None,
+    };
+    let names = rem
+        .split(|&b| b == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| str::from_utf8(name).ok())
+        .collect();
+    Some((addr, names))
+}
+
+impl NameTable {
+    /// Add the records of a Name Resolution Block, and return the number of names added
+    pub(crate) fn handle_nrb(&self, nrb: &NameResolutionBlock) -> usize {
+        let mut count = 0;
+        for record in &nrb.nr {
+            match record.record_type {
+                NameRecordType::End => (),
+                NameRecordType::Ipv4 | NameRecordType::Ipv6 => match parse_record(record) {
+                    Some((addr, names)) => {
+                        for name in names {
+                            count += usize::from(self.insert(addr, name));
+                        }
+                    }
+                    None => warn!("Invalid name resolution record"),
+                },
+                NameRecordType(t) => debug!("Ignoring name resolution record type {}", t),
+            }
+        }
+        count
+    }
+
+    /// Add a name for `addr`, and return `true` if it was not already known
+    pub fn insert(&self, addr: IpAddr, name: &str) -> bool {
+        let mut table = self.names.write().expect("name table lock poisoned");
+        let names = table.entry(addr).or_default();
+        if names.iter().any(|n| n == name) {
+            return false;
+        }
+        names.push(name.to_owned());
+        true
+    }
+
+    /// First name of `addr`
+    pub fn name(&self, addr: &IpAddr) -> Option<String> {
+        let table = self.names.read().expect("name table lock poisoned");
+        table.get(addr)?.first().cloned()
+    }
+
+    /// All names of `addr`
+    pub fn names(&self, addr: &IpAddr) -> Vec<String> {
+        let table = self.names.read().expect("name table lock poisoned");
+        table.get(addr).cloned().unwrap_or_default()
+    }
+
+    /// Number of addresses with a name
+    pub fn len(&self) -> usize {
+        self.names.read().expect("name table lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use pako_tools::pcap_parser::{NameRecord, NameRecordType};
+
+    use super::{parse_record, NameTable};
+
+    #[test]
+    fn name_table_records() {
+        let value = b"\x0a\x00\x00\x01host.example.com\x00alias\x00\x00\x00";
+        let record = NameRecord {
+            record_type: NameRecordType::Ipv4,
+            record_value: value,
+        };
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let (parsed_addr, names) = parse_record(&record).expect("valid record");
+        assert_eq!(parsed_addr, addr);
+        assert_eq!(names, vec!["host.example.com", "alias"]);
+        // address without name
+        let record = NameRecord {
+            record_type: NameRecordType::Ipv6,
+            record_value: &[0u8; 16],
+        };
+        assert!(parse_record(&record).is_none());
+
+        let table = NameTable::default();
+        for name in names {
+            assert!(table.insert(addr, name));
+        }
+        assert!(!table.insert(addr, "alias"));
+        assert_eq!(table.name(&addr).as_deref(), Some("host.example.com"));
+        assert_eq!(table.names(&addr).len(), 2);
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,205 @@
+//!
+//! ARP (RFC 826) and IPv6 Neighbor Discovery (RFC 4861) decoding
+//!
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use pnet_base::MacAddr;
+use pnet_packet::{
+    arp::{ArpHardwareTypes, ArpOperations, ArpPacket},
+    ethernet::EtherTypes,
+};
+
+/// Type of an address resolution message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NeighborMessageType {
+    ArpRequest,
+    ArpReply,
+    RouterSolicitation,
+    RouterAdvertisement,
+    NeighborSolicitation,
+    NeighborAdvertisement,
+    Redirect,
+}
+
+/// An ARP or NDP message, decoded as an IP to link-layer address binding
+///
+/// The binding is the one announced by the message: for ARP and most NDP messages,
+/// the sender addresses; for Neighbor Advertisements and Redirects, the target addresses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NeighborMessage {
+    pub msg_type: NeighborMessageType,
+    /// IP address announced by this message, if any
+    pub ip: Option<IpAddr>,
+    /// Link-layer address bound to `ip`, if present in the message
+    pub mac: Option<MacAddr>,
+    /// Address being resolved (ARP target, NS target) or redirected to
+    pub target_ip: Option<IpAddr>,
+    /// True for gratuitous ARP, or unsolicited Neighbor Advertisements with override flag
+    pub gratuitous: bool,
+    /// True if the message is a reply or advertisement (as opposed to a request or solicitation)
+    pub is_reply: bool,
+}
+
+/// Decode an ARP packet (Ethernet/IPv4 only)
+pub fn parse_arp(data: &[u8]) -> Option<NeighborMessage> {
+    let arp = ArpPacket::new(data)?;
+    if arp.get_hardware_type() != ArpHardwareTypes::Ethernet
+        || arp.get_protocol_type() != EtherTypes::Ipv4
+        || arp.get_hw_addr_len() != 6
+        || arp.get_proto_addr_len() != 4
+    {
+        return None;
+    }
+    let msg_type = match arp.get_operation() {
+        ArpOperations::Request => NeighborMessageType::ArpRequest,
+        ArpOperations::Reply => NeighborMessageType::ArpReply,
+        _ => return None,
+    };
+    let sender_ip = arp.get_sender_proto_addr();
+    let target_ip = arp.get_target_proto_addr();
+    // ARP probes (RFC 5227) have a null sender address, and do not announce any binding
+    let ip = if sender_ip.is_unspecified() {
+        None
+    } else {
+        Some(IpAddr::V4(sender_ip))
+    };
+    // gratuitous ARP: request or reply for its own address, or broadcast reply
+    let gratuitous = ip.is_some()
+        && (sender_ip == target_ip
+            || (msg_type == NeighborMessageType::ArpReply
+                && arp.get_target_hw_addr() == MacAddr::broadcast()));
+    Some(NeighborMessage {
+        msg_type,
+        ip,
+        mac: ip.map(|_| arp.get_sender_hw_addr()),
+        target_ip: Some(IpAddr::V4(target_ip)),
+        gratuitous,
+        is_reply: msg_type == NeighborMessageType::ArpReply,
+    })
+}
+
+// NDP option types
+const NDP_OPT_SOURCE_LL_ADDR: u8 = 1;
+const NDP_OPT_TARGET_LL_ADDR: u8 = 2;
+
+/// Find a link-layer address option in NDP options
+fn ndp_ll_option(mut options: &[u8], option_type: u8) -> Option<MacAddr> {
+    while options.len() >= 2 {
+        let len = options[1] as usize * 8;
+        if len == 0 || len > options.len() {
+            return None;
+        }
+        if options[0] == option_type && len >= 8 {
+            let o = &options[2..8];
+            return Some(MacAddr::new(o[0], o[1], o[2], o[3], o[4], o[5]));
+        }
+        options = &options[len..];
+    }
+    None
+}
+
+fn ipv6_at(data: &[u8], offset: usize) -> Option<Ipv6Addr> {
+    let a: [u8; 16] = data.get(offset..offset + 16)?.try_into().ok()?;
+    Some(Ipv6Addr::from(a))
+}
+
+/// Decode an ICMPv6 Neighbor Discovery message
+///
+/// `src` is the IPv6 source address, and `data` the complete ICMPv6 message.
+pub fn parse_ndp(src: &Ipv6Addr, data: &[u8]) -> Option<NeighborMessage> {
+    if data.len() < 8 {
+        return None;
+    }
+    let source_ip = if src.is_unspecified() {
+        // duplicate address detection
+        None
+    } else {
+        Some(IpAddr::V6(*src))
+    };
+    let msg = match data[0] {
+        133 => NeighborMessage {
+            msg_type: NeighborMessageType::RouterSolicitation,
+            ip: source_ip,
+            mac: ndp_ll_option(&data[8..], NDP_OPT_SOURCE_LL_ADDR),
+            target_ip: None,
+            gratuitous: false,
+            is_reply: false,
+        },
+        134 => NeighborMessage {
+            msg_type: NeighborMessageType::RouterAdvertisement,
+            ip: source_ip,
+            mac: ndp_ll_option(data.get(16..)?, NDP_OPT_SOURCE_LL_ADDR),
+            target_ip: None,
+            gratuitous: false,
+            is_reply: true,
+        },
+        135 => NeighborMessage {
+            msg_type: NeighborMessageType::NeighborSolicitation,
+            ip: source_ip,
+            mac: ndp_ll_option(data.get(24..)?, NDP_OPT_SOURCE_LL_ADDR),
+            target_ip: Some(IpAddr::V6(ipv6_at(data, 8)?)),
+            gratuitous: false,
+            is_reply: false,
+        },
+        136 => {
+            // flags: router, solicited, override
+            let solicited = data[4] & 0x40 != 0;
+            let override_flag = data[4] & 0x20 != 0;
+            NeighborMessage {
+                msg_type: NeighborMessageType::NeighborAdvertisement,
+                ip: Some(IpAddr::V6(ipv6_at(data, 8)?)),
+                mac: ndp_ll_option(data.get(24..)?, NDP_OPT_TARGET_LL_ADDR),
+                target_ip: None,
+                gratuitous: !solicited && override_flag,
+                is_reply: true,
+            }
+        }
+        137 => NeighborMessage {
+            msg_type: NeighborMessageType::Redirect,
+            ip: Some(IpAddr::V6(ipv6_at(data, 8)?)),
+            mac: ndp_ll_option(data.get(40..)?, NDP_OPT_TARGET_LL_ADDR),
+            target_ip: Some(IpAddr::V6(ipv6_at(data, 24)?)),
+            gratuitous: false,
+            is_reply: false,
+        },
+        _ => return None,
+    };
+    Some(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use pnet_base::MacAddr;
+
+    use super::*;
+
+    #[test]
+    fn neighbor_gratuitous_arp() {
+        // reply, sender 00:11:22:33:44:55 / 10.0.0.1, target ff:ff:ff:ff:ff:ff / 10.0.0.1
+        let data = b"\x00\x01\x08\x00\x06\x04\x00\x02\
+            \x00\x11\x22\x33\x44\x55\x0a\x00\x00\x01\
+            \xff\xff\xff\xff\xff\xff\x0a\x00\x00\x01";
+        let msg = parse_arp(data).expect("ARP");
+        assert_eq!(msg.msg_type, NeighborMessageType::ArpReply);
+        assert_eq!(msg.ip, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert_eq!(msg.mac, Some(MacAddr::new(0, 0x11, 0x22, 0x33, 0x44, 0x55)));
+        assert!(msg.gratuitous);
+    }
+
+    #[test]
+    fn neighbor_ndp_advertisement() {
+        // NA, solicited + override, target fe80::1, target link-layer address option
+        let mut data = b"\x88\x00\x00\x00\x60\x00\x00\x00".to_vec();
+        data.extend_from_slice(&Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1).octets());
+        data.extend_from_slice(b"\x02\x01\x00\x11\x22\x33\x44\x55");
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let msg = parse_ndp(&src, &data).expect("NDP");
+        assert_eq!(msg.msg_type, NeighborMessageType::NeighborAdvertisement);
+        assert_eq!(msg.ip, Some(IpAddr::V6(src)));
+        assert_eq!(msg.mac, Some(MacAddr::new(0, 0x11, 0x22, 0x33, 0x44, 0x55)));
+        assert!(!msg.gratuitous);
+    }
+}
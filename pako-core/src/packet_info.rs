@@ -1,5 +1,10 @@
 use pako_tools::{FiveTuple, Flow};
 
+use crate::{
+    checksum::ChecksumStatus, icmp_error::IcmpError, ipv6_ext::Ipv6ExtensionChain,
+    sctp_reassembly::SctpDataInfo,
+};
+
 pub struct PacketInfo<'l3, 'l4, 't, 'f> {
     /// The five-tuple for *this packet*
     pub five_tuple: &'t FiveTuple,
@@ -7,6 +12,8 @@ pub struct PacketInfo<'l3, 'l4, 't, 'f> {
     /// seen in this flow
     pub to_server: bool,
     pub l3_type: u16,
+    /// IPv6 extension headers (empty for IPv4)
+    pub ipv6_extensions: &'t Ipv6ExtensionChain,
     /// Raw L4 data
     pub l4_data: &'l3 [u8],
     /// L4 payload type
@@ -14,5 +21,11 @@ pub struct PacketInfo<'l3, 'l4, 't, 'f> {
     /// L4 payload, if protocol is known by core engine
     pub l4_payload: Option<&'l4 [u8]>,
     pub flow: Option<&'f Flow>,
+    /// SCTP stream information, if payload is a SCTP user message
+    pub sctp: Option<SctpDataInfo>,
+    /// Quoted packet information, if this packet is an ICMP or ICMPv6 error
+    pub icmp_error: Option<&'t IcmpError>,
+    /// Result of the transport layer checksum validation
+    pub l4_checksum: ChecksumStatus,
     pub pcap_index: usize,
 }
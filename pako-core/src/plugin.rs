@@ -1,8 +1,11 @@
-use std::any::Any;
+use std::{any::Any, sync::Arc};
 
 use pako_tools::{Config, FiveTuple, Flow, Packet, ThreeTuple};
 
-use crate::{analyzer::L3Info, packet_info::PacketInfo, plugin_registry::PluginRegistry};
+use crate::{
+    analysis_context::AnalysisContext, analyzer::L3Info, flow_event::FlowEvent,
+    neighbor::NeighborMessage, packet_info::PacketInfo, plugin_registry::PluginRegistry,
+};
 
 /// Result struct manipulated by all plugins
 ///
@@ -57,6 +60,10 @@ pub const PLUGIN_L4: u16 = 0b1000;
 pub const PLUGIN_FLOW_NEW: u16 = 0b0001_0000;
 /// Indicates the plugin registers for 'flow destroyed' events
 pub const PLUGIN_FLOW_DEL: u16 = 0b0010_0000;
+/// Indicates the plugin registers for flow events (for ex. SCTP association state changes)
+pub const PLUGIN_FLOW_EVENT: u16 = 0b0100_0000;
+/// Indicates the plugin registers for address resolution messages (ARP and NDP)
+pub const PLUGIN_NEIGHBOR: u16 = 0b1000_0000;
 
 /// Indicates the plugin register for all layers
 pub const PLUGIN_ALL: u16 = 0b1111_1111;
@@ -81,6 +88,9 @@ pub trait Plugin: Sync + Send {
     /// Plugin initialization function
     /// Called before processing a pcap file
     fn pre_process(&mut self) {}
+    /// Receives the shared state of the analysis (counters, TLS secrets, names)
+    /// Called before `pre_process`. The context is updated while packets are processed.
+    fn set_context(&mut self, _context: Arc<AnalysisContext>) {}
     /// Plugin end of processing function
     /// Called after processing a pcap file
     fn post_process(&mut self) {}
@@ -137,6 +147,12 @@ pub trait Plugin: Sync + Send {
     /// Callback function when a flow is destroyed
     /// `PLUGIN_FLOW_DEL` must be added to `plugin_type()` return
     fn flow_destroyed(&mut self, _flow: &Flow) {}
+    /// Callback function when an event is attached to a flow
+    /// `PLUGIN_FLOW_EVENT` must be added to `plugin_type()` return
+    fn flow_event(&mut self, _flow: &Flow, _event: &FlowEvent) {}
+    /// Callback function when an address resolution message (ARP or NDP) is seen
+    /// `PLUGIN_NEIGHBOR` must be added to `plugin_type()` return
+    fn neighbor_message(&mut self, _packet: &Packet, _msg: &NeighborMessage) {}
 
     /// Get results, if present
     fn get_results(&mut self) -> Option<Box<dyn Any>> {
@@ -147,6 +163,18 @@ pub trait Plugin: Sync + Send {
     fn save_results(&mut self, _path: &str) -> Result<(), &'static str> {
         Ok(())
     }
+
+    /// Merge the state of another instance of this plugin
+    ///
+    /// Called for plugins having one instance per worker thread: each worker instance
+    /// is merged into the main instance, before `post_process` and `save_results`.
+    /// `other` was created by the same builder, use `as_any_mut` to downcast it.
+    fn merge(&mut self, _other: &mut dyn Plugin) {}
+
+    /// Returns the plugin as `Any`, so it can be downcasted (for ex. in `merge`)
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
 }
 
 /// Derives a plugin builder
@@ -157,9 +185,37 @@ pub trait Plugin: Sync + Send {
 /// By default (if no closure was provided), the plugin is created
 /// using the `Plugin::default()` function.
 ///
+/// If `per_worker` is added after the closure, multi-threaded analyzers create one
+/// instance of the plugin per worker thread, and the plugin must implement `Plugin::merge`.
+///
 /// Note: the plugin builder may create plugins of different types
 #[macro_export]
 macro_rules! plugin_builder {
+    ($name:ident, $builder_name:ident, $build_fn:expr, per_worker) => {
+        pub struct $builder_name;
+
+        impl $crate::PluginBuilder for $builder_name {
+            fn name(&self) -> &'static str {
+                stringify!($builder_name)
+            }
+            fn build(
+                &self,
+                registry: &mut $crate::PluginRegistry,
+                config: &pako_tools::Config,
+            ) -> Result<(), $crate::PluginBuilderError> {
+                let plugin = $build_fn(config);
+                let protos = plugin.plugin_type();
+                let safe_p = $crate::build_safeplugin!(plugin);
+                let builder: $crate::WorkerPluginBuilder =
+                    ::std::sync::Arc::new(|config: &pako_tools::Config| -> $crate::SafePlugin {
+                        $crate::build_safeplugin!($build_fn(config))
+                    });
+                let id = registry.add_worker_plugin(safe_p, builder);
+                $crate::plugin_builder!(@register registry, protos, id);
+                Ok(())
+            }
+        }
+    };
     ($name:ident, $builder_name:ident, $build_fn:expr) => {
         pub struct $builder_name;
 
@@ -176,18 +232,7 @@ macro_rules! plugin_builder {
                 let protos = plugin.plugin_type();
                 let safe_p = $crate::build_safeplugin!(plugin);
                 let id = registry.add_plugin(safe_p);
-                if protos & $crate::PLUGIN_L2 != 0 {
-                    // XXX no filter, so register for all
-                    registry.register_layer(2, 0, id)?;
-                }
-                if protos & $crate::PLUGIN_L3 != 0 {
-                    // XXX no filter, so register for all
-                    registry.register_layer(3, 0, id)?;
-                }
-                if protos & $crate::PLUGIN_L4 != 0 {
-                    // XXX no filter, so register for all
-                    registry.register_layer(4, 0, id)?;
-                }
+                $crate::plugin_builder!(@register registry, protos, id);
                 Ok(())
             }
         }
@@ -195,6 +240,20 @@ macro_rules! plugin_builder {
     ($name:ident, $builder_name:ident) => {
         $crate::plugin_builder!($name, $builder_name, |_| $name::default());
     };
+    (@register $registry:ident, $protos:ident, $id:ident) => {
+        if $protos & $crate::PLUGIN_L2 != 0 {
+            // XXX no filter, so register for all
+            $registry.register_layer(2, 0, $id)?;
+        }
+        if $protos & $crate::PLUGIN_L3 != 0 {
+            // XXX no filter, so register for all
+            $registry.register_layer(3, 0, $id)?;
+        }
+        if $protos & $crate::PLUGIN_L4 != 0 {
+            // XXX no filter, so register for all
+            $registry.register_layer(4, 0, $id)?;
+        }
+    };
 }
 /// Derives a plugin builder relying on the Plugin::default() function
 #[macro_export]
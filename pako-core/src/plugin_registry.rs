@@ -5,6 +5,7 @@ use std::sync::{Arc, Mutex};
 use log::trace;
 // use libpcap_tools::{Packet, ThreeTuple};
 use multimap::MultiMap;
+use pako_tools::Config;
 
 use crate::plugin::*;
 
@@ -12,6 +13,8 @@ use crate::plugin::*;
 pub type SafePlugin = Arc<Mutex<dyn Plugin>>;
 /// Unique identifier for a plugin instance
 pub type PluginID = usize;
+/// Function creating a new instance of a plugin, for a worker thread
+pub type WorkerPluginBuilder = Arc<dyn Fn(&Config) -> SafePlugin + Send + Sync>;
 
 #[macro_export]
 macro_rules! build_safeplugin {
@@ -43,6 +46,9 @@ pub struct PluginRegistry {
     plugins_all: Vec<SafePlugin>,
 
     plugins: MultiMap<PluginInfo, SafePlugin>,
+
+    /// Plugins having one instance per worker thread, and their builders
+    worker_builders: Vec<(PluginID, WorkerPluginBuilder)>,
 }
 
 impl PluginRegistry {
@@ -72,6 +78,79 @@ impl PluginRegistry {
         id
     }
 
+    /// Add a plugin to the registry, with one instance per worker thread
+    ///
+    /// When used with a multi-threaded analyzer, `builder` is called to create a
+    /// new instance of the plugin for each worker. At the end of the analysis,
+    /// worker instances are merged into `plugin` (see `Plugin::merge`).
+    pub fn add_worker_plugin(
+        &mut self,
+        plugin: SafePlugin,
+        builder: WorkerPluginBuilder,
+    ) -> PluginID {
+        let id = self.add_plugin(plugin);
+        self.worker_builders.push((id, builder));
+        id
+    }
+
+    /// Return true if at least one plugin has per-worker instances
+    pub fn has_worker_plugins(&self) -> bool {
+        !self.worker_builders.is_empty()
+    }
+
+    /// Create the registry used by a worker thread
+    ///
+    /// Plugins having per-worker instances are instantiated, all other plugins
+    /// are shared with this registry. Layer registrations are identical.
+    pub fn build_worker_registry(&self, config: &Config) -> PluginRegistry {
+        let mut plugins_all = self.plugins_all.clone();
+        for (id, builder) in &self.worker_builders {
+            plugins_all[*id] = builder(config);
+        }
+        let mut plugins = MultiMap::new();
+        for (info, plugin) in self
+            .plugins
+            .iter_all()
+            .flat_map(|(info, v)| v.iter().map(move |p| (info, p)))
+        {
+            let id = self.plugins_all.iter().position(|p| Arc::ptr_eq(p, plugin));
+            let plugin = id.map_or_else(|| plugin.clone(), |id| plugins_all[id].clone());
+            plugins.insert(info.clone(), plugin);
+        }
+        PluginRegistry {
+            plugins_all,
+            plugins,
+            worker_builders: self.worker_builders.clone(),
+        }
+    }
+
+    /// Run function `F` on plugins having per-worker instances
+    pub fn run_worker_plugins<F>(&self, mut f: F)
+    where
+        F: FnMut(&mut dyn Plugin),
+    {
+        for (id, _) in &self.worker_builders {
+            let mut p = self.plugins_all[*id].lock().unwrap();
+            f(&mut *p);
+        }
+    }
+
+    /// Merge per-worker plugin instances of `worker` into the instances of this registry
+    ///
+    /// `worker` must have been created by `build_worker_registry`.
+    pub fn merge_worker_registry(&self, worker: &PluginRegistry) {
+        for (id, _) in &self.worker_builders {
+            let (main, other) = (&self.plugins_all[*id], &worker.plugins_all[*id]);
+            if Arc::ptr_eq(main, other) {
+                continue;
+            }
+            let mut main = main.lock().unwrap();
+            let mut other = other.lock().unwrap();
+            trace!("merging worker instance of plugin {}", main.name());
+            main.merge(&mut *other);
+        }
+    }
+
     // pub fn register_l2(&mut self, plugin: SafePlugin) {
     //     self.plugins_l2.push(plugin);
     // }
@@ -1,4 +1,4 @@
-use std::any::Any;
+use std::{any::Any, sync::Arc};
 
 use indexmap::IndexMap;
 use pako_tools::{FiveTuple, FlowID, Packet, ThreeTuple};
@@ -6,6 +6,7 @@ use serde::Serialize;
 use serde_json::{json, Value};
 
 use crate::{
+    analysis_context::AnalysisContext,
     output,
     packet_info::PacketInfo,
     plugin::{Plugin, PluginResult, PLUGIN_L3, PLUGIN_L4},
@@ -19,6 +20,14 @@ struct Stats {
     flow_id: Option<FlowID>,
 }
 
+impl Stats {
+    fn add(&mut self, other: Stats) {
+        self.num_bytes += other.num_bytes;
+        self.num_packets += other.num_packets;
+        self.flow_id = self.flow_id.or(other.flow_id);
+    }
+}
+
 #[derive(Default)]
 pub struct BasicStats {
     pub total_bytes_l3: usize,
@@ -26,9 +35,16 @@ pub struct BasicStats {
 
     l3_conversations: IndexMap<ThreeTuple, Stats>,
     l4_conversations: IndexMap<FiveTuple, Stats>,
+
+    context: Option<Arc<AnalysisContext>>,
 }
 
-plugin_builder!(BasicStats, BasicStatsBuilder);
+plugin_builder!(
+    BasicStats,
+    BasicStatsBuilder,
+    |_| BasicStats::default(),
+    per_worker
+);
 
 impl Plugin for BasicStats {
     fn name(&self) -> &'static str {
@@ -38,6 +54,10 @@ impl Plugin for BasicStats {
         PLUGIN_L3 | PLUGIN_L4
     }
 
+    fn set_context(&mut self, context: Arc<AnalysisContext>) {
+        self.context = Some(context);
+    }
+
     fn handle_layer_network<'s, 'i>(
         &'s mut self,
         _packet: &'s Packet,
@@ -83,6 +103,28 @@ impl Plugin for BasicStats {
         serde_json::to_writer(file, &results).or(Err("Cannot save results to file"))?;
         Ok(())
     }
+
+    fn merge(&mut self, other: &mut dyn Plugin) {
+        let other = match other
+            .as_any_mut()
+            .and_then(|o| o.downcast_mut::<BasicStats>())
+        {
+            Some(other) => other,
+            None => return,
+        };
+        self.total_bytes_l3 += other.total_bytes_l3;
+        self.total_packets += other.total_packets;
+        for (t3, s) in other.l3_conversations.drain(..) {
+            self.l3_conversations.entry(t3).or_default().add(s);
+        }
+        for (t5, s) in other.l4_conversations.drain(..) {
+            self.l4_conversations.entry(t5).or_default().add(s);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
 }
 
 impl BasicStats {
@@ -99,6 +141,14 @@ impl BasicStats {
             .iter()
             .map(|(t3, s)| {
                 if let Value::Object(mut m) = json!(t3) {
+                    if let Some(names) = self.context.as_ref().map(|c| c.name_table()) {
+                        if let Some(name) = names.name(&t3.src) {
+                            m.insert("src_name".into(), name.into());
+                        }
+                        if let Some(name) = names.name(&t3.dst) {
+                            m.insert("dst_name".into(), name.into());
+                        }
+                    }
                     m.insert("num_bytes".into(), s.num_bytes.into());
                     m.insert("num_packets".into(), s.num_packets.into());
                     Value::Object(m)
@@ -123,13 +173,21 @@ impl BasicStats {
                 }
             })
             .collect();
-        let js = json!({
+        let mut js = json!({
             "total_l3": self.total_bytes_l3,
             "total_l3_packets": self.total_packets,
             "l3": l3,
             "total_l4": total_l4,
             "l4": l4,
         });
+        if let Some(stats) = self.context.as_ref().map(|c| c.capture_stats()) {
+            js["capture"] = json!({
+                "packets": stats.packets(),
+                "dropped": stats.dropped(),
+                "completeness": stats.completeness(),
+                "sections": stats.sections(),
+            });
+        }
         js
     }
 }
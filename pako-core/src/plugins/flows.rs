@@ -1,19 +1,28 @@
 //! Plugin to get/save information on flows
 
-use std::any::Any;
+use std::{any::Any, sync::Arc};
 
 use indexmap::IndexMap;
 use pako_tools::{Flow, FlowID};
 use serde_json::{json, Value};
 
-use crate::{output, plugin::Plugin, plugin_builder, PLUGIN_FLOW_DEL, PLUGIN_FLOW_NEW};
+use crate::{
+    analysis_context::AnalysisContext, output, plugin::Plugin, plugin_builder, PLUGIN_FLOW_DEL,
+    PLUGIN_FLOW_NEW,
+};
 
 #[derive(Default)]
 pub struct FlowsInfo {
     pub flows: IndexMap<FlowID, Flow>,
+    context: Option<Arc<AnalysisContext>>,
 }
 
-plugin_builder!(FlowsInfo, FlowsInfoBuilder);
+plugin_builder!(
+    FlowsInfo,
+    FlowsInfoBuilder,
+    |_| FlowsInfo::default(),
+    per_worker
+);
 
 impl Plugin for FlowsInfo {
     fn name(&self) -> &'static str {
@@ -23,6 +32,10 @@ impl Plugin for FlowsInfo {
         PLUGIN_FLOW_NEW | PLUGIN_FLOW_DEL
     }
 
+    fn set_context(&mut self, context: Arc<AnalysisContext>) {
+        self.context = Some(context);
+    }
+
     fn flow_destroyed(&mut self, flow: &Flow) {
         let f = flow.clone();
         self.flows.insert(f.flow_id, f);
@@ -40,6 +53,19 @@ impl Plugin for FlowsInfo {
         serde_json::to_writer(file, &results).or(Err("Cannot save results to file"))?;
         Ok(())
     }
+
+    fn merge(&mut self, other: &mut dyn Plugin) {
+        if let Some(other) = other
+            .as_any_mut()
+            .and_then(|o| o.downcast_mut::<FlowsInfo>())
+        {
+            self.flows.extend(other.flows.drain(..));
+        }
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
 }
 
 impl FlowsInfo {
@@ -47,9 +73,17 @@ impl FlowsInfo {
         let iter = self.flows.iter().map(|(&flow_id, f)| {
             if let Value::Object(mut m) = json!(f.five_tuple) {
                 m.insert("flow_id".into(), json!(flow_id));
-                let first_seen = format!("{}.{}", f.first_seen.secs, f.first_seen.micros);
+                if let Some(names) = self.context.as_ref().map(|c| c.name_table()) {
+                    if let Some(name) = names.name(&f.five_tuple.src) {
+                        m.insert("src_name".into(), json!(name));
+                    }
+                    if let Some(name) = names.name(&f.five_tuple.dst) {
+                        m.insert("dst_name".into(), json!(name));
+                    }
+                }
+                let first_seen = f.first_seen.to_string();
                 m.insert("first_seen".into(), json!(first_seen));
-                let last_seen = format!("{}.{}", f.last_seen.secs, f.last_seen.micros);
+                let last_seen = f.last_seen.to_string();
                 m.insert("last_seen".into(), json!(last_seen));
                 (flow_id.to_string(), Value::Object(m))
             } else {
@@ -14,6 +14,7 @@ mod examples;
 mod flows;
 #[cfg(feature = "plugins_debug")]
 mod hexdump;
+mod neighbors;
 #[cfg(feature = "plugin_ospf")]
 mod ospf;
 #[cfg(feature = "plugin_rusticata")]
@@ -93,6 +94,7 @@ impl Default for PluginsFactory {
         let mut v: Vec<Box<dyn PluginBuilder>> = vec![
             Box::new(basic_stats::BasicStatsBuilder),
             Box::new(flows::FlowsInfoBuilder),
+            Box::new(neighbors::NeighborTableBuilder),
         ];
 
         #[cfg(feature = "plugin_community_id")]
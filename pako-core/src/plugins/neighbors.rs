@@ -0,0 +1,501 @@
+//! Plugin to build a passive neighbor table (IP to MAC bindings) from ARP and NDP messages
+//!
+//! Detects address conflicts, gratuitous ARP storms and possible ARP/NDP spoofing.
+
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+};
+
+use indexmap::IndexMap;
+use log::{debug, warn};
+use pako_tools::{Config, Duration, Packet};
+use pnet_base::MacAddr;
+use serde_json::{json, Value};
+
+use crate::{
+    neighbor::{NeighborMessage, NeighborMessageType},
+    output,
+    plugin::{Plugin, PLUGIN_NEIGHBOR},
+    plugin_builder,
+};
+
+/// Default number of gratuitous messages from a single host to trigger a storm alert
+const DEFAULT_STORM_THRESHOLD: usize = 10;
+/// Default storm detection window, in seconds
+const DEFAULT_STORM_WINDOW: u32 = 10;
+/// Delay (in seconds) after which an unanswered request is forgotten
+const REQUEST_TIMEOUT: u64 = 5;
+/// Number of pending requests after which expired requests are removed
+const MAX_PENDING_REQUESTS: usize = 4096;
+
+struct NeighborEntry {
+    mac: MacAddr,
+    first_seen: Duration,
+    last_seen: Duration,
+    num_messages: usize,
+    /// Link-layer addresses previously bound to this IP address
+    previous: Vec<MacAddr>,
+}
+
+struct NeighborChange {
+    ts: Duration,
+    ip: IpAddr,
+    old_mac: MacAddr,
+    new_mac: MacAddr,
+    pcap_index: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum AlertKind {
+    /// IP address is claimed by another host while the previous binding is still active
+    Conflict,
+    /// Too many gratuitous messages from a single host
+    GratuitousStorm,
+    /// Unsolicited reply changing a binding, or binding reverting to a previous address
+    PossibleSpoofing,
+}
+
+struct NeighborAlert {
+    ts: Duration,
+    kind: AlertKind,
+    ip: Option<IpAddr>,
+    mac: MacAddr,
+    pcap_index: usize,
+}
+
+pub struct NeighborTable {
+    table: IndexMap<IpAddr, NeighborEntry>,
+    changes: Vec<NeighborChange>,
+    alerts: Vec<NeighborAlert>,
+    /// Timestamps of recent gratuitous messages, by link-layer address
+    gratuitous: HashMap<MacAddr, VecDeque<Duration>>,
+    /// Hosts for which a storm was reported, and not finished yet
+    storms: HashMap<MacAddr, Duration>,
+    /// Pending requests (ARP request or Neighbor Solicitation), by target address
+    requests: HashMap<IpAddr, Duration>,
+
+    storm_threshold: usize,
+    storm_window: u32,
+}
+
+plugin_builder!(NeighborTable, NeighborTableBuilder, NeighborTable::new);
+
+impl NeighborTable {
+    fn new(config: &Config) -> Self {
+        let storm_threshold = config
+            .get_usize("plugin.neighbors.storm_threshold")
+            .unwrap_or(DEFAULT_STORM_THRESHOLD);
+        let storm_window = config
+            .get_usize("plugin.neighbors.storm_window")
+            .map_or(DEFAULT_STORM_WINDOW, |w| w as u32);
+        NeighborTable {
+            table: IndexMap::new(),
+            changes: Vec::new(),
+            alerts: Vec::new(),
+            gratuitous: HashMap::new(),
+            storms: HashMap::new(),
+            requests: HashMap::new(),
+            storm_threshold,
+            storm_window,
+        }
+    }
+
+    fn alert(&mut self, packet: &Packet, kind: AlertKind, ip: Option<IpAddr>, mac: MacAddr) {
+        warn!(
+            "Neighbor alert: {:?} ip={:?} mac={} (idx={})",
+            kind, ip, mac, packet.pcap_index
+        );
+        self.alerts.push(NeighborAlert {
+            ts: packet.ts,
+            kind,
+            ip,
+            mac,
+            pcap_index: packet.pcap_index,
+        });
+    }
+
+    fn check_storm(&mut self, packet: &Packet, msg: &NeighborMessage, mac: MacAddr) {
+        let now = packet.ts;
+        let window = self.storm_window;
+        let q = self.gratuitous.entry(mac).or_default();
+        q.push_back(now);
+        while let Some(&first) = q.front() {
+            if (now - first).secs() > u64::from(window) {
+                q.pop_front();
+            } else {
+                break;
+            }
+        }
+        let count = q.len();
+        if count < self.storm_threshold {
+            self.storms.remove(&mac);
+            return;
+        }
+        // report each storm only once
+        if self.storms.insert(mac, now).is_none() {
+            debug!(
+                "{} gratuitous messages from {} in {}s",
+                count, mac, self.storm_window
+            );
+            self.alert(packet, AlertKind::GratuitousStorm, msg.ip, mac);
+        }
+    }
+
+    /// Return true if a reply for this address was expected
+    fn is_solicited(&mut self, msg: &NeighborMessage, ip: IpAddr, now: Duration) -> bool {
+        match msg.msg_type {
+            NeighborMessageType::ArpReply | NeighborMessageType::NeighborAdvertisement => self
+                .requests
+                .remove(&ip)
+                .is_some_and(|ts| (now - ts).secs() <= REQUEST_TIMEOUT),
+            // not a reply
+            _ => true,
+        }
+    }
+
+    fn update_binding(&mut self, packet: &Packet, msg: &NeighborMessage, ip: IpAddr, mac: MacAddr) {
+        let now = packet.ts;
+        let solicited = self.is_solicited(msg, ip, now);
+        let entry = match self.table.get_mut(&ip) {
+            Some(entry) => entry,
+            None => {
+                self.table.insert(
+                    ip,
+                    NeighborEntry {
+                        mac,
+                        first_seen: now,
+                        last_seen: now,
+                        num_messages: 1,
+                        previous: Vec::new(),
+                    },
+                );
+                return;
+            }
+        };
+        entry.num_messages += 1;
+        if entry.mac == mac {
+            entry.last_seen = now;
+            return;
+        }
+        // binding changed
+        let old_mac = entry.mac;
+        let recently_active = (now - entry.last_seen).secs() <= REQUEST_TIMEOUT;
+        let reverted = entry.previous.contains(&mac);
+        if !entry.previous.contains(&old_mac) {
+            entry.previous.push(old_mac);
+        }
+        entry.mac = mac;
+        entry.last_seen = now;
+        self.changes.push(NeighborChange {
+            ts: now,
+            ip,
+            old_mac,
+            new_mac: mac,
+            pcap_index: packet.pcap_index,
+        });
+        if (msg.is_reply && !solicited && !msg.gratuitous) || reverted {
+            self.alert(packet, AlertKind::PossibleSpoofing, Some(ip), mac);
+        } else if recently_active {
+            self.alert(packet, AlertKind::Conflict, Some(ip), mac);
+        }
+    }
+
+    fn get_results_json(&self) -> Value {
+        let ts = |d: &Duration| d.to_string();
+        let table: Vec<_> = self
+            .table
+            .iter()
+            .map(|(ip, e)| {
+                json!({
+                    "ip": ip,
+                    "mac": e.mac.to_string(),
+                    "first_seen": ts(&e.first_seen),
+                    "last_seen": ts(&e.last_seen),
+                    "num_messages": e.num_messages,
+                    "previous_macs": e.previous.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let changes: Vec<_> = self
+            .changes
+            .iter()
+            .map(|c| {
+                json!({
+                    "ts": ts(&c.ts),
+                    "ip": c.ip,
+                    "old_mac": c.old_mac.to_string(),
+                    "new_mac": c.new_mac.to_string(),
+                    "pcap_index": c.pcap_index,
+                })
+            })
+            .collect();
+        let alerts: Vec<_> = self
+            .alerts
+            .iter()
+            .map(|a| {
+                json!({
+                    "ts": ts(&a.ts),
+                    "kind": format!("{:?}", a.kind),
+                    "ip": a.ip,
+                    "mac": a.mac.to_string(),
+                    "pcap_index": a.pcap_index,
+                })
+            })
+            .collect();
+        json!({
+            "table": table,
+            "changes": changes,
+            "alerts": alerts,
+        })
+    }
+}
+
+impl Plugin for NeighborTable {
+    fn name(&self) -> &'static str {
+        "NeighborTable"
+    }
+    fn plugin_type(&self) -> u16 {
+        PLUGIN_NEIGHBOR
+    }
+
+    fn neighbor_message(&mut self, packet: &Packet, msg: &NeighborMessage) {
+        let now = packet.ts;
+        if !msg.is_reply {
+            if let Some(target) = msg.target_ip {
+                if self.requests.len() >= MAX_PENDING_REQUESTS {
+                    self.requests
+                        .retain(|_, ts| (now - *ts).secs() <= REQUEST_TIMEOUT);
+                }
+                self.requests.insert(target, now);
+            }
+        }
+        let (ip, mac) = match (msg.ip, msg.mac) {
+            (Some(ip), Some(mac)) => (ip, mac),
+            _ => return,
+        };
+        if msg.gratuitous {
+            self.check_storm(packet, msg, mac);
+        }
+        self.update_binding(packet, msg, ip, mac);
+    }
+
+    fn get_results(&mut self) -> Option<Box<dyn Any>> {
+        let v = self.get_results_json();
+        Some(Box::new(v))
+    }
+
+    fn save_results(&mut self, path: &str) -> Result<(), &'static str> {
+        let results = self.get_results_json();
+        // save data to file
+        let file =
+            output::create_file(path, "neighbors.json").or(Err("Cannot create output file"))?;
+        serde_json::to_writer(file, &results).or(Err("Cannot save results to file"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use pako_tools::{
+        pcap_parser::{data::PacketData, Linktype},
+        Config, Duration, Packet,
+    };
+    use pnet_base::MacAddr;
+    use serde_json::Value;
+
+    use super::{AlertKind, NeighborTable};
+    use crate::{
+        neighbor::{NeighborMessage, NeighborMessageType},
+        plugin::Plugin,
+    };
+
+    const IP_A: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const IP_B: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    fn mac(n: u8) -> MacAddr {
+        MacAddr::new(0, 0, 0, 0, 0, n)
+    }
+
+    fn request(ip: IpAddr, mac: MacAddr, target: IpAddr) -> NeighborMessage {
+        NeighborMessage {
+            msg_type: NeighborMessageType::ArpRequest,
+            ip: Some(ip),
+            mac: Some(mac),
+            target_ip: Some(target),
+            gratuitous: false,
+            is_reply: false,
+        }
+    }
+
+    fn reply(ip: IpAddr, mac: MacAddr) -> NeighborMessage {
+        NeighborMessage {
+            msg_type: NeighborMessageType::ArpReply,
+            ip: Some(ip),
+            mac: Some(mac),
+            target_ip: None,
+            gratuitous: false,
+            is_reply: true,
+        }
+    }
+
+    fn gratuitous(ip: IpAddr, mac: MacAddr) -> NeighborMessage {
+        NeighborMessage {
+            gratuitous: true,
+            ..reply(ip, mac)
+        }
+    }
+
+    /// Feed messages (with their timestamp in seconds) to the table, pcap index starts at 1
+    fn feed(table: &mut NeighborTable, messages: &[(u64, NeighborMessage)]) {
+        for (idx, (secs, msg)) in messages.iter().enumerate() {
+            let packet = Packet {
+                interface: 0,
+                ts: Duration::from_secs(*secs),
+                link_type: Linktype::ETHERNET,
+                data: PacketData::L2(&[]),
+                caplen: 0,
+                origlen: 0,
+                pcap_index: idx + 1,
+            };
+            table.neighbor_message(&packet, msg);
+        }
+    }
+
+    fn alerts(table: &NeighborTable) -> Vec<(AlertKind, usize)> {
+        table
+            .alerts
+            .iter()
+            .map(|a| (a.kind, a.pcap_index))
+            .collect()
+    }
+
+    #[test]
+    fn neighbors_binding_conflict() {
+        let mut table = NeighborTable::new(&Config::default());
+        feed(
+            &mut table,
+            &[
+                (1, request(IP_A, mac(1), IP_B)),
+                // another host claims the address while the first one is active
+                (2, request(IP_A, mac(2), IP_B)),
+                // host replaced after the binding expired: not a conflict
+                (20, request(IP_A, mac(3), IP_B)),
+                (21, request(IP_A, mac(3), IP_B)),
+            ],
+        );
+        assert_eq!(alerts(&table), vec![(AlertKind::Conflict, 2)]);
+        assert_eq!(table.changes.len(), 2);
+        let entry = &table.table[&IP_A];
+        assert_eq!(entry.mac, mac(3));
+        assert_eq!(entry.previous, vec![mac(1), mac(2)]);
+        assert_eq!(entry.num_messages, 4);
+        assert_eq!(entry.first_seen, Duration::from_secs(1));
+        assert_eq!(entry.last_seen, Duration::from_secs(21));
+    }
+
+    #[test]
+    fn neighbors_gratuitous_storm() {
+        let mut config = Config::default();
+        config.add_section("", "plugin").unwrap();
+        config.add_section("plugin", "neighbors").unwrap();
+        config.set("plugin.neighbors.storm_threshold", 3).unwrap();
+        config.set("plugin.neighbors.storm_window", 10).unwrap();
+        let mut table = NeighborTable::new(&config);
+        let messages: Vec<_> = [0, 1, 2, 3, 4, 30, 45, 60, 61, 62]
+            .into_iter()
+            .map(|secs| (secs, gratuitous(IP_A, mac(1))))
+            .collect();
+        feed(&mut table, &messages);
+        // one alert per storm: the first one ends when messages leave the window,
+        // spaced messages do not trigger an alert
+        assert_eq!(
+            alerts(&table),
+            vec![
+                (AlertKind::GratuitousStorm, 3),
+                (AlertKind::GratuitousStorm, 10)
+            ]
+        );
+        assert!(table.changes.is_empty());
+    }
+
+    #[test]
+    fn neighbors_unsolicited_reply() {
+        let mut table = NeighborTable::new(&Config::default());
+        feed(
+            &mut table,
+            &[
+                (1, request(IP_A, mac(1), IP_B)),
+                // reply changing the binding, without request
+                (2, reply(IP_A, mac(2))),
+                // requested reply, binding unchanged
+                (3, request(IP_B, mac(9), IP_A)),
+                (3, reply(IP_A, mac(2))),
+                // binding reverts to a previous address
+                (20, request(IP_A, mac(1), IP_B)),
+                // requested reply changing an expired binding
+                (40, request(IP_B, mac(9), IP_A)),
+                (41, reply(IP_A, mac(3))),
+                // reply to a request which timed out
+                (50, request(IP_B, mac(9), IP_A)),
+                (60, reply(IP_A, mac(4))),
+            ],
+        );
+        assert_eq!(
+            alerts(&table),
+            vec![
+                (AlertKind::PossibleSpoofing, 2),
+                (AlertKind::PossibleSpoofing, 5),
+                (AlertKind::PossibleSpoofing, 9),
+            ]
+        );
+        assert_eq!(table.changes.len(), 4);
+    }
+
+    #[test]
+    fn neighbors_save_results() {
+        let mut table = NeighborTable::new(&Config::default());
+        feed(
+            &mut table,
+            &[
+                (1, request(IP_A, mac(1), IP_B)),
+                (2, reply(IP_A, mac(2))),
+                (3, reply(IP_B, mac(3))),
+            ],
+        );
+        let dir = env::temp_dir().join(format!("pako-neighbors-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        table.save_results(dir.to_str().unwrap()).unwrap();
+        let data = fs::read(dir.join("neighbors.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let results: Value = serde_json::from_slice(&data).unwrap();
+
+        let entries = results["table"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["ip"], "10.0.0.1");
+        assert_eq!(entries[0]["mac"], "00:00:00:00:00:02");
+        assert_eq!(entries[0]["first_seen"], "1.000000000");
+        assert_eq!(entries[0]["last_seen"], "2.000000000");
+        assert_eq!(entries[0]["num_messages"], 2);
+        assert_eq!(entries[0]["previous_macs"][0], "00:00:00:00:00:01");
+        assert_eq!(entries[1]["ip"], "10.0.0.2");
+
+        let changes = results["changes"].as_array().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0]["old_mac"], "00:00:00:00:00:01");
+        assert_eq!(changes[0]["new_mac"], "00:00:00:00:00:02");
+        assert_eq!(changes[0]["pcap_index"], 2);
+
+        let alerts = results["alerts"].as_array().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0]["kind"], "PossibleSpoofing");
+        assert_eq!(alerts[0]["ip"], "10.0.0.1");
+        assert_eq!(alerts[0]["ts"], "2.000000000");
+    }
+}
@@ -0,0 +1,331 @@
+//!
+//! Support for Stream Control Transmission Protocol (SCTP) packets (RFC 9260)
+//!
+
+use pnet_macros_support::types::{u16be, u32be};
+
+#[derive(PartialEq)]
+/// A structure enabling manipulation of on the wire packets
+pub struct SctpPacket<'p> {
+    packet: pnet_macros_support::packet::PacketData<'p>,
+}
+
+impl<'a> SctpPacket<'a> {
+    /// Constructs a new SCTP packet. If the provided buffer is less than the minimum required
+    /// packet size, this will return None.
+    pub fn new(packet: &[u8]) -> Option<SctpPacket<'_>> {
+        if packet.len() >= SctpPacket::minimum_packet_size() {
+            use ::pnet_macros_support::packet::PacketData;
+            Some(SctpPacket {
+                packet: PacketData::Borrowed(packet),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The minimum size (in bytes) a packet of this type can be. It's based on the total size
+    /// of the fixed-size fields.
+    pub const fn minimum_packet_size() -> usize {
+        12
+    }
+
+    /// Get the source port
+    pub fn get_source(&self) -> u16be {
+        ((self.packet[0] as u16be) << 8) | (self.packet[1] as u16be)
+    }
+
+    /// Get the destination port
+    pub fn get_destination(&self) -> u16be {
+        ((self.packet[2] as u16be) << 8) | (self.packet[3] as u16be)
+    }
+
+    /// Get the verification tag
+    pub fn get_verification_tag(&self) -> u32be {
+        u32::from_be_bytes([
+            self.packet[4],
+            self.packet[5],
+            self.packet[6],
+            self.packet[7],
+        ])
+    }
+
+    /// Get the checksum (CRC32c)
+    pub fn get_checksum(&self) -> u32be {
+        u32::from_be_bytes([
+            self.packet[8],
+            self.packet[9],
+            self.packet[10],
+            self.packet[11],
+        ])
+    }
+
+    /// Get the chunks as iterator
+    pub fn get_chunks_iter(&self) -> SctpChunkIterable<'_> {
+        use pnet_macros_support::packet::Packet;
+        SctpChunkIterable {
+            buf: self.payload(),
+        }
+    }
+}
+
+impl<'a> pnet_macros_support::packet::Packet for SctpPacket<'a> {
+    fn packet(&self) -> &[u8] {
+        &self.packet[..]
+    }
+
+    fn payload(&self) -> &[u8] {
+        &self.packet[12..]
+    }
+}
+
+/// Represents a SCTP chunk type
+#[derive(Hash, Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
+pub struct SctpChunkType(pub u8);
+
+/// SCTP chunk types as defined in RFC 9260 (and extensions)
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod SctpChunkTypes {
+    use super::SctpChunkType;
+
+    pub const Data: SctpChunkType = SctpChunkType(0);
+    pub const Init: SctpChunkType = SctpChunkType(1);
+    pub const InitAck: SctpChunkType = SctpChunkType(2);
+    pub const Sack: SctpChunkType = SctpChunkType(3);
+    pub const Heartbeat: SctpChunkType = SctpChunkType(4);
+    pub const HeartbeatAck: SctpChunkType = SctpChunkType(5);
+    pub const Abort: SctpChunkType = SctpChunkType(6);
+    pub const Shutdown: SctpChunkType = SctpChunkType(7);
+    pub const ShutdownAck: SctpChunkType = SctpChunkType(8);
+    pub const Error: SctpChunkType = SctpChunkType(9);
+    pub const CookieEcho: SctpChunkType = SctpChunkType(10);
+    pub const CookieAck: SctpChunkType = SctpChunkType(11);
+    pub const ShutdownComplete: SctpChunkType = SctpChunkType(14);
+    /// Authentication chunk (RFC 4895)
+    pub const Auth: SctpChunkType = SctpChunkType(15);
+    /// Forward Cumulative TSN (RFC 3758)
+    pub const ForwardTsn: SctpChunkType = SctpChunkType(192);
+}
+
+/// DATA chunk flag: unordered delivery
+pub const SCTP_DATA_FLAG_UNORDERED: u8 = 0b0100;
+/// DATA chunk flag: first fragment of a user message
+pub const SCTP_DATA_FLAG_BEGIN: u8 = 0b0010;
+/// DATA chunk flag: last fragment of a user message
+pub const SCTP_DATA_FLAG_END: u8 = 0b0001;
+
+#[derive(PartialEq)]
+/// A structure enabling manipulation of on the wire packets
+pub struct SctpChunkPacket<'p> {
+    packet: pnet_macros_support::packet::PacketData<'p>,
+}
+
+impl<'a> SctpChunkPacket<'a> {
+    /// Constructs a new SctpChunkPacket. If the provided buffer is less than the minimum required
+    /// packet size, this will return None.
+    pub fn new(packet: &'_ [u8]) -> Option<SctpChunkPacket<'_>> {
+        if packet.len() >= SctpChunkPacket::minimum_packet_size() {
+            use ::pnet_macros_support::packet::PacketData;
+            Some(SctpChunkPacket {
+                packet: PacketData::Borrowed(packet),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The minimum size (in bytes) a packet of this type can be. It's based on the total size
+    /// of the fixed-size fields.
+    pub const fn minimum_packet_size() -> usize {
+        4
+    }
+
+    /// Get the chunk type
+    pub fn get_chunk_type(&self) -> SctpChunkType {
+        SctpChunkType(self.packet[0])
+    }
+
+    /// Get the chunk flags
+    pub fn get_chunk_flags(&self) -> u8 {
+        self.packet[1]
+    }
+
+    /// Get the chunk length (header included, padding excluded)
+    pub fn get_chunk_length(&self) -> u16be {
+        ((self.packet[2] as u16be) << 8) | (self.packet[3] as u16be)
+    }
+}
+
+impl<'a> pnet_macros_support::packet::Packet for SctpChunkPacket<'a> {
+    fn packet(&self) -> &[u8] {
+        &self.packet[..]
+    }
+
+    /// The chunk value, without padding
+    fn payload(&self) -> &[u8] {
+        let end = (self.get_chunk_length() as usize).clamp(4, self.packet.len());
+        &self.packet[4..end]
+    }
+}
+
+impl<'a> pnet_macros_support::packet::PacketSize for SctpChunkPacket<'a> {
+    /// The chunk length, including padding to a 4-bytes boundary
+    fn packet_size(&self) -> usize {
+        ((self.get_chunk_length() as usize) + 3) & !3
+    }
+}
+
+/// Iterator over the chunks of a SCTP packet
+///
+/// Iteration stops at the first chunk with an invalid length.
+pub struct SctpChunkIterable<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for SctpChunkIterable<'a> {
+    type Item = SctpChunkPacket<'a>;
+    fn next(&mut self) -> Option<SctpChunkPacket<'a>> {
+        use std::cmp::min;
+
+        use pnet_macros_support::packet::PacketSize;
+        if !self.buf.is_empty() {
+            if let Some(ret) = SctpChunkPacket::new(self.buf) {
+                let len = ret.get_chunk_length() as usize;
+                if len < 4 || len > self.buf.len() {
+                    self.buf = &[];
+                    return None;
+                }
+                let start = min(ret.packet_size(), self.buf.len());
+                self.buf = &self.buf[start..];
+                return Some(ret);
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+/// Fields of a DATA chunk
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SctpDataChunk<'a> {
+    pub flags: u8,
+    /// Transmission Sequence Number
+    pub tsn: u32,
+    /// Stream Identifier
+    pub stream_id: u16,
+    /// Stream Sequence Number
+    pub ssn: u16,
+    /// Payload Protocol Identifier
+    pub ppid: u32,
+    /// User data (fragment)
+    pub data: &'a [u8],
+}
+
+impl<'a> SctpDataChunk<'a> {
+    /// Parse the value of a DATA chunk
+    pub fn from_chunk(chunk: &'a SctpChunkPacket) -> Option<SctpDataChunk<'a>> {
+        use pnet_macros_support::packet::Packet;
+        if chunk.get_chunk_type() != SctpChunkTypes::Data {
+            return None;
+        }
+        let value = chunk.payload();
+        if value.len() < 12 {
+            return None;
+        }
+        Some(SctpDataChunk {
+            flags: chunk.get_chunk_flags(),
+            tsn: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+            stream_id: u16::from_be_bytes([value[4], value[5]]),
+            ssn: u16::from_be_bytes([value[6], value[7]]),
+            ppid: u32::from_be_bytes([value[8], value[9], value[10], value[11]]),
+            data: &value[12..],
+        })
+    }
+
+    #[inline]
+    pub fn is_unordered(&self) -> bool {
+        self.flags & SCTP_DATA_FLAG_UNORDERED != 0
+    }
+
+    #[inline]
+    pub fn is_begin(&self) -> bool {
+        self.flags & SCTP_DATA_FLAG_BEGIN != 0
+    }
+
+    #[inline]
+    pub fn is_end(&self) -> bool {
+        self.flags & SCTP_DATA_FLAG_END != 0
+    }
+}
+
+/// Fields of an INIT or INIT ACK chunk (parameters are ignored)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SctpInitChunk {
+    pub initiate_tag: u32,
+    pub a_rwnd: u32,
+    pub outbound_streams: u16,
+    pub inbound_streams: u16,
+    pub initial_tsn: u32,
+}
+
+impl SctpInitChunk {
+    /// Parse the value of an INIT or INIT ACK chunk
+    pub fn from_chunk(chunk: &SctpChunkPacket) -> Option<SctpInitChunk> {
+        use pnet_macros_support::packet::Packet;
+        let chunk_type = chunk.get_chunk_type();
+        if chunk_type != SctpChunkTypes::Init && chunk_type != SctpChunkTypes::InitAck {
+            return None;
+        }
+        let value = chunk.payload();
+        if value.len() < 16 {
+            return None;
+        }
+        Some(SctpInitChunk {
+            initiate_tag: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+            a_rwnd: u32::from_be_bytes([value[4], value[5], value[6], value[7]]),
+            outbound_streams: u16::from_be_bytes([value[8], value[9]]),
+            inbound_streams: u16::from_be_bytes([value[10], value[11]]),
+            initial_tsn: u32::from_be_bytes([value[12], value[13], value[14], value[15]]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // common header, INIT ACK chunk, then a DATA chunk with 3 bytes of data (padded)
+    const DATA: &[u8] = b"\x0b\x59\x0b\x59\x12\x34\x56\x78\x00\x00\x00\x00\
+        \x02\x00\x00\x14\xaa\xbb\xcc\xdd\x00\x01\x00\x00\x00\x0a\x00\x0a\x00\x00\x00\x64\
+        \x00\x03\x00\x13\x00\x00\x00\x64\x00\x01\x00\x00\x00\x00\x00\x03\x41\x42\x43\x00";
+    #[test]
+    fn sctp_test() {
+        let packet = SctpPacket::new(DATA).expect("SctpPacket");
+        assert_eq!(packet.get_source(), 2905);
+        assert_eq!(packet.get_destination(), 2905);
+        assert_eq!(packet.get_verification_tag(), 0x1234_5678);
+        let chunks: Vec<_> = packet.get_chunks_iter().collect();
+        assert_eq!(chunks.len(), 2);
+        let init = SctpInitChunk::from_chunk(&chunks[0]).expect("INIT ACK chunk");
+        assert_eq!(init.initiate_tag, 0xaabb_ccdd);
+        assert_eq!(init.outbound_streams, 10);
+        assert_eq!(init.initial_tsn, 100);
+        let data = SctpDataChunk::from_chunk(&chunks[1]).expect("DATA chunk");
+        assert!(data.is_begin() && data.is_end() && !data.is_unordered());
+        assert_eq!(data.tsn, 100);
+        assert_eq!(data.stream_id, 1);
+        assert_eq!(data.ssn, 0);
+        assert_eq!(data.ppid, 3);
+        assert_eq!(data.data, b"ABC");
+    }
+
+    #[test]
+    fn sctp_invalid_chunk_length() {
+        // chunk length (0x40) is larger than the packet
+        let data = b"\x0b\x59\x0b\x59\x00\x00\x00\x00\x00\x00\x00\x00\x0b\x00\x00\x40";
+        let packet = SctpPacket::new(data).expect("SctpPacket");
+        assert_eq!(packet.get_chunks_iter().count(), 0);
+    }
+}
@@ -0,0 +1,809 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, trace, warn};
+use pako_tools::{
+    pcap_parser::{data::PacketData, Linktype},
+    Duration, Flow, FlowID, Packet, ParseContext,
+};
+
+use crate::{
+    analyzer::{send_sctp_messages, Analyzer},
+    ipv6_ext::Ipv6ExtensionChain,
+    sctp::*,
+};
+
+/// Delay (in seconds) after which a missing TSN, fragment or SSN is considered lost
+const SCTP_GAP_TIMEOUT: u64 = 10;
+/// Maximum number of TSNs, fragments or messages waiting for a missing one
+const SCTP_MAX_PENDING: usize = 1024;
+
+/// Association state, as seen by a passive observer
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SctpStatus {
+    #[default]
+    Closed = 0,
+    /// INIT seen, waiting for INIT ACK / COOKIE ECHO
+    CookieWait,
+    /// COOKIE ECHO seen, waiting for COOKIE ACK
+    CookieEchoed,
+    Established,
+    ShutdownSent,
+    ShutdownAckSent,
+}
+
+/// Association lifecycle events, sent to plugins as `FlowEvent::Sctp`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SctpAssociationEvent {
+    /// An INIT chunk was seen (association setup, or restart if the association was established)
+    Init { initiate_tag: u32 },
+    /// A COOKIE ACK was seen, the association is established
+    Established,
+    /// A SHUTDOWN chunk was seen, graceful close started
+    ShutdownStarted,
+    /// A SHUTDOWN COMPLETE chunk was seen, the association is closed
+    Closed,
+    /// An ABORT chunk was seen, the association is closed
+    Aborted,
+}
+
+/// Stream information attached to a SCTP user message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SctpDataInfo {
+    /// Stream Identifier
+    pub stream_id: u16,
+    /// Stream Sequence Number
+    pub ssn: u16,
+    /// Payload Protocol Identifier
+    pub ppid: u32,
+    /// true if the message was sent with the unordered flag
+    pub unordered: bool,
+    /// true if fragments of the message are missing (lost, or association ended)
+    pub partial: bool,
+}
+
+/// A complete (reassembled) user message
+#[derive(Debug)]
+pub struct SctpMessage {
+    pub info: SctpDataInfo,
+    pub data: Vec<u8>,
+    /// Index of the packet containing the first fragment
+    pub pcap_index: usize,
+    /// true if the message was sent by the client
+    pub to_server: bool,
+}
+
+/// Result of the update of an association with a new packet
+#[derive(Debug, Default)]
+pub struct SctpUpdate {
+    /// Lifecycle events, in order of chunks
+    pub events: Vec<SctpAssociationEvent>,
+    /// User messages ready to be delivered to upper layers, in delivery order
+    pub messages: Vec<SctpMessage>,
+}
+
+#[derive(Debug)]
+struct SctpFragment {
+    flags: u8,
+    stream_id: u16,
+    ssn: u16,
+    ppid: u32,
+    data: Vec<u8>,
+    pcap_index: usize,
+    ts: Duration,
+}
+
+#[derive(Default)]
+struct SctpStream {
+    /// Next expected Stream Sequence Number (ordered delivery)
+    next_ssn: Option<u16>,
+    /// Ordered messages received out of sequence, indexed by SSN
+    pending: HashMap<u16, SctpMessage>,
+    /// Time at which messages started waiting for the missing SSN
+    blocked_since: Option<Duration>,
+}
+
+impl SctpStream {
+    /// Move the messages following `next_ssn` in sequence to `v`
+    fn release(&mut self, v: &mut Vec<SctpMessage>) {
+        let Some(mut next_ssn) = self.next_ssn else {
+            return;
+        };
+        while let Some(m) = self.pending.remove(&next_ssn) {
+            v.push(m);
+            next_ssn = next_ssn.wrapping_add(1);
+        }
+        self.next_ssn = Some(next_ssn);
+    }
+}
+
+/// Return true if the gap started at `since` timed out, or too many items are waiting
+fn gap_expired(since: Duration, now: Duration, waiting: usize) -> bool {
+    (now - since).secs() > SCTP_GAP_TIMEOUT || waiting > SCTP_MAX_PENDING
+}
+
+/// One side (data sender) of an association
+#[derive(Default)]
+pub struct SctpPeer {
+    /// Lowest TSN not yet received
+    next_tsn: Option<u32>,
+    /// TSNs received above `next_tsn`
+    received: HashSet<u32>,
+    /// Time at which the TSN following `next_tsn` started missing
+    gap_since: Option<Duration>,
+    /// Fragments of incomplete user messages, indexed by TSN
+    fragments: HashMap<u32, SctpFragment>,
+    streams: HashMap<u16, SctpStream>,
+    /// true if the association setup was seen (SSN start at 0)
+    handshake_seen: bool,
+}
+
+impl SctpPeer {
+    fn reset(&mut self, initial_tsn: u32) {
+        *self = SctpPeer {
+            next_tsn: Some(initial_tsn),
+            handshake_seen: true,
+            ..SctpPeer::default()
+        };
+    }
+
+    /// Record TSN, and return false if it was already received
+    ///
+    /// TSNs still missing after the gap timeout are skipped, so `received` stays bounded.
+    fn check_tsn(&mut self, tsn: u32, now: Duration) -> bool {
+        let next_tsn = *self.next_tsn.get_or_insert(tsn);
+        // serial number arithmetic (RFC 1982)
+        if (tsn.wrapping_sub(next_tsn) as i32) < 0 || !self.received.insert(tsn) {
+            return false;
+        }
+        self.advance_tsn(next_tsn);
+        if self.received.is_empty() {
+            self.gap_since = None;
+            return true;
+        }
+        let since = *self.gap_since.get_or_insert(now);
+        if gap_expired(since, now, self.received.len()) {
+            let next_tsn = self.next_tsn.unwrap_or(tsn);
+            // safety: received is not empty
+            let first = *self
+                .received
+                .iter()
+                .min_by_key(|&&t| t.wrapping_sub(next_tsn))
+                .unwrap();
+            debug!("SCTP: skipping missing TSNs {}..{}", next_tsn, first);
+            self.advance_tsn(first);
+            self.gap_since = (!self.received.is_empty()).then_some(now);
+        }
+        true
+    }
+
+    /// Set the lowest TSN not yet received, starting from `next_tsn`
+    fn advance_tsn(&mut self, mut next_tsn: u32) {
+        while self.received.remove(&next_tsn) {
+            next_tsn = next_tsn.wrapping_add(1);
+        }
+        self.next_tsn = Some(next_tsn);
+    }
+
+    /// Add a DATA chunk, and return the messages ready for delivery
+    fn add_data(
+        &mut self,
+        chunk: &SctpDataChunk,
+        pcap_index: usize,
+        now: Duration,
+    ) -> Vec<SctpMessage> {
+        if !self.check_tsn(chunk.tsn, now) {
+            trace!("SCTP: duplicate TSN {}", chunk.tsn);
+            return Vec::new();
+        }
+        // messages with a lost fragment are delivered partial, to release their SSN
+        let mut v = self.expire_fragments(now);
+        let fragment = SctpFragment {
+            flags: chunk.flags,
+            stream_id: chunk.stream_id,
+            ssn: chunk.ssn,
+            ppid: chunk.ppid,
+            data: chunk.data.to_vec(),
+            pcap_index,
+            ts: now,
+        };
+        self.fragments.insert(chunk.tsn, fragment);
+        if let Some(msg) = self.assemble(chunk.tsn) {
+            v.extend(self.deliver(msg, now));
+        }
+        v
+    }
+
+    /// Deliver the fragments waiting for a missing TSN since the gap timeout
+    fn expire_fragments(&mut self, now: Duration) -> Vec<SctpMessage> {
+        let too_many = self.fragments.len() > SCTP_MAX_PENDING;
+        let expired: Vec<_> = self
+            .fragments
+            .iter()
+            .filter(|(_, f)| too_many || gap_expired(f.ts, now, 0))
+            .map(|(&tsn, _)| tsn)
+            .collect();
+        if expired.is_empty() {
+            return Vec::new();
+        }
+        debug!(
+            "SCTP: {} fragments of incomplete messages expired",
+            expired.len()
+        );
+        let mut v = Vec::new();
+        for msg in self.partial_messages(expired) {
+            v.extend(self.deliver(msg, now));
+        }
+        v
+    }
+
+    /// Remove fragments `tsns`, and build partial messages from the fragments of each message
+    fn partial_messages(&mut self, mut tsns: Vec<u32>) -> Vec<SctpMessage> {
+        let next_tsn = self.next_tsn.unwrap_or(0);
+        tsns.sort_by_key(|&t| t.wrapping_sub(next_tsn) as i32);
+        let mut v: Vec<SctpMessage> = Vec::new();
+        let mut prev: Option<(u32, u8)> = None;
+        for tsn in tsns {
+            // safety: tsns are keys of fragments
+            let f = self.fragments.remove(&tsn).unwrap();
+            let same_message = match (prev, v.last()) {
+                // fragments of an ordered message have the same SSN, even with a gap
+                (Some((prev_tsn, prev_flags)), Some(m)) => {
+                    (prev_tsn.wrapping_add(1) == tsn || !m.info.unordered)
+                        && prev_flags & SCTP_DATA_FLAG_END == 0
+                        && f.flags & SCTP_DATA_FLAG_BEGIN == 0
+                        && m.info.stream_id == f.stream_id
+                        && m.info.ssn == f.ssn
+                }
+                _ => false,
+            };
+            prev = Some((tsn, f.flags));
+            match v.last_mut() {
+                Some(m) if same_message => m.data.extend_from_slice(&f.data),
+                _ => v.push(SctpMessage {
+                    info: SctpDataInfo {
+                        stream_id: f.stream_id,
+                        ssn: f.ssn,
+                        ppid: f.ppid,
+                        unordered: f.flags & SCTP_DATA_FLAG_UNORDERED != 0,
+                        partial: true,
+                    },
+                    data: f.data,
+                    pcap_index: f.pcap_index,
+                    to_server: false,
+                }),
+            }
+        }
+        v
+    }
+
+    /// Try to build a complete message containing the fragment with this TSN
+    fn assemble(&mut self, tsn: u32) -> Option<SctpMessage> {
+        let mut first = tsn;
+        while self.fragments.get(&first)?.flags & SCTP_DATA_FLAG_BEGIN == 0 {
+            first = first.wrapping_sub(1);
+        }
+        let mut last = tsn;
+        while self.fragments.get(&last)?.flags & SCTP_DATA_FLAG_END == 0 {
+            last = last.wrapping_add(1);
+        }
+        // safety: all fragments between first and last are present
+        let head = self.fragments.remove(&first).unwrap();
+        let mut data = head.data;
+        let mut tsn = first;
+        while tsn != last {
+            tsn = tsn.wrapping_add(1);
+            let f = self.fragments.remove(&tsn).unwrap();
+            data.extend_from_slice(&f.data);
+        }
+        Some(SctpMessage {
+            info: SctpDataInfo {
+                stream_id: head.stream_id,
+                ssn: head.ssn,
+                ppid: head.ppid,
+                unordered: head.flags & SCTP_DATA_FLAG_UNORDERED != 0,
+                partial: false,
+            },
+            data,
+            pcap_index: head.pcap_index,
+            to_server: false,
+        })
+    }
+
+    /// Queue message in its stream, and return messages in SSN order
+    ///
+    /// SSNs still missing after the gap timeout are skipped.
+    fn deliver(&mut self, msg: SctpMessage, now: Duration) -> Vec<SctpMessage> {
+        if msg.info.unordered {
+            return vec![msg];
+        }
+        let handshake_seen = self.handshake_seen;
+        let stream_id = msg.info.stream_id;
+        let stream = self.streams.entry(stream_id).or_default();
+        let next_ssn =
+            *stream
+                .next_ssn
+                .get_or_insert(if handshake_seen { 0 } else { msg.info.ssn });
+        if (msg.info.ssn.wrapping_sub(next_ssn) as i16) < 0 {
+            warn!(
+                "SCTP: message for stream {} has old SSN {} (expected {})",
+                msg.info.stream_id, msg.info.ssn, next_ssn
+            );
+            return Vec::new();
+        }
+        stream.pending.insert(msg.info.ssn, msg);
+        let mut v = Vec::new();
+        stream.release(&mut v);
+        if stream.pending.is_empty() {
+            stream.blocked_since = None;
+            return v;
+        }
+        let since = *stream.blocked_since.get_or_insert(now);
+        if gap_expired(since, now, stream.pending.len()) {
+            let next_ssn = stream.next_ssn.unwrap_or(0);
+            // safety: pending is not empty
+            let first = *stream
+                .pending
+                .keys()
+                .min_by_key(|&&ssn| ssn.wrapping_sub(next_ssn))
+                .unwrap();
+            debug!(
+                "SCTP: stream {}: skipping missing SSNs {}..{}",
+                stream_id, next_ssn, first
+            );
+            stream.next_ssn = Some(first);
+            stream.release(&mut v);
+            stream.blocked_since = (!stream.pending.is_empty()).then_some(now);
+        }
+        v
+    }
+
+    /// Return all messages still waiting for a missing SSN or fragment, in stream and SSN order
+    ///
+    /// Incomplete messages are returned as partial messages.
+    fn flush(&mut self) -> Vec<SctpMessage> {
+        let mut v = Vec::new();
+        let tsns: Vec<_> = self.fragments.keys().copied().collect();
+        for msg in self.partial_messages(tsns) {
+            if msg.info.unordered {
+                v.push(msg);
+                continue;
+            }
+            let stream = self.streams.entry(msg.info.stream_id).or_default();
+            let next_ssn = *stream.next_ssn.get_or_insert(msg.info.ssn);
+            if (msg.info.ssn.wrapping_sub(next_ssn) as i16) >= 0 {
+                stream.pending.entry(msg.info.ssn).or_insert(msg);
+            }
+        }
+        for stream in self.streams.values_mut() {
+            let next_ssn = stream.next_ssn.unwrap_or(0);
+            let mut msgs: Vec<_> = stream.pending.drain().map(|(_, m)| m).collect();
+            msgs.sort_by_key(|m| m.info.ssn.wrapping_sub(next_ssn));
+            v.extend(msgs);
+            stream.blocked_since = None;
+        }
+        v.sort_by_key(|m| m.info.stream_id);
+        v
+    }
+}
+
+pub struct SctpAssociation {
+    pub client: SctpPeer,
+    pub server: SctpPeer,
+    pub status: SctpStatus,
+    pub last_seen_ts: Duration,
+}
+
+impl SctpAssociation {
+    pub fn new(flow: &Flow) -> Self {
+        SctpAssociation {
+            client: SctpPeer::default(),
+            server: SctpPeer::default(),
+            status: SctpStatus::Closed,
+            last_seen_ts: flow.last_seen,
+        }
+    }
+
+    /// Return the messages of both peers still waiting for a missing SSN or fragment
+    fn flush(&mut self) -> Vec<SctpMessage> {
+        let mut v = sent_by(self.client.flush(), true);
+        v.extend(sent_by(self.server.flush(), false));
+        v
+    }
+}
+
+/// Set the direction of the messages sent by one peer
+fn sent_by(mut messages: Vec<SctpMessage>, to_server: bool) -> Vec<SctpMessage> {
+    messages.iter_mut().for_each(|m| m.to_server = to_server);
+    messages
+}
+
+#[derive(Default)]
+pub struct SctpAssociations {
+    pub m: HashMap<FlowID, SctpAssociation>,
+}
+
+impl SctpAssociations {
+    /// Update the association of `flow` with all chunks of the packet
+    ///
+    /// Messages which are still waiting for a missing SSN or fragment are delivered
+    /// after a gap timeout, or when the association is closed, aborted or finalized.
+    pub(crate) fn update(
+        &mut self,
+        flow: &Flow,
+        sctp: &SctpPacket,
+        to_server: bool,
+        pcap_index: usize,
+    ) -> SctpUpdate {
+        trace!("5-t: {}", flow.five_tuple);
+        trace!("  flow id: {:x}", flow.flow_id);
+        trace!("  vtag: 0x{:08x}", sctp.get_verification_tag());
+
+        let assoc = self
+            .m
+            .entry(flow.flow_id)
+            .or_insert_with(|| SctpAssociation::new(flow));
+        assoc.last_seen_ts = flow.last_seen;
+        let now = assoc.last_seen_ts;
+
+        let mut update = SctpUpdate::default();
+        let mut remove = false;
+        for chunk in sctp.get_chunks_iter() {
+            let chunk_type = chunk.get_chunk_type();
+            trace!("  chunk type={} status={:?}", chunk_type.0, assoc.status);
+            let (origin, destination) = if to_server {
+                (&mut assoc.client, &mut assoc.server)
+            } else {
+                (&mut assoc.server, &mut assoc.client)
+            };
+            match chunk_type {
+                SctpChunkTypes::Data => {
+                    let data = match SctpDataChunk::from_chunk(&chunk) {
+                        Some(data) => data,
+                        None => {
+                            warn!("SCTP: invalid DATA chunk idx={}", pcap_index);
+                            continue;
+                        }
+                    };
+                    if assoc.status == SctpStatus::Closed {
+                        debug!("SCTP: DATA on unknown association, assuming established");
+                        assoc.status = SctpStatus::Established;
+                    }
+                    let messages = origin.add_data(&data, pcap_index, now);
+                    update.messages.extend(sent_by(messages, to_server));
+                }
+                SctpChunkTypes::Init | SctpChunkTypes::InitAck => {
+                    let init = match SctpInitChunk::from_chunk(&chunk) {
+                        Some(init) => init,
+                        None => {
+                            warn!("SCTP: invalid INIT chunk idx={}", pcap_index);
+                            continue;
+                        }
+                    };
+                    if chunk_type == SctpChunkTypes::Init {
+                        if assoc.status != SctpStatus::Closed {
+                            debug!("SCTP: INIT on existing association (restart?)");
+                            update.messages.extend(sent_by(origin.flush(), to_server));
+                            update
+                                .messages
+                                .extend(sent_by(destination.flush(), !to_server));
+                        }
+                        assoc.status = SctpStatus::CookieWait;
+                        update.events.push(SctpAssociationEvent::Init {
+                            initiate_tag: init.initiate_tag,
+                        });
+                    }
+                    // each side announces the initial TSN of the data it will send
+                    origin.reset(init.initial_tsn);
+                }
+                SctpChunkTypes::CookieEcho => {
+                    if assoc.status == SctpStatus::Closed || assoc.status == SctpStatus::CookieWait
+                    {
+                        assoc.status = SctpStatus::CookieEchoed;
+                    }
+                }
+                SctpChunkTypes::CookieAck => {
+                    if assoc.status != SctpStatus::Established {
+                        assoc.status = SctpStatus::Established;
+                        update.events.push(SctpAssociationEvent::Established);
+                    }
+                }
+                SctpChunkTypes::Shutdown => {
+                    if assoc.status != SctpStatus::ShutdownSent
+                        && assoc.status != SctpStatus::ShutdownAckSent
+                    {
+                        assoc.status = SctpStatus::ShutdownSent;
+                        update.events.push(SctpAssociationEvent::ShutdownStarted);
+                    }
+                }
+                SctpChunkTypes::ShutdownAck => {
+                    assoc.status = SctpStatus::ShutdownAckSent;
+                }
+                SctpChunkTypes::ShutdownComplete | SctpChunkTypes::Abort => {
+                    update.messages.extend(assoc.flush());
+                    assoc.status = SctpStatus::Closed;
+                    update.events.push(if chunk_type == SctpChunkTypes::Abort {
+                        SctpAssociationEvent::Aborted
+                    } else {
+                        SctpAssociationEvent::Closed
+                    });
+                    remove = true;
+                    break;
+                }
+                _ => (),
+            }
+        }
+        if remove {
+            self.m.remove(&flow.flow_id);
+        }
+        update
+    }
+}
+
+/// Deliver the messages still pending in all associations to plugins, and clear associations
+pub(crate) fn finalize_sctp_associations(analyzer: &mut Analyzer) {
+    let associations = std::mem::take(&mut analyzer.sctp_defrag.m);
+    for (flow_id, mut assoc) in associations {
+        if assoc.status != SctpStatus::Closed {
+            debug!(
+                "SCTP association {:x} still open (status {:?})",
+                flow_id, assoc.status
+            );
+        }
+        let messages = assoc.flush();
+        if messages.is_empty() {
+            continue;
+        }
+        let flow = match analyzer.flows.get_flow(flow_id) {
+            Some(flow) => flow.clone(),
+            None => {
+                warn!("SCTP association {:x} has no flow", flow_id);
+                continue;
+            }
+        };
+        let ipv6_extensions = Ipv6ExtensionChain::default();
+        for msg in messages {
+            let packet = Packet {
+                interface: 0,
+                ts: assoc.last_seen_ts,
+                link_type: Linktype::RAW,
+                data: PacketData::L4(flow.five_tuple.proto, &[]),
+                caplen: 0,
+                origlen: 0,
+                pcap_index: msg.pcap_index,
+            };
+            let ctx = ParseContext {
+                pcap_index: msg.pcap_index,
+                ..ParseContext::default()
+            };
+            let res = send_sctp_messages(
+                &packet,
+                &ctx,
+                &flow,
+                &ipv6_extensions,
+                &[],
+                vec![msg],
+                analyzer,
+            );
+            if let Err(e) = res {
+                warn!("SCTP: could not deliver message of {:x}: {}", flow_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use pako_tools::{Config, FiveTuple, Flow, PcapAnalyzer};
+
+    use super::*;
+    use crate::{
+        packet_info::PacketInfo,
+        plugin::{Plugin, PluginResult, PLUGIN_L4},
+        plugin_registry::PluginRegistry,
+    };
+
+    const HEADER: &[u8] = b"\x0b\x59\x0b\x59\x00\x00\x00\x00\x00\x00\x00\x00";
+
+    fn init_packet(initial_tsn: u32) -> Vec<u8> {
+        let mut v = HEADER.to_vec();
+        v.extend_from_slice(b"\x01\x00\x00\x14\xaa\xbb\xcc\xdd\x00\x01\x00\x00\x00\x0a\x00\x0a");
+        v.extend_from_slice(&initial_tsn.to_be_bytes());
+        v
+    }
+
+    fn data_packet(flags: u8, tsn: u32, ssn: u16, data: u8) -> Vec<u8> {
+        let mut v = HEADER.to_vec();
+        v.extend_from_slice(&[0x00, flags, 0x00, 0x11]);
+        v.extend_from_slice(&tsn.to_be_bytes());
+        v.extend_from_slice(&[0x00, 0x01]);
+        v.extend_from_slice(&ssn.to_be_bytes());
+        v.extend_from_slice(&[0x00, 0x00, 0x00, 0x03, data, 0x00, 0x00, 0x00]);
+        v
+    }
+
+    const ABORT: &[u8] = b"\x06\x00\x00\x04";
+
+    /// Send packets to the client side, with their timestamp in seconds
+    fn run(assocs: &mut SctpAssociations, packets: &[(u64, Vec<u8>)]) -> Vec<SctpMessage> {
+        let mut flow = Flow::new(&FiveTuple::default(), Duration::default());
+        flow.flow_id = 1;
+        let mut delivered = Vec::new();
+        for (idx, (secs, p)) in packets.iter().enumerate() {
+            flow.last_seen = Duration::from_secs(*secs);
+            let sctp = SctpPacket::new(p).expect("SctpPacket");
+            delivered.extend(assocs.update(&flow, &sctp, true, idx).messages);
+        }
+        delivered
+    }
+
+    #[test]
+    fn sctp_lost_tsn() {
+        let mut assocs = SctpAssociations::default();
+        let complete = SCTP_DATA_FLAG_BEGIN | SCTP_DATA_FLAG_END;
+        let delivered = run(
+            &mut assocs,
+            &[
+                (0, init_packet(10)),
+                (1, data_packet(complete, 10, 0, b'a')),
+                // TSN 11 (SSN 1) is lost
+                (2, data_packet(complete, 12, 2, b'c')),
+                (3, data_packet(complete, 13, 3, b'd')),
+            ],
+        );
+        let data: Vec<_> = delivered.iter().map(|m| m.data.clone()).collect();
+        assert_eq!(data, vec![b"a".to_vec()]);
+
+        // after the gap timeout, the missing TSN and SSN are skipped
+        let delivered = run(&mut assocs, &[(20, data_packet(complete, 14, 4, b'e'))]);
+        let data: Vec<_> = delivered.iter().map(|m| m.data.clone()).collect();
+        assert_eq!(data, vec![b"c".to_vec(), b"d".to_vec(), b"e".to_vec()]);
+        assert!(delivered.iter().all(|m| m.to_server && !m.info.partial));
+        let client = &assocs.m[&1].client;
+        assert!(client.received.is_empty());
+        assert_eq!(client.next_tsn, Some(15));
+        assert!(client.streams[&1].pending.is_empty());
+    }
+
+    #[test]
+    fn sctp_lost_fragment() {
+        let mut assocs = SctpAssociations::default();
+        let complete = SCTP_DATA_FLAG_BEGIN | SCTP_DATA_FLAG_END;
+        let delivered = run(
+            &mut assocs,
+            &[
+                (0, init_packet(10)),
+                // SSN 0 in three fragments, the second one is lost
+                (1, data_packet(SCTP_DATA_FLAG_BEGIN, 10, 0, b'a')),
+                (1, data_packet(0, 12, 0, b'c')),
+                (2, data_packet(complete, 13, 1, b'd')),
+                // the incomplete message expires, and is delivered partial
+                (20, data_packet(complete, 14, 2, b'e')),
+                // SSN 3 waits for its last fragment when the association is aborted
+                (21, data_packet(SCTP_DATA_FLAG_BEGIN, 15, 3, b'f')),
+                (21, data_packet(complete, 17, 4, b'h')),
+                (22, [HEADER, ABORT].concat()),
+            ],
+        );
+        let data: Vec<_> = delivered
+            .iter()
+            .map(|m| (m.data.clone(), m.info.partial))
+            .collect();
+        assert_eq!(
+            data,
+            vec![
+                (b"ac".to_vec(), true),
+                (b"d".to_vec(), false),
+                (b"e".to_vec(), false),
+                (b"f".to_vec(), true),
+                (b"h".to_vec(), false),
+            ]
+        );
+        assert!(assocs.m.is_empty());
+    }
+
+    /// Record the SCTP messages sent to plugins
+    #[derive(Default)]
+    struct MessageRecorder {
+        messages: Vec<(Vec<u8>, bool, usize)>,
+    }
+
+    impl Plugin for MessageRecorder {
+        fn name(&self) -> &'static str {
+            "MessageRecorder"
+        }
+
+        fn plugin_type(&self) -> u16 {
+            PLUGIN_L4
+        }
+
+        fn handle_layer_transport<'s, 'i>(
+            &'s mut self,
+            _packet: &'s Packet,
+            pinfo: &PacketInfo,
+        ) -> PluginResult<'i> {
+            if let (Some(info), Some(data)) = (pinfo.sctp, pinfo.l4_payload) {
+                self.messages
+                    .push((data.to_vec(), info.partial, pinfo.pcap_index));
+            }
+            PluginResult::None
+        }
+    }
+
+    #[test]
+    fn sctp_finalize_pending() {
+        let recorder = Arc::new(Mutex::new(MessageRecorder::default()));
+        let mut registry = PluginRegistry::new();
+        let id = registry.add_plugin(recorder.clone());
+        registry.register_layer(4, 132, id).unwrap();
+        let mut analyzer = Analyzer::new(Arc::new(registry), &Config::default());
+        analyzer.init().expect("init");
+        let complete = SCTP_DATA_FLAG_BEGIN | SCTP_DATA_FLAG_END;
+        let packets = [
+            init_packet(10),
+            // SSN 0 is incomplete, SSN 1 waits for it
+            data_packet(SCTP_DATA_FLAG_BEGIN, 10, 0, b'a'),
+            data_packet(complete, 12, 1, b'c'),
+        ];
+        for (idx, sctp) in packets.iter().enumerate() {
+            let mut data =
+                b"\x45\x00\x00\x00\x00\x01\x00\x00\x40\x84\x00\x00\x0a\x00\x00\x01\x0a\x00\x00\x02"
+                    .to_vec();
+            data.extend_from_slice(sctp);
+            let len = data.len() as u16;
+            data[2..4].copy_from_slice(&len.to_be_bytes());
+            let packet = Packet {
+                interface: 0,
+                ts: Duration::from_secs(1),
+                link_type: Linktype::RAW,
+                data: PacketData::L3(0x0800, &data),
+                caplen: data.len() as u32,
+                origlen: data.len() as u32,
+                pcap_index: idx + 1,
+            };
+            let ctx = ParseContext {
+                pcap_index: idx + 1,
+                ..ParseContext::default()
+            };
+            analyzer.handle_packet(&packet, &ctx).expect("packet");
+        }
+        assert!(recorder.lock().unwrap().messages.is_empty());
+        analyzer.teardown();
+        assert_eq!(
+            recorder.lock().unwrap().messages,
+            vec![(b"a".to_vec(), true, 2), (b"c".to_vec(), false, 3)]
+        );
+    }
+
+    #[test]
+    fn sctp_stream_ordering() {
+        let mut flow = Flow::new(&FiveTuple::default(), Duration::default());
+        flow.flow_id = 1;
+        let mut assocs = SctpAssociations::default();
+        let packets = [
+            init_packet(10),
+            // SSN 1, in two fragments received in reverse order
+            data_packet(SCTP_DATA_FLAG_END, 12, 1, b'c'),
+            data_packet(SCTP_DATA_FLAG_BEGIN, 11, 1, b'b'),
+            // SSN 0, then a retransmission
+            data_packet(SCTP_DATA_FLAG_BEGIN | SCTP_DATA_FLAG_END, 10, 0, b'a'),
+            data_packet(SCTP_DATA_FLAG_BEGIN | SCTP_DATA_FLAG_END, 10, 0, b'a'),
+        ];
+        let mut events = Vec::new();
+        let mut delivered = Vec::new();
+        for (idx, p) in packets.iter().enumerate() {
+            let sctp = SctpPacket::new(p).expect("SctpPacket");
+            let update = assocs.update(&flow, &sctp, true, idx);
+            events.extend(update.events);
+            delivered.extend(update.messages.into_iter().map(|m| m.data));
+        }
+        assert_eq!(
+            events,
+            vec![SctpAssociationEvent::Init {
+                initiate_tag: 0xaabb_ccdd
+            }]
+        );
+        assert_eq!(delivered, vec![b"a".to_vec(), b"bc".to_vec()]);
+    }
+}
@@ -117,7 +117,7 @@ impl Default for TcpStreamReassembly {
     fn default() -> Self {
         TcpStreamReassembly {
             m: HashMap::new(),
-            timeout: Duration::new(14400, 0),
+            timeout: Duration::from_secs(14400),
         }
     }
 }
@@ -970,7 +970,7 @@ impl TcpStreamReassembly {
 
 pub(crate) fn finalize_tcp_streams(analyzer: &mut crate::analyzer::Analyzer) {
     warn!("expiring all TCP connections");
-    for (flow_id, _stream) in analyzer.tcp_defrag.m.iter() {
+    for flow_id in analyzer.tcp_defrag.m.keys() {
         // TODO do we have anything to do?
         if let Some(flow) = analyzer.flows.get_flow(*flow_id) {
             debug!("  flow: {:?}", flow);
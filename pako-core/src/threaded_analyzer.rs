@@ -1,26 +1,132 @@
 use std::{
-    cmp::min,
+    ops::{Deref, Range},
     panic::AssertUnwindSafe,
-    sync::{Arc, Barrier},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Barrier,
+    },
     thread,
 };
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use log::{debug, info, trace, warn};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::{debug, trace, warn};
 use pako_tools::*;
-use pcap_parser::data::PacketData;
-use pnet_packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use pcap_parser::{data::PacketData, Linktype};
+use pnet_packet::ethernet::EtherType;
 
 use crate::{
-    analyzer::{handle_l3, run_plugins_v2_link, run_plugins_v2_physical, Analyzer},
-    layers::LinkLayerType,
+    analyzer::{finish_plugins, fixup_packet_data, handle_l2, handle_l3, handle_l4, Analyzer},
+    buffer_pool::{BufferPool, PooledBuffer},
+    fanout::FanOut,
     plugin_registry::PluginRegistry,
 };
 
-pub enum Job<'a> {
+/// Maximum number of pending jobs for each worker
+///
+/// When a queue is full, the main thread blocks until the worker catches up.
+const JOB_QUEUE_SIZE: usize = 1024;
+
+/// Layer of the data of a `PacketJob`
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum JobLayer {
+    /// Ethernet frame
+    L2,
+    /// Network layer, with the given type
+    L3(EtherType),
+    /// Transport layer, with the given protocol
+    L4(u8),
+}
+
+/// Data of a `PacketJob`
+enum JobData {
+    /// Copy of the packet data
+    Copied(PooledBuffer),
+    /// Packet data in a memory-mapped capture, shared without copy
+    Mapped(MappedCapture, Range<usize>),
+}
+
+impl Deref for JobData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            JobData::Copied(buf) => buf,
+            JobData::Mapped(capture, range) => &capture.data()[range.clone()],
+        }
+    }
+}
+
+/// Packet sent to a worker
+///
+/// The packet data is copied to an owned buffer, so the packet source is free to
+/// reuse or move its own buffers as soon as the packet is dispatched. If the source is a
+/// memory-mapped capture, the job only keeps a reference to the mapping.
+///
+/// The job keeps the parsing context of its packet, since the packet can be held back
+/// (for ex. IP fragments) and sent after other packets.
+pub struct PacketJob {
+    ctx: ParseContext,
+    interface: u32,
+    ts: Duration,
+    link_type: Linktype,
+    caplen: u32,
+    origlen: u32,
+    pcap_index: usize,
+    data: JobData,
+    layer: JobLayer,
+}
+
+impl PacketJob {
+    fn new(packet: &Packet, ctx: &ParseContext, data: JobData, layer: JobLayer) -> Self {
+        PacketJob {
+            ctx: ctx.clone(),
+            interface: packet.interface,
+            ts: packet.ts,
+            link_type: packet.link_type,
+            caplen: packet.caplen,
+            origlen: packet.origlen,
+            pcap_index: packet.pcap_index,
+            data,
+            layer,
+        }
+    }
+
+    /// Rebuild the packet, borrowing data from the job buffer
+    fn packet(&self) -> Packet<'_> {
+        let data = match self.layer {
+            JobLayer::L2 => PacketData::L2(&self.data),
+            JobLayer::L3(ethertype) => PacketData::L3(ethertype.0, &self.data),
+            JobLayer::L4(proto) => PacketData::L4(proto, &self.data),
+        };
+        Packet {
+            interface: self.interface,
+            ts: self.ts,
+            link_type: self.link_type,
+            data,
+            caplen: self.caplen,
+            origlen: self.origlen,
+            pcap_index: self.pcap_index,
+        }
+    }
+
+    pub(crate) fn ts(&self) -> Duration {
+        self.ts
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn layer(&self) -> JobLayer {
+        self.layer
+    }
+}
+
+pub enum Job {
+    /// Expire all flows, and stop worker
     Exit,
     PrintDebug,
-    New(Packet<'a>, ParseContext, &'a [u8], EtherType),
+    New(PacketJob),
     Wait,
 }
 
@@ -31,41 +137,65 @@ pub struct Worker {
 
 /// Pcap/Pcap-ng Multi-threaded analyzer
 ///
-pub struct ThreadedAnalyzer<'a> {
+/// Packets are copied to pooled buffers before being sent to workers, so the
+/// analyzer does not depend on the lifetime of the data provided by the packet source.
+/// When the source is a memory-mapped file (see `MmapEngine`), packets borrow the mapping
+/// for the entire run and are dispatched without copy.
+///
+/// Packets are dispatched using a symmetric hash of the (inner) 5-tuple, see `FanOut`
+/// for the configuration options. All layers, including L2 plugins, are handled by workers.
+pub struct ThreadedAnalyzer {
     registry: Arc<PluginRegistry>,
-    /// create a local analyzer, so L2 packets can be handled without
-    /// dispatching them to threads
+    /// registries used by workers (identical to `registry`, unless plugins have per-worker instances)
+    worker_registries: Vec<Arc<PluginRegistry>>,
+    /// local analyzer, holding state shared with workers
     analyzer: Analyzer,
 
-    local_jobs: Vec<Sender<Job<'a>>>,
+    local_jobs: Vec<Sender<Job>>,
     workers: Vec<Worker>,
     barrier: Arc<Barrier>,
+    /// set by workers when an error occurs in strict mode
+    failed: Arc<AtomicBool>,
+    pool: BufferPool,
+    /// memory-mapped input, if any
+    mapping: Option<MappedCapture>,
+    fanout: FanOut,
+    /// jobs ready to be sent (reused to avoid allocations)
+    ready: Vec<(usize, PacketJob)>,
 }
 
-impl<'a> ThreadedAnalyzer<'a> {
+impl ThreadedAnalyzer {
     pub fn new(registry: PluginRegistry, config: &Config) -> Self {
         let n_workers = config
             .get_usize("num_threads")
             .map_or_else(num_cpus::get, |n| if n == 0 { num_cpus::get() } else { n });
         let barrier = Arc::new(Barrier::new(n_workers + 1));
+        let failed = Arc::new(AtomicBool::new(false));
         let registry = Arc::new(registry);
         let analyzer = Analyzer::new(registry.clone(), config);
 
         let mut workers = Vec::new();
         let mut local_jobs = Vec::new();
+        let mut worker_registries = Vec::new();
         for idx in 0..n_workers {
             let n = format!("worker {idx}");
-            let a = Analyzer::new(registry.clone(), config);
-            let (sender, receiver) = unbounded();
-            // NOTE: remove job queue from lifetime management, it must be made 'static
-            // to be sent to threads
-            let r: Receiver<Job<'static>> = unsafe { ::std::mem::transmute(receiver) };
+            let worker_registry = if registry.has_worker_plugins() {
+                Arc::new(registry.build_worker_registry(config))
+            } else {
+                registry.clone()
+            };
+            worker_registries.push(worker_registry.clone());
+            let mut a = Analyzer::new(worker_registry, config);
+            // all workers share the same counters
+            a.context = analyzer.context.clone();
+            let (sender, r) = bounded(JOB_QUEUE_SIZE);
             let barrier = barrier.clone();
+            let failed = failed.clone();
             let builder = thread::Builder::new();
             let handler = builder
                 .name(n)
                 .spawn(move || {
-                    worker(a, idx, r, barrier);
+                    worker(a, idx, r, barrier, failed);
                 })
                 .unwrap();
             let worker = Worker { _id: idx, handler };
@@ -75,10 +205,17 @@ impl<'a> ThreadedAnalyzer<'a> {
 
         ThreadedAnalyzer {
             registry,
+            worker_registries,
             analyzer,
             local_jobs,
             workers,
             barrier,
+            failed,
+            // enough free buffers for all queued jobs
+            pool: BufferPool::new(n_workers * JOB_QUEUE_SIZE),
+            mapping: None,
+            fanout: FanOut::new(n_workers, config),
+            ready: Vec::new(),
         }
     }
 
@@ -94,97 +231,91 @@ impl<'a> ThreadedAnalyzer<'a> {
         self.barrier.wait();
     }
 
-    fn dispatch(&mut self, packet: Packet<'static>, ctx: &ParseContext) -> Result<(), Error> {
-        match packet.data {
-            PacketData::L2(data) => self.handle_l2(packet, ctx, data),
-            PacketData::L3(ethertype, data) => {
-                extern_dispatch_l3(&self.local_jobs, packet, ctx, data, EtherType(ethertype))
-            }
-            PacketData::L4(_, _) => {
-                warn!("Unsupported packet data layer 4");
-                unimplemented!() // XXX
-            }
+    fn dispatch(&mut self, packet: &Packet, ctx: &ParseContext) -> Result<(), Error> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Err(Error::Generic("Error in worker thread (strict mode)"));
+        }
+        self.analyzer.context.error_stats.add_packet();
+        let (data, layer) = match fixup_packet_data(packet) {
+            PacketData::L2(data) => (data, JobLayer::L2),
+            PacketData::L3(ethertype, data) => (data, JobLayer::L3(EtherType(ethertype))),
+            PacketData::L4(proto, data) => (data, JobLayer::L4(proto)),
             PacketData::Unsupported(_) => {
-                warn!("Unsupported linktype");
-                unimplemented!() // XXX
+                warn!(
+                    "Unsupported data format (unknown linktype {}) idx={}",
+                    packet.link_type, ctx.pcap_index
+                );
+                let e = Error::unsupported(Layer::Link, "link type");
+                return self.analyzer.handle_error(ctx, e);
             }
-        }
+        };
+        let data = match self
+            .mapping
+            .as_ref()
+            .and_then(|capture| Some((capture, capture.range_of(data)?)))
+        {
+            Some((capture, range)) => JobData::Mapped(capture.clone(), range),
+            None => JobData::Copied(self.pool.copy_from_slice(data)),
+        };
+        let job = PacketJob::new(packet, ctx, data, layer);
+        self.fanout.assign(job, &mut self.ready);
+        self.send_ready_jobs()
     }
 
-    fn handle_l2(
-        &mut self,
-        packet: Packet<'static>,
-        ctx: &ParseContext,
-        data: &'static [u8],
-    ) -> Result<(), Error> {
-        trace!("handle_l2 (idx={})", ctx.pcap_index);
-        // resize slice to remove padding
-        let datalen = min(packet.caplen as usize, data.len());
-        let data = &data[..datalen];
-
-        // let start = ::std::time::Instant::now();
-        run_plugins_v2_physical(&packet, ctx, data, &mut self.analyzer)?;
-        // let elapsed = start.elapsed();
-        // debug!("Time to run l2 plugins: {}.{}", elapsed.as_secs(), elapsed.as_millis());
-
-        match EthernetPacket::new(data) {
-            Some(eth) => {
-                // debug!("    source: {}", eth.get_source());
-                // debug!("    dest  : {}", eth.get_destination());
-                match &data[..6] {
-                    [0x01, 0x00, 0x0c, 0xcc, 0xcc, 0xcc] => {
-                        info!("Cisco CDP/VTP/UDLD - ignoring");
-                        // the 'ethertype' field is used for length
-                        return Ok(());
-                    }
-                    [0x01, 0x00, 0x0c, 0xcd, 0xcd, 0xd0] => {
-                        info!("Cisco Multicast address - ignoring");
-                        return Ok(());
-                    }
-                    _ => {
-                        info!("Ethernet broadcast (unknown type) (idx={})", ctx.pcap_index);
-                    }
-                }
-                let ethertype = eth.get_ethertype();
-                let payload = &data[14..];
-                trace!("    ethertype: 0x{:x}", ethertype.0);
-                run_plugins_v2_link(
-                    &packet,
-                    ctx,
-                    LinkLayerType::Ethernet,
-                    payload,
-                    &mut self.analyzer,
-                )?;
-                extern_dispatch_l3(&self.local_jobs, packet, ctx, payload, ethertype)
-            }
-            None => {
-                // packet too small to be ethernet
-                Ok(())
-            }
+    fn send_ready_jobs(&mut self) -> Result<(), Error> {
+        for (i, job) in self.ready.drain(..) {
+            debug_assert!(i < self.local_jobs.len());
+            self.local_jobs[i]
+                .send(Job::New(job))
+                .or(Err(Error::Generic("Error while sending job")))?;
         }
+        Ok(())
     }
 }
 
-impl<'a> PcapAnalyzer for ThreadedAnalyzer<'a> {
+impl PcapAnalyzer for ThreadedAnalyzer {
     fn init(&mut self) -> Result<(), Error> {
+        self.analyzer.init_shared_state()?;
         self.registry.run_plugins(|_| true, |p| p.pre_process());
+        if self.registry.has_worker_plugins() {
+            let analyzer = &self.analyzer;
+            for r in &self.worker_registries {
+                r.run_worker_plugins(|p| {
+                    analyzer.share_state(p);
+                    p.pre_process();
+                });
+            }
+        }
 
         Ok(())
     }
 
+    /// Read TLS secrets, statistics and names from blocks, before dispatching the next packets
+    fn handle_block(
+        &mut self,
+        block: &PcapBlockOwned,
+        block_ctx: &ParseBlockContext,
+    ) -> Result<(), Error> {
+        self.analyzer.handle_block(block, block_ctx)
+    }
+
     fn handle_packet(&mut self, packet: &Packet, ctx: &ParseContext) -> Result<(), Error> {
-        // NOTE: remove packet from lifetime management, it must be made 'static
-        // to be sent to threads
-        let packet: Packet<'static> = unsafe { ::std::mem::transmute(packet.clone()) };
-        self.dispatch(packet, ctx)?;
-        Ok(())
+        self.dispatch(packet, ctx)
+    }
+
+    fn set_mapped_capture(&mut self, capture: &MappedCapture) {
+        self.mapping = Some(capture.clone());
     }
 
     fn teardown(&mut self) {
         debug!("main: exit");
+        // send fragments still waiting for the first fragment of their datagram
+        self.fanout.flush(&mut self.ready);
+        if self.send_ready_jobs().is_err() {
+            warn!("Could not send pending fragments to workers");
+        }
         self.wait_for_empty_jobs();
         for job in self.local_jobs.iter() {
-            // XXX expire flows?
             job.send(Job::PrintDebug).expect("Error while sending job");
             job.send(Job::Exit).expect("Error while sending job");
         }
@@ -193,121 +324,63 @@ impl<'a> PcapAnalyzer for ThreadedAnalyzer<'a> {
         }
         self.local_jobs.clear();
         debug!("main: all workers ended");
-
-        self.registry.run_plugins(|_| true, |p| p.post_process());
-    }
-
-    fn before_refill(&mut self) {
-        self.wait_for_empty_jobs();
-        trace!("threads synchronized, refill");
-    }
-}
-
-pub(crate) fn extern_dispatch_l3<'a>(
-    jobs: &[Sender<Job<'a>>],
-    packet: Packet<'a>,
-    ctx: &ParseContext,
-    data: &'a [u8],
-    ethertype: EtherType,
-) -> Result<(), Error> {
-    let n_workers = jobs.len();
-    let i = fan_out(data, ethertype, n_workers);
-    debug_assert!(i < n_workers);
-    jobs[i]
-        .send(Job::New(packet, ctx.clone(), data, ethertype))
-        .or(Err(Error::Generic("Error while sending job")))
-}
-
-fn fan_out(data: &[u8], ethertype: EtherType, n_workers: usize) -> usize {
-    match ethertype {
-        EtherTypes::Ipv4 => {
-            if data.len() >= 20 {
-                // let src = &data[12..15];
-                // let dst = &data[16..19];
-                // let proto = data[9];
-                // (src[0] ^ dst[0] ^ proto) as usize % n_workers
-                let mut buf: [u8; 20] = [0; 20];
-                let sz = 4;
-                buf[0] = data[12] ^ data[16];
-                buf[1] = data[13] ^ data[17];
-                buf[2] = data[14] ^ data[18];
-                buf[3] = data[15] ^ data[19];
-                // we may append source and destination ports
-                // XXX breaks fragmentation
-                // if data[9] == crate::plugin::TRANSPORT_TCP || data[9] == crate::plugin::TRANSPORT_UDP {
-                //     if data.len() >= 24 {
-                //         // source port, in network-order
-                //         buf[8] = data[20];
-                //         buf[9] = data[21];
-                //         // destination port, in network-order
-                //         buf[10] = data[22];
-                //         buf[11] = data[23];
-                //         sz = 12;
-                //     }
-                // }
-                // let hash = crate::toeplitz::toeplitz_hash(crate::toeplitz::KEY, &buf[..sz]);
-                let hash = seahash::hash(&buf[..sz]);
-                // debug!("{:?} -- hash --> 0x{:x}", buf, hash);
-                // ((hash >> 24) ^ (hash & 0xff)) as usize % n_workers
-                hash as usize % n_workers
-            } else {
-                n_workers - 1
-            }
+        debug!("Checksums: {}", self.analyzer.context.checksum_stats);
+        if self.analyzer.num_errors() > 0 {
+            warn!(
+                "{} packets could not be handled",
+                self.analyzer.num_errors()
+            );
         }
-        EtherTypes::Ipv6 => {
-            if data.len() >= 40 {
-                let mut buf: [u8; 40] = [0; 40];
-                // let sz = 32;
-                // source IP + destination IP, in network-order
-                // buf[0..32].copy_from_slice(&data[8..40]);
-                let sz = 16;
-                for i in 0..16 {
-                    buf[i] = data[8 + i] ^ data[24 + i];
-                }
-                // we may append source and destination ports
-                // XXX breaks fragmentation
-                // if data[6] == crate::plugin::TRANSPORT_TCP || data[6] == crate::plugin::TRANSPORT_UDP {
-                //     if data.len() >= 44 {
-                //         // source port, in network-order
-                //         buf[33] = data[40];
-                //         buf[34] = data[41];
-                //         // destination port, in network-order
-                //         buf[35] = data[42];
-                //         buf[36] = data[43];
-                //         sz += 4;
-                //     }
-                // }
-                // let hash = crate::toeplitz::toeplitz_hash(crate::toeplitz::KEY, &buf[..sz]);
-                let hash = seahash::hash(&buf[..sz]);
-                // debug!("{:?} -- hash --> 0x{:x}", buf, hash);
-                // ((hash >> 24) ^ (hash & 0xff)) as usize % n_workers
-                hash as usize % n_workers
-            } else {
-                n_workers - 1
-            }
+        debug!("Errors: {}", self.analyzer.context.error_stats);
+
+        for r in self.worker_registries.drain(..) {
+            self.registry.merge_worker_registry(&r);
         }
-        _ => 0,
+
+        finish_plugins(&self.registry, self.analyzer.output_dir());
     }
 }
 
-fn worker(mut a: Analyzer, idx: usize, r: Receiver<Job>, barrier: Arc<Barrier>) {
+fn worker(
+    mut a: Analyzer,
+    idx: usize,
+    r: Receiver<Job>,
+    barrier: Arc<Barrier>,
+    failed: Arc<AtomicBool>,
+) {
     debug!("worker thread {} starting", idx);
     let mut pcap_index = 0;
     let res = ::std::panic::catch_unwind(AssertUnwindSafe(|| loop {
         if let Ok(msg) = r.recv() {
             match msg {
-                Job::Exit => break,
+                Job::Exit => {
+                    // each worker owns a part of the flows
+                    a.finalize_flows();
+                    break;
+                }
                 Job::PrintDebug => {
                     {
                         debug!("thread {}: hash table size: {}", idx, a.flows.len());
                     };
                 }
-                Job::New(packet, ctx, data, ethertype) => {
+                Job::New(job) => {
+                    let ctx = &job.ctx;
                     pcap_index = ctx.pcap_index;
                     trace!("thread {}: got a job", idx);
-                    let h3_res = handle_l3(&packet, &ctx, data, ethertype, &mut a);
-                    if h3_res.is_err() {
-                        warn!("thread {}: handle_l3 failed", idx);
+                    let packet = job.packet();
+                    let res = match job.layer {
+                        JobLayer::L2 => handle_l2(&packet, ctx, job.data(), &mut a),
+                        JobLayer::L3(ethertype) => {
+                            handle_l3(&packet, ctx, job.data(), ethertype, &mut a)
+                        }
+                        JobLayer::L4(proto) => handle_l4(&packet, ctx, job.data(), proto, &mut a),
+                    };
+                    if let Err(e) = res.or_else(|e| a.handle_error(ctx, e)) {
+                        warn!(
+                            "thread {}: packet handling failed: {} (idx={})",
+                            idx, e, ctx.pcap_index
+                        );
+                        failed.store(true, Ordering::Relaxed);
                     }
                 }
                 Job::Wait => {
@@ -334,11 +407,177 @@ fn worker(mut a: Analyzer, idx: usize, r: Receiver<Job>, barrier: Arc<Barrier>)
 
 #[cfg(test)]
 mod tests {
-    use std::mem;
+    use std::{
+        mem,
+        sync::{Arc, Mutex},
+    };
+
+    use pako_tools::{
+        pcap_parser::{data::PacketData, Linktype},
+        Config, Duration, Flow, Packet, ParseContext, PcapAnalyzer,
+    };
+    use pnet_packet::ethernet::EtherType;
+
+    use super::{Job, JobData, JobLayer, PacketJob, ThreadedAnalyzer};
+    use crate::{
+        analysis_context::AnalysisContext,
+        buffer_pool::BufferPool,
+        fanout::FanOut,
+        flow_event::FlowEvent,
+        plugin::{Plugin, PLUGIN_FLOW_EVENT},
+        plugin_registry::PluginRegistry,
+    };
+
+    /// Count ICMP errors attached to an UDP flow
+    #[derive(Default)]
+    struct IcmpErrorCounter {
+        errors: usize,
+    }
+
+    impl Plugin for IcmpErrorCounter {
+        fn name(&self) -> &'static str {
+            "IcmpErrorCounter"
+        }
+
+        fn plugin_type(&self) -> u16 {
+            PLUGIN_FLOW_EVENT
+        }
+
+        fn flow_event(&mut self, flow: &Flow, event: &FlowEvent) {
+            if let FlowEvent::IcmpError(e) = event {
+                assert_eq!(flow.five_tuple.proto, 17);
+                assert_eq!(e.quoted.dst_port, 53);
+                self.errors += 1;
+            }
+        }
+    }
+
+    /// Read the shared counters at the end of the analysis
+    #[derive(Default)]
+    struct ContextReader {
+        context: Option<Arc<AnalysisContext>>,
+        skipped_checksums: u64,
+        packets: u64,
+    }
+
+    impl Plugin for ContextReader {
+        fn name(&self) -> &'static str {
+            "ContextReader"
+        }
+
+        fn set_context(&mut self, context: Arc<AnalysisContext>) {
+            self.context = Some(context);
+        }
+
+        fn post_process(&mut self) {
+            let context = self.context.as_ref().expect("context not set");
+            self.skipped_checksums = context.checksum_stats().ipv4.skipped();
+            self.packets = context.error_stats().packets();
+        }
+    }
+
+    fn ipv4(src: [u8; 4], dst: [u8; 4], proto: u8, payload: &[u8]) -> Vec<u8> {
+        let len = (20 + payload.len()) as u16;
+        let mut v = vec![0x45, 0];
+        v.extend_from_slice(&len.to_be_bytes());
+        v.extend_from_slice(&[0, 1, 0, 0, 64, proto, 0, 0]);
+        v.extend_from_slice(&src);
+        v.extend_from_slice(&dst);
+        v.extend_from_slice(payload);
+        v
+    }
 
-    use pako_tools::{Flow, Packet, ParseContext};
+    #[test]
+    fn threaded_icmp_error_flow() {
+        let mut config = Config::default();
+        config.set("num_threads", 4);
+        let counter = Arc::new(Mutex::new(IcmpErrorCounter::default()));
+        let reader = Arc::new(Mutex::new(ContextReader::default()));
+        let mut registry = PluginRegistry::new();
+        registry.add_plugin(counter.clone());
+        registry.add_plugin(reader.clone());
+        let mut analyzer = ThreadedAnalyzer::new(registry, &config);
+        analyzer.init().expect("init");
+
+        let udp = ipv4(
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            17,
+            b"\x30\x39\x00\x35\x00\x08\x00\x00",
+        );
+        // port unreachable errors from several routers, hashed on their outer addresses
+        // to different workers
+        let mut packets = vec![udp.clone()];
+        for router in 1..=16 {
+            let mut icmp = vec![3, 3, 0, 0, 0, 0, 0, 0];
+            icmp.extend_from_slice(&udp);
+            packets.push(ipv4([192, 168, 0, router], [10, 0, 0, 1], 1, &icmp));
+        }
+        for (idx, data) in packets.iter().enumerate() {
+            let packet = Packet {
+                interface: 0,
+                ts: Duration::from_nanos_parts(1, idx as u32),
+                link_type: Linktype::RAW,
+                data: PacketData::L3(0x0800, data),
+                caplen: data.len() as u32,
+                origlen: data.len() as u32,
+                pcap_index: idx + 1,
+            };
+            let ctx = ParseContext {
+                pcap_index: idx + 1,
+                ..ParseContext::default()
+            };
+            analyzer.handle_packet(&packet, &ctx).expect("packet");
+        }
+        analyzer.teardown();
+        assert_eq!(counter.lock().unwrap().errors, 16);
+        // plugins see the counters of all workers (zero IPv4 checksums are not checked)
+        let reader = reader.lock().unwrap();
+        assert_eq!(reader.packets, 17);
+        assert_eq!(reader.skipped_checksums, 17);
+    }
+
+    #[test]
+    fn threaded_held_fragment_context() {
+        let mut fanout = FanOut::new(4, &Config::default());
+        let pool = BufferPool::new(4);
+        let mut first = ipv4([10, 0, 0, 1], [10, 0, 0, 2], 17, &[0; 16]);
+        // more fragments, offset 0
+        first[6] = 0x20;
+        let mut second = ipv4([10, 0, 0, 1], [10, 0, 0, 2], 17, &[0; 8]);
+        // offset 16 bytes
+        second[7] = 2;
+        let mut ready = Vec::new();
+        // the second fragment arrives first, and is held until the first one
+        for (idx, data) in [(1, &second), (2, &first)] {
+            let packet = Packet {
+                interface: 0,
+                ts: Duration::from_nanos_parts(1, idx as u32),
+                link_type: Linktype::RAW,
+                data: PacketData::L3(0x0800, data),
+                caplen: data.len() as u32,
+                origlen: data.len() as u32,
+                pcap_index: idx,
+            };
+            let ctx = ParseContext {
+                pcap_index: idx,
+                ..ParseContext::default()
+            };
+            let data = JobData::Copied(pool.copy_from_slice(data));
+            let job = PacketJob::new(&packet, &ctx, data, JobLayer::L3(EtherType(0x0800)));
+            fanout.assign(job, &mut ready);
+            if idx == 1 {
+                assert!(ready.is_empty());
+            }
+        }
+        // each job keeps the context of its own packet
+        let indexes: Vec<_> = ready
+            .iter()
+            .map(|(_, job)| (job.pcap_index, job.ctx.pcap_index))
+            .collect();
+        assert_eq!(indexes, vec![(2, 2), (1, 1)]);
+    }
 
-    use super::Job;
     #[test]
     fn size_of_structs() {
         println!("sizeof ParseContext: {}", mem::size_of::<ParseContext>());
@@ -0,0 +1,209 @@
+//!
+//! TLS session secrets, in the NSS key log format
+//!
+//! Secrets can be read from an `SSLKEYLOGFILE` (set the `ssl_keylog_file` configuration
+//! variable), or from the Decryption Secrets Blocks (DSB) of a pcap-ng capture.
+//!
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    str::{self, FromStr},
+    sync::RwLock,
+};
+
+use log::{debug, warn};
+
+/// Size of the TLS client random, used to identify sessions
+pub const TLS_CLIENT_RANDOM_LEN: usize = 32;
+
+/// Label of a secret in a key log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TlsSecretLabel {
+    /// Master secret (TLS 1.2 and earlier)
+    ClientRandom,
+    /// TLS 1.3 traffic secrets
+    ClientEarlyTrafficSecret,
+    ClientHandshakeTrafficSecret,
+    ServerHandshakeTrafficSecret,
+    ClientTrafficSecret0,
+    ServerTrafficSecret0,
+    /// TLS 1.3 exporter secrets
+    EarlyExporterSecret,
+    ExporterSecret,
+}
+
+impl TlsSecretLabel {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            TlsSecretLabel::ClientRandom => "CLIENT_RANDOM",
+            TlsSecretLabel::ClientEarlyTrafficSecret => "CLIENT_EARLY_TRAFFIC_SECRET",
+            TlsSecretLabel::ClientHandshakeTrafficSecret => "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+            TlsSecretLabel::ServerHandshakeTrafficSecret => "SERVER_HANDSHAKE_TRAFFIC_SECRET",
+            TlsSecretLabel::ClientTrafficSecret0 => "CLIENT_TRAFFIC_SECRET_0",
+            TlsSecretLabel::ServerTrafficSecret0 => "SERVER_TRAFFIC_SECRET_0",
+            TlsSecretLabel::EarlyExporterSecret => "EARLY_EXPORTER_SECRET",
+            TlsSecretLabel::ExporterSecret => "EXPORTER_SECRET",
+        }
+    }
+}
+
+impl FromStr for TlsSecretLabel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let label = match s {
+            "CLIENT_RANDOM" => TlsSecretLabel::ClientRandom,
+            "CLIENT_EARLY_TRAFFIC_SECRET" => TlsSecretLabel::ClientEarlyTrafficSecret,
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET" => TlsSecretLabel::ClientHandshakeTrafficSecret,
+            "SERVER_HANDSHAKE_TRAFFIC_SECRET" => TlsSecretLabel::ServerHandshakeTrafficSecret,
+            "CLIENT_TRAFFIC_SECRET_0" => TlsSecretLabel::ClientTrafficSecret0,
+            "SERVER_TRAFFIC_SECRET_0" => TlsSecretLabel::ServerTrafficSecret0,
+            "EARLY_EXPORTER_SECRET" => TlsSecretLabel::EarlyExporterSecret,
+            "EXPORTER_SECRET" => TlsSecretLabel::ExporterSecret,
+            _ => return Err(()),
+        };
+        Ok(label)
+    }
+}
+
+/// Secrets of a TLS session, by label
+pub type TlsSessionSecrets = HashMap<TlsSecretLabel, Vec<u8>>;
+
+/// TLS session secrets, indexed by client random
+///
+/// The key log is shared between the analyzer (and its workers) and the plugins, which
+/// receive it in `Plugin::set_context`. It is updated while packets are processed, when a
+/// Decryption Secrets Block is read: secrets of a session are available to plugins as soon as
+/// the DSB precedes the session in the capture, which is what capture tools do.
+///
+/// Lines with an unknown label (for ex. `RSA`) are ignored.
+#[derive(Debug, Default)]
+pub struct TlsKeyLog {
+    sessions: RwLock<HashMap<[u8; TLS_CLIENT_RANDOM_LEN], TlsSessionSecrets>>,
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| u8::from_str_radix(str::from_utf8(c).ok()?, 16).ok())
+        .collect()
+}
+
+impl TlsKeyLog {
+    /// Add the secrets of a key log (NSS key log format), and return the number of secrets added
+    ///
+    /// Invalid lines are skipped.
+    pub fn parse(&self, data: &[u8]) -> usize {
+        let mut sessions = self.sessions.write().expect("TLS key log lock poisoned");
+        let mut count = 0;
+        for (idx, line) in data.split(|&b| b == b'\n').enumerate() {
+            let Ok(line) = str::from_utf8(line) else {
+                warn!("TLS key log: invalid UTF-8 (line {})", idx + 1);
+                continue;
+            };
+            // DSB data may be padded with NUL bytes
+            let line = line.trim_matches(|c: char| c.is_ascii_whitespace() || c == '\0');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_ascii_whitespace();
+            let (Some(label), Some(client_random), Some(secret), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                warn!("TLS key log: invalid line {}", idx + 1);
+                continue;
+            };
+            let Ok(label) = label.parse::<TlsSecretLabel>() else {
+                debug!("TLS key log: ignoring label {} (line {})", label, idx + 1);
+                continue;
+            };
+            let client_random = decode_hex(client_random).and_then(|v| v.try_into().ok());
+            match (client_random, decode_hex(secret)) {
+                (Some(client_random), Some(secret)) => {
+                    sessions
+                        .entry(client_random)
+                        .or_default()
+                        .insert(label, secret);
+                    count += 1;
+                }
+                _ => warn!("TLS key log: invalid hex value (line {})", idx + 1),
+            }
+        }
+        count
+    }
+
+    /// Add the secrets of a key log file, and return the number of secrets added
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> Result<usize, io::Error> {
+        let data = fs::read(path)?;
+        Ok(self.parse(&data))
+    }
+
+    /// Return the secret with `label` for the session identified by `client_random`
+    pub fn secret(&self, client_random: &[u8], label: TlsSecretLabel) -> Option<Vec<u8>> {
+        let sessions = self.sessions.read().expect("TLS key log lock poisoned");
+        let client_random: &[u8; TLS_CLIENT_RANDOM_LEN] = client_random.try_into().ok()?;
+        sessions.get(client_random)?.get(&label).cloned()
+    }
+
+    /// Return all secrets of the session identified by `client_random`
+    pub fn session(&self, client_random: &[u8]) -> Option<TlsSessionSecrets> {
+        let sessions = self.sessions.read().expect("TLS key log lock poisoned");
+        let client_random: &[u8; TLS_CLIENT_RANDOM_LEN] = client_random.try_into().ok()?;
+        sessions.get(client_random).cloned()
+    }
+
+    /// Number of sessions with at least one secret
+    pub fn len(&self) -> usize {
+        self.sessions
+            .read()
+            .expect("TLS key log lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TlsKeyLog, TlsSecretLabel};
+
+    #[test]
+    fn tls_keylog_parse() {
+        let random = "a1".repeat(32);
+        let keylog = format!(
+            "# comment\n\
+             CLIENT_RANDOM {random} {}\n\
+             CLIENT_TRAFFIC_SECRET_0 {random} 0102\r\n\
+             RSA 0011223344556677 {}\n\
+             CLIENT_RANDOM 00 01\n\
+             SERVER_TRAFFIC_SECRET_0 {random} 0g\n\
+             \0\0\0",
+            "ab".repeat(48),
+            "cd".repeat(48),
+        );
+        let secrets = TlsKeyLog::default();
+        assert_eq!(secrets.parse(keylog.as_bytes()), 2);
+        assert_eq!(secrets.len(), 1);
+        let client_random = [0xa1; 32];
+        assert_eq!(
+            secrets.secret(&client_random, TlsSecretLabel::ClientRandom),
+            Some(vec![0xab; 48])
+        );
+        assert_eq!(
+            secrets.secret(&client_random, TlsSecretLabel::ClientTrafficSecret0),
+            Some(vec![1, 2])
+        );
+        assert!(secrets
+            .secret(&client_random, TlsSecretLabel::ServerTrafficSecret0)
+            .is_none());
+        assert_eq!(secrets.session(&client_random).map(|s| s.len()), Some(2));
+        assert!(secrets.session(&client_random[1..]).is_none());
+    }
+}
@@ -16,11 +16,34 @@
 //!    |                      Block Total Length                       |
 //!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-use nom::IResult;
+use nom::{
+    bytes::streaming::take,
+    error::{ErrorKind, ParseError},
+    IResult,
+};
+
+use crate::{
+    custom::Custom,
+    decryption_secrets::DecryptionSecrets,
+    endian::{BigEndian, ByteOrder, Endianness, LittleEndian},
+    enhanced_packet::EnhancedPacket,
+    interface_description::InterfaceDescription,
+    interface_statistics::InterfaceStatistics,
+    journal_export::SystemJournalExport,
+    legacy::{LegacyPacket, PcapHeader, PCAP_HEADER_SIZE},
+    name_resolution::NameResolution,
+    options::Options,
+    section_header::{section_header, SectionHeader},
+    simple_packet::SimplePacket,
+    Error,
+};
+
+/// Size of the block type and of the two block total length fields
+pub(crate) const BLOCK_OVERHEAD: usize = 12;
 
 /// The block type uniquely identifies a block.
 #[repr(u32)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockType {
     SectionHeader = 0x0A0D_0D0A,
     InterfaceDescription = 0x0000_0001,
@@ -31,22 +54,424 @@ pub enum BlockType {
     SystemJournalExport = 0x0000_0009,
     DecryptionSecretsBlock = 0x0000_000A,
     Custom = 0x0000_0BAD,
+    CustomNoCopy = 0x4000_0BAD,
+}
+
+impl TryFrom<u32> for BlockType {
+    type Error = Error<&'static [u8]>;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0x0A0D_0D0A => Ok(BlockType::SectionHeader),
+            0x0000_0001 => Ok(BlockType::InterfaceDescription),
+            0x0000_0003 => Ok(BlockType::SimplePacket),
+            0x0000_0004 => Ok(BlockType::NameResolution),
+            0x0000_0005 => Ok(BlockType::InterfaceStatistic),
+            0x0000_0006 => Ok(BlockType::EnhancedPacket),
+            0x0000_0009 => Ok(BlockType::SystemJournalExport),
+            0x0000_000A => Ok(BlockType::DecryptionSecretsBlock),
+            0x0000_0BAD => Ok(BlockType::Custom),
+            0x4000_0BAD => Ok(BlockType::CustomNoCopy),
+            _ => Err(Error::Type(value)),
+        }
+    }
+}
+
+/// A block with a block type this crate does not know
+///
+/// Readers must skip unknown blocks, so the body is kept without interpretation.
+#[derive(Debug)]
+pub struct UnknownBlock<'a> {
+    pub block_type: u32,
+    pub total_length: u32,
+    pub body: &'a [u8],
+    pub total_length_dup: u32,
+}
+
+/// A parsed pcapng block, or a part of a legacy pcap file
+#[derive(Debug)]
+pub enum Block<'a> {
+    SectionHeader(SectionHeader<'a>),
+    InterfaceDescription(InterfaceDescription<'a>),
+    EnhancedPacket(EnhancedPacket<'a>),
+    SimplePacket(SimplePacket<'a>),
+    NameResolution(NameResolution<'a>),
+    InterfaceStatistics(InterfaceStatistics<'a>),
+    DecryptionSecrets(DecryptionSecrets<'a>),
+    SystemJournalExport(SystemJournalExport<'a>),
+    Custom(Custom<'a>),
+    Unknown(UnknownBlock<'a>),
+    /// Legacy pcap file header
+    LegacyHeader(PcapHeader),
+    /// Legacy pcap packet record
+    LegacyPacket(LegacyPacket<'a>),
+}
+
+impl<'a> Block<'a> {
+    /// Numeric block type (`0` for legacy pcap blocks, which have no type)
+    pub fn block_type(&self) -> u32 {
+        match self {
+            Block::SectionHeader(b) => b.block_type as u32,
+            Block::InterfaceDescription(b) => b.block_type as u32,
+            Block::EnhancedPacket(b) => b.block_type as u32,
+            Block::SimplePacket(b) => b.block_type as u32,
+            Block::NameResolution(b) => b.block_type as u32,
+            Block::InterfaceStatistics(b) => b.block_type as u32,
+            Block::DecryptionSecrets(b) => b.block_type as u32,
+            Block::SystemJournalExport(b) => b.block_type as u32,
+            Block::Custom(b) => b.block_type as u32,
+            Block::Unknown(b) => b.block_type,
+            Block::LegacyHeader(_) | Block::LegacyPacket(_) => 0,
+        }
+    }
+
+    /// Block total length, including block type and length fields (or record header, for
+    /// legacy pcap packets)
+    pub fn total_length(&self) -> u32 {
+        match self {
+            Block::SectionHeader(b) => b.total_length,
+            Block::InterfaceDescription(b) => b.total_length,
+            Block::EnhancedPacket(b) => b.total_length,
+            Block::SimplePacket(b) => b.total_length,
+            Block::NameResolution(b) => b.total_length,
+            Block::InterfaceStatistics(b) => b.total_length,
+            Block::DecryptionSecrets(b) => b.total_length,
+            Block::SystemJournalExport(b) => b.total_length,
+            Block::Custom(b) => b.total_length,
+            Block::Unknown(b) => b.total_length,
+            Block::LegacyHeader(_) => PCAP_HEADER_SIZE as u32,
+            Block::LegacyPacket(b) => {
+                let header_size = if b.modified.is_some() { 24 } else { 16 };
+                header_size + b.captured_len
+            }
+        }
+    }
+
+    /// Decoded options of the block
+    ///
+    /// Blocks without options (SPB, systemd journal, custom, unknown and legacy blocks) return an
+    /// empty iterator. `endianness` is the byte order of the current section.
+    pub fn options(&self, endianness: Endianness) -> Options<'a> {
+        let (block_type, options) = match self {
+            Block::SectionHeader(b) => (b.block_type, b.options),
+            Block::InterfaceDescription(b) => (b.block_type, b.options),
+            Block::EnhancedPacket(b) => (b.block_type, b.options),
+            Block::NameResolution(b) => (b.block_type, b.options),
+            Block::InterfaceStatistics(b) => (b.block_type, b.options),
+            Block::DecryptionSecrets(b) => (b.block_type, b.options),
+            Block::SimplePacket(b) => (b.block_type, None),
+            Block::SystemJournalExport(b) => (b.block_type, None),
+            Block::Custom(b) => (b.block_type, None),
+            Block::Unknown(_) | Block::LegacyHeader(_) | Block::LegacyPacket(_) => {
+                (BlockType::Custom, None)
+            }
+        };
+        Options::new(block_type, endianness, options)
+    }
+}
+
+/// Generic block structure, with the body not yet parsed
+#[derive(Debug)]
+pub(crate) struct RawBlock<'a> {
+    pub block_type: u32,
+    pub total_length: u32,
+    pub body: &'a [u8],
+    pub total_length_dup: u32,
+}
+
+/// Split the next block of `input` into type, lengths and body
+///
+/// Returns `nom::Err::Incomplete` if `input` does not contain the complete block.
+pub(crate) fn raw_block<'a, B, E>(input: &'a [u8]) -> IResult<&'a [u8], RawBlock<'a>, E>
+where
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    let (_, header) = take(8usize)(input)?;
+    let (header, block_type) = B::parse_u32(header)?;
+    let (_, total_length) = B::parse_u32(header)?;
+    if (total_length as usize) < BLOCK_OVERHEAD || total_length % 4 != 0 {
+        return Err(nom::Err::Error(E::from_error_kind(
+            input,
+            ErrorKind::Verify,
+        )));
+    }
+    let (rest, block) = take(total_length)(input)?;
+    let (body, trailer) = block[8..].split_at(block.len() - BLOCK_OVERHEAD);
+    let (_, total_length_dup) = B::parse_u32(trailer)?;
+    if total_length_dup != total_length {
+        return Err(nom::Err::Error(E::from_error_kind(
+            input,
+            ErrorKind::Verify,
+        )));
+    }
+    Ok((
+        rest,
+        RawBlock {
+            block_type,
+            total_length,
+            body,
+            total_length_dup,
+        },
+    ))
+}
+
+/// Return `None` for an empty options field
+pub(crate) fn options_field(input: &[u8]) -> Option<&[u8]> {
+    if input.is_empty() {
+        None
+    } else {
+        Some(input)
+    }
 }
 
 /// [`BlockParser`] is implemented by each block type in order to parse the
 /// block type specific block body
-trait BlockParser<O> {
-    /// Block header size
+pub trait BlockParser<'a>: Sized {
+    /// Size of the fixed fields at the start of the block body
     const HEADER_SIZE: usize;
 
     /// Block type identifier
     const BLOCK_TYPE: BlockType;
 
     /// Block body parser
-    fn parse_body<E>(
-        blk_type: BlockType,
+    ///
+    /// `blk_body` is the body only, without block type and lengths.
+    fn parse_body<B, E>(
+        blk_type: u32,
         blk_len1: u32,
-        blk_body: &[u8],
+        blk_body: &'a [u8],
         blk_len2: u32,
-    ) -> IResult<&[u8], O, E>;
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>;
+
+    /// Test if a numeric block type can be parsed by this parser
+    fn accepts(blk_type: u32) -> bool {
+        blk_type == Self::BLOCK_TYPE as u32
+    }
+
+    /// Parse a complete block of this type, with byte order `B`
+    fn parse<B, E>(input: &'a [u8]) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (rest, raw) = raw_block::<B, E>(input)?;
+        if !Self::accepts(raw.block_type) {
+            return Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Tag)));
+        }
+        let block = parse_raw::<Self, B, E>(raw)?;
+        Ok((rest, block))
+    }
+}
+
+/// Parse the body of a block split by [`raw_block`]
+pub(crate) fn parse_raw<'a, P, B, E>(raw: RawBlock<'a>) -> Result<P, nom::Err<E>>
+where
+    P: BlockParser<'a>,
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    if raw.body.len() < P::HEADER_SIZE {
+        return Err(nom::Err::Error(E::from_error_kind(
+            raw.body,
+            ErrorKind::Eof,
+        )));
+    }
+    let (_, block) = P::parse_body::<B, E>(
+        raw.block_type,
+        raw.total_length,
+        raw.body,
+        raw.total_length_dup,
+    )?;
+    Ok(block)
+}
+
+/// Parse the next block of `input`, with byte order `B`
+///
+/// A Section Header Block is parsed using its own byte order, which the caller must use
+/// for all following blocks of the section.
+pub fn parse_block<'a, B, E>(input: &'a [u8]) -> IResult<&'a [u8], Block<'a>, E>
+where
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    // the SHB block type is a palindrome, and can be read before knowing the byte order
+    let (_, block_type) = take(4usize)(input)?;
+    let (_, block_type) = B::parse_u32(block_type)?;
+    if block_type == BlockType::SectionHeader as u32 {
+        let (rest, shb) = section_header(input)?;
+        return Ok((rest, Block::SectionHeader(shb)));
+    }
+    let (rest, raw) = raw_block::<B, E>(input)?;
+    let block = match BlockType::try_from(raw.block_type) {
+        Ok(BlockType::InterfaceDescription) => {
+            Block::InterfaceDescription(parse_raw::<InterfaceDescription, B, E>(raw)?)
+        }
+        Ok(BlockType::EnhancedPacket) => {
+            Block::EnhancedPacket(parse_raw::<EnhancedPacket, B, E>(raw)?)
+        }
+        Ok(BlockType::SimplePacket) => Block::SimplePacket(parse_raw::<SimplePacket, B, E>(raw)?),
+        Ok(BlockType::NameResolution) => {
+            Block::NameResolution(parse_raw::<NameResolution, B, E>(raw)?)
+        }
+        Ok(BlockType::InterfaceStatistic) => {
+            Block::InterfaceStatistics(parse_raw::<InterfaceStatistics, B, E>(raw)?)
+        }
+        Ok(BlockType::DecryptionSecretsBlock) => {
+            Block::DecryptionSecrets(parse_raw::<DecryptionSecrets, B, E>(raw)?)
+        }
+        Ok(BlockType::SystemJournalExport) => {
+            Block::SystemJournalExport(parse_raw::<SystemJournalExport, B, E>(raw)?)
+        }
+        Ok(BlockType::Custom) | Ok(BlockType::CustomNoCopy) => {
+            Block::Custom(parse_raw::<Custom, B, E>(raw)?)
+        }
+        // handled above
+        Ok(BlockType::SectionHeader) | Err(_) => Block::Unknown(UnknownBlock {
+            block_type: raw.block_type,
+            total_length: raw.total_length,
+            body: raw.body,
+            total_length_dup: raw.total_length_dup,
+        }),
+    };
+    Ok((rest, block))
+}
+
+/// Parse the next block of a little-endian section
+pub fn parse_block_le(input: &[u8]) -> IResult<&[u8], Block<'_>, Error<&[u8]>> {
+    parse_block::<LittleEndian, _>(input)
+}
+
+/// Parse the next block of a big-endian section
+pub fn parse_block_be(input: &[u8]) -> IResult<&[u8], Block<'_>, Error<&[u8]>> {
+    parse_block::<BigEndian, _>(input)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use super::{parse_block_be, parse_block_le, raw_block, Block, BlockType};
+    use crate::endian::{BigEndian, ByteOrder, LittleEndian};
+
+    pub(crate) const BLOCKS_BE: &[u8] = include_bytes!("../assets/blocks_be.pcapng");
+    pub(crate) const BLOCKS_LE: &[u8] = include_bytes!("../assets/blocks_le.pcapng");
+
+    /// Return the input starting at the `n`-th block of type `block_type`
+    pub(crate) fn find_block<B: ByteOrder>(
+        mut input: &'static [u8],
+        block_type: BlockType,
+        n: usize,
+    ) -> &'static [u8] {
+        let mut count = 0;
+        loop {
+            let (rest, raw) = raw_block::<B, VerboseError<&[u8]>>(input).expect("block");
+            if raw.block_type == block_type as u32 {
+                if count == n {
+                    return input;
+                }
+                count += 1;
+            }
+            input = rest;
+        }
+    }
+
+    #[test]
+    fn find_block_works() {
+        let be = find_block::<BigEndian>(BLOCKS_BE, BlockType::SimplePacket, 0);
+        let le = find_block::<LittleEndian>(BLOCKS_LE, BlockType::SimplePacket, 0);
+        assert_eq!(be.len(), le.len());
+    }
+
+    #[test]
+    fn block_type_try_from() {
+        assert_eq!(BlockType::try_from(6).unwrap(), BlockType::EnhancedPacket);
+        assert_eq!(
+            BlockType::try_from(0x4000_0BAD).unwrap(),
+            BlockType::CustomNoCopy
+        );
+        assert!(BlockType::try_from(2).is_err());
+    }
+
+    #[test]
+    fn parse_all_blocks() -> Result<()> {
+        let expected = [
+            BlockType::SectionHeader,
+            BlockType::InterfaceDescription,
+            BlockType::EnhancedPacket,
+            BlockType::EnhancedPacket,
+            BlockType::EnhancedPacket,
+            BlockType::EnhancedPacket,
+            BlockType::SimplePacket,
+            BlockType::NameResolution,
+            BlockType::InterfaceStatistic,
+            BlockType::DecryptionSecretsBlock,
+            BlockType::Custom,
+            BlockType::SystemJournalExport,
+        ];
+        let mut be = BLOCKS_BE;
+        let mut le = BLOCKS_LE;
+        for t in expected {
+            let (rest, block) = parse_block_be(be)?;
+            assert_eq!(block.block_type(), t as u32);
+            be = rest;
+            let (rest, block) = parse_block_le(le)?;
+            assert_eq!(block.block_type(), t as u32);
+            assert!(!matches!(block, Block::Unknown(_)));
+            le = rest;
+        }
+        assert!(be.is_empty());
+        assert!(le.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_block() -> Result<()> {
+        let input: &[u8] = &[
+            0x01, 0x02, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0xaa, 0xbb, 0xcc, 0xdd, 0x10, 0x00,
+            0x00, 0x00,
+        ];
+        let (rest, block) = parse_block_le(input)?;
+        assert!(rest.is_empty());
+        match block {
+            Block::Unknown(b) => {
+                assert_eq!(b.block_type, 0x0201);
+                assert_eq!(b.body, &[0xaa, 0xbb, 0xcc, 0xdd]);
+            }
+            _ => panic!("unexpected block"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_block() {
+        // truncated SHB
+        assert!(matches!(
+            parse_block_le(&BLOCKS_LE[..40]),
+            Err(nom::Err::Incomplete(_))
+        ));
+        // truncated EPB
+        let epb = find_block::<LittleEndian>(BLOCKS_LE, BlockType::EnhancedPacket, 0);
+        assert!(matches!(
+            parse_block_le(&epb[..30]),
+            Err(nom::Err::Incomplete(_))
+        ));
+        assert!(matches!(
+            parse_block_le(&BLOCKS_LE[..4]),
+            Err(nom::Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let input = [
+            0x01, 0x02, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0xaa, 0xbb, 0xcc, 0xdd, 0x14, 0x00,
+            0x00, 0x00,
+        ];
+        assert!(matches!(parse_block_le(&input), Err(nom::Err::Error(_))));
+    }
 }
@@ -0,0 +1,110 @@
+//!
+//! Custom Block (CB) support
+//!
+
+use nom::{error::ParseError, IResult};
+
+use crate::{block::BlockParser, endian::ByteOrder, BlockType};
+
+/// A Custom Block (CB) is the container for storing custom data that is not
+/// part of another block. The data is identified by a Private Enterprise Number.
+///
+/// The layout of custom data and options is private: both are kept in `data`.
+#[derive(Debug)]
+pub struct Custom<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    /// Private Enterprise Number
+    pub pen: u32,
+    /// Custom data and options
+    pub data: &'a [u8],
+    pub total_length_dup: u32,
+}
+
+impl<'a> Custom<'a> {
+    /// Test if the block can be copied to a new file (`CustomNoCopy` blocks must not)
+    pub fn copyable(&self) -> bool {
+        self.block_type == BlockType::Custom
+    }
+}
+
+impl<'a> BlockParser<'a> for Custom<'a> {
+    const HEADER_SIZE: usize = 4;
+    const BLOCK_TYPE: BlockType = BlockType::Custom;
+
+    fn accepts(blk_type: u32) -> bool {
+        blk_type == BlockType::Custom as u32 || blk_type == BlockType::CustomNoCopy as u32
+    }
+
+    fn parse_body<B, E>(
+        blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (data, pen) = B::parse_u32(blk_body)?;
+        let block_type = if blk_type == BlockType::CustomNoCopy as u32 {
+            BlockType::CustomNoCopy
+        } else {
+            BlockType::Custom
+        };
+
+        Ok((
+            &[],
+            Custom {
+                block_type,
+                total_length: blk_len1,
+                pen,
+                data,
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        custom::Custom,
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::Custom, 0);
+        let (_, cb) = Custom::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(32473, cb.pen);
+        assert_eq!(b"pako", cb.data);
+        assert!(cb.copyable());
+        Ok(())
+    }
+
+    #[test]
+    fn custom_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+
+    #[test]
+    fn custom_no_copy() -> Result<()> {
+        let input: &[u8] = &[
+            0xad, 0x0b, 0x00, 0x40, 0x14, 0x00, 0x00, 0x00, 0xd9, 0x7e, 0x00, 0x00, 0x70, 0x61,
+            0x6b, 0x6f, 0x14, 0x00, 0x00, 0x00,
+        ];
+        let (_, cb) = Custom::parse::<LittleEndian, VerboseError<&[u8]>>(input)?;
+        assert_eq!(BlockType::CustomNoCopy, cb.block_type);
+        assert!(!cb.copyable());
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//!
+//! Decryption Secrets Block (DSB) support
+//!
+
+use nom::{bytes::complete::take, error::ParseError, IResult};
+
+use crate::{
+    align,
+    block::{options_field, BlockParser},
+    endian::ByteOrder,
+    BlockType,
+};
+
+/// TLS Key Log (NSS key log format)
+pub const SECRETS_TYPE_TLS: u32 = 0x544c_534b;
+/// SSH Key Log
+pub const SECRETS_TYPE_SSH: u32 = 0x5353_484b;
+/// WireGuard Key Log
+pub const SECRETS_TYPE_WIREGUARD: u32 = 0x5747_4b4c;
+/// ZigBee NWK Key
+pub const SECRETS_TYPE_ZIGBEE_NWK: u32 = 0x5a4e_574b;
+/// ZigBee APS Key
+pub const SECRETS_TYPE_ZIGBEE_APS: u32 = 0x5a41_5053;
+/// OPC UA Key Log
+pub const SECRETS_TYPE_OPCUA: u32 = 0x5555_414b;
+
+/// A Decryption Secrets Block (DSB) stores (session) secrets that enable
+/// decryption of packets within the capture file.
+#[derive(Debug)]
+pub struct DecryptionSecrets<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub secrets_type: u32,
+    pub secrets_len: u32,
+    /// Secrets data, without padding
+    pub data: &'a [u8],
+    pub options: Option<&'a [u8]>,
+    pub total_length_dup: u32,
+}
+
+impl<'a> BlockParser<'a> for DecryptionSecrets<'a> {
+    const HEADER_SIZE: usize = 8;
+    const BLOCK_TYPE: BlockType = BlockType::DecryptionSecretsBlock;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (input, secrets_type) = B::parse_u32(blk_body)?;
+        let (input, secrets_len) = B::parse_u32(input)?;
+        let (input, data) = take(align!(secrets_len as usize, 4))(input)?;
+
+        Ok((
+            &[],
+            DecryptionSecrets {
+                block_type: BlockType::DecryptionSecretsBlock,
+                total_length: blk_len1,
+                secrets_type,
+                secrets_len,
+                data: &data[..secrets_len as usize],
+                options: options_field(input),
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        decryption_secrets::{DecryptionSecrets, SECRETS_TYPE_TLS},
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::DecryptionSecretsBlock, 0);
+        let (_, dsb) = DecryptionSecrets::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(SECRETS_TYPE_TLS, dsb.secrets_type);
+        assert_eq!(b"CLIENT_RANDOM 0123 4567\n", dsb.data);
+        assert!(dsb.options.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn decryption_secrets_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+}
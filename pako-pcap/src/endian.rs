@@ -1,4 +1,4 @@
-use nom::{error::ParseError, IResult};
+use nom::{error::ParseError, number::complete as number, IResult};
 
 pub enum BigEndian {}
 
@@ -15,8 +15,60 @@ pub type NativeEndian = LittleEndian;
 /// Network byte order
 pub type NetworkEndian = BigEndian;
 
+/// Byte order of a section, known at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
 /// [`ByteOrder`] can deserialize integers from bytes
-pub(crate) trait ByteOrder {
+pub trait ByteOrder {
+    /// Runtime value of this byte order
+    const ENDIANNESS: Endianness;
+
     fn parse_u16<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u16, E>;
     fn parse_u32<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u32, E>;
+    fn parse_u64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u64, E>;
+    fn parse_i64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], i64, E>;
+}
+
+impl ByteOrder for BigEndian {
+    const ENDIANNESS: Endianness = Endianness::Big;
+
+    fn parse_u16<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u16, E> {
+        number::be_u16(input)
+    }
+
+    fn parse_u32<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u32, E> {
+        number::be_u32(input)
+    }
+
+    fn parse_u64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u64, E> {
+        number::be_u64(input)
+    }
+
+    fn parse_i64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], i64, E> {
+        number::be_i64(input)
+    }
+}
+
+impl ByteOrder for LittleEndian {
+    const ENDIANNESS: Endianness = Endianness::Little;
+
+    fn parse_u16<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u16, E> {
+        number::le_u16(input)
+    }
+
+    fn parse_u32<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u32, E> {
+        number::le_u32(input)
+    }
+
+    fn parse_u64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], u64, E> {
+        number::le_u64(input)
+    }
+
+    fn parse_i64<'a, E: ParseError<&'a [u8]>>(input: &'a [u8]) -> IResult<&'a [u8], i64, E> {
+        number::le_i64(input)
+    }
 }
@@ -0,0 +1,125 @@
+//!
+//! Enhanced Packet Block (EPB) support
+//!
+
+use nom::{bytes::complete::take, error::ParseError, IResult};
+
+use crate::{
+    align,
+    block::{options_field, BlockParser},
+    endian::ByteOrder,
+    BlockType,
+};
+
+/// An Enhanced Packet Block (EPB) is the standard container for storing the
+/// packets coming from the network.
+#[derive(Debug)]
+pub struct EnhancedPacket<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub interface_id: u32,
+    pub timestamp_high: u32,
+    pub timestamp_low: u32,
+    pub captured_len: u32,
+    pub original_len: u32,
+    /// Packet data, without padding
+    pub data: &'a [u8],
+    pub options: Option<&'a [u8]>,
+    pub total_length_dup: u32,
+}
+
+impl<'a> EnhancedPacket<'a> {
+    /// Timestamp, in units of the interface time resolution
+    pub fn timestamp(&self) -> u64 {
+        (u64::from(self.timestamp_high) << 32) | u64::from(self.timestamp_low)
+    }
+}
+
+impl<'a> BlockParser<'a> for EnhancedPacket<'a> {
+    const HEADER_SIZE: usize = 20;
+    const BLOCK_TYPE: BlockType = BlockType::EnhancedPacket;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (input, interface_id) = B::parse_u32(blk_body)?;
+        let (input, timestamp_high) = B::parse_u32(input)?;
+        let (input, timestamp_low) = B::parse_u32(input)?;
+        let (input, captured_len) = B::parse_u32(input)?;
+        let (input, original_len) = B::parse_u32(input)?;
+        let (input, data) = take(align!(captured_len as usize, 4))(input)?;
+
+        Ok((
+            &[],
+            EnhancedPacket {
+                block_type: BlockType::EnhancedPacket,
+                total_length: blk_len1,
+                interface_id,
+                timestamp_high,
+                timestamp_low,
+                captured_len,
+                original_len,
+                data: &data[..captured_len as usize],
+                options: options_field(input),
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        enhanced_packet::EnhancedPacket,
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let first = find_block::<B>(input, BlockType::EnhancedPacket, 0);
+        let (_, epb) = EnhancedPacket::parse::<B, VerboseError<&[u8]>>(first)?;
+        assert_eq!(0, epb.interface_id);
+        assert_eq!(1_658_321_070_679_827_000, epb.timestamp());
+        assert_eq!(74, epb.captured_len);
+        assert_eq!(74, epb.original_len);
+        assert_eq!(74, epb.data.len());
+        // Ethernet, IPv4
+        assert_eq!(&[0x08, 0x00], &epb.data[12..14]);
+        assert_eq!(Some(20), epb.options.map(|o| o.len()));
+
+        let third = find_block::<B>(input, BlockType::EnhancedPacket, 2);
+        let (_, epb) = EnhancedPacket::parse::<B, VerboseError<&[u8]>>(third)?;
+        assert_eq!(66, epb.data.len());
+        assert!(epb.options.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn enhanced_packet_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+
+    #[test]
+    fn enhanced_packet_bad_caplen() {
+        let mut input =
+            find_block::<LittleEndian>(BLOCKS_LE, BlockType::EnhancedPacket, 0)[..128].to_vec();
+        // captured length larger than the block
+        input[20] = 0xff;
+        assert!(EnhancedPacket::parse::<LittleEndian, VerboseError<&[u8]>>(&input).is_err());
+    }
+}
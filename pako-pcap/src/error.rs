@@ -19,11 +19,44 @@ where
     #[error("Nom parser error {0:?}")]
     Nom(I, ErrorKind),
 
+    /// Not enough data to parse a block, the number of additional bytes is returned
+    /// if known (`0` otherwise)
+    #[error("Incomplete data, {0} more bytes needed")]
+    Incomplete(usize),
+
+    /// Block larger than `MAX_BLOCK_SIZE`, probably a corrupted block length
+    #[error("Block too large ({0} bytes)")]
+    BlockSize(usize),
+
+    /// End of input
+    #[error("End of input")]
+    Eof,
+
+    /// I/O error while reading input
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
     /// Other error
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+impl<I> Error<I> {
+    /// Convert to an error not borrowing the input (the input of nom errors is dropped)
+    pub fn into_static(self) -> Error<&'static [u8]> {
+        match self {
+            Error::Type(t) => Error::Type(t),
+            Error::Option(o) => Error::Option(o),
+            Error::Nom(_, kind) => Error::Nom(&[], kind),
+            Error::Incomplete(n) => Error::Incomplete(n),
+            Error::BlockSize(n) => Error::BlockSize(n),
+            Error::Eof => Error::Eof,
+            Error::Io(e) => Error::Io(e),
+            Error::Other(e) => Error::Other(e),
+        }
+    }
+}
+
 impl<I> ParseError<I> for Error<I> {
     fn from_error_kind(input: I, kind: ErrorKind) -> Self {
         Error::Nom(input, kind)
@@ -0,0 +1,95 @@
+//!
+//! Interface Description Block (IDB) support
+//!
+
+use nom::{error::ParseError, IResult};
+
+use crate::{
+    block::{options_field, BlockParser},
+    endian::ByteOrder,
+    BlockType,
+};
+
+/// An Interface Description Block (IDB) is the container for information
+/// describing an interface on which packet data is captured.
+#[derive(Debug)]
+pub struct InterfaceDescription<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub link_type: u16,
+    pub reserved: u16,
+    pub snap_len: u32,
+    pub options: Option<&'a [u8]>,
+    pub total_length_dup: u32,
+}
+
+impl<'a> BlockParser<'a> for InterfaceDescription<'a> {
+    const HEADER_SIZE: usize = 8;
+    const BLOCK_TYPE: BlockType = BlockType::InterfaceDescription;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (input, link_type) = B::parse_u16(blk_body)?;
+        let (input, reserved) = B::parse_u16(input)?;
+        let (input, snap_len) = B::parse_u32(input)?;
+
+        Ok((
+            &[],
+            InterfaceDescription {
+                block_type: BlockType::InterfaceDescription,
+                total_length: blk_len1,
+                link_type,
+                reserved,
+                snap_len,
+                options: options_field(input),
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        interface_description::InterfaceDescription,
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::InterfaceDescription, 0);
+        let (_, idb) = InterfaceDescription::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(40, idb.total_length);
+        assert_eq!(1, idb.link_type);
+        assert_eq!(262144, idb.snap_len);
+        assert_eq!(Some(20), idb.options.map(|o| o.len()));
+        Ok(())
+    }
+
+    #[test]
+    fn interface_description_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+
+    #[test]
+    fn interface_description_wrong_type() {
+        let input = find_block::<LittleEndian>(BLOCKS_LE, BlockType::EnhancedPacket, 0);
+        assert!(InterfaceDescription::parse::<LittleEndian, VerboseError<&[u8]>>(input).is_err());
+    }
+}
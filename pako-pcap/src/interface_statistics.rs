@@ -0,0 +1,95 @@
+//!
+//! Interface Statistics Block (ISB) support
+//!
+
+use nom::{error::ParseError, IResult};
+
+use crate::{
+    block::{options_field, BlockParser},
+    endian::ByteOrder,
+    BlockType,
+};
+
+/// The Interface Statistics Block (ISB) contains the capture statistics for a
+/// given interface. The statistics are stored in the options.
+#[derive(Debug)]
+pub struct InterfaceStatistics<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub interface_id: u32,
+    pub timestamp_high: u32,
+    pub timestamp_low: u32,
+    pub options: Option<&'a [u8]>,
+    pub total_length_dup: u32,
+}
+
+impl<'a> InterfaceStatistics<'a> {
+    /// Timestamp, in units of the interface time resolution
+    pub fn timestamp(&self) -> u64 {
+        (u64::from(self.timestamp_high) << 32) | u64::from(self.timestamp_low)
+    }
+}
+
+impl<'a> BlockParser<'a> for InterfaceStatistics<'a> {
+    const HEADER_SIZE: usize = 12;
+    const BLOCK_TYPE: BlockType = BlockType::InterfaceStatistic;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (input, interface_id) = B::parse_u32(blk_body)?;
+        let (input, timestamp_high) = B::parse_u32(input)?;
+        let (input, timestamp_low) = B::parse_u32(input)?;
+
+        Ok((
+            &[],
+            InterfaceStatistics {
+                block_type: BlockType::InterfaceStatistic,
+                total_length: blk_len1,
+                interface_id,
+                timestamp_high,
+                timestamp_low,
+                options: options_field(input),
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        interface_statistics::InterfaceStatistics,
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::InterfaceStatistic, 0);
+        let (_, isb) = InterfaceStatistics::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(0, isb.interface_id);
+        assert_eq!(1_658_321_071_583_385_000, isb.timestamp());
+        assert_eq!(Some(28), isb.options.map(|o| o.len()));
+        Ok(())
+    }
+
+    #[test]
+    fn interface_statistics_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+}
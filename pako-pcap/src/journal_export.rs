@@ -0,0 +1,86 @@
+//!
+//! systemd Journal Export Block support
+//!
+
+use nom::{error::ParseError, IResult};
+
+use crate::{block::BlockParser, endian::ByteOrder, BlockType};
+
+/// The systemd Journal Export Block is a lightweight container for systemd
+/// Journal Export Format entry data.
+#[derive(Debug)]
+pub struct SystemJournalExport<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    /// Journal entry, including padding
+    pub data: &'a [u8],
+    pub total_length_dup: u32,
+}
+
+impl<'a> SystemJournalExport<'a> {
+    /// Journal entry, without trailing padding
+    pub fn entry(&self) -> &'a [u8] {
+        let len = self
+            .data
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(0, |pos| pos + 1);
+        &self.data[..len]
+    }
+}
+
+impl<'a> BlockParser<'a> for SystemJournalExport<'a> {
+    const HEADER_SIZE: usize = 0;
+    const BLOCK_TYPE: BlockType = BlockType::SystemJournalExport;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        Ok((
+            &[],
+            SystemJournalExport {
+                block_type: BlockType::SystemJournalExport,
+                total_length: blk_len1,
+                data: blk_body,
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        journal_export::SystemJournalExport,
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::SystemJournalExport, 0);
+        let (_, sje) = SystemJournalExport::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(40, sje.data.len());
+        assert_eq!(b"__REALTIME_TIMESTAMP=1658321070679827\n", sje.entry());
+        Ok(())
+    }
+
+    #[test]
+    fn journal_export_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+}
@@ -0,0 +1,366 @@
+//!
+//! Legacy pcap file format support
+//!
+//! A legacy pcap file is a file header, followed by packet records:
+//!
+//!     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//!  0 |                          Magic Number                         |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//!  4 |          Major Version        |         Minor Version         |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//!  8 |                           Reserved1                           |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! 12 |                           Reserved2                           |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! 16 |                            SnapLen                            |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! 20 | FCS |f|0 0 0 0 0 0 0 0 0 0 0 0|         LinkType              |
+//!    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//!
+//! The magic number gives the byte order and the timestamp resolution. The "modified"
+//! pcap format (Alexey Kuznetzov's patches) adds interface index, protocol and packet
+//! type to each record.
+
+use nom::{
+    bytes::streaming::take,
+    error::{ErrorKind, ParseError},
+    IResult,
+};
+
+use crate::endian::{BigEndian, ByteOrder, Endianness, LittleEndian};
+
+/// Magic number of pcap files with microsecond timestamps
+pub const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+/// Magic number of pcap files with nanosecond timestamps
+pub const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+/// Magic number of modified pcap files (microsecond timestamps)
+pub const PCAP_MAGIC_MODIFIED: u32 = 0xA1B2_CD34;
+
+/// Size of the file header
+pub const PCAP_HEADER_SIZE: usize = 24;
+
+/// `f` bit of the link type field: the FCS length is present
+const LINKTYPE_FCS_PRESENT: u32 = 0x1000_0000;
+
+/// Test if `magic` (4 bytes, in file order) is the magic number of a legacy pcap file
+pub fn is_legacy_magic(magic: &[u8]) -> bool {
+    let Ok(magic) = <[u8; 4]>::try_from(magic) else {
+        return false;
+    };
+    [u32::from_be_bytes(magic), u32::from_le_bytes(magic)]
+        .iter()
+        .any(|m| [PCAP_MAGIC_MICROS, PCAP_MAGIC_NANOS, PCAP_MAGIC_MODIFIED].contains(m))
+}
+
+/// Header of a legacy pcap file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapHeader {
+    pub magic: u32,
+    pub version_major: u16,
+    pub version_minor: u16,
+    /// GMT to local correction, in seconds (`0` in practice)
+    pub thiszone: i32,
+    /// Accuracy of timestamps (`0` in practice)
+    pub sigfigs: u32,
+    pub snap_len: u32,
+    /// Link type and FCS length (see [`PcapHeader::link_type`] and [`PcapHeader::fcs_len`])
+    pub network: u32,
+    /// Byte order of the file, detected from the magic number
+    pub endianness: Endianness,
+}
+
+impl PcapHeader {
+    /// Header of a version 2.4 file, with microsecond or nanosecond timestamps
+    pub fn new(endianness: Endianness, link_type: u16, snap_len: u32, nanos: bool) -> Self {
+        PcapHeader {
+            magic: if nanos {
+                PCAP_MAGIC_NANOS
+            } else {
+                PCAP_MAGIC_MICROS
+            },
+            version_major: 2,
+            version_minor: 4,
+            thiszone: 0,
+            sigfigs: 0,
+            snap_len,
+            network: u32::from(link_type),
+            endianness,
+        }
+    }
+
+    /// Link type of the packets
+    pub fn link_type(&self) -> u16 {
+        self.network as u16
+    }
+
+    /// Length of the FCS at the end of packets in bytes, if known
+    pub fn fcs_len(&self) -> Option<u8> {
+        if self.network & LINKTYPE_FCS_PRESENT == 0 {
+            return None;
+        }
+        // FCS length is stored as a number of 16-bit words
+        Some(((self.network >> 29) as u8) * 2)
+    }
+
+    /// Set the FCS length in bytes (must be even, and at most 14 bytes)
+    pub fn set_fcs_len(&mut self, fcs_len: Option<u8>) {
+        self.network &= 0xffff;
+        if let Some(len) = fcs_len {
+            let words = u32::from(len / 2).min(7);
+            self.network |= LINKTYPE_FCS_PRESENT | (words << 29);
+        }
+    }
+
+    /// Test if timestamps are in nanoseconds
+    pub fn is_nanosecond(&self) -> bool {
+        self.magic == PCAP_MAGIC_NANOS
+    }
+
+    /// Test if the file uses the modified pcap format
+    pub fn is_modified(&self) -> bool {
+        self.magic == PCAP_MAGIC_MODIFIED
+    }
+
+    /// Number of timestamp units per second
+    pub fn ts_resolution(&self) -> u64 {
+        if self.is_nanosecond() {
+            1_000_000_000
+        } else {
+            1_000_000
+        }
+    }
+
+    /// Size of the header of packet records
+    pub fn record_header_size(&self) -> usize {
+        if self.is_modified() {
+            24
+        } else {
+            16
+        }
+    }
+}
+
+/// Additional fields of modified pcap records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifiedRecord {
+    pub if_index: u32,
+    /// Ethernet protocol
+    pub protocol: u16,
+    /// Packet type (broadcast, multicast, outgoing, ...)
+    pub pkt_type: u8,
+}
+
+/// A packet record of a legacy pcap file
+#[derive(Debug)]
+pub struct LegacyPacket<'a> {
+    pub ts_sec: u32,
+    /// Fraction of second, in microseconds or nanoseconds (see [`PcapHeader::ts_resolution`])
+    pub ts_frac: u32,
+    pub captured_len: u32,
+    pub original_len: u32,
+    /// Additional fields, for modified pcap files
+    pub modified: Option<ModifiedRecord>,
+    pub data: &'a [u8],
+}
+
+impl<'a> LegacyPacket<'a> {
+    /// Timestamp, in units of `ts_resolution` per second
+    pub fn timestamp(&self, ts_resolution: u64) -> u64 {
+        u64::from(self.ts_sec) * ts_resolution + u64::from(self.ts_frac)
+    }
+}
+
+/// Parse a legacy pcap file header
+///
+/// The byte order is detected from the magic number, and returned in `endianness`.
+pub fn pcap_header<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], PcapHeader, E>
+where
+    E: ParseError<&'a [u8]>,
+{
+    let (rest, header) = take(PCAP_HEADER_SIZE)(input)?;
+    let magic = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let res = match magic {
+        PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS | PCAP_MAGIC_MODIFIED => {
+            pcap_header_fields::<BigEndian, E>(header)
+        }
+        _ if is_legacy_magic(&header[..4]) => pcap_header_fields::<LittleEndian, E>(header),
+        _ => Err(nom::Err::Error(E::from_error_kind(
+            input,
+            ErrorKind::Verify,
+        ))),
+    };
+    res.map(|(_, header)| (rest, header))
+}
+
+fn pcap_header_fields<'a, B, E>(input: &'a [u8]) -> IResult<&'a [u8], PcapHeader, E>
+where
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    let (input, magic) = B::parse_u32(input)?;
+    let (input, version_major) = B::parse_u16(input)?;
+    let (input, version_minor) = B::parse_u16(input)?;
+    let (input, thiszone) = B::parse_u32(input)?;
+    let (input, sigfigs) = B::parse_u32(input)?;
+    let (input, snap_len) = B::parse_u32(input)?;
+    let (input, network) = B::parse_u32(input)?;
+
+    Ok((
+        input,
+        PcapHeader {
+            magic,
+            version_major,
+            version_minor,
+            thiszone: thiszone as i32,
+            sigfigs,
+            snap_len,
+            network,
+            endianness: B::ENDIANNESS,
+        },
+    ))
+}
+
+/// Parse a packet record of a legacy pcap file, with byte order `B`
+///
+/// `modified` must be set for modified pcap files (see [`PcapHeader::is_modified`]).
+pub fn legacy_packet<'a, B, E>(
+    input: &'a [u8],
+    modified: bool,
+) -> IResult<&'a [u8], LegacyPacket<'a>, E>
+where
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    let (input, header) = take(if modified { 24usize } else { 16 })(input)?;
+    let (hdr, ts_sec) = B::parse_u32(header)?;
+    let (hdr, ts_frac) = B::parse_u32(hdr)?;
+    let (hdr, captured_len) = B::parse_u32(hdr)?;
+    let (hdr, original_len) = B::parse_u32(hdr)?;
+    let modified = if modified {
+        let (hdr, if_index) = B::parse_u32(hdr)?;
+        let (hdr, protocol) = B::parse_u16(hdr)?;
+        Some(ModifiedRecord {
+            if_index,
+            protocol,
+            pkt_type: hdr[0],
+        })
+    } else {
+        None
+    };
+    let (input, data) = take(captured_len as usize)(input)?;
+
+    Ok((
+        input,
+        LegacyPacket {
+            ts_sec,
+            ts_frac,
+            captured_len,
+            original_len,
+            modified,
+            data,
+        },
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        endian::{BigEndian, ByteOrder, Endianness, LittleEndian},
+        legacy::{is_legacy_magic, legacy_packet, pcap_header, PCAP_MAGIC_MODIFIED},
+    };
+
+    pub(crate) const LEGACY_LE_US: &[u8] = include_bytes!("../assets/legacy_le_us.pcap");
+    pub(crate) const LEGACY_BE_NS: &[u8] = include_bytes!("../assets/legacy_be_ns.pcap");
+    pub(crate) const LEGACY_MODIFIED: &[u8] = include_bytes!("../assets/legacy_modified.pcap");
+
+    #[test]
+    fn pcap_header_works() -> Result<()> {
+        let (rest, header) = pcap_header::<VerboseError<&[u8]>>(LEGACY_LE_US)?;
+        assert_eq!(344, rest.len());
+        assert_eq!(Endianness::Little, header.endianness);
+        assert_eq!((2, 4), (header.version_major, header.version_minor));
+        assert_eq!(262_144, header.snap_len);
+        assert_eq!(1, header.link_type());
+        assert_eq!(None, header.fcs_len());
+        assert_eq!(1_000_000, header.ts_resolution());
+        assert!(!header.is_modified());
+
+        let (_, header) = pcap_header::<VerboseError<&[u8]>>(LEGACY_BE_NS)?;
+        assert_eq!(Endianness::Big, header.endianness);
+        assert_eq!(1, header.link_type());
+        assert_eq!(Some(4), header.fcs_len());
+        assert_eq!(1_000_000_000, header.ts_resolution());
+
+        let (_, header) = pcap_header::<VerboseError<&[u8]>>(LEGACY_MODIFIED)?;
+        assert_eq!(PCAP_MAGIC_MODIFIED, header.magic);
+        assert!(header.is_modified());
+        assert_eq!(24, header.record_header_size());
+        Ok(())
+    }
+
+    #[test]
+    fn pcap_header_fcs_len() -> Result<()> {
+        let (_, mut header) = pcap_header::<VerboseError<&[u8]>>(LEGACY_LE_US)?;
+        header.set_fcs_len(Some(4));
+        assert_eq!(0x5000_0001, header.network);
+        assert_eq!(Some(4), header.fcs_len());
+        header.set_fcs_len(None);
+        assert_eq!(1, header.network);
+        Ok(())
+    }
+
+    #[test]
+    fn pcap_header_bad_magic() {
+        assert!(pcap_header::<VerboseError<&[u8]>>(&LEGACY_LE_US[1..]).is_err());
+        assert!(!is_legacy_magic(b"\x0a\x0d\x0d\x0a"));
+        assert!(is_legacy_magic(&LEGACY_BE_NS[..4]));
+        // not enough data
+        assert!(matches!(
+            pcap_header::<VerboseError<&[u8]>>(&LEGACY_LE_US[..20]),
+            Err(nom::Err::Incomplete(_))
+        ));
+    }
+
+    /// Check the first two packets, and return the timestamp of the second one
+    fn check_packet<B: ByteOrder>(input: &'static [u8], modified: bool) -> Result<(u32, u32)> {
+        let (rest, packet) = legacy_packet::<B, VerboseError<&[u8]>>(input, modified)?;
+        assert_eq!((74, 74), (packet.captured_len, packet.original_len));
+        assert_eq!(&[0x08, 0x00], &packet.data[12..14]);
+        let (_, packet) = legacy_packet::<B, VerboseError<&[u8]>>(rest, modified)?;
+        assert_eq!(74, packet.data.len());
+        Ok((packet.ts_sec, packet.ts_frac))
+    }
+
+    #[test]
+    fn legacy_packet_works() -> Result<()> {
+        assert_eq!(
+            (1_658_321_070, 679_979),
+            check_packet::<LittleEndian>(&LEGACY_LE_US[24..], false)?
+        );
+        assert_eq!(
+            (1_658_321_070, 679_979_007),
+            check_packet::<BigEndian>(&LEGACY_BE_NS[24..], false)?
+        );
+        check_packet::<LittleEndian>(&LEGACY_MODIFIED[24..], true)?;
+
+        let (_, packet) =
+            legacy_packet::<LittleEndian, VerboseError<&[u8]>>(&LEGACY_MODIFIED[24..], true)?;
+        let modified = packet.modified.as_ref().unwrap();
+        assert_eq!(
+            (2, 0x0800, 0),
+            (modified.if_index, modified.protocol, modified.pkt_type)
+        );
+        assert_eq!(1_658_321_070_679_827, packet.timestamp(1_000_000));
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_packet_truncated() {
+        let res = legacy_packet::<LittleEndian, VerboseError<&[u8]>>(&LEGACY_LE_US[24..60], false);
+        assert!(matches!(res, Err(nom::Err::Incomplete(_))));
+    }
+}
@@ -2,12 +2,39 @@
 #![allow(dead_code)]
 
 mod block;
+mod custom;
+mod decryption_secrets;
 mod endian;
+mod enhanced_packet;
 mod error;
+mod interface_description;
+mod interface_statistics;
+mod journal_export;
+mod legacy;
+mod name_resolution;
 mod options;
+mod reader;
+mod recovery;
 mod section_header;
+mod simple_packet;
+mod writer;
 
-pub use block::BlockType;
-pub use endian::{NativeEndian, NetworkEndian};
+pub use block::{
+    parse_block, parse_block_be, parse_block_le, Block, BlockParser, BlockType, UnknownBlock,
+};
+pub use custom::Custom;
+pub use decryption_secrets::*;
+pub use endian::{BigEndian, ByteOrder, Endianness, LittleEndian, NativeEndian, NetworkEndian};
+pub use enhanced_packet::EnhancedPacket;
 pub use error::{Error, Result};
-pub use section_header::SectionHeader;
+pub use interface_description::InterfaceDescription;
+pub use interface_statistics::InterfaceStatistics;
+pub use journal_export::SystemJournalExport;
+pub use legacy::*;
+pub use name_resolution::*;
+pub use options::{BlockOption, EpbOption, IfOption, IsbOption, NsOption, Options, ShbOption};
+pub use reader::{ts_resolution_from, Format, Interface, PcapNgReader};
+pub use recovery::{SkippedRange, MAX_BLOCK_SIZE, MAX_TIMESTAMP_GAP};
+pub use section_header::{section_header, SectionHeader};
+pub use simple_packet::SimplePacket;
+pub use writer::{LegacyPcapWriter, PcapNgWriter};
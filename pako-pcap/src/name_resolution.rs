@@ -0,0 +1,189 @@
+//!
+//! Name Resolution Block (NRB) support
+//!
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use nom::{bytes::complete::take, error::ParseError, IResult};
+
+use crate::{
+    align,
+    block::{options_field, BlockParser},
+    endian::ByteOrder,
+    BlockType,
+};
+
+/// End of the list of records
+pub const NRES_ENDOFRECORD: u16 = 0;
+/// IPv4 address, followed by one or more names
+pub const NRES_IP4RECORD: u16 = 1;
+/// IPv6 address, followed by one or more names
+pub const NRES_IP6RECORD: u16 = 2;
+/// EUI-48 address, followed by one or more names
+pub const NRES_EUI48RECORD: u16 = 3;
+/// EUI-64 address, followed by one or more names
+pub const NRES_EUI64RECORD: u16 = 4;
+
+/// A single name resolution record
+#[derive(Debug)]
+pub struct NameRecord<'a> {
+    pub record_type: u16,
+    /// Record value, without padding
+    pub value: &'a [u8],
+}
+
+impl<'a> NameRecord<'a> {
+    /// Size of the address, for known record types
+    fn address_len(&self) -> Option<usize> {
+        match self.record_type {
+            NRES_IP4RECORD => Some(4),
+            NRES_IP6RECORD => Some(16),
+            NRES_EUI48RECORD => Some(6),
+            NRES_EUI64RECORD => Some(8),
+            _ => None,
+        }
+    }
+
+    /// IP address of an IPv4 or IPv6 record
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        match self.record_type {
+            NRES_IP4RECORD => {
+                let addr: [u8; 4] = self.value.get(..4)?.try_into().ok()?;
+                Some(IpAddr::V4(Ipv4Addr::from(addr)))
+            }
+            NRES_IP6RECORD => {
+                let addr: [u8; 16] = self.value.get(..16)?.try_into().ok()?;
+                Some(IpAddr::V6(Ipv6Addr::from(addr)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Raw address (IPv4, IPv6, EUI-48 or EUI-64)
+    pub fn address(&self) -> Option<&'a [u8]> {
+        self.value.get(..self.address_len()?)
+    }
+
+    /// Names associated to the address (zero-terminated strings, without terminator)
+    pub fn names(&self) -> impl Iterator<Item = &'a [u8]> {
+        let names = self
+            .address_len()
+            .and_then(|len| self.value.get(len..))
+            .unwrap_or_default();
+        names.split(|&b| b == 0).filter(|name| !name.is_empty())
+    }
+}
+
+/// The Name Resolution Block (NRB) is used to support the correlation of
+/// numeric addresses (present in the captured packets) and their
+/// corresponding canonical names.
+#[derive(Debug)]
+pub struct NameResolution<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub records: Vec<NameRecord<'a>>,
+    pub options: Option<&'a [u8]>,
+    pub total_length_dup: u32,
+}
+
+/// Parse a record, padding included
+fn name_record<'a, B, E>(input: &'a [u8]) -> IResult<&'a [u8], NameRecord<'a>, E>
+where
+    B: ByteOrder,
+    E: ParseError<&'a [u8]>,
+{
+    let (input, record_type) = B::parse_u16(input)?;
+    let (input, len) = B::parse_u16(input)?;
+    let (input, value) = take(align!(len as usize, 4))(input)?;
+
+    Ok((
+        input,
+        NameRecord {
+            record_type,
+            value: &value[..len as usize],
+        },
+    ))
+}
+
+impl<'a> BlockParser<'a> for NameResolution<'a> {
+    const HEADER_SIZE: usize = 0;
+    const BLOCK_TYPE: BlockType = BlockType::NameResolution;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let mut input = blk_body;
+        let mut records = Vec::new();
+        // the end of records is optional if there are no options
+        while !input.is_empty() {
+            let (rem, record) = name_record::<B, E>(input)?;
+            input = rem;
+            if record.record_type == NRES_ENDOFRECORD {
+                break;
+            }
+            records.push(record);
+        }
+
+        Ok((
+            &[],
+            NameResolution {
+                block_type: BlockType::NameResolution,
+                total_length: blk_len1,
+                records,
+                options: options_field(input),
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        name_resolution::{NameResolution, NRES_IP4RECORD, NRES_IP6RECORD},
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::NameResolution, 0);
+        let (_, nrb) = NameResolution::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(2, nrb.records.len());
+
+        let r = &nrb.records[0];
+        assert_eq!(NRES_IP4RECORD, r.record_type);
+        assert_eq!(Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), r.ip_addr());
+        assert_eq!(vec![&b"localhost"[..]], r.names().collect::<Vec<_>>());
+
+        let r = &nrb.records[1];
+        assert_eq!(NRES_IP6RECORD, r.record_type);
+        assert_eq!(Some(IpAddr::V6(Ipv6Addr::LOCALHOST)), r.ip_addr());
+        assert_eq!(Some(16), r.address().map(|a| a.len()));
+        assert_eq!(vec![&b"localhost"[..]], r.names().collect::<Vec<_>>());
+
+        assert_eq!(Some(12), nrb.options.map(|o| o.len()));
+        Ok(())
+    }
+
+    #[test]
+    fn name_resolution_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+}
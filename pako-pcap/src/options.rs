@@ -2,9 +2,18 @@
 //! Optional fields support
 //!
 
-use nom::{bytes::complete::take, number::complete::be_u16, IResult};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str,
+};
 
-use crate::Error;
+use nom::{bytes::complete::take, IResult};
+
+use crate::{
+    endian::{ByteOrder, Endianness},
+    writer::Body,
+    BlockType, Error,
+};
 
 /// Align input value `val` to a power of 2 alignment `align`
 #[macro_export]
@@ -15,6 +24,9 @@ macro_rules! align {
 }
 
 /// Code that specifies the type of the current TLV record
+///
+/// Only the codes shared by several blocks are listed here, other codes depend on the
+/// block type (see [`BlockOption`]).
 #[repr(u16)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Code {
@@ -53,34 +65,519 @@ impl TryFrom<u16> for Code {
 /// is not really needed for packet processing.
 #[derive(Debug)]
 pub struct Option<'a> {
-    pub code: Code,
+    /// Option code (see [`Code`])
+    pub code: u16,
     pub len: u16,
     pub value: &'a [u8],
 }
 
-/// Parse an [`Option`}
-pub(crate) fn option(input: &[u8]) -> IResult<&[u8], Option, Error<&[u8]>> {
-    let (input, code) = be_u16(input)?;
-    let (input, len) = be_u16(input)?;
+/// Parse an [`Option`], with byte order `B`
+pub(crate) fn option<B: ByteOrder>(input: &[u8]) -> IResult<&[u8], Option<'_>, Error<&[u8]>> {
+    let (input, code) = B::parse_u16(input)?;
+    let (input, len) = B::parse_u16(input)?;
     let (input, value) = take(align!(len, 4))(input)?;
 
-    Ok((
-        input,
-        Option {
-            code: Code::try_from(code).map_err(nom::Err::Error)?,
-            len,
+    Ok((input, Option { code, len, value }))
+}
+
+/// Iterator over the options of an options field, without decoding the option code
+///
+/// Items are `(code, value)`, where `value` does not include padding. Iteration stops at
+/// the end of options, or on a truncated option.
+#[derive(Clone)]
+pub(crate) struct RawOptions<'a> {
+    endianness: Endianness,
+    input: &'a [u8],
+}
+
+impl<'a> RawOptions<'a> {
+    pub(crate) fn new(endianness: Endianness, input: std::option::Option<&'a [u8]>) -> Self {
+        RawOptions {
+            endianness,
+            input: input.unwrap_or_default(),
+        }
+    }
+
+    fn read_u16(&self, b: [u8; 2]) -> u16 {
+        match self.endianness {
+            Endianness::Big => u16::from_be_bytes(b),
+            Endianness::Little => u16::from_le_bytes(b),
+        }
+    }
+}
+
+impl<'a> Iterator for RawOptions<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        if self.input.len() < 4 {
+            return None;
+        }
+        let code = self.read_u16([self.input[0], self.input[1]]);
+        let len = self.read_u16([self.input[2], self.input[3]]) as usize;
+        if code == Code::EndOfOpt as u16 || self.input.len() < 4 + len {
+            self.input = &[];
+            return None;
+        }
+        let value = &self.input[4..4 + len];
+        self.input = &self.input[(4 + align!(len, 4)).min(self.input.len())..];
+        Some((code, value))
+    }
+}
+
+/// Read an integer from an option value, in the byte order of the section
+macro_rules! read_int {
+    ($t:ty, $endianness:expr, $value:expr) => {
+        $value
+            .get(..std::mem::size_of::<$t>())
+            .and_then(|b| b.try_into().ok())
+            .map(|b| match $endianness {
+                Endianness::Big => <$t>::from_be_bytes(b),
+                Endianness::Little => <$t>::from_le_bytes(b),
+            })
+    };
+}
+
+/// Timestamp stored as two 32-bit values, high part first
+fn read_timestamp(endianness: Endianness, value: &[u8]) -> std::option::Option<u64> {
+    let high = read_int!(u32, endianness, value)?;
+    let low = read_int!(u32, endianness, value.get(4..)?)?;
+    Some((u64::from(high) << 32) | u64::from(low))
+}
+
+/// UTF-8 string value (some writers add a terminating zero, which is removed)
+fn read_str(value: &[u8]) -> std::option::Option<&str> {
+    let len = value.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+    str::from_utf8(&value[..len]).ok()
+}
+
+/// Value of a fixed-size array option
+fn read_array<const N: usize>(value: &[u8]) -> std::option::Option<[u8; N]> {
+    value.get(..N)?.try_into().ok()
+}
+
+/// Section Header Block options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShbOption<'a> {
+    /// `shb_hardware`
+    Hardware(&'a str),
+    /// `shb_os`
+    Os(&'a str),
+    /// `shb_userappl`
+    UserAppl(&'a str),
+}
+
+/// Interface Description Block options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfOption<'a> {
+    /// `if_name`
+    Name(&'a str),
+    /// `if_description`
+    Description(&'a str),
+    /// `if_IPv4addr`
+    Ipv4Addr { addr: Ipv4Addr, netmask: Ipv4Addr },
+    /// `if_IPv6addr`
+    Ipv6Addr { addr: Ipv6Addr, prefix_len: u8 },
+    /// `if_MACaddr`
+    MacAddr([u8; 6]),
+    /// `if_EUIaddr`
+    EuiAddr([u8; 8]),
+    /// `if_speed`, in bits per second
+    Speed(u64),
+    /// `if_tsresol`, see [`crate::ts_resolution_from`]
+    TsResol(u8),
+    /// `if_tzone`
+    Tzone(u32),
+    /// `if_filter`: filter type (0: libpcap filter string, 1: BPF program) and filter
+    Filter { kind: u8, filter: &'a [u8] },
+    /// `if_os`
+    Os(&'a str),
+    /// `if_fcslen`
+    FcsLen(u8),
+    /// `if_tsoffset`, in seconds
+    TsOffset(i64),
+    /// `if_hardware`
+    Hardware(&'a str),
+    /// `if_txspeed`, in bits per second
+    TxSpeed(u64),
+    /// `if_rxspeed`, in bits per second
+    RxSpeed(u64),
+}
+
+/// Enhanced Packet Block options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpbOption<'a> {
+    /// `epb_flags` (direction, reception type, FCS length, link-layer errors)
+    Flags(u32),
+    /// `epb_hash`: algorithm and hash value
+    Hash { algorithm: u8, value: &'a [u8] },
+    /// `epb_dropcount`: packets lost between this packet and the preceding one
+    DropCount(u64),
+    /// `epb_packetid`
+    PacketId(u64),
+    /// `epb_queue`
+    Queue(u32),
+    /// `epb_verdict`: verdict type (0: hardware, 1: eBPF TC, 2: eBPF XDP) and data
+    Verdict { kind: u8, value: &'a [u8] },
+}
+
+/// Interface Statistics Block options
+///
+/// Counters are 64-bit values, timestamps use the resolution of the interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsbOption {
+    /// `isb_starttime`
+    StartTime(u64),
+    /// `isb_endtime`
+    EndTime(u64),
+    /// `isb_ifrecv`: packets received from the physical interface
+    IfRecv(u64),
+    /// `isb_ifdrop`: packets dropped by the interface
+    IfDrop(u64),
+    /// `isb_filteraccept`: packets accepted by the filter
+    FilterAccept(u64),
+    /// `isb_osdrop`: packets dropped by the operating system
+    OsDrop(u64),
+    /// `isb_usrdeliv`: packets delivered to the user
+    UsrDeliv(u64),
+}
+
+/// Name Resolution Block options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NsOption<'a> {
+    /// `ns_dnsname`
+    DnsName(&'a str),
+    /// `ns_dnsIP4addr`
+    DnsIp4Addr(Ipv4Addr),
+    /// `ns_dnsIP6addr`
+    DnsIp6Addr(Ipv6Addr),
+}
+
+/// A decoded option
+///
+/// Options with an unknown code, or a value which cannot be decoded (for ex. too short)
+/// are returned as `Raw`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockOption<'a> {
+    /// `opt_comment`
+    Comment(&'a str),
+    /// `opt_custom`: Private Enterprise Number and custom data
+    Custom {
+        code: u16,
+        pen: u32,
+        value: &'a [u8],
+    },
+    Shb(ShbOption<'a>),
+    If(IfOption<'a>),
+    Epb(EpbOption<'a>),
+    Isb(IsbOption),
+    Ns(NsOption<'a>),
+    /// Unknown or invalid option, value does not include padding
+    Raw {
+        code: u16,
+        value: &'a [u8],
+    },
+}
+
+impl<'a> BlockOption<'a> {
+    /// Decode an option of a block of type `block_type`
+    pub fn decode(
+        block_type: BlockType,
+        endianness: Endianness,
+        code: u16,
+        value: &'a [u8],
+    ) -> Self {
+        Self::decode_typed(block_type, endianness, code, value)
+            .unwrap_or(BlockOption::Raw { code, value })
+    }
+
+    fn decode_typed(
+        block_type: BlockType,
+        e: Endianness,
+        code: u16,
+        value: &'a [u8],
+    ) -> std::option::Option<Self> {
+        let v = value;
+        let option = match (block_type, code) {
+            (_, 1) => BlockOption::Comment(read_str(v)?),
+            (_, 2988) | (_, 2989) | (_, 19372) | (_, 19373) => BlockOption::Custom {
+                code,
+                pen: read_int!(u32, e, v)?,
+                value: &v[4..],
+            },
+            (BlockType::SectionHeader, _) => BlockOption::Shb(match code {
+                2 => ShbOption::Hardware(read_str(v)?),
+                3 => ShbOption::Os(read_str(v)?),
+                4 => ShbOption::UserAppl(read_str(v)?),
+                _ => return None,
+            }),
+            (BlockType::InterfaceDescription, _) => BlockOption::If(match code {
+                2 => IfOption::Name(read_str(v)?),
+                3 => IfOption::Description(read_str(v)?),
+                4 => IfOption::Ipv4Addr {
+                    addr: Ipv4Addr::from(read_array::<4>(v)?),
+                    netmask: Ipv4Addr::from(read_array::<4>(v.get(4..)?)?),
+                },
+                5 => IfOption::Ipv6Addr {
+                    addr: Ipv6Addr::from(read_array::<16>(v)?),
+                    prefix_len: *v.get(16)?,
+                },
+                6 => IfOption::MacAddr(read_array(v)?),
+                7 => IfOption::EuiAddr(read_array(v)?),
+                8 => IfOption::Speed(read_int!(u64, e, v)?),
+                9 => IfOption::TsResol(*v.first()?),
+                10 => IfOption::Tzone(read_int!(u32, e, v)?),
+                11 => IfOption::Filter {
+                    kind: *v.first()?,
+                    filter: &v[1..],
+                },
+                12 => IfOption::Os(read_str(v)?),
+                13 => IfOption::FcsLen(*v.first()?),
+                14 => IfOption::TsOffset(read_int!(i64, e, v)?),
+                15 => IfOption::Hardware(read_str(v)?),
+                16 => IfOption::TxSpeed(read_int!(u64, e, v)?),
+                17 => IfOption::RxSpeed(read_int!(u64, e, v)?),
+                _ => return None,
+            }),
+            (BlockType::EnhancedPacket, _) => BlockOption::Epb(match code {
+                2 => EpbOption::Flags(read_int!(u32, e, v)?),
+                3 => EpbOption::Hash {
+                    algorithm: *v.first()?,
+                    value: &v[1..],
+                },
+                4 => EpbOption::DropCount(read_int!(u64, e, v)?),
+                5 => EpbOption::PacketId(read_int!(u64, e, v)?),
+                6 => EpbOption::Queue(read_int!(u32, e, v)?),
+                7 => EpbOption::Verdict {
+                    kind: *v.first()?,
+                    value: &v[1..],
+                },
+                _ => return None,
+            }),
+            (BlockType::InterfaceStatistic, _) => BlockOption::Isb(match code {
+                2 => IsbOption::StartTime(read_timestamp(e, v)?),
+                3 => IsbOption::EndTime(read_timestamp(e, v)?),
+                4 => IsbOption::IfRecv(read_int!(u64, e, v)?),
+                5 => IsbOption::IfDrop(read_int!(u64, e, v)?),
+                6 => IsbOption::FilterAccept(read_int!(u64, e, v)?),
+                7 => IsbOption::OsDrop(read_int!(u64, e, v)?),
+                8 => IsbOption::UsrDeliv(read_int!(u64, e, v)?),
+                _ => return None,
+            }),
+            (BlockType::NameResolution, _) => BlockOption::Ns(match code {
+                2 => NsOption::DnsName(read_str(v)?),
+                3 => NsOption::DnsIp4Addr(Ipv4Addr::from(read_array::<4>(v)?)),
+                4 => NsOption::DnsIp6Addr(Ipv6Addr::from(read_array::<16>(v)?)),
+                _ => return None,
+            }),
+            _ => return None,
+        };
+        Some(option)
+    }
+}
+
+impl<'a> BlockOption<'a> {
+    /// Option code
+    pub fn code(&self) -> u16 {
+        match self {
+            BlockOption::Comment(_) => Code::Comment as u16,
+            BlockOption::Custom { code, .. } | BlockOption::Raw { code, .. } => *code,
+            BlockOption::Shb(o) => match o {
+                ShbOption::Hardware(_) => 2,
+                ShbOption::Os(_) => 3,
+                ShbOption::UserAppl(_) => 4,
+            },
+            BlockOption::If(o) => match o {
+                IfOption::Name(_) => 2,
+                IfOption::Description(_) => 3,
+                IfOption::Ipv4Addr { .. } => 4,
+                IfOption::Ipv6Addr { .. } => 5,
+                IfOption::MacAddr(_) => 6,
+                IfOption::EuiAddr(_) => 7,
+                IfOption::Speed(_) => 8,
+                IfOption::TsResol(_) => 9,
+                IfOption::Tzone(_) => 10,
+                IfOption::Filter { .. } => 11,
+                IfOption::Os(_) => 12,
+                IfOption::FcsLen(_) => 13,
+                IfOption::TsOffset(_) => 14,
+                IfOption::Hardware(_) => 15,
+                IfOption::TxSpeed(_) => 16,
+                IfOption::RxSpeed(_) => 17,
+            },
+            BlockOption::Epb(o) => match o {
+                EpbOption::Flags(_) => 2,
+                EpbOption::Hash { .. } => 3,
+                EpbOption::DropCount(_) => 4,
+                EpbOption::PacketId(_) => 5,
+                EpbOption::Queue(_) => 6,
+                EpbOption::Verdict { .. } => 7,
+            },
+            BlockOption::Isb(o) => match o {
+                IsbOption::StartTime(_) => 2,
+                IsbOption::EndTime(_) => 3,
+                IsbOption::IfRecv(_) => 4,
+                IsbOption::IfDrop(_) => 5,
+                IsbOption::FilterAccept(_) => 6,
+                IsbOption::OsDrop(_) => 7,
+                IsbOption::UsrDeliv(_) => 8,
+            },
+            BlockOption::Ns(o) => match o {
+                NsOption::DnsName(_) => 2,
+                NsOption::DnsIp4Addr(_) => 3,
+                NsOption::DnsIp6Addr(_) => 4,
+            },
+        }
+    }
+
+    /// Append the option value, without padding, to `body`
+    fn write_value(&self, body: &mut Body) {
+        match self {
+            BlockOption::Comment(s) => body.put_bytes(s.as_bytes()),
+            BlockOption::Custom { pen, value, .. } => {
+                body.put_u32(*pen);
+                body.put_bytes(value);
+            }
+            BlockOption::Raw { value, .. } => body.put_bytes(value),
+            BlockOption::Shb(
+                ShbOption::Hardware(s) | ShbOption::Os(s) | ShbOption::UserAppl(s),
+            ) => body.put_bytes(s.as_bytes()),
+            BlockOption::If(o) => match o {
+                IfOption::Name(s)
+                | IfOption::Description(s)
+                | IfOption::Os(s)
+                | IfOption::Hardware(s) => body.put_bytes(s.as_bytes()),
+                IfOption::Ipv4Addr { addr, netmask } => {
+                    body.put_bytes(&addr.octets());
+                    body.put_bytes(&netmask.octets());
+                }
+                IfOption::Ipv6Addr { addr, prefix_len } => {
+                    body.put_bytes(&addr.octets());
+                    body.put_u8(*prefix_len);
+                }
+                IfOption::MacAddr(addr) => body.put_bytes(addr),
+                IfOption::EuiAddr(addr) => body.put_bytes(addr),
+                IfOption::Speed(v) | IfOption::TxSpeed(v) | IfOption::RxSpeed(v) => {
+                    body.put_u64(*v)
+                }
+                IfOption::TsResol(v) | IfOption::FcsLen(v) => body.put_u8(*v),
+                IfOption::Tzone(v) => body.put_u32(*v),
+                IfOption::Filter { kind, filter } => {
+                    body.put_u8(*kind);
+                    body.put_bytes(filter);
+                }
+                IfOption::TsOffset(v) => body.put_i64(*v),
+            },
+            BlockOption::Epb(o) => match o {
+                EpbOption::Flags(v) | EpbOption::Queue(v) => body.put_u32(*v),
+                EpbOption::Hash {
+                    algorithm: kind,
+                    value,
+                }
+                | EpbOption::Verdict { kind, value } => {
+                    body.put_u8(*kind);
+                    body.put_bytes(value);
+                }
+                EpbOption::DropCount(v) | EpbOption::PacketId(v) => body.put_u64(*v),
+            },
+            BlockOption::Isb(o) => match o {
+                IsbOption::StartTime(ts) | IsbOption::EndTime(ts) => {
+                    body.put_u32((ts >> 32) as u32);
+                    body.put_u32(*ts as u32);
+                }
+                IsbOption::IfRecv(v)
+                | IsbOption::IfDrop(v)
+                | IsbOption::FilterAccept(v)
+                | IsbOption::OsDrop(v)
+                | IsbOption::UsrDeliv(v) => body.put_u64(*v),
+            },
+            BlockOption::Ns(o) => match o {
+                NsOption::DnsName(s) => body.put_bytes(s.as_bytes()),
+                NsOption::DnsIp4Addr(addr) => body.put_bytes(&addr.octets()),
+                NsOption::DnsIp6Addr(addr) => body.put_bytes(&addr.octets()),
+            },
+        }
+    }
+}
+
+/// Append an options field to `body`
+///
+/// The field is terminated by `opt_endofopt`, unless `options` is empty. Returns
+/// `Error::Option` if the value of an option is larger than 65535 bytes.
+pub(crate) fn write_options(
+    body: &mut Body,
+    options: &[BlockOption],
+) -> Result<(), Error<&'static [u8]>> {
+    if options.is_empty() {
+        return Ok(());
+    }
+    for option in options {
+        let mut value = Body::new(body.endianness());
+        option.write_value(&mut value);
+        let len = u16::try_from(value.len()).map_err(|_| Error::Option(option.code()))?;
+        body.put_u16(option.code());
+        body.put_u16(len);
+        body.put_bytes(value.as_bytes());
+        body.pad();
+    }
+    body.put_u16(Code::EndOfOpt as u16);
+    body.put_u16(0);
+    Ok(())
+}
+
+/// Iterator over the decoded options of a block
+///
+/// Iteration stops at the end of options (`opt_endofopt`), or on a truncated option.
+#[derive(Clone)]
+pub struct Options<'a> {
+    block_type: BlockType,
+    raw: RawOptions<'a>,
+}
+
+impl<'a> Options<'a> {
+    /// Decode the options field `options` of a block of type `block_type`
+    pub fn new(
+        block_type: BlockType,
+        endianness: Endianness,
+        options: std::option::Option<&'a [u8]>,
+    ) -> Self {
+        Options {
+            block_type,
+            raw: RawOptions::new(endianness, options),
+        }
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = BlockOption<'a>;
+
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        let (code, value) = self.raw.next()?;
+        Some(BlockOption::decode(
+            self.block_type,
+            self.raw.endianness,
+            code,
             value,
-        },
-    ))
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
     use anyhow::Result;
 
     use crate::{
-        options::{option, Code},
-        Error,
+        block::tests::{BLOCKS_BE, BLOCKS_LE},
+        endian::{BigEndian, Endianness, LittleEndian},
+        options::{
+            option, write_options, BlockOption, Code, EpbOption, IfOption, IsbOption, NsOption,
+            Options, ShbOption,
+        },
+        parse_block_be, parse_block_le,
+        writer::Body,
+        BlockType, Error,
     };
 
     #[test]
@@ -128,8 +625,8 @@ mod tests {
         ];
 
         for (input, want) in tests {
-            let (_, option) = option(input.leak())?;
-            assert_eq!(want.0, option.code);
+            let (_, option) = option::<BigEndian>(input.leak())?;
+            assert_eq!(want.0 as u16, option.code);
             assert_eq!(want.1, option.len);
             assert_eq!(
                 want.2,
@@ -141,14 +638,213 @@ mod tests {
     }
 
     #[test]
-    fn option_fails() {
+    fn option_le_works() -> Result<()> {
+        let input = vec![
+            0x01, 0x00, 0x07, 0x00, 0x74, 0x65, 0x73, 0x74, 0x30, 0x30, 0x32, 0x00,
+        ];
+        let (rest, option) = option::<LittleEndian>(input.leak())?;
+        assert!(rest.is_empty());
+        assert_eq!(Code::Comment as u16, option.code);
+        assert_eq!(7, option.len);
+        assert_eq!(b"test002", &option.value[..7]);
+        Ok(())
+    }
+
+    #[test]
+    fn option_unknown_code() -> Result<()> {
         let input = vec![
             0x00, 0x42, 0x00, 0x09, 0x41, 0x70, 0x70, 0x6C, 0x65, 0x20, 0x4D, 0x42, 0x50, 0x00,
             0x00, 0x00,
         ];
+        let (rest, option) = option::<BigEndian>(input.leak())?;
+        assert!(rest.is_empty());
+        assert_eq!(66, option.code);
+        assert_eq!(9, option.len);
+        Ok(())
+    }
+
+    #[test]
+    fn option_truncated() {
+        let input = vec![0x00, 0x01, 0x00, 0x09, 0x41, 0x70, 0x70, 0x6C];
         assert!(matches!(
-            option(input.leak()).err(),
-            Some(nom::Err::Error(Error::Option(66)))
+            option::<BigEndian>(input.leak()).err(),
+            Some(nom::Err::Error(Error::Nom(_, _)))
         ));
     }
+
+    /// Build an options field from `(code, value)` pairs
+    fn options_field(e: Endianness, options: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut v = Vec::new();
+        let u16_bytes = |x: u16| match e {
+            Endianness::Big => x.to_be_bytes(),
+            Endianness::Little => x.to_le_bytes(),
+        };
+        for (code, value) in options {
+            v.extend_from_slice(&u16_bytes(*code));
+            v.extend_from_slice(&u16_bytes(value.len() as u16));
+            v.extend_from_slice(value);
+            v.resize(align!(v.len(), 4), 0);
+        }
+        v.extend_from_slice(&[0, 0, 0, 0]);
+        v
+    }
+
+    #[test]
+    fn block_options_works() {
+        for e in [Endianness::Big, Endianness::Little] {
+            let (u32_b, u64_b): ([u8; 4], [u8; 8]) = match e {
+                Endianness::Big => (3u32.to_be_bytes(), 10u64.to_be_bytes()),
+                Endianness::Little => (3u32.to_le_bytes(), 10u64.to_le_bytes()),
+            };
+            let ts: Vec<u8> = [u32_b, u32_b].concat();
+
+            let field = options_field(e, &[(1, b"hello"), (2, b"eth0"), (9, &[9]), (42, b"?")]);
+            let options: Vec<_> =
+                Options::new(BlockType::InterfaceDescription, e, Some(&field)).collect();
+            assert_eq!(
+                vec![
+                    BlockOption::Comment("hello"),
+                    BlockOption::If(IfOption::Name("eth0")),
+                    BlockOption::If(IfOption::TsResol(9)),
+                    BlockOption::Raw {
+                        code: 42,
+                        value: b"?"
+                    },
+                ],
+                options
+            );
+
+            let field = options_field(e, &[(2, &u32_b), (4, &u64_b), (3, &[2, 0xaa, 0xbb])]);
+            let options: Vec<_> =
+                Options::new(BlockType::EnhancedPacket, e, Some(&field)).collect();
+            assert_eq!(
+                vec![
+                    BlockOption::Epb(EpbOption::Flags(3)),
+                    BlockOption::Epb(EpbOption::DropCount(10)),
+                    BlockOption::Epb(EpbOption::Hash {
+                        algorithm: 2,
+                        value: &[0xaa, 0xbb]
+                    }),
+                ],
+                options
+            );
+
+            let field = options_field(e, &[(2, &ts), (5, &u64_b), (5, &u32_b)]);
+            let options: Vec<_> =
+                Options::new(BlockType::InterfaceStatistic, e, Some(&field)).collect();
+            assert_eq!(
+                vec![
+                    BlockOption::Isb(IsbOption::StartTime((3 << 32) | 3)),
+                    BlockOption::Isb(IsbOption::IfDrop(10)),
+                    // value too short
+                    BlockOption::Raw {
+                        code: 5,
+                        value: &u32_b
+                    },
+                ],
+                options
+            );
+
+            let field = options_field(e, &[(2, b"dns\0"), (3, &[10, 0, 0, 1])]);
+            let options: Vec<_> =
+                Options::new(BlockType::NameResolution, e, Some(&field)).collect();
+            assert_eq!(
+                vec![
+                    BlockOption::Ns(NsOption::DnsName("dns")),
+                    BlockOption::Ns(NsOption::DnsIp4Addr(Ipv4Addr::new(10, 0, 0, 1))),
+                ],
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn block_options_write_read() -> Result<()> {
+        let options = [
+            (
+                BlockType::SectionHeader,
+                vec![BlockOption::Shb(ShbOption::Hardware("x86_64"))],
+            ),
+            (
+                BlockType::InterfaceDescription,
+                vec![
+                    BlockOption::If(IfOption::Ipv6Addr {
+                        addr: Ipv6Addr::LOCALHOST,
+                        prefix_len: 128,
+                    }),
+                    BlockOption::If(IfOption::MacAddr([0, 1, 2, 3, 4, 5])),
+                    BlockOption::If(IfOption::Speed(1_000_000_000)),
+                    BlockOption::If(IfOption::Filter {
+                        kind: 0,
+                        filter: b"tcp",
+                    }),
+                    BlockOption::If(IfOption::TsOffset(-3)),
+                ],
+            ),
+            (
+                BlockType::EnhancedPacket,
+                vec![
+                    BlockOption::Epb(EpbOption::PacketId(7)),
+                    BlockOption::Epb(EpbOption::Verdict {
+                        kind: 2,
+                        value: &[1, 0, 0, 0, 0, 0, 0, 0],
+                    }),
+                    BlockOption::Custom {
+                        code: 2988,
+                        pen: 32473,
+                        value: b"pako",
+                    },
+                ],
+            ),
+            (
+                BlockType::InterfaceStatistic,
+                vec![BlockOption::Isb(IsbOption::EndTime(u64::MAX - 1))],
+            ),
+            (
+                BlockType::NameResolution,
+                vec![BlockOption::Ns(NsOption::DnsIp6Addr(Ipv6Addr::LOCALHOST))],
+            ),
+        ];
+        for e in [Endianness::Big, Endianness::Little] {
+            for (block_type, options) in &options {
+                let mut body = Body::new(e);
+                write_options(&mut body, options)?;
+                let decoded: Vec<_> = Options::new(*block_type, e, Some(body.as_bytes())).collect();
+                assert_eq!(options, &decoded);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn block_options_from_file() -> Result<()> {
+        for (mut input, e) in [
+            (BLOCKS_BE, Endianness::Big),
+            (BLOCKS_LE, Endianness::Little),
+        ] {
+            let mut options = Vec::new();
+            while !input.is_empty() {
+                let (rest, block) = match e {
+                    Endianness::Big => parse_block_be(input)?,
+                    Endianness::Little => parse_block_le(input)?,
+                };
+                options.extend(block.options(e));
+                input = rest;
+            }
+            assert_eq!(
+                vec![
+                    BlockOption::Shb(ShbOption::UserAppl("pako test")),
+                    BlockOption::If(IfOption::Name("lo")),
+                    BlockOption::If(IfOption::TsResol(9)),
+                    BlockOption::Comment("first packet"),
+                    BlockOption::Epb(EpbOption::Flags(1)),
+                    BlockOption::Ns(NsOption::DnsName("dns")),
+                    BlockOption::Isb(IsbOption::IfRecv(5)),
+                    BlockOption::Isb(IsbOption::IfDrop(0)),
+                ],
+                options
+            );
+        }
+        Ok(())
+    }
 }
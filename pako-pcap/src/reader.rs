@@ -0,0 +1,860 @@
+//!
+//! Streaming pcapng and legacy pcap reader
+//!
+
+use std::io::{ErrorKind, Read};
+
+use nom::Needed;
+
+use crate::{
+    block::{parse_block, Block},
+    endian::{BigEndian, Endianness, LittleEndian},
+    interface_description::InterfaceDescription,
+    legacy::{is_legacy_magic, legacy_packet, pcap_header, PcapHeader},
+    options::{BlockOption, IfOption, Options},
+    recovery::{check_file_header, Candidate, SkippedRange, SyncState, MAX_BLOCK_SIZE},
+    BlockType, Error,
+};
+
+/// Default size of the read buffer
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// Capture interface, as described by an Interface Description Block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub link_type: u16,
+    pub snap_len: u32,
+    /// Number of timestamp units per second (`if_tsresol`, default: microseconds)
+    pub ts_resolution: u64,
+    /// Offset of timestamps, in seconds (`if_tsoffset`)
+    pub ts_offset: i64,
+}
+
+impl Interface {
+    pub(crate) fn new(idb: &InterfaceDescription, endianness: Endianness) -> Self {
+        let options = Options::new(BlockType::InterfaceDescription, endianness, idb.options);
+        Self::with_options(idb.link_type, idb.snap_len, options)
+    }
+
+    /// Interface with the time resolution and offset of the IDB options `options`
+    pub(crate) fn with_options<'a>(
+        link_type: u16,
+        snap_len: u32,
+        options: impl IntoIterator<Item = BlockOption<'a>>,
+    ) -> Self {
+        let mut ts_resolution = 1_000_000;
+        let mut ts_offset = 0;
+        for option in options {
+            match option {
+                BlockOption::If(IfOption::TsResol(tsresol)) => {
+                    if let Some(resolution) = ts_resolution_from(tsresol) {
+                        ts_resolution = resolution;
+                    }
+                }
+                BlockOption::If(IfOption::TsOffset(offset)) => ts_offset = offset,
+                _ => (),
+            }
+        }
+        Interface {
+            link_type,
+            snap_len,
+            ts_resolution,
+            ts_offset,
+        }
+    }
+
+    /// Convert a timestamp of this interface to nanoseconds since the epoch
+    ///
+    /// `if_tsoffset` is added, timestamps before the epoch are clamped to `0`.
+    pub fn ts_to_nanos(&self, ts: u64) -> u64 {
+        let nanos = u128::from(ts) * 1_000_000_000 / u128::from(self.ts_resolution.max(1));
+        let nanos = nanos as i128 + i128::from(self.ts_offset) * 1_000_000_000;
+        nanos.clamp(0, i128::from(u64::MAX)) as u64
+    }
+
+    /// Convert nanoseconds since the epoch to a timestamp of this interface
+    ///
+    /// This is the inverse of [`Interface::ts_to_nanos`], precision is lost if the interface
+    /// resolution is lower than nanoseconds.
+    pub fn ts_from_nanos(&self, nanos: u64) -> u64 {
+        let nanos = i128::from(nanos) - i128::from(self.ts_offset) * 1_000_000_000;
+        let ts = nanos.max(0) * i128::from(self.ts_resolution) / 1_000_000_000;
+        ts.min(i128::from(u64::MAX)) as u64
+    }
+}
+
+impl From<&PcapHeader> for Interface {
+    fn from(header: &PcapHeader) -> Self {
+        Interface {
+            link_type: header.link_type(),
+            snap_len: header.snap_len,
+            ts_resolution: header.ts_resolution(),
+            ts_offset: i64::from(header.thiszone),
+        }
+    }
+}
+
+/// Format of a capture file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    PcapNg,
+    /// Legacy pcap, including the nanosecond and modified variants
+    Legacy,
+}
+
+/// Convert a `if_tsresol` value to a number of units per second
+///
+/// Returns `None` if the resolution does not fit in 64 bits.
+pub fn ts_resolution_from(tsresol: u8) -> Option<u64> {
+    let exp = u32::from(tsresol & 0x7f);
+    if tsresol & 0x80 == 0 {
+        10u64.checked_pow(exp)
+    } else {
+        2u64.checked_pow(exp)
+    }
+}
+
+/// Streaming reader of pcapng blocks
+///
+/// `PcapNgReader` reads data from any `Read` into a buffer, and parses blocks from it.
+/// The byte order of the current section and its interfaces are tracked while reading.
+///
+/// Legacy pcap files are also accepted: the file header is returned as a
+/// `Block::LegacyHeader`, followed by `Block::LegacyPacket` blocks. The header describes
+/// a single interface, with ID `0`.
+///
+/// The methods are modeled on the `PcapReaderIterator` interface of `pcap-parser`:
+///   - `next_block` parses the next block, but does not consume it
+///   - `consume` must then be called with the returned offset
+///   - on `Error::Incomplete`, `refill` reads more data. If the reader is exhausted,
+///     the input is truncated.
+///
+/// The buffer grows if a block is larger than its capacity, up to `MAX_BLOCK_SIZE`: larger
+/// blocks are considered corrupted, and return `Error::BlockSize`.
+///
+/// For simple uses, `read_block` handles all these steps.
+///
+/// ## Recovery mode
+///
+/// By default, a corrupted or truncated block ends reading with an error. In recovery mode
+/// (see `set_recovery`), `read_block` searches forward for the next plausible block instead,
+/// and resumes parsing from there. The skipped bytes are reported by `skipped`. If the file
+/// header itself is damaged, create the reader with `with_recovery`.
+/// See the `recovery` module for the validation of blocks.
+pub struct PcapNgReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// Start of unconsumed data in buffer
+    start: usize,
+    /// End of data in buffer
+    end: usize,
+    /// Total number of consumed bytes
+    consumed: usize,
+    /// Additional bytes needed to parse the current block, from last `Incomplete`
+    needed: usize,
+    reader_exhausted: bool,
+    format: Format,
+    /// Header of a legacy pcap file, once parsed
+    legacy_header: Option<PcapHeader>,
+    /// Position of the file header (not 0 if `with_recovery` skipped leading data)
+    header_offset: usize,
+    endianness: Endianness,
+    interfaces: Vec<Interface>,
+    /// Position of the last block used to update section state
+    last_state_update: Option<usize>,
+    /// Size of the block returned by `read_block`, to be consumed on next call
+    pending: usize,
+    recovery: bool,
+    /// Timestamp of the last packet, in nanoseconds
+    last_ts: Option<u64>,
+    skipped: Vec<SkippedRange>,
+}
+
+impl<R: Read> PcapNgReader<R> {
+    /// Create a new reader, with the default buffer size
+    pub fn new(reader: R) -> Result<Self, Error<&'static [u8]>> {
+        Self::with_capacity(DEFAULT_CAPACITY, reader)
+    }
+
+    /// Create a new reader, with a buffer of `capacity` bytes
+    ///
+    /// The input must start with a Section Header Block, or a legacy pcap file header.
+    pub fn with_capacity(capacity: usize, reader: R) -> Result<Self, Error<&'static [u8]>> {
+        let mut r = Self::empty(capacity, reader);
+        r.refill()?;
+        r.detect_format()?;
+        Ok(r)
+    }
+
+    /// Create a new reader in recovery mode, with the default buffer size
+    ///
+    /// Unlike `new`, the input does not need to start with a file header: data is skipped
+    /// until the first plausible Section Header Block or legacy pcap file header, and
+    /// reported by `skipped`. Returns `Error::Eof` if no header is found.
+    pub fn with_recovery(reader: R) -> Result<Self, Error<&'static [u8]>> {
+        let mut r = Self::empty(DEFAULT_CAPACITY, reader);
+        r.recovery = true;
+        r.refill()?;
+        let mut pos = 0;
+        loop {
+            let available = r.end - r.start;
+            let candidate = if pos < available {
+                check_file_header(&r.buffer[r.start + pos..r.end], r.reader_exhausted)
+            } else {
+                Candidate::Incomplete(0)
+            };
+            match candidate {
+                Candidate::Valid(_) => break,
+                Candidate::Invalid => pos += 1,
+                Candidate::Incomplete(_) if r.reader_exhausted => {
+                    if pos >= available {
+                        r.consume(available);
+                        break;
+                    }
+                    pos += 1;
+                }
+                Candidate::Incomplete(n) => {
+                    // drop skipped data, and read more
+                    let skip = pos.min(available);
+                    r.consume(skip);
+                    pos -= skip;
+                    r.needed = n;
+                    r.refill()?;
+                }
+            }
+        }
+        r.consume(pos);
+        r.needed = 0;
+        r.header_offset = r.consumed;
+        if r.consumed > 0 {
+            r.skipped.push(SkippedRange {
+                offset: 0,
+                len: r.consumed,
+                reason: "no file header".to_string(),
+            });
+        }
+        r.detect_format()?;
+        Ok(r)
+    }
+
+    fn empty(capacity: usize, reader: R) -> Self {
+        PcapNgReader {
+            reader,
+            buffer: vec![0; capacity.max(64)],
+            start: 0,
+            end: 0,
+            consumed: 0,
+            needed: 0,
+            reader_exhausted: false,
+            format: Format::PcapNg,
+            legacy_header: None,
+            header_offset: 0,
+            endianness: Endianness::Big,
+            interfaces: Vec::new(),
+            last_state_update: None,
+            pending: 0,
+            recovery: false,
+            last_ts: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Set the format from the magic of the file header, at the current position
+    fn detect_format(&mut self) -> Result<(), Error<&'static [u8]>> {
+        if self.end - self.start < 4 {
+            return Err(Error::Eof);
+        }
+        let magic = &self.buffer[self.start..self.start + 4];
+        if is_legacy_magic(magic) {
+            self.format = Format::Legacy;
+        } else if magic != (BlockType::SectionHeader as u32).to_be_bytes() {
+            let block_type = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+            return Err(Error::Type(block_type));
+        }
+        Ok(())
+    }
+
+    /// Format of the input
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Header of a legacy pcap file (`None` before the header is returned by `next_block`)
+    pub fn legacy_header(&self) -> Option<&PcapHeader> {
+        self.legacy_header.as_ref()
+    }
+
+    /// Byte order of the current section
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Interfaces of the current section, indexed by interface ID
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// Interface of the current section
+    pub fn interface(&self, interface_id: u32) -> Option<&Interface> {
+        self.interfaces.get(interface_id as usize)
+    }
+
+    /// Enable or disable recovery mode
+    pub fn set_recovery(&mut self, recovery: bool) {
+        self.recovery = recovery;
+    }
+
+    /// Test if recovery mode is enabled
+    pub fn recovery(&self) -> bool {
+        self.recovery
+    }
+
+    /// Byte ranges skipped by `resync`, in input order
+    pub fn skipped(&self) -> &[SkippedRange] {
+        &self.skipped
+    }
+
+    fn sync_state(&self) -> SyncState<'_> {
+        SyncState {
+            format: self.format,
+            legacy_header: self.legacy_header.as_ref(),
+            endianness: self.endianness,
+            interfaces: &self.interfaces,
+            last_ts: self.last_ts,
+            at_end: self.reader_exhausted,
+        }
+    }
+
+    /// Parse the next block, and return it with its size
+    ///
+    /// The block is not consumed: `consume` must be called with the returned size
+    /// before calling `next_block` again.
+    pub fn next_block(&mut self) -> Result<(usize, Block<'_>), Error<&'static [u8]>> {
+        let data = &self.buffer[self.start..self.end];
+        if data.is_empty() {
+            return Err(if self.reader_exhausted {
+                Error::Eof
+            } else {
+                Error::Incomplete(0)
+            });
+        }
+        let res = match (self.format, &self.legacy_header) {
+            (Format::PcapNg, _) => match self.endianness {
+                Endianness::Big => parse_block::<BigEndian, Error<&[u8]>>(data),
+                Endianness::Little => parse_block::<LittleEndian, Error<&[u8]>>(data),
+            },
+            // the file header is at the start of the input
+            (Format::Legacy, Some(header)) if self.consumed > self.header_offset => {
+                let modified = header.is_modified();
+                match self.endianness {
+                    Endianness::Big => legacy_packet::<BigEndian, Error<&[u8]>>(data, modified),
+                    Endianness::Little => {
+                        legacy_packet::<LittleEndian, Error<&[u8]>>(data, modified)
+                    }
+                }
+                .map(|(rest, packet)| (rest, Block::LegacyPacket(packet)))
+            }
+            (Format::Legacy, _) => pcap_header::<Error<&[u8]>>(data)
+                .map(|(rest, header)| (rest, Block::LegacyHeader(header))),
+        };
+        match res {
+            Ok((rest, block)) => {
+                self.needed = 0;
+                let offset = data.len() - rest.len();
+                let position = self.consumed;
+                // update state only once per block, if `next_block` is called again without
+                // `consume`
+                if self.last_state_update != Some(position) {
+                    let state = self.sync_state();
+                    if self.recovery && !state.plausible(&block, false) {
+                        return Err(Error::Nom(&[], nom::error::ErrorKind::Verify));
+                    }
+                    if let Some(ts) = state.packet_ts(&block) {
+                        self.last_ts = Some(ts);
+                    }
+                    self.last_state_update = Some(position);
+                    match block {
+                        Block::SectionHeader(ref shb) => {
+                            self.endianness = shb.endianness;
+                            self.interfaces.clear();
+                        }
+                        Block::InterfaceDescription(ref idb) => {
+                            self.interfaces.push(Interface::new(idb, self.endianness));
+                        }
+                        Block::LegacyHeader(ref header) => {
+                            self.endianness = header.endianness;
+                            self.interfaces = vec![Interface::from(header)];
+                            self.legacy_header = Some(header.clone());
+                        }
+                        _ => (),
+                    }
+                }
+                Ok((offset, block))
+            }
+            Err(nom::Err::Incomplete(needed)) => {
+                self.needed = match needed {
+                    Needed::Size(n) => n.get(),
+                    Needed::Unknown => 0,
+                };
+                // do not read a corrupted block length into memory
+                let size = data.len() + self.needed;
+                if size > MAX_BLOCK_SIZE {
+                    self.needed = 0;
+                    return Err(Error::BlockSize(size));
+                }
+                Err(Error::Incomplete(self.needed))
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => Err(e.into_static()),
+        }
+    }
+
+    /// Consume `offset` bytes of the buffer
+    pub fn consume(&mut self, offset: usize) {
+        let offset = offset.min(self.end - self.start);
+        self.start += offset;
+        self.consumed += offset;
+    }
+
+    /// Consume `offset` bytes of the buffer (data is only moved on `refill`)
+    pub fn consume_noshift(&mut self, offset: usize) {
+        self.consume(offset)
+    }
+
+    /// Unconsumed data of the buffer
+    ///
+    /// After `next_block`, the block is the first `offset` bytes of the data.
+    pub fn data(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+
+    /// Total number of consumed bytes
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Position of unconsumed data in the buffer
+    pub fn position(&self) -> usize {
+        self.start
+    }
+
+    /// Test if the underlying reader has reached end of input
+    pub fn reader_exhausted(&self) -> bool {
+        self.reader_exhausted
+    }
+
+    /// Move unconsumed data to the start of the buffer, and read more data
+    ///
+    /// The buffer is extended if needed to contain the current block, up to `MAX_BLOCK_SIZE`.
+    pub fn refill(&mut self) -> Result<(), Error<&'static [u8]>> {
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        let required = self.end + self.needed;
+        if required > MAX_BLOCK_SIZE
+            || (self.end == self.buffer.len() && self.buffer.len() >= MAX_BLOCK_SIZE)
+        {
+            self.needed = 0;
+            return Err(Error::BlockSize(required));
+        }
+        if required > self.buffer.len() {
+            self.buffer.resize(required, 0);
+        } else if self.end == self.buffer.len() {
+            // full buffer, and required size is unknown
+            self.buffer.resize(self.buffer.len() * 2, 0);
+        }
+        while !self.reader_exhausted && self.end < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[self.end..]) {
+                Ok(0) => self.reader_exhausted = true,
+                Ok(n) => self.end += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip data until the next plausible block, after an error at the current position
+    ///
+    /// The search starts one byte after the current position, refilling the buffer as needed.
+    /// The skipped bytes are added to `skipped`, with `reason`. Returns `false` if no block
+    /// was found before the end of input (all remaining data is then consumed).
+    pub fn resync(&mut self, reason: &str) -> Result<bool, Error<&'static [u8]>> {
+        let offset = self.consumed;
+        let mut pos = 1;
+        let found = loop {
+            let available = self.end - self.start;
+            let candidate = if pos < available {
+                let data = &self.buffer[self.start + pos..self.end];
+                self.sync_state().check(data)
+            } else {
+                Candidate::Incomplete(0)
+            };
+            match candidate {
+                Candidate::Valid(_) => break true,
+                Candidate::Invalid => pos += 1,
+                Candidate::Incomplete(_) if self.reader_exhausted => {
+                    if pos >= available {
+                        pos = available;
+                        break false;
+                    }
+                    pos += 1;
+                }
+                Candidate::Incomplete(n) => {
+                    // drop skipped data, and read more
+                    let skip = pos.min(available);
+                    self.consume(skip);
+                    pos -= skip;
+                    self.needed = n;
+                    self.refill()?;
+                }
+            }
+        };
+        self.consume(pos);
+        self.needed = 0;
+        let len = self.consumed - offset;
+        if len > 0 {
+            self.skipped.push(SkippedRange {
+                offset,
+                len,
+                reason: reason.to_string(),
+            });
+        }
+        Ok(found)
+    }
+
+    /// Read the next block, refilling the buffer as needed
+    ///
+    /// The block returned by the previous call is consumed. Returns `None` at end of input,
+    /// and `Error::Incomplete` if the input ends in the middle of a block.
+    ///
+    /// In recovery mode, parsing errors and truncated blocks are skipped using `resync`.
+    pub fn read_block(&mut self) -> Result<Option<Block<'_>>, Error<&'static [u8]>> {
+        let pending = std::mem::take(&mut self.pending);
+        self.consume(pending);
+        // make sure a complete block is available, before returning a borrowed block
+        let offset = loop {
+            let reader_exhausted = self.reader_exhausted;
+            let e = match self.next_block() {
+                Ok((offset, _)) => break offset,
+                Err(Error::Eof) => return Ok(None),
+                Err(Error::Incomplete(_)) if !reader_exhausted => {
+                    self.refill()?;
+                    continue;
+                }
+                Err(e @ Error::Io(_)) => return Err(e),
+                Err(e) => e,
+            };
+            if !self.recovery {
+                return Err(e);
+            }
+            if !self.resync(&e.to_string())? {
+                return Ok(None);
+            }
+        };
+        self.pending = offset;
+        let (_, block) = self.next_block()?;
+        Ok(Some(block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use anyhow::Result;
+
+    use crate::{
+        block::{
+            tests::{BLOCKS_BE, BLOCKS_LE},
+            Block, BlockType,
+        },
+        endian::Endianness,
+        legacy::tests::{LEGACY_BE_NS, LEGACY_LE_US, LEGACY_MODIFIED},
+        reader::{ts_resolution_from, Format, Interface, PcapNgReader},
+        Error, SkippedRange, MAX_BLOCK_SIZE,
+    };
+
+    /// A reader returning at most 7 bytes per call
+    struct SlowReader<'a>(&'a [u8]);
+
+    impl<'a> Read for SlowReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn block_types<R: Read>(reader: &mut PcapNgReader<R>) -> Result<Vec<u32>> {
+        let mut v = Vec::new();
+        while let Some(block) = reader.read_block()? {
+            v.push(block.block_type());
+        }
+        Ok(v)
+    }
+
+    #[test]
+    fn reader_works() -> Result<()> {
+        for (input, endianness) in [
+            (BLOCKS_BE, Endianness::Big),
+            (BLOCKS_LE, Endianness::Little),
+        ] {
+            // small buffer: blocks do not fit, and the buffer must grow
+            let mut reader = PcapNgReader::with_capacity(64, SlowReader(input)).unwrap();
+            let types = block_types(&mut reader)?;
+            assert_eq!(12, types.len());
+            assert_eq!(BlockType::SystemJournalExport as u32, types[11]);
+            assert_eq!(endianness, reader.endianness());
+            assert_eq!(
+                Some(&Interface {
+                    link_type: 1,
+                    snap_len: 262144,
+                    ts_resolution: 1_000_000_000,
+                    ts_offset: 0,
+                }),
+                reader.interface(0)
+            );
+            assert_eq!(input.len(), reader.consumed());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reader_sections() -> Result<()> {
+        let input = [BLOCKS_BE, BLOCKS_LE].concat();
+        let mut reader = PcapNgReader::new(Cursor::new(input)).unwrap();
+        let mut sections = Vec::new();
+        while let Some(block) = reader.read_block()? {
+            if let Block::SectionHeader(shb) = block {
+                sections.push(shb.endianness);
+            }
+        }
+        assert_eq!(vec![Endianness::Big, Endianness::Little], sections);
+        // interfaces of the first section were dropped
+        assert_eq!(1, reader.interfaces().len());
+        Ok(())
+    }
+
+    #[test]
+    fn reader_next_consume() {
+        let mut reader = PcapNgReader::new(Cursor::new(BLOCKS_LE)).unwrap();
+        let (offset, _) = reader.next_block().unwrap();
+        reader.consume(offset);
+        // calling next_block twice must not register the interface twice
+        let _ = reader.next_block().unwrap();
+        let (offset, _) = reader.next_block().unwrap();
+        reader.consume(offset);
+        assert_eq!(1, reader.interfaces().len());
+    }
+
+    #[test]
+    fn reader_truncated() {
+        let input = &BLOCKS_LE[..BLOCKS_LE.len() - 10];
+        let mut reader = PcapNgReader::with_capacity(64, SlowReader(input)).unwrap();
+        let mut count = 0;
+        let err = loop {
+            match reader.read_block() {
+                Ok(Some(_)) => count += 1,
+                Ok(None) => panic!("truncated input not detected"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(11, count);
+        assert!(matches!(err, Error::Incomplete(_)));
+        assert!(reader.reader_exhausted());
+    }
+
+    #[test]
+    fn reader_block_too_large() {
+        // corrupted length of the second EPB (offset 216): the block is not read into memory
+        let mut input = BLOCKS_LE.to_vec();
+        input[216 + 4..216 + 8].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        let mut reader = PcapNgReader::with_capacity(64, SlowReader(&input)).unwrap();
+        let mut count = 0;
+        let err = loop {
+            match reader.read_block() {
+                Ok(Some(_)) => count += 1,
+                Ok(None) => panic!("corrupted length not detected"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(3, count);
+        assert!(matches!(err, Error::BlockSize(_)));
+        assert!(reader.buffer.len() <= MAX_BLOCK_SIZE);
+        // in recovery mode, the block is skipped
+        let (count, skipped) = read_recovered(&input).unwrap();
+        assert_eq!(11, count);
+        assert_eq!(vec![(216, 120)], skipped);
+    }
+
+    #[test]
+    fn reader_not_pcapng() {
+        let input: &[u8] = b"GIF89a";
+        assert!(matches!(
+            PcapNgReader::new(Cursor::new(input)),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(
+            PcapNgReader::new(Cursor::new(Vec::new())),
+            Err(Error::Eof)
+        ));
+    }
+
+    #[test]
+    fn reader_legacy() -> Result<()> {
+        for (input, e, ts_resolution) in [
+            (LEGACY_LE_US, Endianness::Little, 1_000_000),
+            (LEGACY_BE_NS, Endianness::Big, 1_000_000_000),
+            (LEGACY_MODIFIED, Endianness::Little, 1_000_000),
+        ] {
+            let mut reader = PcapNgReader::with_capacity(64, SlowReader(input))?;
+            assert_eq!(Format::Legacy, reader.format());
+            assert!(reader.legacy_header().is_none());
+            let mut lengths = Vec::new();
+            while let Some(block) = reader.read_block()? {
+                match block {
+                    Block::LegacyHeader(header) => assert_eq!(e, header.endianness),
+                    Block::LegacyPacket(packet) => {
+                        assert_eq!(input == LEGACY_MODIFIED, packet.modified.is_some());
+                        lengths.push(packet.data.len());
+                    }
+                    _ => panic!("unexpected block"),
+                }
+            }
+            assert_eq!(vec![74, 74, 66, 66], lengths);
+            assert_eq!(e, reader.endianness());
+            assert_eq!(ts_resolution, reader.interfaces()[0].ts_resolution);
+            assert_eq!(input.len(), reader.consumed());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reader_legacy_truncated() -> Result<()> {
+        let mut reader = PcapNgReader::new(&LEGACY_LE_US[..150])?;
+        assert!(matches!(reader.read_block()?, Some(Block::LegacyHeader(_))));
+        assert!(matches!(reader.read_block()?, Some(Block::LegacyPacket(_))));
+        assert!(matches!(reader.read_block(), Err(Error::Incomplete(_))));
+        Ok(())
+    }
+
+    /// Read `input` in recovery mode, and return the number of blocks and skipped ranges
+    fn read_recovered(input: &[u8]) -> Result<(usize, Vec<(usize, usize)>)> {
+        let mut reader = PcapNgReader::with_capacity(64, SlowReader(input))?;
+        reader.set_recovery(true);
+        let types = block_types(&mut reader)?;
+        let skipped = skipped_ranges(&reader);
+        assert_eq!(input.len(), reader.consumed());
+        Ok((types.len(), skipped))
+    }
+
+    fn skipped_ranges<R: Read>(reader: &PcapNgReader<R>) -> Vec<(usize, usize)> {
+        reader
+            .skipped()
+            .iter()
+            .map(|SkippedRange { offset, len, .. }| (*offset, *len))
+            .collect()
+    }
+
+    #[test]
+    fn reader_recovery() -> Result<()> {
+        // wrong trailing length in the second EPB (offset 216, 120 bytes)
+        let mut input = BLOCKS_LE.to_vec();
+        input[216 + 116] = 0xff;
+        let mut reader = PcapNgReader::new(Cursor::new(&input))?;
+        assert!(block_types(&mut reader).is_err());
+        let (count, skipped) = read_recovered(&input)?;
+        assert_eq!(11, count);
+        assert_eq!(vec![(216, 120)], skipped);
+
+        // garbage between blocks
+        let input = [&BLOCKS_LE[..436], &[0xff; 7], &BLOCKS_LE[436..]].concat();
+        let (count, skipped) = read_recovered(&input)?;
+        assert_eq!(12, count);
+        assert_eq!(vec![(436, 7)], skipped);
+
+        // truncated input: the last block is skipped
+        let input = &BLOCKS_LE[..BLOCKS_LE.len() - 10];
+        let (count, skipped) = read_recovered(input)?;
+        assert_eq!(11, count);
+        assert_eq!(vec![(824, 42)], skipped);
+
+        // no data loss on valid input
+        let (count, skipped) = read_recovered(BLOCKS_BE)?;
+        assert_eq!(12, count);
+        assert!(skipped.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reader_recovery_file_header() -> Result<()> {
+        // garbage before the file header
+        for (input, count) in [(BLOCKS_LE, 12), (LEGACY_LE_US, 5)] {
+            let input = [&[0x0a; 13][..], input].concat();
+            let mut reader = PcapNgReader::with_recovery(SlowReader(&input))?;
+            assert!(matches!(
+                reader.read_block()?,
+                Some(Block::SectionHeader(_) | Block::LegacyHeader(_))
+            ));
+            assert_eq!(count - 1, block_types(&mut reader)?.len());
+            assert_eq!(input.len(), reader.consumed());
+            assert_eq!(vec![(0, 13)], skipped_ranges(&reader));
+        }
+        // damaged SHB: the first section is skipped
+        let mut input = [BLOCKS_LE, BLOCKS_BE].concat();
+        input[0] = 0;
+        let mut reader = PcapNgReader::with_recovery(Cursor::new(&input))?;
+        assert_eq!(12, block_types(&mut reader)?.len());
+        assert_eq!(Endianness::Big, reader.endianness());
+        assert_eq!(vec![(0, BLOCKS_LE.len())], skipped_ranges(&reader));
+        // no file header
+        assert!(matches!(
+            PcapNgReader::with_recovery(Cursor::new(&BLOCKS_LE[4..])),
+            Err(Error::Eof)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reader_recovery_legacy() -> Result<()> {
+        // captured length of the second record (offset 114, 90 bytes) larger than snaplen
+        let mut input = LEGACY_LE_US.to_vec();
+        input[114 + 8..114 + 12].copy_from_slice(&0x7fff_0000u32.to_le_bytes());
+        let (count, skipped) = read_recovered(&input)?;
+        assert_eq!(4, count);
+        assert_eq!(vec![(114, 90)], skipped);
+
+        // timestamp fraction out of range in the third record
+        let mut input = LEGACY_LE_US.to_vec();
+        input[204 + 4..204 + 8].copy_from_slice(&2_000_000u32.to_le_bytes());
+        let (count, skipped) = read_recovered(&input)?;
+        assert_eq!(4, count);
+        assert_eq!(vec![(204, 82)], skipped);
+        Ok(())
+    }
+
+    #[test]
+    fn ts_resolution() {
+        assert_eq!(Some(1_000_000), ts_resolution_from(6));
+        assert_eq!(Some(1_000_000_000), ts_resolution_from(9));
+        assert_eq!(Some(1024), ts_resolution_from(0x8a));
+        assert_eq!(None, ts_resolution_from(20));
+    }
+
+    #[test]
+    fn interface_ts_conversion() {
+        let mut interface = Interface {
+            link_type: 1,
+            snap_len: 0,
+            ts_resolution: 1_000_000,
+            ts_offset: 0,
+        };
+        assert_eq!(1_500_000_000, interface.ts_to_nanos(1_500_000));
+        assert_eq!(1_500_000, interface.ts_from_nanos(1_500_000_999));
+        interface.ts_offset = 10;
+        assert_eq!(11_000_000_000, interface.ts_to_nanos(1_000_000));
+        assert_eq!(1_000_000, interface.ts_from_nanos(11_000_000_000));
+        assert_eq!(0, interface.ts_from_nanos(0));
+    }
+}
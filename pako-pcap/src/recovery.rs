@@ -0,0 +1,330 @@
+//!
+//! Recovery of corrupted captures
+//!
+//! After a parsing error, the reader can search forward for the next plausible block:
+//!
+//! - pcapng: the block type must be known, the total length must be valid and equal to the
+//!   trailing length, and the block must parse. Packet and statistics blocks must refer to
+//!   a known interface.
+//! - legacy pcap: lengths must be consistent with each other and with the snapshot length,
+//!   and the next record must also be plausible (or the record must end the input).
+//!
+//! In both formats, packet timestamps must be close to the timestamp of the last valid
+//! packet (see [`MAX_TIMESTAMP_GAP`]).
+//!
+
+use crate::{
+    block::{parse_block, Block, BlockType},
+    endian::{BigEndian, Endianness, LittleEndian},
+    legacy::{is_legacy_magic, pcap_header, LegacyPacket, PcapHeader, PCAP_HEADER_SIZE},
+    reader::{Format, Interface},
+    Error,
+};
+
+/// Maximum size of a block
+///
+/// Larger blocks are considered corrupted: the reader returns an error instead of reading them
+/// into memory, and skips them while resynchronizing.
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum difference with the timestamp of the last valid packet, in nanoseconds (1 day)
+pub const MAX_TIMESTAMP_GAP: u64 = 86_400 * 1_000_000_000;
+
+/// Bytes skipped while recovering from a corrupted or truncated block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRange {
+    /// Offset of the first skipped byte in the input
+    pub offset: usize,
+    /// Number of skipped bytes
+    pub len: usize,
+    /// Error which triggered the recovery
+    pub reason: String,
+}
+
+/// Result of the validation of a possible block position
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Candidate {
+    /// A plausible block, with its size
+    Valid(usize),
+    Invalid,
+    /// More data is needed to validate the block (`0` if unknown)
+    Incomplete(usize),
+}
+
+/// Test if a file header starts at the beginning of `data`
+///
+/// A file header is a plausible Section Header Block, or a legacy pcap file header followed by
+/// a plausible record (or by the end of input). `at_end` is true if no more data can be read.
+pub(crate) fn check_file_header(data: &[u8], at_end: bool) -> Candidate {
+    let state = SyncState {
+        format: Format::PcapNg,
+        legacy_header: None,
+        endianness: Endianness::Big,
+        interfaces: &[],
+        last_ts: None,
+        at_end,
+    };
+    let candidate = match data.get(..4) {
+        None => Candidate::Incomplete(4 - data.len()),
+        Some(magic) if magic == (BlockType::SectionHeader as u32).to_be_bytes() => {
+            return state.check(data);
+        }
+        Some(magic) if is_legacy_magic(magic) => {
+            match pcap_header::<Error<&[u8]>>(data) {
+                Ok((rest, header)) => {
+                    let interfaces = [Interface::from(&header)];
+                    let state = SyncState {
+                        format: Format::Legacy,
+                        legacy_header: Some(&header),
+                        interfaces: &interfaces,
+                        ..state
+                    };
+                    let header_size = data.len() - rest.len();
+                    match state.check(rest) {
+                        Candidate::Valid(_) => Candidate::Valid(header_size),
+                        // header without records
+                        _ if rest.is_empty() && at_end => Candidate::Valid(header_size),
+                        Candidate::Incomplete(n) => Candidate::Incomplete(n),
+                        Candidate::Invalid => Candidate::Invalid,
+                    }
+                }
+                Err(nom::Err::Incomplete(_)) => Candidate::Incomplete(PCAP_HEADER_SIZE),
+                Err(_) => Candidate::Invalid,
+            }
+        }
+        Some(_) => Candidate::Invalid,
+    };
+    match candidate {
+        Candidate::Incomplete(_) if at_end => Candidate::Invalid,
+        candidate => candidate,
+    }
+}
+
+/// Reader state used to validate blocks
+pub(crate) struct SyncState<'s> {
+    pub format: Format,
+    pub legacy_header: Option<&'s PcapHeader>,
+    pub endianness: Endianness,
+    pub interfaces: &'s [Interface],
+    /// Timestamp of the last valid packet, in nanoseconds
+    pub last_ts: Option<u64>,
+    /// The input ends after the data being validated
+    pub at_end: bool,
+}
+
+fn read_u32(endianness: Endianness, b: &[u8]) -> u32 {
+    let b = [b[0], b[1], b[2], b[3]];
+    match endianness {
+        Endianness::Big => u32::from_be_bytes(b),
+        Endianness::Little => u32::from_le_bytes(b),
+    }
+}
+
+/// Test if the lengths and timestamp fraction of a legacy packet record are consistent
+fn legacy_lengths_plausible(
+    header: &PcapHeader,
+    ts_frac: u32,
+    captured_len: u32,
+    original_len: u32,
+) -> bool {
+    captured_len <= original_len
+        && (header.snap_len == 0 || captured_len <= header.snap_len)
+        && captured_len as usize <= MAX_BLOCK_SIZE
+        && u64::from(ts_frac) < header.ts_resolution()
+}
+
+impl<'s> SyncState<'s> {
+    fn ts_plausible(&self, nanos: u64) -> bool {
+        match self.last_ts {
+            Some(last_ts) => nanos.abs_diff(last_ts) <= MAX_TIMESTAMP_GAP,
+            None => true,
+        }
+    }
+
+    /// Timestamp of a packet block, in nanoseconds
+    pub fn packet_ts(&self, block: &Block) -> Option<u64> {
+        match block {
+            Block::EnhancedPacket(epb) => self
+                .interfaces
+                .get(epb.interface_id as usize)
+                .map(|interface| interface.ts_to_nanos(epb.timestamp())),
+            Block::LegacyPacket(packet) => self
+                .interfaces
+                .first()
+                .map(|interface| interface.ts_to_nanos(packet.timestamp(interface.ts_resolution))),
+            _ => None,
+        }
+    }
+
+    /// Test if a parsed block is consistent with the state of the reader
+    ///
+    /// Timestamps are only verified if `check_ts` is set.
+    pub fn plausible(&self, block: &Block, check_ts: bool) -> bool {
+        let has_interface = |id: u32| (id as usize) < self.interfaces.len();
+        let valid = match block {
+            Block::EnhancedPacket(epb) => has_interface(epb.interface_id),
+            Block::SimplePacket(_) => !self.interfaces.is_empty(),
+            Block::InterfaceStatistics(isb) => has_interface(isb.interface_id),
+            Block::LegacyPacket(LegacyPacket {
+                ts_frac,
+                captured_len,
+                original_len,
+                ..
+            }) => self.legacy_header.is_some_and(|header| {
+                legacy_lengths_plausible(header, *ts_frac, *captured_len, *original_len)
+            }),
+            _ => true,
+        };
+        valid && (!check_ts || self.packet_ts(block).is_none_or(|ts| self.ts_plausible(ts)))
+    }
+
+    /// Test if a plausible block starts at the beginning of `data`
+    pub fn check(&self, data: &[u8]) -> Candidate {
+        match self.check_block(data) {
+            // no more data can be read
+            Candidate::Incomplete(_) if self.at_end => Candidate::Invalid,
+            candidate => candidate,
+        }
+    }
+
+    fn check_block(&self, data: &[u8]) -> Candidate {
+        match (self.format, self.legacy_header) {
+            (Format::PcapNg, _) => self.check_pcapng(data),
+            (Format::Legacy, Some(header)) => match self.check_legacy_record(header, data) {
+                // the next record must also be plausible
+                Candidate::Valid(len) if data.len() == len => {
+                    if self.at_end {
+                        Candidate::Valid(len)
+                    } else {
+                        Candidate::Incomplete(header.record_header_size())
+                    }
+                }
+                Candidate::Valid(len) => match self.check_legacy_record(header, &data[len..]) {
+                    Candidate::Valid(_) => Candidate::Valid(len),
+                    candidate => candidate,
+                },
+                candidate => candidate,
+            },
+            // without file header, records cannot be parsed
+            (Format::Legacy, None) => Candidate::Invalid,
+        }
+    }
+
+    fn check_pcapng(&self, data: &[u8]) -> Candidate {
+        if data.len() < 12 {
+            return Candidate::Incomplete(12 - data.len());
+        }
+        // the SHB block type is a palindrome, and the SHB has its own byte order
+        let block_type = read_u32(self.endianness, data);
+        let endianness = if block_type == BlockType::SectionHeader as u32 {
+            match data[8..12] {
+                [0x1A, 0x2B, 0x3C, 0x4D] => Endianness::Big,
+                [0x4D, 0x3C, 0x2B, 0x1A] => Endianness::Little,
+                _ => return Candidate::Invalid,
+            }
+        } else if BlockType::try_from(block_type).is_ok() {
+            self.endianness
+        } else {
+            return Candidate::Invalid;
+        };
+        let len = read_u32(endianness, &data[4..]) as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > MAX_BLOCK_SIZE {
+            return Candidate::Invalid;
+        }
+        if data.len() < len {
+            return Candidate::Incomplete(len - data.len());
+        }
+        let res = match endianness {
+            Endianness::Big => parse_block::<BigEndian, Error<&[u8]>>(&data[..len]),
+            Endianness::Little => parse_block::<LittleEndian, Error<&[u8]>>(&data[..len]),
+        };
+        match res {
+            Ok((_, block)) if self.plausible(&block, true) => Candidate::Valid(len),
+            _ => Candidate::Invalid,
+        }
+    }
+
+    fn check_legacy_record(&self, header: &PcapHeader, data: &[u8]) -> Candidate {
+        let header_size = header.record_header_size();
+        if data.len() < header_size {
+            return Candidate::Incomplete(header_size - data.len());
+        }
+        let e = header.endianness;
+        let ts_sec = read_u32(e, data);
+        let ts_frac = read_u32(e, &data[4..]);
+        let captured_len = read_u32(e, &data[8..]);
+        let original_len = read_u32(e, &data[12..]);
+        if !legacy_lengths_plausible(header, ts_frac, captured_len, original_len) {
+            return Candidate::Invalid;
+        }
+        if let Some(interface) = self.interfaces.first() {
+            let ts = u64::from(ts_sec) * interface.ts_resolution + u64::from(ts_frac);
+            if !self.ts_plausible(interface.ts_to_nanos(ts)) {
+                return Candidate::Invalid;
+            }
+        }
+        let len = header_size + captured_len as usize;
+        if data.len() < len {
+            Candidate::Incomplete(len - data.len())
+        } else {
+            Candidate::Valid(len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block::tests::BLOCKS_LE,
+        endian::Endianness,
+        legacy::{pcap_header, tests::LEGACY_LE_US},
+        reader::{Format, Interface},
+        recovery::{Candidate, SyncState},
+    };
+
+    #[test]
+    fn check_candidates() {
+        let interfaces = [Interface {
+            link_type: 1,
+            snap_len: 262144,
+            ts_resolution: 1_000_000_000,
+            ts_offset: 0,
+        }];
+        let state = SyncState {
+            format: Format::PcapNg,
+            legacy_header: None,
+            endianness: Endianness::Little,
+            interfaces: &interfaces,
+            last_ts: None,
+            at_end: false,
+        };
+        assert!(matches!(state.check(BLOCKS_LE), Candidate::Valid(_)));
+        assert_eq!(Candidate::Invalid, state.check(&BLOCKS_LE[1..]));
+        assert!(matches!(
+            state.check(&BLOCKS_LE[..20]),
+            Candidate::Incomplete(_)
+        ));
+
+        let (_, header) = pcap_header::<nom::error::Error<&[u8]>>(LEGACY_LE_US).unwrap();
+        let interfaces = [Interface::from(&header)];
+        let mut state = SyncState {
+            format: Format::Legacy,
+            legacy_header: Some(&header),
+            endianness: Endianness::Little,
+            interfaces: &interfaces,
+            last_ts: None,
+            at_end: true,
+        };
+        let records = &LEGACY_LE_US[24..];
+        assert_eq!(Candidate::Valid(16 + 74), state.check(records));
+        assert_eq!(Candidate::Invalid, state.check(&records[3..]));
+        // last record
+        assert_eq!(
+            Candidate::Valid(16 + 66),
+            state.check(&records[records.len() - 82..])
+        );
+        // timestamp too far from the last packet
+        state.last_ts = Some(0);
+        assert_eq!(Candidate::Invalid, state.check(records));
+    }
+}
@@ -3,12 +3,16 @@
 //!
 
 use nom::{
-    error::ParseError,
-    number::complete::{be_i64, be_u16, be_u32},
+    bytes::streaming::take,
+    error::{ErrorKind, ParseError},
     IResult,
 };
 
-use crate::BlockType;
+use crate::{
+    block::{options_field, BlockParser},
+    endian::{BigEndian, ByteOrder, Endianness, LittleEndian},
+    BlockType,
+};
 
 // The writing application writes `0x1A2B3C4D` with it's native byte ordering
 // format into the byte order magic field. The reading application will read
@@ -31,32 +35,69 @@ pub struct SectionHeader<'a> {
     pub section_length: i64,
     pub options: Option<&'a [u8]>,
     pub total_length_dup: u32,
+    /// Byte order of the section, detected from the byte order magic
+    pub endianness: Endianness,
 }
 
-pub fn section_header<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], SectionHeader, E>
+impl<'a> BlockParser<'a> for SectionHeader<'a> {
+    const HEADER_SIZE: usize = 16;
+    const BLOCK_TYPE: BlockType = BlockType::SectionHeader;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (input, byte_order) = B::parse_u32(blk_body)?;
+        if byte_order != BYTE_ORDER_MAGIC {
+            return Err(nom::Err::Error(E::from_error_kind(
+                blk_body,
+                ErrorKind::Verify,
+            )));
+        }
+        let (input, major) = B::parse_u16(input)?;
+        let (input, minor) = B::parse_u16(input)?;
+        let (input, section_length) = B::parse_i64(input)?;
+
+        Ok((
+            &[],
+            SectionHeader {
+                block_type: BlockType::SectionHeader,
+                total_length: blk_len1,
+                byte_order,
+                major,
+                minor,
+                section_length,
+                options: options_field(input),
+                total_length_dup: blk_len2,
+                endianness: B::ENDIANNESS,
+            },
+        ))
+    }
+}
+
+/// Parse a Section Header Block
+///
+/// The byte order is detected from the byte order magic, and returned in `endianness`.
+pub fn section_header<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], SectionHeader<'a>, E>
 where
     E: ParseError<&'a [u8]>,
 {
-    let (input, _) = be_u32(input)?;
-    let (input, total_length) = be_u32(input)?;
-    let (input, byte_order) = be_u32(input)?;
-    let (input, major) = be_u16(input)?;
-    let (input, minor) = be_u16(input)?;
-    let (input, section_length) = be_i64(input)?;
-
-    Ok((
-        input,
-        SectionHeader {
-            block_type: BlockType::SectionHeader,
-            total_length,
-            byte_order,
-            major,
-            minor,
-            section_length,
-            options: None,
-            total_length_dup: total_length,
-        },
-    ))
+    // block type, block total length and byte order magic
+    let (_, header) = take(12usize)(input)?;
+    match header[8..12] {
+        [0x1A, 0x2B, 0x3C, 0x4D] => SectionHeader::parse::<BigEndian, E>(input),
+        [0x4D, 0x3C, 0x2B, 0x1A] => SectionHeader::parse::<LittleEndian, E>(input),
+        _ => Err(nom::Err::Error(E::from_error_kind(
+            input,
+            ErrorKind::Verify,
+        ))),
+    }
 }
 
 #[cfg(test)]
@@ -64,7 +105,11 @@ mod tests {
     use anyhow::Result;
     use nom::error::VerboseError;
 
-    use crate::section_header::{section_header, BYTE_ORDER_MAGIC};
+    use crate::{
+        block::tests::{BLOCKS_BE, BLOCKS_LE},
+        endian::Endianness,
+        section_header::{section_header, BYTE_ORDER_MAGIC},
+    };
 
     #[test]
     fn byte_order_be_works() -> Result<()> {
@@ -75,22 +120,47 @@ mod tests {
     }
 
     #[test]
-    fn byte_order_le_fails() -> Result<()> {
+    fn byte_order_le_works() -> Result<()> {
         let pcap = include_bytes!("../assets/test002_le.pcapng");
         let (_, shb) = section_header::<VerboseError<&[u8]>>(pcap)?;
-        assert_ne!(BYTE_ORDER_MAGIC, shb.byte_order);
+        assert_eq!(BYTE_ORDER_MAGIC, shb.byte_order);
+        assert_eq!(Endianness::Little, shb.endianness);
         Ok(())
     }
 
     #[test]
-    /// TODO: complete this test
     fn section_header_works() -> Result<()> {
-        let pcap = include_bytes!("../assets/test002_be.pcapng");
-        let (_, shb) = section_header::<VerboseError<&[u8]>>(pcap)?;
+        for pcap in [
+            &include_bytes!("../assets/test002_be.pcapng")[..],
+            &include_bytes!("../assets/test002_le.pcapng")[..],
+        ] {
+            let (rest, shb) = section_header::<VerboseError<&[u8]>>(pcap)?;
+
+            assert!(rest.is_empty());
+            assert_eq!(96, shb.total_length);
+            assert_eq!(96, shb.total_length_dup);
+            assert_eq!((1, 0), (shb.major, shb.minor));
+            assert_eq!(-1, shb.section_length);
+            assert_eq!(Some(68), shb.options.map(|o| o.len()));
+        }
 
-        assert_eq!(96, shb.total_length);
-        assert_eq!(-1, shb.section_length);
+        Ok(())
+    }
 
+    #[test]
+    fn section_header_blocks() -> Result<()> {
+        let (_, be) = section_header::<VerboseError<&[u8]>>(BLOCKS_BE)?;
+        let (_, le) = section_header::<VerboseError<&[u8]>>(BLOCKS_LE)?;
+        assert_eq!(Endianness::Big, be.endianness);
+        assert_eq!(Endianness::Little, le.endianness);
+        assert_eq!(be.options.map(|o| o.len()), le.options.map(|o| o.len()));
         Ok(())
     }
+
+    #[test]
+    fn section_header_bad_magic() {
+        let mut pcap = include_bytes!("../assets/test002_be.pcapng").to_vec();
+        pcap[8] = 0;
+        assert!(section_header::<VerboseError<&[u8]>>(&pcap).is_err());
+    }
 }
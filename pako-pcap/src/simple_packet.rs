@@ -0,0 +1,96 @@
+//!
+//! Simple Packet Block (SPB) support
+//!
+
+use nom::{error::ParseError, IResult};
+
+use crate::{block::BlockParser, endian::ByteOrder, BlockType};
+
+/// The Simple Packet Block (SPB) is a lightweight container for storing the
+/// packets coming from the network. Packets are implicitly captured on the
+/// first interface of the section.
+#[derive(Debug)]
+pub struct SimplePacket<'a> {
+    pub block_type: BlockType,
+    pub total_length: u32,
+    pub original_len: u32,
+    /// Packet data, including padding (see [`SimplePacket::packet_data`])
+    pub data: &'a [u8],
+    pub total_length_dup: u32,
+}
+
+impl<'a> SimplePacket<'a> {
+    /// Packet data, without padding
+    ///
+    /// The captured length is not stored in the block: it is the minimum of the original
+    /// length and of the snap length of the interface (`0` means no limit).
+    pub fn packet_data(&self, snap_len: u32) -> &'a [u8] {
+        let mut len = self.original_len as usize;
+        if snap_len != 0 {
+            len = len.min(snap_len as usize);
+        }
+        &self.data[..len.min(self.data.len())]
+    }
+}
+
+impl<'a> BlockParser<'a> for SimplePacket<'a> {
+    const HEADER_SIZE: usize = 4;
+    const BLOCK_TYPE: BlockType = BlockType::SimplePacket;
+
+    fn parse_body<B, E>(
+        _blk_type: u32,
+        blk_len1: u32,
+        blk_body: &'a [u8],
+        blk_len2: u32,
+    ) -> IResult<&'a [u8], Self, E>
+    where
+        B: ByteOrder,
+        E: ParseError<&'a [u8]>,
+    {
+        let (data, original_len) = B::parse_u32(blk_body)?;
+
+        Ok((
+            &[],
+            SimplePacket {
+                block_type: BlockType::SimplePacket,
+                total_length: blk_len1,
+                original_len,
+                data,
+                total_length_dup: blk_len2,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nom::error::VerboseError;
+
+    use crate::{
+        block::{
+            tests::{find_block, BLOCKS_BE, BLOCKS_LE},
+            BlockParser,
+        },
+        endian::{BigEndian, ByteOrder, LittleEndian},
+        simple_packet::SimplePacket,
+        BlockType,
+    };
+
+    fn check<B: ByteOrder>(input: &'static [u8]) -> Result<()> {
+        let input = find_block::<B>(input, BlockType::SimplePacket, 0);
+        let (_, spb) = SimplePacket::parse::<B, VerboseError<&[u8]>>(input)?;
+        assert_eq!(74, spb.original_len);
+        assert_eq!(76, spb.data.len());
+        assert_eq!(74, spb.packet_data(0).len());
+        assert_eq!(64, spb.packet_data(64).len());
+        assert_eq!(74, spb.packet_data(262144).len());
+        Ok(())
+    }
+
+    #[test]
+    fn simple_packet_works() -> Result<()> {
+        check::<BigEndian>(BLOCKS_BE)?;
+        check::<LittleEndian>(BLOCKS_LE)
+    }
+}
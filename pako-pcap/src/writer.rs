@@ -0,0 +1,901 @@
+//!
+//! pcapng writer
+//!
+
+use std::io::Write;
+
+use crate::{
+    align,
+    block::{Block, BLOCK_OVERHEAD},
+    endian::Endianness,
+    legacy::{LegacyPacket, ModifiedRecord, PcapHeader},
+    name_resolution::{NameRecord, NRES_ENDOFRECORD},
+    options::{write_options, BlockOption, IfOption, Options},
+    reader::Interface,
+    BlockType, Error,
+};
+
+/// Byte order magic of the Section Header Block
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// Block body being serialized, with the byte order of the section
+pub(crate) struct Body {
+    endianness: Endianness,
+    buf: Vec<u8>,
+}
+
+impl Body {
+    pub(crate) fn new(endianness: Endianness) -> Self {
+        Body {
+            endianness,
+            buf: Vec::new(),
+        }
+    }
+
+    pub(crate) fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub(crate) fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub(crate) fn put_u16(&mut self, v: u16) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    pub(crate) fn put_u32(&mut self, v: u32) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    pub(crate) fn put_u64(&mut self, v: u64) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    pub(crate) fn put_i64(&mut self, v: i64) {
+        self.put_u64(v as u64);
+    }
+
+    pub(crate) fn put_bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    /// Pad with zeros to a 32-bit boundary
+    pub(crate) fn pad(&mut self) {
+        self.buf.resize(align!(self.buf.len(), 4), 0);
+    }
+}
+
+/// Writer of pcapng files
+///
+/// A file is a sequence of sections: `write_section_header` starts a new section, with
+/// its own byte order and interfaces. All other blocks must follow a Section Header Block,
+/// and blocks referencing an interface must follow its Interface Description Block.
+///
+/// Blocks are either built from their fields and typed options (`write_*` methods), or
+/// copied from a parsed [`Block`] with `write_block`.
+pub struct PcapNgWriter<W: Write> {
+    writer: W,
+    endianness: Endianness,
+    /// Interfaces of the current section
+    interfaces: Vec<Interface>,
+    in_section: bool,
+    /// Total number of written bytes
+    written: usize,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Create a new writer. Nothing is written before the first section header.
+    pub fn new(writer: W) -> Self {
+        PcapNgWriter {
+            writer,
+            endianness: Endianness::Big,
+            interfaces: Vec::new(),
+            in_section: false,
+            written: 0,
+        }
+    }
+
+    /// Byte order of the current section
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Interfaces of the current section, indexed by interface id
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// Interface `interface_id` of the current section
+    pub fn interface(&self, interface_id: u32) -> Option<&Interface> {
+        self.interfaces.get(interface_id as usize)
+    }
+
+    /// Total number of bytes written
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> Result<(), Error<&'static [u8]>> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> Result<W, Error<&'static [u8]>> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Start a new section, with byte order `endianness`
+    ///
+    /// The section length is not known in advance, and is written as `-1`.
+    pub fn write_section_header(
+        &mut self,
+        endianness: Endianness,
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = Body::new(endianness);
+        write_options(&mut body, options)?;
+        self.write_section_header_body(endianness, 1, 0, -1, body)
+    }
+
+    /// Write an Interface Description Block, and return the new interface id
+    pub fn write_interface_description(
+        &mut self,
+        link_type: u16,
+        snap_len: u32,
+        options: &[BlockOption],
+    ) -> Result<u32, Error<&'static [u8]>> {
+        self.write_interface_description_len(link_type, snap_len, options)?;
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    /// Write an Interface Description Block, and return the block size
+    fn write_interface_description_len(
+        &mut self,
+        link_type: u16,
+        snap_len: u32,
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = self.body()?;
+        body.put_u16(link_type);
+        body.put_u16(0);
+        body.put_u32(snap_len);
+        write_options(&mut body, options)?;
+
+        let written = self.write_raw_block(BlockType::InterfaceDescription as u32, &body)?;
+        self.interfaces.push(Interface::with_options(
+            link_type,
+            snap_len,
+            options.iter().cloned(),
+        ));
+        Ok(written)
+    }
+
+    /// Write an Enhanced Packet Block
+    ///
+    /// `timestamp` is in units of the interface resolution (see [`Interface::ts_from_nanos`]).
+    /// The captured length is the length of `data`.
+    pub fn write_enhanced_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp: u64,
+        original_len: u32,
+        data: &[u8],
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        self.check_interface(interface_id)?;
+        let mut body = self.body()?;
+        body.put_u32(interface_id);
+        body.put_u32((timestamp >> 32) as u32);
+        body.put_u32(timestamp as u32);
+        body.put_u32(data_len(data)?);
+        body.put_u32(original_len);
+        body.put_bytes(data);
+        body.pad();
+        write_options(&mut body, options)?;
+        self.write_raw_block(BlockType::EnhancedPacket as u32, &body)
+    }
+
+    /// Write a Simple Packet Block, captured on the first interface of the section
+    pub fn write_simple_packet(
+        &mut self,
+        original_len: u32,
+        data: &[u8],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        self.check_interface(0)?;
+        let mut body = self.body()?;
+        body.put_u32(original_len);
+        body.put_bytes(data);
+        body.pad();
+        self.write_raw_block(BlockType::SimplePacket as u32, &body)
+    }
+
+    /// Write a Name Resolution Block
+    pub fn write_name_resolution(
+        &mut self,
+        records: &[NameRecord],
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = self.body()?;
+        for record in records {
+            let len = u16::try_from(record.value.len())
+                .map_err(|_| Error::Other(anyhow::anyhow!("Name record too large")))?;
+            body.put_u16(record.record_type);
+            body.put_u16(len);
+            body.put_bytes(record.value);
+            body.pad();
+        }
+        body.put_u16(NRES_ENDOFRECORD);
+        body.put_u16(0);
+        write_options(&mut body, options)?;
+        self.write_raw_block(BlockType::NameResolution as u32, &body)
+    }
+
+    /// Write an Interface Statistics Block
+    ///
+    /// `timestamp` is in units of the interface resolution.
+    pub fn write_interface_statistics(
+        &mut self,
+        interface_id: u32,
+        timestamp: u64,
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        self.check_interface(interface_id)?;
+        let mut body = self.body()?;
+        body.put_u32(interface_id);
+        body.put_u32((timestamp >> 32) as u32);
+        body.put_u32(timestamp as u32);
+        write_options(&mut body, options)?;
+        self.write_raw_block(BlockType::InterfaceStatistic as u32, &body)
+    }
+
+    /// Write a Decryption Secrets Block (see `SECRETS_TYPE_*` for `secrets_type`)
+    pub fn write_decryption_secrets(
+        &mut self,
+        secrets_type: u32,
+        data: &[u8],
+        options: &[BlockOption],
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = self.body()?;
+        body.put_u32(secrets_type);
+        body.put_u32(data_len(data)?);
+        body.put_bytes(data);
+        body.pad();
+        write_options(&mut body, options)?;
+        self.write_raw_block(BlockType::DecryptionSecretsBlock as u32, &body)
+    }
+
+    /// Write a Custom Block, with Private Enterprise Number `pen`
+    ///
+    /// `data` contains the custom data and options, and is padded to 32 bits. If `copyable`
+    /// is false, a `CustomNoCopy` block is written.
+    pub fn write_custom(
+        &mut self,
+        pen: u32,
+        data: &[u8],
+        copyable: bool,
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = self.body()?;
+        body.put_u32(pen);
+        body.put_bytes(data);
+        body.pad();
+        let block_type = if copyable {
+            BlockType::Custom
+        } else {
+            BlockType::CustomNoCopy
+        };
+        self.write_raw_block(block_type as u32, &body)
+    }
+
+    /// Write a systemd Journal Export Block
+    pub fn write_journal_export(&mut self, entry: &[u8]) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = self.body()?;
+        body.put_bytes(entry);
+        body.pad();
+        self.write_raw_block(BlockType::SystemJournalExport as u32, &body)
+    }
+
+    /// Copy a parsed block, read from a section with byte order `endianness`
+    ///
+    /// A Section Header Block starts a new section with the byte order of the block, so
+    /// copying all the blocks of a file produces the same file. Other blocks are converted
+    /// to the byte order of the current section: options with an unknown code, and the
+    /// content of custom and unknown blocks, are copied unchanged.
+    pub fn write_block(
+        &mut self,
+        block: &Block,
+        endianness: Endianness,
+    ) -> Result<usize, Error<&'static [u8]>> {
+        match block {
+            Block::SectionHeader(b) => {
+                let mut body = Body::new(b.endianness);
+                copy_options(&mut body, BlockType::SectionHeader, b.endianness, b.options)?;
+                self.write_section_header_body(
+                    b.endianness,
+                    b.major,
+                    b.minor,
+                    b.section_length,
+                    body,
+                )
+            }
+            Block::InterfaceDescription(b) => {
+                let mut body = self.body()?;
+                body.put_u16(b.link_type);
+                body.put_u16(b.reserved);
+                body.put_u32(b.snap_len);
+                copy_options(&mut body, b.block_type, endianness, b.options)?;
+                let written = self.write_raw_block(b.block_type as u32, &body)?;
+                self.interfaces.push(Interface::new(b, endianness));
+                Ok(written)
+            }
+            Block::EnhancedPacket(b) => {
+                self.check_interface(b.interface_id)?;
+                let mut body = self.body()?;
+                body.put_u32(b.interface_id);
+                body.put_u32(b.timestamp_high);
+                body.put_u32(b.timestamp_low);
+                body.put_u32(b.captured_len);
+                body.put_u32(b.original_len);
+                body.put_bytes(b.data);
+                body.pad();
+                copy_options(&mut body, b.block_type, endianness, b.options)?;
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::SimplePacket(b) => {
+                self.check_interface(0)?;
+                let mut body = self.body()?;
+                body.put_u32(b.original_len);
+                body.put_bytes(b.data);
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::NameResolution(b) => {
+                let mut body = self.body()?;
+                for record in &b.records {
+                    body.put_u16(record.record_type);
+                    body.put_u16(record.value.len() as u16);
+                    body.put_bytes(record.value);
+                    body.pad();
+                }
+                body.put_u16(NRES_ENDOFRECORD);
+                body.put_u16(0);
+                copy_options(&mut body, b.block_type, endianness, b.options)?;
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::InterfaceStatistics(b) => {
+                self.check_interface(b.interface_id)?;
+                let mut body = self.body()?;
+                body.put_u32(b.interface_id);
+                body.put_u32(b.timestamp_high);
+                body.put_u32(b.timestamp_low);
+                copy_options(&mut body, b.block_type, endianness, b.options)?;
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::DecryptionSecrets(b) => {
+                let mut body = self.body()?;
+                body.put_u32(b.secrets_type);
+                body.put_u32(b.secrets_len);
+                body.put_bytes(b.data);
+                body.pad();
+                copy_options(&mut body, b.block_type, endianness, b.options)?;
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::SystemJournalExport(b) => {
+                let mut body = self.body()?;
+                body.put_bytes(b.data);
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::Custom(b) => {
+                let mut body = self.body()?;
+                body.put_u32(b.pen);
+                body.put_bytes(b.data);
+                self.write_raw_block(b.block_type as u32, &body)
+            }
+            Block::Unknown(b) => {
+                let mut body = self.body()?;
+                body.put_bytes(b.body);
+                self.write_raw_block(b.block_type, &body)
+            }
+            // legacy pcap header: new section, with the interface described by the header
+            Block::LegacyHeader(header) => {
+                let written = self.write_section_header(header.endianness, &[])?;
+                let mut options = vec![BlockOption::If(IfOption::TsResol(
+                    if header.is_nanosecond() { 9 } else { 6 },
+                ))];
+                if let Some(fcs_len) = header.fcs_len() {
+                    options.push(BlockOption::If(IfOption::FcsLen(fcs_len)));
+                }
+                if header.thiszone != 0 {
+                    options.push(BlockOption::If(IfOption::TsOffset(header.thiszone.into())));
+                }
+                let written = written
+                    + self.write_interface_description_len(
+                        header.link_type(),
+                        header.snap_len,
+                        &options,
+                    )?;
+                Ok(written)
+            }
+            // legacy pcap packet: EPB on interface 0, additional fields of modified pcap
+            // are dropped
+            Block::LegacyPacket(b) => {
+                let ts_resolution = self
+                    .interface(0)
+                    .ok_or_else(|| Error::Other(anyhow::anyhow!("Invalid interface id 0")))?
+                    .ts_resolution;
+                self.write_enhanced_packet(
+                    0,
+                    b.timestamp(ts_resolution),
+                    b.original_len,
+                    b.data,
+                    &[],
+                )
+            }
+        }
+    }
+
+    fn write_section_header_body(
+        &mut self,
+        endianness: Endianness,
+        major: u16,
+        minor: u16,
+        section_length: i64,
+        options: Body,
+    ) -> Result<usize, Error<&'static [u8]>> {
+        self.endianness = endianness;
+        self.interfaces.clear();
+        self.in_section = true;
+
+        let mut body = Body::new(endianness);
+        body.put_u32(BYTE_ORDER_MAGIC);
+        body.put_u16(major);
+        body.put_u16(minor);
+        body.put_i64(section_length);
+        body.put_bytes(options.as_bytes());
+        self.write_raw_block(BlockType::SectionHeader as u32, &body)
+    }
+
+    /// New block body, for the current section
+    fn body(&self) -> Result<Body, Error<&'static [u8]>> {
+        if !self.in_section {
+            return Err(Error::Other(anyhow::anyhow!(
+                "Block written before a Section Header Block"
+            )));
+        }
+        Ok(Body::new(self.endianness))
+    }
+
+    fn check_interface(&self, interface_id: u32) -> Result<(), Error<&'static [u8]>> {
+        if self.interface(interface_id).is_none() {
+            return Err(Error::Other(anyhow::anyhow!(
+                "Invalid interface id {interface_id}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Write block type, lengths and `body` (already padded)
+    fn write_raw_block(
+        &mut self,
+        block_type: u32,
+        body: &Body,
+    ) -> Result<usize, Error<&'static [u8]>> {
+        let total_length = BLOCK_OVERHEAD + body.len();
+        let len = u32::try_from(total_length)
+            .map_err(|_| Error::Other(anyhow::anyhow!("Block too large")))?;
+
+        let mut header = Body::new(self.endianness);
+        header.put_u32(block_type);
+        header.put_u32(len);
+        let mut trailer = Body::new(self.endianness);
+        trailer.put_u32(len);
+
+        self.writer.write_all(header.as_bytes())?;
+        self.writer.write_all(body.as_bytes())?;
+        self.writer.write_all(trailer.as_bytes())?;
+        self.written += total_length;
+        Ok(total_length)
+    }
+}
+
+/// Writer of legacy pcap files
+///
+/// The file header is written on creation. The byte order, timestamp resolution and
+/// record format (standard or modified) are given by the header.
+pub struct LegacyPcapWriter<W: Write> {
+    writer: W,
+    header: PcapHeader,
+    /// Total number of written bytes
+    written: usize,
+}
+
+impl<W: Write> LegacyPcapWriter<W> {
+    /// Create a new writer, and write the file header
+    pub fn new(writer: W, header: PcapHeader) -> Result<Self, Error<&'static [u8]>> {
+        let mut w = LegacyPcapWriter {
+            writer,
+            header,
+            written: 0,
+        };
+        let mut body = Body::new(w.header.endianness);
+        body.put_u32(w.header.magic);
+        body.put_u16(w.header.version_major);
+        body.put_u16(w.header.version_minor);
+        body.put_u32(w.header.thiszone as u32);
+        body.put_u32(w.header.sigfigs);
+        body.put_u32(w.header.snap_len);
+        body.put_u32(w.header.network);
+        w.write_all(&body)?;
+        Ok(w)
+    }
+
+    /// File header
+    pub fn header(&self) -> &PcapHeader {
+        &self.header
+    }
+
+    /// Total number of bytes written
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> Result<W, Error<&'static [u8]>> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Write a packet record
+    ///
+    /// `ts_frac` is in microseconds or nanoseconds, depending on the header. The captured
+    /// length is the length of `data`. For modified pcap files, missing additional fields
+    /// are written as zeros.
+    pub fn write_packet(&mut self, packet: &LegacyPacket) -> Result<usize, Error<&'static [u8]>> {
+        let mut body = Body::new(self.header.endianness);
+        body.put_u32(packet.ts_sec);
+        body.put_u32(packet.ts_frac);
+        body.put_u32(data_len(packet.data)?);
+        body.put_u32(packet.original_len);
+        if self.header.is_modified() {
+            let modified = packet.modified.clone().unwrap_or(ModifiedRecord {
+                if_index: 0,
+                protocol: 0,
+                pkt_type: 0,
+            });
+            body.put_u32(modified.if_index);
+            body.put_u16(modified.protocol);
+            body.put_u8(modified.pkt_type);
+            body.put_u8(0);
+        }
+        body.put_bytes(packet.data);
+        self.write_all(&body)
+    }
+
+    /// Write a parsed block
+    ///
+    /// Legacy packets are written, other blocks have no equivalent in legacy pcap files and
+    /// are skipped (`0` is returned).
+    pub fn write_block(&mut self, block: &Block) -> Result<usize, Error<&'static [u8]>> {
+        match block {
+            Block::LegacyPacket(packet) => self.write_packet(packet),
+            _ => Ok(0),
+        }
+    }
+
+    fn write_all(&mut self, body: &Body) -> Result<usize, Error<&'static [u8]>> {
+        self.writer.write_all(body.as_bytes())?;
+        self.written += body.len();
+        Ok(body.len())
+    }
+}
+
+fn data_len(data: &[u8]) -> Result<u32, Error<&'static [u8]>> {
+    u32::try_from(data.len()).map_err(|_| Error::Other(anyhow::anyhow!("Data too large")))
+}
+
+/// Append the options field of a parsed block to `body`
+///
+/// The field is copied if the byte order is unchanged, otherwise options are decoded and
+/// written again.
+fn copy_options(
+    body: &mut Body,
+    block_type: BlockType,
+    endianness: Endianness,
+    options: Option<&[u8]>,
+) -> Result<(), Error<&'static [u8]>> {
+    if endianness == body.endianness() {
+        body.put_bytes(options.unwrap_or_default());
+        return Ok(());
+    }
+    let options: Vec<_> = Options::new(block_type, endianness, options).collect();
+    write_options(body, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use anyhow::Result;
+
+    use crate::{
+        block::{
+            tests::{BLOCKS_BE, BLOCKS_LE},
+            Block,
+        },
+        decryption_secrets::SECRETS_TYPE_TLS,
+        endian::Endianness,
+        legacy::{
+            tests::{LEGACY_BE_NS, LEGACY_LE_US, LEGACY_MODIFIED},
+            LegacyPacket, PcapHeader,
+        },
+        name_resolution::{NameRecord, NRES_IP4RECORD},
+        options::{BlockOption, EpbOption, IfOption, IsbOption, ShbOption},
+        reader::{Interface, PcapNgReader},
+        writer::{LegacyPcapWriter, PcapNgWriter},
+    };
+
+    /// Copy all blocks of `input`, converting sections to `endianness` if set
+    fn copy(input: &[u8], endianness: Option<Endianness>) -> Result<Vec<u8>> {
+        let mut reader = PcapNgReader::new(input)?;
+        let mut writer = PcapNgWriter::new(Vec::new());
+        let mut section_endianness = Endianness::Big;
+        while let Some(block) = reader.read_block()? {
+            match (&block, endianness) {
+                (Block::SectionHeader(shb), Some(e)) => {
+                    section_endianness = shb.endianness;
+                    let options: Vec<_> = block.options(shb.endianness).collect();
+                    writer.write_section_header(e, &options)?;
+                }
+                (Block::SectionHeader(shb), None) => {
+                    section_endianness = shb.endianness;
+                    writer.write_block(&block, section_endianness)?;
+                }
+                _ => {
+                    writer.write_block(&block, section_endianness)?;
+                }
+            }
+        }
+        Ok(writer.into_inner()?)
+    }
+
+    #[test]
+    fn writer_copy_works() -> Result<()> {
+        assert_eq!(BLOCKS_BE, copy(BLOCKS_BE, None)?);
+        assert_eq!(BLOCKS_LE, copy(BLOCKS_LE, None)?);
+        let sections = [BLOCKS_BE, BLOCKS_LE].concat();
+        assert_eq!(sections, copy(&sections, None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn writer_byte_order_conversion() -> Result<()> {
+        assert_eq!(BLOCKS_LE, copy(BLOCKS_BE, Some(Endianness::Little))?);
+        assert_eq!(BLOCKS_BE, copy(BLOCKS_LE, Some(Endianness::Big))?);
+        Ok(())
+    }
+
+    #[test]
+    fn writer_blocks_works() -> Result<()> {
+        let data = &BLOCKS_BE[..74];
+        let mut writer = PcapNgWriter::new(Vec::new());
+        for e in [Endianness::Little, Endianness::Big] {
+            writer.write_section_header(e, &[BlockOption::Shb(ShbOption::Os("linux"))])?;
+            let eth = writer.write_interface_description(
+                1,
+                0,
+                &[
+                    BlockOption::If(IfOption::Name("eth0")),
+                    BlockOption::If(IfOption::TsResol(9)),
+                ],
+            )?;
+            let raw = writer.write_interface_description(
+                101,
+                65535,
+                &[BlockOption::If(IfOption::Ipv4Addr {
+                    addr: Ipv4Addr::new(10, 0, 0, 1),
+                    netmask: Ipv4Addr::new(255, 0, 0, 0),
+                })],
+            )?;
+            assert_eq!((0, 1), (eth, raw));
+            let ts = writer.interface(raw).unwrap().ts_from_nanos(1_500_000_000);
+            writer.write_enhanced_packet(
+                eth,
+                1_500_000_000,
+                100,
+                &data[..73],
+                &[BlockOption::Epb(EpbOption::Flags(1))],
+            )?;
+            writer.write_enhanced_packet(raw, ts, 73, &data[..73], &[])?;
+            writer.write_name_resolution(
+                &[NameRecord {
+                    record_type: NRES_IP4RECORD,
+                    value: b"\x0a\x00\x00\x01host\0",
+                }],
+                &[BlockOption::Comment("names")],
+            )?;
+            writer.write_interface_statistics(
+                eth,
+                1_600_000_000,
+                &[BlockOption::Isb(IsbOption::IfRecv(2))],
+            )?;
+            writer.write_decryption_secrets(SECRETS_TYPE_TLS, b"CLIENT_RANDOM 01 23\n", &[])?;
+            writer.write_custom(32473, b"pako", false)?;
+        }
+        let output = writer.into_inner()?;
+
+        let mut reader = PcapNgReader::new(&output[..])?;
+        let mut types = Vec::new();
+        let mut e = Endianness::Big;
+        while let Some(block) = reader.read_block()? {
+            match &block {
+                Block::SectionHeader(shb) => e = shb.endianness,
+                Block::EnhancedPacket(epb) if epb.interface_id == 0 => {
+                    assert_eq!(1_500_000_000, epb.timestamp());
+                    assert_eq!((73, 100), (epb.captured_len, epb.original_len));
+                    assert_eq!(&data[..73], epb.data);
+                    let options: Vec<_> = block.options(e).collect();
+                    assert_eq!(vec![BlockOption::Epb(EpbOption::Flags(1))], options);
+                }
+                Block::EnhancedPacket(epb) => assert_eq!(1_500_000, epb.timestamp()),
+                Block::NameResolution(nrb) => {
+                    assert_eq!(
+                        Some(Ipv4Addr::new(10, 0, 0, 1).into()),
+                        nrb.records[0].ip_addr()
+                    );
+                    assert_eq!(vec![b"host"], nrb.records[0].names().collect::<Vec<_>>());
+                }
+                Block::InterfaceStatistics(isb) => assert_eq!(1_600_000_000, isb.timestamp()),
+                Block::DecryptionSecrets(dsb) => {
+                    assert_eq!(b"CLIENT_RANDOM 01 23\n", dsb.data);
+                }
+                Block::Custom(cb) => {
+                    assert!(!cb.copyable());
+                    assert_eq!(b"pako", cb.data);
+                }
+                _ => (),
+            }
+            types.push((e, block.block_type()));
+        }
+        let section = [0x0A0D_0D0A, 1, 1, 6, 6, 4, 5, 10, 0x4000_0BAD];
+        let expected: Vec<_> = [Endianness::Little, Endianness::Big]
+            .into_iter()
+            .flat_map(|e| section.iter().map(move |&t| (e, t)))
+            .collect();
+        assert_eq!(expected, types);
+        assert_eq!(2, reader.interfaces().len());
+        assert_eq!(1_000_000_000, reader.interfaces()[0].ts_resolution);
+        assert_eq!(101, reader.interfaces()[1].link_type);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_writer_copy_works() -> Result<()> {
+        for input in [LEGACY_LE_US, LEGACY_BE_NS, LEGACY_MODIFIED] {
+            let mut reader = PcapNgReader::new(input)?;
+            let Some(Block::LegacyHeader(header)) = reader.read_block()? else {
+                panic!("no legacy header");
+            };
+            let mut writer = LegacyPcapWriter::new(Vec::new(), header)?;
+            while let Some(block) = reader.read_block()? {
+                writer.write_block(&block)?;
+            }
+            assert_eq!(input.len(), writer.written());
+            assert_eq!(input, writer.into_inner()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_writer_works() -> Result<()> {
+        let mut header = PcapHeader::new(Endianness::Big, 101, 65535, true);
+        header.set_fcs_len(Some(2));
+        let mut writer = LegacyPcapWriter::new(Vec::new(), header.clone())?;
+        let packet = LegacyPacket {
+            ts_sec: 10,
+            ts_frac: 999_999_999,
+            captured_len: 0,
+            original_len: 100,
+            modified: None,
+            data: &BLOCKS_BE[..40],
+        };
+        assert_eq!(56, writer.write_packet(&packet)?);
+        let output = writer.into_inner()?;
+
+        let mut reader = PcapNgReader::new(&output[..])?;
+        assert!(matches!(reader.read_block()?, Some(Block::LegacyHeader(h)) if h == header));
+        let Some(Block::LegacyPacket(p)) = reader.read_block()? else {
+            panic!("no legacy packet");
+        };
+        assert_eq!((10, 999_999_999), (p.ts_sec, p.ts_frac));
+        assert_eq!((40, 100), (p.captured_len, p.original_len));
+        assert_eq!(Some(2), reader.legacy_header().and_then(|h| h.fcs_len()));
+        Ok(())
+    }
+
+    #[test]
+    fn writer_legacy_conversion() -> Result<()> {
+        for input in [LEGACY_LE_US, LEGACY_BE_NS, LEGACY_MODIFIED] {
+            let mut reader = PcapNgReader::new(input)?;
+            let mut writer = PcapNgWriter::new(Vec::new());
+            let mut interface = None;
+            let mut timestamps = Vec::new();
+            while let Some(block) = reader.read_block()? {
+                match &block {
+                    Block::LegacyHeader(header) => interface = Some(Interface::from(header)),
+                    Block::LegacyPacket(packet) => {
+                        let interface = interface.as_ref().unwrap();
+                        let ts = packet.timestamp(interface.ts_resolution);
+                        timestamps.push(interface.ts_to_nanos(ts));
+                    }
+                    _ => (),
+                }
+                // legacy blocks do not depend on the endianness of the input
+                writer.write_block(&block, Endianness::Big)?;
+            }
+            let output = writer.into_inner()?;
+
+            let mut reader = PcapNgReader::new(&output[..])?;
+            let mut e = Endianness::Big;
+            let mut interface = None;
+            let mut converted = Vec::new();
+            let mut fcs_len = None;
+            while let Some(block) = reader.read_block()? {
+                match &block {
+                    Block::SectionHeader(shb) => e = shb.endianness,
+                    Block::InterfaceDescription(idb) => {
+                        interface = Some(Interface::new(idb, e));
+                        fcs_len = block.options(e).find_map(|o| match o {
+                            BlockOption::If(IfOption::FcsLen(len)) => Some(len),
+                            _ => None,
+                        });
+                    }
+                    Block::EnhancedPacket(epb) => {
+                        let interface = interface.as_ref().unwrap();
+                        converted.push(interface.ts_to_nanos(epb.timestamp()));
+                    }
+                    _ => (),
+                }
+            }
+            assert_eq!(4, converted.len());
+            assert_eq!(timestamps, converted);
+            assert_eq!(input == LEGACY_BE_NS, fcs_len == Some(4));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writer_errors() -> Result<()> {
+        let mut writer = PcapNgWriter::new(Vec::new());
+        assert!(writer.write_interface_description(1, 0, &[]).is_err());
+        writer.write_section_header(Endianness::Little, &[])?;
+        assert!(writer.write_enhanced_packet(0, 0, 0, &[], &[]).is_err());
+        let value = vec![0; 70_000];
+        let option = BlockOption::Raw {
+            code: 42,
+            value: &value,
+        };
+        assert!(writer
+            .write_section_header(Endianness::Little, &[option])
+            .is_err());
+        assert_eq!(28, writer.written());
+        Ok(())
+    }
+}
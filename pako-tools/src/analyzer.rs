@@ -1,6 +1,6 @@
 pub use pcap_parser::PcapBlockOwned;
 
-use crate::{context::*, error::Error, packet::Packet};
+use crate::{context::*, error::Error, mapped_capture::MappedCapture, packet::Packet};
 
 /// Common trait for pcap/pcap-ng analyzers
 pub trait PcapAnalyzer {
@@ -25,6 +25,12 @@ pub trait PcapAnalyzer {
     fn teardown(&mut self) {}
 
     fn before_refill(&mut self) {}
+
+    /// Called before `init` when the input is a memory-mapped file (optional)
+    ///
+    /// Packets passed to `handle_packet` then borrow `capture`, and stay valid for
+    /// the entire run: their data can be shared using `capture.range_of()` instead of copied.
+    fn set_mapped_capture(&mut self, _capture: &MappedCapture) {}
 }
 
 /// Common trait for pcap/pcap-ng analyzers (thread-safe version)
@@ -0,0 +1,266 @@
+//! Async engine and packet stream (feature `async`)
+//!
+//! Parsing is done by the blocking engines, in a task started with `spawn_blocking`.
+//! Data is read from the `AsyncRead` and sent to the blocking task through a bounded
+//! channel, so reading is paused when the analysis is slower than the input.
+
+use std::{
+    future::{pending, Future},
+    io::{self, Read},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+    task,
+};
+
+use crate::{
+    analyzer::PcapAnalyzer,
+    config::Config,
+    context::ParseContext,
+    data_engine::PcapDataEngine,
+    engine::PcapEngine,
+    error::Error,
+    packet_iterator::{OwnedPacket, PacketIterator},
+};
+
+/// Size of chunks read from the input
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Maximum number of chunks waiting to be parsed
+const CHANNEL_SIZE: usize = 16;
+/// Maximum number of decoded packets waiting to be consumed (`PacketStream`)
+const PACKET_QUEUE_SIZE: usize = 1024;
+
+/// Blocking `Read` adapter, reading chunks from a channel
+///
+/// End of input is reached when the channel is closed.
+struct ChannelReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChannelReader {
+    fn new(rx: mpsc::Receiver<Vec<u8>>) -> Self {
+        ChannelReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Read all data from `reader` and send it to `tx`, until end of input or `cancel` completes
+///
+/// The channel is closed when this function returns.
+async fn feed<R, F>(mut reader: R, tx: mpsc::Sender<Vec<u8>>, cancel: F) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    F: Future<Output = ()>,
+{
+    tokio::pin!(cancel);
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let n = tokio::select! {
+            res = reader.read(&mut chunk) => res?,
+            _ = &mut cancel => {
+                debug!("async engine: input cancelled");
+                return Ok(());
+            }
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        chunk.truncate(n);
+        // wait if the parser is late (backpressure)
+        let sent = tokio::select! {
+            res = tx.send(chunk) => res.is_ok(),
+            _ = &mut cancel => {
+                debug!("async engine: input cancelled");
+                return Ok(());
+            }
+        };
+        if !sent {
+            // parser stopped (error, or end of data)
+            return Ok(());
+        }
+    }
+}
+
+/// pcap/pcap-ng data analyzer engine, reading from an `AsyncRead`
+///
+/// This engine wraps a `PcapDataEngine`, which is run in a blocking task.
+/// The analyzer must be `Send`, since it is moved to the blocking task during the analysis.
+///
+/// ## example
+///
+/// ```ignore
+/// let mut engine = AsyncPcapEngine::new(analyzer, &config);
+/// let stream = tokio::net::TcpStream::connect(addr).await?;
+/// engine.run(stream).await?;
+/// let analyzer = engine.into_analyzer();
+/// ```
+pub struct AsyncPcapEngine<A: PcapAnalyzer + Send + 'static> {
+    engine: Option<PcapDataEngine<A>>,
+}
+
+impl<A: PcapAnalyzer + Send + 'static> AsyncPcapEngine<A> {
+    pub fn new(data_analyzer: A, config: &Config) -> Self {
+        let engine = PcapDataEngine::new(data_analyzer, config);
+        AsyncPcapEngine {
+            engine: Some(engine),
+        }
+    }
+
+    /// Return the analyzer, or `None` if the engine was lost (a previous `run` was cancelled
+    /// by dropping its future, or the blocking task panicked)
+    pub fn data_analyzer(&self) -> Option<&A> {
+        self.engine.as_ref().map(|e| e.data_analyzer())
+    }
+
+    /// Consume the engine and return the analyzer (see `data_analyzer`)
+    pub fn into_analyzer(self) -> Option<A> {
+        self.engine.map(|e| e.into_data_analyzer())
+    }
+
+    /// Read all pcap data from `reader`, and call analyzer for each packet
+    pub async fn run<R: AsyncRead + Unpin>(&mut self, reader: R) -> Result<(), Error> {
+        self.run_until(reader, pending()).await
+    }
+
+    /// Read pcap data from `reader` until end of input, or until `cancel` completes
+    ///
+    /// On cancellation, reading stops and the analyzer processes the remaining buffered data,
+    /// then `teardown` is called as if the input had ended.
+    ///
+    /// If the returned future is dropped before completion, the analysis still ends
+    /// (with `teardown`) in the blocking task, but the analyzer cannot be recovered.
+    pub async fn run_until<R, F>(&mut self, reader: R, cancel: F) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        F: Future<Output = ()>,
+    {
+        let mut engine = self
+            .engine
+            .take()
+            .ok_or(Error::Generic("Async engine: analyzer is not available"))?;
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+        let handle = task::spawn_blocking(move || {
+            let mut input = ChannelReader::new(rx);
+            let res = engine.run(&mut input);
+            (engine, res)
+        });
+        let feed_res = feed(reader, tx, cancel).await;
+        let (engine, res) = handle
+            .await
+            .map_err(|_| Error::Generic("Async engine: analysis task failed"))?;
+        self.engine = Some(engine);
+        feed_res.and(res)
+    }
+}
+
+/// Stream of the packets of a pcap/pcap-ng input, read from an `AsyncRead`
+///
+/// This is the async version of `PacketIterator`. Packets are decoded in a blocking task,
+/// and at most a fixed number of packets are queued: the input is not read further
+/// until packets are consumed.
+///
+/// Dropping the stream stops reading and decoding.
+pub struct PacketStream {
+    rx: mpsc::Receiver<Result<(OwnedPacket, ParseContext), Error>>,
+}
+
+impl PacketStream {
+    /// Create a new stream, and start reading `reader`
+    ///
+    /// This function must be called from a tokio runtime.
+    pub fn new<R>(reader: R, config: &Config) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let capacity = config
+            .get_usize("buffer_initial_capacity")
+            .unwrap_or(128 * 1024);
+        let (data_tx, data_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (tx, rx) = mpsc::channel(PACKET_QUEUE_SIZE);
+        tokio::spawn(async move {
+            let cancel = {
+                let tx = data_tx.clone();
+                // stop reading when the parser has stopped
+                async move { tx.closed().await }
+            };
+            if let Err(e) = feed(reader, data_tx, cancel).await {
+                warn!("PacketStream: error while reading input: {}", e);
+            }
+        });
+        task::spawn_blocking(move || {
+            let input = ChannelReader::new(data_rx);
+            let iter = match PacketIterator::with_capacity(input, capacity) {
+                Ok(iter) => iter,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+            for item in iter {
+                if tx.blocking_send(item).is_err() {
+                    // stream was dropped
+                    break;
+                }
+            }
+        });
+        PacketStream { rx }
+    }
+}
+
+impl Stream for PacketStream {
+    type Item = Result<(OwnedPacket, ParseContext), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tokio::sync::mpsc;
+
+    use super::ChannelReader;
+
+    #[test]
+    fn channel_reader_chunks() {
+        let (tx, rx) = mpsc::channel(4);
+        tx.try_send(b"abc".to_vec()).unwrap();
+        tx.try_send(Vec::new()).unwrap();
+        tx.try_send(b"defgh".to_vec()).unwrap();
+        drop(tx);
+        let mut reader = ChannelReader::new(rx);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcdefgh");
+    }
+}
@@ -1,8 +1,14 @@
 use std::io::Read;
 
+use pako_pcap::PcapNgReader;
 use pcap_parser::{PcapBlockOwned, PcapError};
 
-use crate::{config::Config, context::*, error::Error};
+use crate::{
+    config::Config,
+    context::*,
+    error::Error,
+    mapped_capture::{Format, MappedBlocks},
+};
 
 pub trait BlockAnalyzer {
     /// Initialization function, called before reading pcap data (optional)
@@ -44,6 +50,10 @@ impl<A: BlockAnalyzer> BlockEngine<A> {
         &mut self.analyzer
     }
 
+    pub fn into_analyzer(self) -> A {
+        self.analyzer
+    }
+
     /// Main function: given a reader, read all pcap data and call analyzer for each Packet
     pub fn run(&mut self, reader: &mut dyn Read) -> Result<(), Error> {
         let mut reader = pcap_parser::create_reader(self.capacity, reader)?;
@@ -98,4 +108,144 @@ impl<A: BlockAnalyzer> BlockEngine<A> {
         self.analyzer.teardown();
         Ok(())
     }
+
+    /// Read all pcap data from a pako-pcap reader, and call analyzer for each block
+    ///
+    /// The reader handles buffering and tracks the section state. Each block it returns is
+    /// parsed again by pcap-parser, and given to the analyzer as a `PcapBlockOwned`, so the
+    /// same analyzers can be used with both readers.
+    pub fn run_pcapng_reader<R: Read>(
+        &mut self,
+        reader: &mut PcapNgReader<R>,
+    ) -> Result<(), Error> {
+        self.analyzer.init()?;
+        let mut ctx = ParseBlockContext::default();
+        let mut format = Format::Unknown;
+        let mut last_incomplete_index = 0;
+
+        loop {
+            match reader.next_block() {
+                Ok((offset, _)) => {
+                    let mut blocks = MappedBlocks::with_format(&reader.data()[..offset], format);
+                    let (_, block) = blocks
+                        .next()
+                        .ok_or(Error::Generic("Block rejected by pcap-parser"))??;
+                    format = blocks.format();
+                    self.analyzer.handle_block(&block, &ctx)?;
+                    ctx.block_index += 1;
+                    reader.consume_noshift(offset);
+                    continue;
+                }
+                Err(pako_pcap::Error::Eof) => break,
+                Err(pako_pcap::Error::Incomplete(_)) => {
+                    if last_incomplete_index == ctx.block_index && reader.reader_exhausted() {
+                        warn!(
+                            "Could not read complete data block (block_index={})",
+                            ctx.block_index
+                        );
+                        warn!(
+                            "  Buffer: consumed={} position={}",
+                            reader.consumed(),
+                            reader.position()
+                        );
+                        warn!("Hint: the input file may be truncated.");
+                        break;
+                    }
+                    last_incomplete_index = ctx.block_index;
+                    debug!("need refill");
+                    self.analyzer.before_refill();
+                    reader.refill()?;
+                    continue;
+                }
+                Err(e) => {
+                    error!("error while reading: {:?}", e);
+                    error!(
+                        "  Buffer: consumed={} position={}",
+                        reader.consumed(),
+                        reader.position()
+                    );
+                    return Err(Error::PcapNg(e));
+                }
+            }
+        }
+
+        self.analyzer.teardown();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Cursor,
+    };
+
+    use pako_pcap::PcapNgReader;
+    use pcap_parser::PcapBlockOwned;
+
+    use super::{BlockAnalyzer, BlockEngine};
+    use crate::{Config, Error, MappedBlocks, ParseBlockContext};
+
+    const ASSETS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../pako-pcap/assets/");
+
+    #[derive(Default)]
+    struct BlockRecorder {
+        blocks: Vec<String>,
+    }
+
+    impl BlockAnalyzer for BlockRecorder {
+        fn handle_block(
+            &mut self,
+            block: &PcapBlockOwned,
+            block_ctx: &ParseBlockContext,
+        ) -> Result<(), Error> {
+            assert_eq!(block_ctx.block_index, self.blocks.len());
+            self.blocks.push(block_string(block));
+            Ok(())
+        }
+    }
+
+    fn block_string(block: &PcapBlockOwned) -> String {
+        match block {
+            PcapBlockOwned::LegacyHeader(header) => format!("{:?}", header),
+            PcapBlockOwned::Legacy(b) => format!("{:?}", b),
+            PcapBlockOwned::NG(b) => format!("{:?}", b),
+        }
+    }
+
+    #[test]
+    fn block_engine_pcapng_reader() {
+        let config = Config::default();
+        for name in [
+            "legacy_le_us.pcap",
+            "legacy_be_ns.pcap",
+            "legacy_modified.pcap",
+            "blocks_le.pcapng",
+            "blocks_be.pcapng",
+            "test002_le.pcapng",
+        ] {
+            let path = format!("{}{}", ASSETS, name);
+            let data = fs::read(&path).expect("read");
+            let expected = MappedBlocks::new(&data)
+                .map(|res| block_string(&res.expect("block").1))
+                .collect::<Vec<_>>();
+            assert!(!expected.is_empty(), "{}", name);
+
+            // small buffer, to test refills
+            let file = File::open(&path).expect("open");
+            let mut reader = PcapNgReader::with_capacity(64, file).expect("reader");
+            let mut engine = BlockEngine::new(BlockRecorder::default(), &config);
+            engine.run_pcapng_reader(&mut reader).expect("run");
+            assert_eq!(engine.into_analyzer().blocks, expected, "{}", name);
+
+            // a truncated last block ends reading without error
+            let cursor = Cursor::new(&data[..data.len() - 3]);
+            let mut reader = PcapNgReader::with_capacity(64, cursor).expect("reader");
+            let mut engine = BlockEngine::new(BlockRecorder::default(), &config);
+            engine.run_pcapng_reader(&mut reader).expect("run");
+            let blocks = engine.into_analyzer().blocks;
+            assert_eq!(blocks[..], expected[..expected.len() - 1], "{}", name);
+        }
+    }
 }
@@ -7,15 +7,28 @@ use crate::{
     block_engine::{BlockAnalyzer, BlockEngine},
     config::Config,
     context::*,
-    duration::{Duration, MICROS_PER_SEC},
+    duration::Duration,
     engine::PcapEngine,
-    error::Error,
+    error::{Error, Layer},
     packet::Packet,
 };
 
-struct PcapDataAnalyzer<A: PcapAnalyzer> {
-    data_analyzer: A,
+pub(crate) struct PcapDataAnalyzer<A: PcapAnalyzer> {
+    pub(crate) data_analyzer: A,
 
+    decoder: PacketDecoder,
+
+    /// If true, stop on the first malformed packet
+    strict: bool,
+    /// Number of packets skipped because of errors
+    pub(crate) num_errors: usize,
+}
+
+/// Decoder of packets from pcap/pcap-ng blocks
+///
+/// Keeps track of the interfaces of the current section, and of the parsing context.
+#[derive(Default)]
+pub(crate) struct PacketDecoder {
     ctx: ParseContext,
     interfaces: Vec<InterfaceInfo>,
 }
@@ -53,13 +66,16 @@ struct PcapDataAnalyzer<A: PcapAnalyzer> {
 /// let mut input = Cursor::new(vec![1, 2, 3, 4, 5]);
 /// let res = engine.run(&mut input);
 /// ```
+///
+/// Packets which cannot be decoded (for ex. referring to an unknown interface) are counted
+/// and skipped, unless the `strict` configuration option is set.
 pub struct PcapDataEngine<A: PcapAnalyzer> {
     engine: BlockEngine<PcapDataAnalyzer<A>>,
 }
 
 impl<A: PcapAnalyzer> PcapDataEngine<A> {
     pub fn new(data_analyzer: A, config: &Config) -> Self {
-        let data_analyzer = PcapDataAnalyzer::new(data_analyzer);
+        let data_analyzer = PcapDataAnalyzer::new(data_analyzer, config);
         let engine = BlockEngine::new(data_analyzer, config);
         PcapDataEngine { engine }
     }
@@ -71,17 +87,42 @@ impl<A: PcapAnalyzer> PcapDataEngine<A> {
     pub fn data_analyzer_mut(&mut self) -> &mut A {
         &mut self.engine.analyzer_mut().data_analyzer
     }
+
+    pub fn into_data_analyzer(self) -> A {
+        self.engine.into_analyzer().data_analyzer
+    }
+
+    /// Return the number of packets skipped because they could not be decoded
+    pub fn num_errors(&self) -> usize {
+        self.engine.analyzer().num_errors
+    }
 }
 
 impl<A: PcapAnalyzer> PcapDataAnalyzer<A> {
-    pub fn new(data_analyzer: A) -> Self {
-        let ctx = ParseContext::default();
-        let interfaces = Vec::new();
+    pub fn new(data_analyzer: A, config: &Config) -> Self {
+        let strict = config.get_bool("strict").unwrap_or(false);
         PcapDataAnalyzer {
             data_analyzer,
-            ctx,
-            interfaces,
+            decoder: PacketDecoder::default(),
+            strict,
+            num_errors: 0,
+        }
+    }
+
+    /// Count a packet decoding error
+    ///
+    /// The error is returned in strict mode (analysis will stop), and ignored otherwise.
+    fn packet_error(&mut self, e: Error) -> Result<(), Error> {
+        self.num_errors += 1;
+        if self.strict {
+            return Err(e);
         }
+        warn!(
+            "Skipping packet: {} (idx={})",
+            e,
+            self.decoder.ctx().pcap_index
+        );
+        Ok(())
     }
 }
 
@@ -102,37 +143,74 @@ impl<A: PcapAnalyzer> BlockAnalyzer for PcapDataAnalyzer<A> {
         block_ctx: &ParseBlockContext,
     ) -> Result<(), Error> {
         self.data_analyzer.handle_block(block, block_ctx)?;
+        match self.decoder.decode(block) {
+            Ok(Some(packet)) => {
+                // call data analyzer
+                self.data_analyzer
+                    .handle_packet(&packet, self.decoder.ctx())?;
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(e) => self.packet_error(e),
+        }
+    }
+
+    fn teardown(&mut self) {
+        self.data_analyzer.teardown()
+    }
+
+    fn before_refill(&mut self) {
+        self.data_analyzer.before_refill()
+    }
+}
+
+impl PacketDecoder {
+    /// Parsing context of the last decoded packet
+    pub(crate) fn ctx(&self) -> &ParseContext {
+        &self.ctx
+    }
+
+    /// Parsing context, for readers which do not decode all blocks in file order
+    pub(crate) fn ctx_mut(&mut self) -> &mut ParseContext {
+        &mut self.ctx
+    }
+
+    fn interface(&self, if_id: u32) -> Result<&InterfaceInfo, Error> {
+        self.interfaces
+            .get(if_id as usize)
+            .ok_or(Error::malformed(Layer::Capture, "interface id"))
+    }
+
+    /// Update state from block, and return the packet if the block contains one
+    ///
+    /// Errors are related to the current packet only: decoding can continue with the next block.
+    pub(crate) fn decode<'a>(
+        &mut self,
+        block: &PcapBlockOwned<'a>,
+    ) -> Result<Option<Packet<'a>>, Error> {
         let packet = match block {
             PcapBlockOwned::NG(Block::SectionHeader(_)) => {
                 // reset section-related variables
                 self.interfaces = Vec::new();
-                return Ok(());
+                return Ok(None);
             }
             PcapBlockOwned::NG(Block::InterfaceDescription(ref idb)) => {
                 let if_info = pcapng_build_interface(idb);
                 self.interfaces.push(if_info);
-                return Ok(());
+                return Ok(None);
             }
             PcapBlockOwned::NG(Block::EnhancedPacket(ref epb)) => {
                 self.ctx.pcap_index += 1;
-                assert!((epb.if_id as usize) < self.interfaces.len());
-                let if_info = &self.interfaces[epb.if_id as usize];
-                let unit = if_info.ts_unit;
-                let (ts_sec, ts_frac) =
-                    pcap_parser::build_ts(epb.ts_high, epb.ts_low, if_info.if_tsoffset, unit);
-                let unit = unit as u32; // XXX lossy cast
-                let ts_usec = if unit != MICROS_PER_SEC {
-                    ts_frac / (unit / MICROS_PER_SEC)
-                } else {
-                    ts_frac
-                };
-                let ts = Duration::new(ts_sec, ts_usec);
+                let if_info = self.interface(epb.if_id)?;
+                let ts = (u64::from(epb.ts_high) << 32) | u64::from(epb.ts_low);
+                let ts = Duration::from_units(ts, if_info.ts_unit)
+                    + Duration::from_secs(if_info.if_tsoffset);
                 let data = pcap_parser::data::get_packetdata(
                     epb.data,
                     if_info.link_type,
                     epb.caplen as usize,
                 )
-                .ok_or(Error::Generic("Parsing PacketData failed (EnhancedPacket)"))?;
+                .ok_or(Error::malformed(Layer::Capture, "EnhancedPacket"))?;
                 Packet {
                     interface: epb.if_id,
                     ts,
@@ -145,11 +223,10 @@ impl<A: PcapAnalyzer> BlockAnalyzer for PcapDataAnalyzer<A> {
             }
             PcapBlockOwned::NG(Block::SimplePacket(ref spb)) => {
                 self.ctx.pcap_index += 1;
-                assert!(!self.interfaces.is_empty());
-                let if_info = &self.interfaces[0];
-                let blen = (spb.block_len1 - 16) as usize;
+                let if_info = self.interface(0)?;
+                let blen = spb.block_len1.saturating_sub(16) as usize;
                 let data = pcap_parser::data::get_packetdata(spb.data, if_info.link_type, blen)
-                    .ok_or(Error::Generic("Parsing PacketData failed (SimplePacket)"))?;
+                    .ok_or(Error::malformed(Layer::Capture, "SimplePacket"))?;
                 Packet {
                     interface: 0,
                     ts: Duration::default(),
@@ -176,19 +253,18 @@ impl<A: PcapAnalyzer> BlockAnalyzer for PcapDataAnalyzer<A> {
                 };
                 self.interfaces.push(if_info);
                 trace!("Legacy pcap,  link type: {}", hdr.network);
-                return Ok(());
+                return Ok(None);
             }
             PcapBlockOwned::Legacy(ref b) => {
                 self.ctx.pcap_index += 1;
-                assert!(!self.interfaces.is_empty());
-                let if_info = &self.interfaces[0];
+                let if_info = self.interface(0)?;
                 let blen = b.caplen as usize;
                 let data = pcap_parser::data::get_packetdata(b.data, if_info.link_type, blen)
-                    .ok_or(Error::Generic("Parsing PacketData failed (Legacy Packet)"))?;
+                    .ok_or(Error::malformed(Layer::Capture, "Legacy Packet"))?;
                 let ts = if if_info.if_tsresol == 6 {
-                    Duration::new(b.ts_sec, b.ts_usec)
+                    Duration::from_micros_parts(u64::from(b.ts_sec), b.ts_usec)
                 } else {
-                    Duration::new(b.ts_sec, b.ts_usec / 1000)
+                    Duration::from_nanos_parts(u64::from(b.ts_sec), b.ts_usec)
                 };
                 Packet {
                     interface: 0,
@@ -201,13 +277,15 @@ impl<A: PcapAnalyzer> BlockAnalyzer for PcapDataAnalyzer<A> {
                 }
             }
             PcapBlockOwned::NG(Block::InterfaceStatistics(_))
-            | PcapBlockOwned::NG(Block::NameResolution(_)) => {
-                // XXX just ignore block
-                return Ok(());
+            | PcapBlockOwned::NG(Block::NameResolution(_))
+            | PcapBlockOwned::NG(Block::DecryptionSecrets(_)) => {
+                // no packet: statistics, names and secrets are available to analyzers
+                // in `handle_block`
+                return Ok(None);
             }
             _ => {
                 warn!("unsupported block");
-                return Ok(());
+                return Ok(None);
             }
         };
         trace!("**************************************************************");
@@ -215,23 +293,9 @@ impl<A: PcapAnalyzer> BlockAnalyzer for PcapDataAnalyzer<A> {
         if self.ctx.first_packet_ts.is_null() {
             self.ctx.first_packet_ts = packet.ts;
         }
-        trace!("    time  : {} / {:06}", packet.ts.secs, packet.ts.micros);
-        self.ctx.rel_ts = packet.ts - self.ctx.first_packet_ts; // an underflow is weird but not critical
-        trace!(
-            "    reltime  : {}.{:06}",
-            self.ctx.rel_ts.secs,
-            self.ctx.rel_ts.micros
-        );
-        // call data analyzer
-        self.data_analyzer.handle_packet(&packet, &self.ctx)?;
-        Ok(())
-    }
-
-    fn teardown(&mut self) {
-        self.data_analyzer.teardown()
-    }
-
-    fn before_refill(&mut self) {
-        self.data_analyzer.before_refill()
+        trace!("    time  : {}", packet.ts);
+        self.ctx.rel_ts = packet.ts - self.ctx.first_packet_ts; // an underflow saturates to 0
+        trace!("    reltime  : {}", self.ctx.rel_ts);
+        Ok(Some(packet))
     }
 }
@@ -1,74 +1,204 @@
-use std::ops::{Add, Sub};
+use std::{
+    fmt,
+    ops::{Add, Sub},
+    time::{SystemTime, SystemTimeError, UNIX_EPOCH},
+};
 
 /// Reimplementation of std::time::Duration, but panic-free
 /// and partial, only to match our needs:
-///   - use micros instead of nanos, avoid casts
-///   - expose fields
+///   - store a single 64-bit count of nanoseconds (valid until year 2554 for timestamps)
+///   - saturating arithmetic
+///
+/// `Duration` is used both for timestamps (duration since the UNIX epoch) and for
+/// relative times.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
 pub struct Duration {
-    pub secs: u32,
-    pub micros: u32,
+    nanos: u64,
 }
 
 pub const MICROS_PER_SEC: u32 = 1_000_000;
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_MICRO: u64 = 1_000;
 
 impl Duration {
+    /// Build Duration from secs and nanos
+    pub const fn from_nanos_parts(secs: u64, nanos: u32) -> Duration {
+        let nanos = secs
+            .saturating_mul(NANOS_PER_SEC)
+            .saturating_add(nanos as u64);
+        Duration { nanos }
+    }
+
     /// Build Duration from secs and micros
-    pub fn new(secs: u32, micros: u32) -> Duration {
-        Duration { secs, micros }
+    pub const fn from_micros_parts(secs: u64, micros: u32) -> Duration {
+        let micros = micros as u64 * NANOS_PER_MICRO;
+        let nanos = secs.saturating_mul(NANOS_PER_SEC).saturating_add(micros);
+        Duration { nanos }
+    }
+
+    pub const fn from_secs(secs: u64) -> Duration {
+        Duration::from_nanos_parts(secs, 0)
+    }
+
+    pub const fn from_micros(micros: u64) -> Duration {
+        Duration {
+            nanos: micros.saturating_mul(NANOS_PER_MICRO),
+        }
+    }
+
+    pub const fn from_nanos(nanos: u64) -> Duration {
+        Duration { nanos }
+    }
+
+    /// Build Duration from a count of `value` units, where `unit` is the number of units
+    /// per second (for ex. `1_000_000` for microseconds)
+    ///
+    /// Fractions of nanoseconds are truncated. A `unit` of 0 is treated as 1.
+    pub fn from_units(value: u64, unit: u64) -> Duration {
+        let unit = unit.max(1);
+        let frac = (u128::from(value % unit) * u128::from(NANOS_PER_SEC)) / u128::from(unit);
+        // frac < NANOS_PER_SEC, cast is safe
+        Duration::from_nanos_parts(value / unit, frac as u32)
     }
+
+    /// Total number of whole seconds
+    #[inline]
+    pub const fn secs(self) -> u64 {
+        self.nanos / NANOS_PER_SEC
+    }
+
+    /// Fractional part, in nanoseconds
+    #[inline]
+    pub const fn subsec_nanos(self) -> u32 {
+        (self.nanos % NANOS_PER_SEC) as u32
+    }
+
+    /// Fractional part, in whole microseconds
+    #[inline]
+    pub const fn subsec_micros(self) -> u32 {
+        self.subsec_nanos() / NANOS_PER_MICRO as u32
+    }
+
+    /// Total number of nanoseconds
+    #[inline]
+    pub const fn as_nanos(self) -> u64 {
+        self.nanos
+    }
+
+    /// Total number of whole microseconds
+    #[inline]
+    pub const fn as_micros(self) -> u64 {
+        self.nanos / NANOS_PER_MICRO
+    }
+
     /// Test if Duration object is null
     #[inline]
     pub fn is_null(self) -> bool {
-        self.secs == 0 && self.micros == 0
+        self.nanos == 0
+    }
+
+    pub fn to_std(self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.nanos)
+    }
+
+    /// Convert a timestamp (duration since the UNIX epoch) to a `SystemTime`
+    pub fn to_system_time(self) -> SystemTime {
+        UNIX_EPOCH + self.to_std()
     }
 }
 
 impl Add for Duration {
     type Output = Duration;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, other: Duration) -> Self::Output {
-        let secs = self.secs.wrapping_add(other.secs);
-        let micros = self.micros.wrapping_add(other.micros);
-        let (secs, micros) = if micros > MICROS_PER_SEC {
-            (secs + (micros / MICROS_PER_SEC), micros % MICROS_PER_SEC)
-        } else {
-            (secs, micros)
-        };
-
-        Duration { secs, micros }
+        Duration {
+            nanos: self.nanos.saturating_add(other.nanos),
+        }
     }
 }
 
 impl Sub for Duration {
     type Output = Duration;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
+    /// Subtract durations, returning 0 on underflow
     fn sub(self, other: Duration) -> Self::Output {
-        let secs = self.secs.wrapping_sub(other.secs);
-        let (secs, micros) = if self.micros >= other.micros {
-            (secs, self.micros - other.micros)
-        } else {
-            let diff = other.micros.wrapping_sub(self.micros);
-            let secs_less = diff / MICROS_PER_SEC;
-            let micros = MICROS_PER_SEC - diff;
-            (secs.wrapping_sub(1 + secs_less), micros)
-        };
-
-        Duration { secs, micros }
+        Duration {
+            nanos: self.nanos.saturating_sub(other.nanos),
+        }
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:09}", self.secs(), self.subsec_nanos())
+    }
+}
+
+impl From<Duration> for std::time::Duration {
+    fn from(d: Duration) -> Self {
+        d.to_std()
+    }
+}
+
+/// Conversion from `std::time::Duration` (saturating)
+impl From<std::time::Duration> for Duration {
+    fn from(d: std::time::Duration) -> Self {
+        Duration::from_nanos_parts(d.as_secs(), d.subsec_nanos())
+    }
+}
+
+impl From<Duration> for SystemTime {
+    fn from(d: Duration) -> Self {
+        d.to_system_time()
+    }
+}
+
+/// Conversion from `SystemTime` (fails if time is before the UNIX epoch)
+impl TryFrom<SystemTime> for Duration {
+    type Error = SystemTimeError;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        t.duration_since(UNIX_EPOCH).map(Duration::from)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::SystemTime;
+
     use super::Duration;
     #[test]
     fn duration_sub() {
-        let d1 = Duration::new(1234, 5678);
-        let d2 = Duration::new(1234, 6789);
+        let d1 = Duration::from_micros_parts(1234, 5678);
+        let d2 = Duration::from_micros_parts(1234, 6789);
         let d = d2 - d1;
-        assert_eq!(d.secs, 0);
-        assert_eq!(d.micros, 1111);
+        assert_eq!(d.secs(), 0);
+        assert_eq!(d.subsec_micros(), 1111);
+        assert!((d1 - d2).is_null());
+    }
+
+    #[test]
+    fn duration_after_2106() {
+        // 2200-01-01, does not fit in 32-bit seconds
+        let d = Duration::from_nanos_parts(7_258_118_400, 123_456_789);
+        assert_eq!(d.secs(), 7_258_118_400);
+        assert_eq!(d.subsec_nanos(), 123_456_789);
+        let t: SystemTime = d.into();
+        assert_eq!(Duration::try_from(t).unwrap(), d);
+        assert_eq!(d.to_string(), "7258118400.123456789");
+    }
+
+    #[test]
+    fn duration_from_units() {
+        assert_eq!(
+            Duration::from_units(1_500_000_001, 1_000_000_000),
+            Duration::from_nanos_parts(1, 500_000_001)
+        );
+        assert_eq!(
+            Duration::from_units(3, 2),
+            Duration::from_nanos_parts(1, 500_000_000)
+        );
+        let std = std::time::Duration::new(5, 6);
+        assert_eq!(Duration::from(std).to_std(), std);
     }
 }
@@ -1,4 +1,4 @@
-use std::io;
+use std::{fmt, io};
 
 use pcap_parser::{
     nom::{error::ErrorKind, Err},
@@ -6,6 +6,61 @@ use pcap_parser::{
 };
 use thiserror::Error;
 
+/// Protocol layer where a decoding error occurred
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Layer {
+    /// Capture file structure (blocks, interfaces)
+    Capture,
+    Link,
+    Network,
+    Transport,
+    Application,
+}
+
+/// Kind of a decoding error
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum DecodeErrorKind {
+    /// Data is too short for the expected header or payload
+    Truncated,
+    /// Data is present, but has invalid values
+    Malformed,
+    /// Protocol or data format is not supported
+    Unsupported,
+    /// Checksum is invalid
+    Checksum,
+    /// Defragmentation or reassembly failed
+    Defrag,
+}
+
+/// Error or anomaly found while decoding a packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub layer: Layer,
+    /// Protocol name (for ex. "TCP")
+    pub protocol: &'static str,
+}
+
+impl DecodeError {
+    pub const fn new(kind: DecodeErrorKind, layer: Layer, protocol: &'static str) -> Self {
+        DecodeError {
+            kind,
+            layer,
+            protocol,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} ({:?} layer)",
+            self.kind, self.protocol, self.layer
+        )
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Internal parser error {0:?}")]
@@ -14,8 +69,65 @@ pub enum Error {
     IoError(#[from] io::Error),
     #[error("Pcap parser error {0:?}")]
     Pcap(#[from] PcapError<&'static [u8]>),
+    #[error("Pcap reader error: {0}")]
+    PcapNg(#[from] pako_pcap::Error<&'static [u8]>),
     #[error("Generic error {0}")]
     Generic(&'static str),
+    #[error("Decoding error: {0}")]
+    Decode(DecodeError),
+}
+
+impl Error {
+    /// Data too short at `layer` for `protocol`
+    pub const fn truncated(layer: Layer, protocol: &'static str) -> Self {
+        Error::Decode(DecodeError::new(
+            DecodeErrorKind::Truncated,
+            layer,
+            protocol,
+        ))
+    }
+
+    /// Invalid data at `layer` for `protocol`
+    pub const fn malformed(layer: Layer, protocol: &'static str) -> Self {
+        Error::Decode(DecodeError::new(
+            DecodeErrorKind::Malformed,
+            layer,
+            protocol,
+        ))
+    }
+
+    /// Unsupported protocol or format at `layer`
+    pub const fn unsupported(layer: Layer, protocol: &'static str) -> Self {
+        Error::Decode(DecodeError::new(
+            DecodeErrorKind::Unsupported,
+            layer,
+            protocol,
+        ))
+    }
+
+    /// Invalid checksum at `layer` for `protocol`
+    pub const fn checksum(layer: Layer, protocol: &'static str) -> Self {
+        Error::Decode(DecodeError::new(DecodeErrorKind::Checksum, layer, protocol))
+    }
+
+    /// Defragmentation or reassembly failure at `layer` for `protocol`
+    pub const fn defrag(layer: Layer, protocol: &'static str) -> Self {
+        Error::Decode(DecodeError::new(DecodeErrorKind::Defrag, layer, protocol))
+    }
+
+    /// Return the decoding error, if this error is a `Decode` error
+    pub fn decode_error(&self) -> Option<&DecodeError> {
+        match self {
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
 }
 
 impl From<&'static str> for Error {
@@ -1,11 +1,11 @@
 use std::{
     fmt,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
 };
 
 use serde::Serialize;
 
-use crate::three_tuple::ThreeTuple;
+use crate::{ipv6_ext::Ipv6ExtensionWalker, three_tuple::ThreeTuple};
 
 /// Network 5-tuple: layer 4 protocol (e.g TCP or UDP), source and destination IP/ports
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize)]
@@ -49,6 +49,77 @@ impl FiveTuple {
             dst_port,
         }
     }
+
+    /// Creates a `FiveTuple` from an IPv4 or IPv6 packet, starting at the IP header
+    ///
+    /// Ports are extracted as in the analyzer: real ports for TCP, UDP and SCTP, type and
+    /// code for ICMP, and 0 for other protocols. IPv6 extension headers are skipped. The
+    /// packet can be truncated (for ex. when quoted in an ICMP error message), ports are
+    /// then 0 if the transport header is missing.
+    ///
+    /// The second value is `false` for IP fragments other than the first, which have no
+    /// transport header (ports are 0). Returns `None` if the IP header is truncated.
+    pub fn from_ip_packet(data: &[u8]) -> Option<(Self, bool)> {
+        let version = data.first()? >> 4;
+        let (src, dst, proto, l4, first_fragment) = match version {
+            4 => {
+                if data.len() < 20 {
+                    return None;
+                }
+                let ihl = (data[0] & 0x0f) as usize * 4;
+                let src = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
+                let dst = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+                let first_fragment = u16::from_be_bytes([data[6], data[7]]) & 0x1fff == 0;
+                let l4 = if first_fragment && ihl >= 20 && ihl <= data.len() {
+                    &data[ihl..]
+                } else {
+                    &[]
+                };
+                (
+                    IpAddr::V4(src),
+                    IpAddr::V4(dst),
+                    data[9],
+                    l4,
+                    first_fragment,
+                )
+            }
+            6 => {
+                if data.len() < 40 {
+                    return None;
+                }
+                let src: [u8; 16] = data[8..24].try_into().ok()?;
+                let dst: [u8; 16] = data[24..40].try_into().ok()?;
+                let (proto, l4, first_fragment) = skip_ipv6_extensions(data[6], &data[40..]);
+                (
+                    IpAddr::V6(Ipv6Addr::from(src)),
+                    IpAddr::V6(Ipv6Addr::from(dst)),
+                    proto,
+                    l4,
+                    first_fragment,
+                )
+            }
+            _ => return None,
+        };
+        let (src_port, dst_port) = match (proto, l4) {
+            // TCP, UDP and SCTP
+            (6 | 17 | 132, [s0, s1, d0, d1, ..]) => (
+                u16::from_be_bytes([*s0, *s1]),
+                u16::from_be_bytes([*d0, *d1]),
+            ),
+            // ICMP
+            (1, [icmp_type, icmp_code, ..]) => (u16::from(*icmp_type), u16::from(*icmp_code)),
+            _ => (0, 0),
+        };
+        let five_tuple = FiveTuple {
+            proto,
+            src,
+            dst,
+            src_port,
+            dst_port,
+        };
+        Some((five_tuple, first_fragment))
+    }
+
     /// Returns the opposite `FiveTuple` (swaps IP addresses, and ports)
     pub fn get_reverse(&self) -> FiveTuple {
         FiveTuple {
@@ -61,6 +132,28 @@ impl FiveTuple {
     }
 }
 
+/// Skip the extension headers of a (possibly truncated) IPv6 packet
+///
+/// Returns the protocol, the transport data, and `false` for fragments other than the first.
+fn skip_ipv6_extensions(next_header: u8, data: &[u8]) -> (u8, &[u8], bool) {
+    let mut walker = Ipv6ExtensionWalker::new(next_header, data);
+    while let Some(res) = walker.next() {
+        match res {
+            // transport header is only present in the first fragment
+            Ok((44, ext)) if Ipv6ExtensionWalker::fragment_offset(ext) != 0 => {
+                return (walker.next_header(), &[], false);
+            }
+            Ok(_) => (),
+            // truncated packet: the protocol may still be known
+            Err(_) => {
+                let next_header = walker.data().first().copied();
+                return (next_header.unwrap_or(walker.next_header()), &[], true);
+            }
+        }
+    }
+    (walker.next_header(), walker.data(), true)
+}
+
 impl Default for FiveTuple {
     fn default() -> Self {
         FiveTuple {
@@ -1,4 +1,7 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    hash::{Hash, Hasher},
+    net::IpAddr,
+};
 
 use crate::{five_tuple::FiveTuple, Duration};
 
@@ -20,13 +23,12 @@ pub struct Flow {
 }
 
 impl Flow {
-    pub fn new(five_tuple: &FiveTuple, ts_sec: u32, ts_usec: u32) -> Self {
-        let d = Duration::new(ts_sec, ts_usec);
+    pub fn new(five_tuple: &FiveTuple, ts: Duration) -> Self {
         Flow {
             flow_id: 0,
             five_tuple: five_tuple.clone(),
-            first_seen: d,
-            last_seen: d,
+            first_seen: ts,
+            last_seen: ts,
         }
     }
 }
@@ -40,3 +42,27 @@ impl Hash for Flow {
         // skip last seen
     }
 }
+
+/// Compute the flow ID of a flow, from the 5-tuple and timestamp of the first packet
+///
+/// The value is a hash of a fixed binary encoding of the fields, so it is stable
+/// across runs, platforms and compiler versions.
+pub fn compute_flow_id(five_t: &FiveTuple, first_seen: Duration) -> FlowID {
+    let mut buf = [0u8; 48];
+    let mut sz = 0;
+    let mut push = |b: &[u8]| {
+        buf[sz..sz + b.len()].copy_from_slice(b);
+        sz += b.len();
+    };
+    push(&[five_t.proto]);
+    for addr in [five_t.src, five_t.dst] {
+        match addr {
+            IpAddr::V4(ip) => push(&ip.octets()),
+            IpAddr::V6(ip) => push(&ip.octets()),
+        }
+    }
+    push(&five_t.src_port.to_be_bytes());
+    push(&five_t.dst_port.to_be_bytes());
+    push(&first_seen.as_nanos().to_be_bytes());
+    seahash::hash(&buf[..sz])
+}
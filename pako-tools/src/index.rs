@@ -0,0 +1,888 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use pcap_parser::{
+    data::{PacketData, ETHERTYPE_IPV4, ETHERTYPE_IPV6},
+    Block, PcapBlockOwned,
+};
+
+use crate::{
+    context::ParseContext,
+    data_engine::PacketDecoder,
+    duration::Duration,
+    error::{Error, Layer},
+    five_tuple::FiveTuple,
+    flow::{compute_flow_id, Flow, FlowID},
+    mapped_capture::{MappedBlocks, MappedCapture},
+    packet::Packet,
+};
+
+/// Magic of index files
+const INDEX_MAGIC: &[u8; 8] = b"PAKOIDX\0";
+/// Version of the index file format
+const INDEX_VERSION: u32 = 2;
+/// Number of bytes at the start of the capture file hashed to detect changes
+const HEADER_HASH_LEN: usize = 64 * 1024;
+
+/// Extension of index files, appended to the name of the capture file
+pub const INDEX_EXTENSION: &str = "idx";
+
+/// Location of the blocks describing a section
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexSection {
+    /// Offset of the Section Header Block (or of the legacy pcap file header)
+    pub offset: u64,
+    /// Offsets of the Interface Description Blocks, by interface ID
+    pub interfaces: Vec<u64>,
+}
+
+/// Location and properties of a packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Offset of the packet block
+    pub offset: u64,
+    /// Index of the section containing the packet
+    pub section: u32,
+    /// Timestamp of the packet (null if unknown)
+    pub ts: Duration,
+    /// Flow of the packet, if the 5-tuple could be extracted
+    pub flow_id: Option<FlowID>,
+}
+
+/// Packet-level index of a capture file
+///
+/// The index records the offset of each packet block, with its timestamp and flow. It is
+/// built in a single pass over the file (`build`), and can be stored in a sidecar file
+/// (`save`, `load`), so that `IndexedCapture` can read a packet range, a time range or the
+/// packets of a flow without reading the entire file.
+///
+/// Packets are identified by their position in the file, starting at 0: the `pcap_index` of
+/// the parsing context is the position plus one.
+///
+/// Both directions of a 5-tuple belong to the same flow, and the `FlowID` is computed like
+/// in the analyzer, by `compute_flow_id` from the 5-tuple and timestamp of the first packet,
+/// with ICMP type and code as ports. But only the outer IP layer is used (tunnels are not
+/// decapsulated), flows never expire, and IP fragments (except the first) are not
+/// reassembled nor assigned to a flow. So the IDs of the analyzer and of the index are the
+/// same for the first flow of a 5-tuple, but not for flows created again after a timeout:
+/// use `packets_of_flow` to find the packets of a flow reported by the analyzer.
+///
+/// The index records the size, modification time and a hash of the first bytes of the
+/// capture file, to detect stale index files.
+#[derive(Debug, Default)]
+pub struct PacketIndex {
+    file_size: u64,
+    /// Modification time of the capture file, in nanoseconds since the epoch (0 if unknown)
+    file_mtime: u64,
+    /// Hash of the first `HEADER_HASH_LEN` bytes of the capture file
+    header_hash: u64,
+    sections: Vec<IndexSection>,
+    packets: Vec<IndexEntry>,
+    flows: HashMap<FlowID, Flow>,
+    /// Positions of the packets of each flow, in file order
+    flow_packets: HashMap<FlowID, Vec<usize>>,
+    /// Flow of each 5-tuple, in the direction of the first packet
+    flow_ids: HashMap<FiveTuple, FlowID>,
+    /// Positions of the packets with a timestamp, sorted by timestamp
+    by_time: Vec<usize>,
+}
+
+/// Return the path of the index file of the capture file at `path`
+pub fn index_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut s = OsString::from(path.as_ref());
+    s.push(".");
+    s.push(INDEX_EXTENSION);
+    PathBuf::from(s)
+}
+
+/// Return the modification time of the file at `path`, in nanoseconds since the epoch
+///
+/// Returns 0 if the time is not available.
+fn file_mtime<P: AsRef<Path>>(path: P) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn header_hash(data: &[u8]) -> u64 {
+    seahash::hash(&data[..data.len().min(HEADER_HASH_LEN)])
+}
+
+impl PacketIndex {
+    /// Build the index of `capture`, reading all blocks once
+    ///
+    /// Packets which cannot be decoded are indexed without timestamp and flow.
+    pub fn build(capture: &MappedCapture) -> Result<Self, Error> {
+        let mut index = PacketIndex {
+            file_size: capture.len() as u64,
+            header_hash: header_hash(capture.data()),
+            ..PacketIndex::default()
+        };
+        let mut decoder = PacketDecoder::default();
+        let mut flow_ids = HashMap::new();
+        for item in capture.blocks() {
+            let (offset, block) = item?;
+            let offset = offset as u64;
+            match block {
+                PcapBlockOwned::NG(Block::SectionHeader(_)) | PcapBlockOwned::LegacyHeader(_) => {
+                    index.sections.push(IndexSection {
+                        offset,
+                        interfaces: Vec::new(),
+                    });
+                }
+                PcapBlockOwned::NG(Block::InterfaceDescription(_)) => {
+                    if let Some(section) = index.sections.last_mut() {
+                        section.interfaces.push(offset);
+                    }
+                }
+                _ => (),
+            }
+            let mut entry = IndexEntry {
+                offset,
+                section: index.sections.len().saturating_sub(1) as u32,
+                ts: Duration::default(),
+                flow_id: None,
+            };
+            match decoder.decode(&block) {
+                Ok(Some(packet)) => {
+                    entry.ts = packet.ts;
+                    entry.flow_id = five_tuple(&packet.data)
+                        .map(|t5| index.insert_packet_flow(&mut flow_ids, t5, packet.ts));
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(
+                        "Could not decode packet: {} (pcap_index={})",
+                        e,
+                        decoder.ctx().pcap_index
+                    );
+                }
+            }
+            index.packets.push(entry);
+        }
+        index.update_lookups();
+        Ok(index)
+    }
+
+    /// Return the ID of the flow of a packet, creating the flow if needed
+    fn insert_packet_flow(
+        &mut self,
+        flow_ids: &mut HashMap<FiveTuple, FlowID>,
+        five_tuple: FiveTuple,
+        ts: Duration,
+    ) -> FlowID {
+        let known = flow_ids
+            .get(&five_tuple)
+            .or_else(|| flow_ids.get(&five_tuple.get_reverse()))
+            .copied();
+        if let Some(flow_id) = known {
+            if let Some(flow) = self.flows.get_mut(&flow_id) {
+                flow.last_seen = flow.last_seen.max(ts);
+            }
+            return flow_id;
+        }
+        // collisions are not resolved, like in the flow map of the analyzer
+        let flow_id = compute_flow_id(&five_tuple, ts);
+        self.flows.entry(flow_id).or_insert_with(|| {
+            let mut flow = Flow::new(&five_tuple, ts);
+            flow.flow_id = flow_id;
+            flow
+        });
+        flow_ids.insert(five_tuple, flow_id);
+        flow_id
+    }
+
+    /// Rebuild the flow and time lookup tables from the packet entries
+    fn update_lookups(&mut self) {
+        self.flow_ids = self
+            .flows
+            .values()
+            .map(|flow| (flow.five_tuple.clone(), flow.flow_id))
+            .collect();
+        self.flow_packets.clear();
+        for (pos, entry) in self.packets.iter().enumerate() {
+            if let Some(flow_id) = entry.flow_id {
+                self.flow_packets.entry(flow_id).or_default().push(pos);
+            }
+        }
+        self.by_time = (0..self.packets.len())
+            .filter(|&pos| !self.packets[pos].ts.is_null())
+            .collect();
+        // stable sort: packets with the same timestamp stay in file order
+        self.by_time.sort_by_key(|&pos| self.packets[pos].ts);
+    }
+
+    /// Size of the indexed capture file, in bytes
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Modification time of the indexed capture file, in nanoseconds since the epoch
+    ///
+    /// This is 0 if unknown, for ex. for an index built by `build` and not saved by
+    /// `IndexedCapture::open`.
+    pub fn file_mtime(&self) -> u64 {
+        self.file_mtime
+    }
+
+    /// Test if the index was built for `capture`, comparing its size and first bytes
+    pub fn matches(&self, capture: &MappedCapture) -> bool {
+        self.file_size == capture.len() as u64 && self.header_hash == header_hash(capture.data())
+    }
+
+    pub fn sections(&self) -> &[IndexSection] {
+        &self.sections
+    }
+
+    /// Number of packets
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Entries of all packets, in file order
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.packets
+    }
+
+    /// Return the entry of the packet at position `pos`
+    pub fn entry(&self, pos: usize) -> Option<&IndexEntry> {
+        self.packets.get(pos)
+    }
+
+    /// Timestamp of the first packet with a timestamp, in file order
+    pub fn first_packet_ts(&self) -> Duration {
+        self.packets
+            .iter()
+            .map(|entry| entry.ts)
+            .find(|ts| !ts.is_null())
+            .unwrap_or_default()
+    }
+
+    /// Iterate over all flows (in no particular order)
+    pub fn flows(&self) -> impl Iterator<Item = &Flow> {
+        self.flows.values()
+    }
+
+    pub fn flow(&self, flow_id: FlowID) -> Option<&Flow> {
+        self.flows.get(&flow_id)
+    }
+
+    /// Return the ID of the flow of `five_tuple`, in either direction
+    pub fn lookup_flow(&self, five_tuple: &FiveTuple) -> Option<FlowID> {
+        self.flow_ids
+            .get(five_tuple)
+            .or_else(|| self.flow_ids.get(&five_tuple.get_reverse()))
+            .copied()
+    }
+
+    /// Positions of the packets of a flow reported by the analyzer, in file order
+    ///
+    /// The flow is found by 5-tuple, and the packets seen from `first_seen` to `last_seen`
+    /// are returned, so this also works if the flow ID differs from the ID of the index.
+    pub fn packets_of_flow(&self, flow: &Flow) -> Vec<usize> {
+        let Some(flow_id) = self.lookup_flow(&flow.five_tuple) else {
+            return Vec::new();
+        };
+        self.flow_packets(flow_id)
+            .iter()
+            .copied()
+            .filter(|&pos| (flow.first_seen..=flow.last_seen).contains(&self.packets[pos].ts))
+            .collect()
+    }
+
+    /// Positions of the packets in `range`, truncated to the number of packets
+    pub fn packet_range(&self, range: Range<usize>) -> Vec<usize> {
+        let end = range.end.min(self.packets.len());
+        (range.start.min(end)..end).collect()
+    }
+
+    /// Positions of the packets with a timestamp in `[start, end)`, in file order
+    pub fn time_range(&self, start: Duration, end: Duration) -> Vec<usize> {
+        let lo = self
+            .by_time
+            .partition_point(|&pos| self.packets[pos].ts < start);
+        let hi = self
+            .by_time
+            .partition_point(|&pos| self.packets[pos].ts < end);
+        let mut positions = self.by_time[lo..hi.max(lo)].to_vec();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Positions of the packets of flow `flow_id`, in file order
+    pub fn flow_packets(&self, flow_id: FlowID) -> &[usize] {
+        self.flow_packets
+            .get(&flow_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Load an index file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+
+    /// Write the index to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read an index in the sidecar file format
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let mut r = IndexReader(reader);
+        let mut magic = [0u8; 8];
+        r.0.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC {
+            return Err(Error::Generic("Invalid index file"));
+        }
+        if r.u32()? != INDEX_VERSION {
+            return Err(Error::Generic("Unsupported index file version"));
+        }
+        let mut index = PacketIndex {
+            file_size: r.u64()?,
+            file_mtime: r.u64()?,
+            header_hash: r.u64()?,
+            ..PacketIndex::default()
+        };
+        for _ in 0..r.u64()? {
+            let offset = r.u64()?;
+            let interfaces = (0..r.u64()?).map(|_| r.u64()).collect::<Result<_, _>>()?;
+            index.sections.push(IndexSection { offset, interfaces });
+        }
+        for _ in 0..r.u64()? {
+            let flow_id = r.u64()?;
+            let five_tuple = FiveTuple {
+                proto: r.u8()?,
+                src: r.ip()?,
+                dst: r.ip()?,
+                src_port: r.u16()?,
+                dst_port: r.u16()?,
+            };
+            let flow = Flow {
+                flow_id,
+                five_tuple,
+                first_seen: Duration::from_nanos(r.u64()?),
+                last_seen: Duration::from_nanos(r.u64()?),
+            };
+            index.flows.insert(flow_id, flow);
+        }
+        for _ in 0..r.u64()? {
+            let offset = r.u64()?;
+            let section = r.u32()?;
+            let ts = Duration::from_nanos(r.u64()?);
+            let flow_id = match r.u8()? {
+                0 => None,
+                _ => Some(r.u64()?),
+            };
+            if section as usize >= index.sections.len()
+                || flow_id.is_some_and(|id| !index.flows.contains_key(&id))
+            {
+                return Err(Error::Generic("Invalid index file"));
+            }
+            index.packets.push(IndexEntry {
+                offset,
+                section,
+                ts,
+                flow_id,
+            });
+        }
+        index.update_lookups();
+        Ok(index)
+    }
+
+    /// Write the index in the sidecar file format (all integers are little-endian)
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut w = IndexWriter(writer);
+        w.0.write_all(INDEX_MAGIC)?;
+        w.u32(INDEX_VERSION)?;
+        w.u64(self.file_size)?;
+        w.u64(self.file_mtime)?;
+        w.u64(self.header_hash)?;
+        w.u64(self.sections.len() as u64)?;
+        for section in &self.sections {
+            w.u64(section.offset)?;
+            w.u64(section.interfaces.len() as u64)?;
+            for &offset in &section.interfaces {
+                w.u64(offset)?;
+            }
+        }
+        w.u64(self.flows.len() as u64)?;
+        for flow in self.flows.values() {
+            let t5 = &flow.five_tuple;
+            w.u64(flow.flow_id)?;
+            w.u8(t5.proto)?;
+            w.ip(t5.src)?;
+            w.ip(t5.dst)?;
+            w.u16(t5.src_port)?;
+            w.u16(t5.dst_port)?;
+            w.u64(flow.first_seen.as_nanos())?;
+            w.u64(flow.last_seen.as_nanos())?;
+        }
+        w.u64(self.packets.len() as u64)?;
+        for entry in &self.packets {
+            w.u64(entry.offset)?;
+            w.u32(entry.section)?;
+            w.u64(entry.ts.as_nanos())?;
+            match entry.flow_id {
+                Some(flow_id) => {
+                    w.u8(1)?;
+                    w.u64(flow_id)?;
+                }
+                None => w.u8(0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct IndexReader<'r, R>(&'r mut R);
+
+impl<R: Read> IndexReader<'_, R> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.0.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        self.bytes::<1>().map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    fn ip(&mut self) -> io::Result<IpAddr> {
+        match self.u8()? {
+            4 => self.bytes::<4>().map(|b| IpAddr::V4(Ipv4Addr::from(b))),
+            6 => self.bytes::<16>().map(|b| IpAddr::V6(Ipv6Addr::from(b))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid IP address version",
+            )),
+        }
+    }
+}
+
+struct IndexWriter<'w, W>(&'w mut W);
+
+impl<W: Write> IndexWriter<'_, W> {
+    fn u8(&mut self, v: u8) -> io::Result<()> {
+        self.0.write_all(&[v])
+    }
+
+    fn u16(&mut self, v: u16) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    fn u32(&mut self, v: u32) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    fn u64(&mut self, v: u64) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    fn ip(&mut self, ip: IpAddr) -> io::Result<()> {
+        match ip {
+            IpAddr::V4(ip) => {
+                self.u8(4)?;
+                self.0.write_all(&ip.octets())
+            }
+            IpAddr::V6(ip) => {
+                self.u8(6)?;
+                self.0.write_all(&ip.octets())
+            }
+        }
+    }
+}
+
+/// Return the layer 3 payload of an Ethernet frame, skipping VLAN tags
+fn l2_payload(data: &[u8]) -> Option<(u16, &[u8])> {
+    let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+    let mut rem = data.get(14..)?;
+    // 802.1Q and 802.1ad
+    while ethertype == 0x8100 || ethertype == 0x88a8 {
+        ethertype = u16::from_be_bytes([*rem.get(2)?, *rem.get(3)?]);
+        rem = rem.get(4..)?;
+    }
+    Some((ethertype, rem))
+}
+
+/// Return the 5-tuple of the outer IP layer of a packet
+///
+/// Returns `None` for fragments other than the first.
+fn five_tuple(data: &PacketData) -> Option<FiveTuple> {
+    let (ethertype, l3) = match *data {
+        PacketData::L2(data) => l2_payload(data)?,
+        PacketData::L3(ethertype, data) => (ethertype, data),
+        PacketData::L4(..) | PacketData::Unsupported(_) => return None,
+    };
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => match FiveTuple::from_ip_packet(l3)? {
+            (five_tuple, true) => Some(five_tuple),
+            (_, false) => None,
+        },
+        _ => None,
+    }
+}
+
+/// A memory-mapped capture file with its packet index
+///
+/// Packets are read directly at the offsets stored in the index: only the packet blocks, and
+/// the blocks describing their section (section header and interfaces), are parsed.
+///
+/// ## example
+///
+/// ```no_run
+/// use pako_tools::{Duration, IndexedCapture};
+///
+/// let capture = IndexedCapture::open("capture.pcapng").expect("could not index file");
+/// let start = Duration::from_secs(1_600_000_000);
+/// for item in capture.time_range(start, start + Duration::from_secs(10)) {
+///     let (packet, ctx) = item.expect("could not read packet");
+///     println!("{}: {} bytes", ctx.pcap_index, packet.caplen);
+/// }
+/// ```
+pub struct IndexedCapture {
+    capture: MappedCapture,
+    index: PacketIndex,
+}
+
+impl IndexedCapture {
+    /// Associate a capture with its index
+    ///
+    /// Returns an error if the index was not built for a file of the same size and first
+    /// bytes (see `PacketIndex::matches`).
+    pub fn new(capture: MappedCapture, index: PacketIndex) -> Result<Self, Error> {
+        if !index.matches(&capture) {
+            return Err(Error::Generic("Index does not match capture file"));
+        }
+        Ok(IndexedCapture { capture, index })
+    }
+
+    /// Map the file at `path` in memory, and load its index
+    ///
+    /// If the index file (see `index_path`) is missing, invalid or stale, the index is built
+    /// and saved. The index is stale if the size, modification time or first bytes of the
+    /// capture file changed. Failing to save the index is not an error.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let capture = MappedCapture::open(&path)?;
+        let mtime = file_mtime(&path);
+        let idx_path = index_path(&path);
+        match PacketIndex::load(&idx_path) {
+            Ok(index) if index.file_mtime == mtime && index.matches(&capture) => {
+                return Ok(IndexedCapture { capture, index });
+            }
+            Ok(_) => debug!("Index file is stale, rebuilding it"),
+            Err(e) => debug!("Could not load index file: {}", e),
+        }
+        let mut index = PacketIndex::build(&capture)?;
+        index.file_mtime = mtime;
+        if let Err(e) = index.save(&idx_path) {
+            warn!("Could not save index file {}: {}", idx_path.display(), e);
+        }
+        Ok(IndexedCapture { capture, index })
+    }
+
+    pub fn capture(&self) -> &MappedCapture {
+        &self.capture
+    }
+
+    pub fn index(&self) -> &PacketIndex {
+        &self.index
+    }
+
+    /// Read the packets at `positions`, in the given order
+    pub fn packets(&self, positions: Vec<usize>) -> IndexedPackets<'_> {
+        IndexedPackets {
+            blocks: self.capture.blocks(),
+            index: &self.index,
+            positions: positions.into_iter(),
+            decoder: PacketDecoder::default(),
+            section: None,
+        }
+    }
+
+    /// Read the packets in `range` (positions start at 0)
+    pub fn packet_range(&self, range: Range<usize>) -> IndexedPackets<'_> {
+        self.packets(self.index.packet_range(range))
+    }
+
+    /// Read the packets with a timestamp in `[start, end)`, in file order
+    pub fn time_range(&self, start: Duration, end: Duration) -> IndexedPackets<'_> {
+        self.packets(self.index.time_range(start, end))
+    }
+
+    /// Read the packets of flow `flow_id`, in file order
+    pub fn flow_packets(&self, flow_id: FlowID) -> IndexedPackets<'_> {
+        self.packets(self.index.flow_packets(flow_id).to_vec())
+    }
+
+    /// Read the packets of a flow reported by the analyzer, in file order
+    ///
+    /// See `PacketIndex::packets_of_flow`.
+    pub fn packets_of_flow(&self, flow: &Flow) -> IndexedPackets<'_> {
+        self.packets(self.index.packets_of_flow(flow))
+    }
+}
+
+/// Iterator over packets of an `IndexedCapture`
+///
+/// Each item contains the packet, and a parsing context with the same `pcap_index` and
+/// relative timestamp as if the file was read sequentially.
+pub struct IndexedPackets<'a> {
+    blocks: MappedBlocks<'a>,
+    index: &'a PacketIndex,
+    positions: std::vec::IntoIter<usize>,
+    decoder: PacketDecoder,
+    /// Section loaded in the decoder
+    section: Option<u32>,
+}
+
+impl<'a> IndexedPackets<'a> {
+    fn read_block_at(&mut self, offset: u64) -> Result<PcapBlockOwned<'a>, Error> {
+        self.blocks.seek(offset as usize);
+        match self.blocks.next() {
+            Some(item) => item.map(|(_, block)| block),
+            None => Err(Error::truncated(Layer::Capture, "indexed block")),
+        }
+    }
+
+    /// Reset the decoder, and decode the blocks describing section `idx`
+    fn load_section(&mut self, idx: u32) -> Result<(), Error> {
+        let index = self.index;
+        let section = index
+            .sections
+            .get(idx as usize)
+            .ok_or(Error::Generic("Invalid section index"))?;
+        self.section = None;
+        self.decoder = PacketDecoder::default();
+        self.decoder.ctx_mut().first_packet_ts = index.first_packet_ts();
+        let offsets = std::iter::once(section.offset).chain(section.interfaces.iter().copied());
+        for offset in offsets {
+            let block = self.read_block_at(offset)?;
+            self.decoder.decode(&block)?;
+        }
+        self.section = Some(idx);
+        Ok(())
+    }
+
+    fn read_packet(&mut self, pos: usize) -> Result<(Packet<'a>, ParseContext), Error> {
+        let entry = *self
+            .index
+            .packets
+            .get(pos)
+            .ok_or(Error::Generic("Invalid packet index"))?;
+        if self.section != Some(entry.section) {
+            self.load_section(entry.section)?;
+        }
+        let block = self.read_block_at(entry.offset)?;
+        // the decoder increments the index before decoding a packet
+        self.decoder.ctx_mut().pcap_index = pos;
+        match self.decoder.decode(&block)? {
+            Some(packet) => Ok((packet, self.decoder.ctx().clone())),
+            None => Err(Error::malformed(Layer::Capture, "indexed packet")),
+        }
+    }
+}
+
+impl<'a> Iterator for IndexedPackets<'a> {
+    type Item = Result<(Packet<'a>, ParseContext), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.positions.next()?;
+        Some(self.read_packet(pos))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.positions.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        net::{IpAddr, Ipv4Addr},
+        time::UNIX_EPOCH,
+    };
+
+    use pcap_parser::data::PacketData;
+
+    use super::{five_tuple, index_path, IndexEntry, IndexSection, IndexedCapture, PacketIndex};
+    use crate::{compute_flow_id, Duration, FiveTuple, Flow};
+
+    fn tcp_frame(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        let mut ip = vec![0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0];
+        ip.extend_from_slice(&src);
+        ip.extend_from_slice(&dst);
+        frame.extend_from_slice(&ip);
+        frame.extend_from_slice(&sport.to_be_bytes());
+        frame.extend_from_slice(&dport.to_be_bytes());
+        frame.extend_from_slice(&[0u8; 16]);
+        frame
+    }
+
+    #[test]
+    fn index_five_tuple() {
+        let frame = tcp_frame([10, 0, 0, 1], [10, 0, 0, 2], 1234, 80);
+        let t5 = five_tuple(&PacketData::L2(&frame)).expect("5-tuple");
+        assert_eq!(
+            t5,
+            FiveTuple {
+                proto: 6,
+                src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                src_port: 1234,
+                dst_port: 80,
+            }
+        );
+        // non-first fragment
+        let mut fragment = frame.clone();
+        fragment[14 + 7] = 0x10;
+        assert!(five_tuple(&PacketData::L2(&fragment)).is_none());
+        assert!(five_tuple(&PacketData::L3(0x0806, &frame[14..])).is_none());
+        // ICMP echo request: type and code are used as ports
+        let mut icmp = frame.clone();
+        icmp[14 + 9] = 1;
+        icmp[34..36].copy_from_slice(&[8, 0]);
+        let t5 = five_tuple(&PacketData::L2(&icmp)).expect("5-tuple");
+        assert_eq!((t5.src_port, t5.dst_port), (8, 0));
+    }
+
+    #[test]
+    fn index_queries_and_roundtrip() {
+        let frames = [
+            tcp_frame([10, 0, 0, 1], [10, 0, 0, 2], 1234, 80),
+            tcp_frame([10, 0, 0, 2], [10, 0, 0, 1], 80, 1234),
+            tcp_frame([10, 0, 0, 3], [10, 0, 0, 2], 4321, 80),
+        ];
+        let mut index = PacketIndex {
+            file_size: 1000,
+            file_mtime: 1_600_000_000_000_000_000,
+            header_hash: 0x1234,
+            sections: vec![IndexSection {
+                offset: 0,
+                interfaces: vec![28],
+            }],
+            ..PacketIndex::default()
+        };
+        let mut flow_ids = Default::default();
+        // timestamps are not in file order
+        for (i, (frame, secs)) in frames.iter().zip([10, 30, 20]).enumerate() {
+            let ts = Duration::from_secs(secs);
+            let t5 = five_tuple(&PacketData::L2(frame)).expect("5-tuple");
+            let flow_id = index.insert_packet_flow(&mut flow_ids, t5, ts);
+            index.packets.push(IndexEntry {
+                offset: 60 + 100 * i as u64,
+                section: 0,
+                ts,
+                flow_id: Some(flow_id),
+            });
+        }
+        index.update_lookups();
+        assert_eq!(index.flows().count(), 2);
+        let flow_id = index.packets[0].flow_id.unwrap();
+        assert_eq!(index.flow_packets(flow_id), &[0, 1]);
+        assert_eq!(
+            index.flow(flow_id).map(|f| f.last_seen),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            index.time_range(Duration::from_secs(15), Duration::from_secs(31)),
+            vec![1, 2]
+        );
+        assert_eq!(index.packet_range(2..10), vec![2]);
+        // IDs are computed like in the analyzer, and flows can be found by 5-tuple
+        let t5 = five_tuple(&PacketData::L2(&frames[0])).expect("5-tuple");
+        assert_eq!(flow_id, compute_flow_id(&t5, Duration::from_secs(10)));
+        assert_eq!(index.lookup_flow(&t5.get_reverse()), Some(flow_id));
+        // analyzer flow created again after a timeout
+        let mut flow = Flow::new(&t5.get_reverse(), Duration::from_secs(25));
+        flow.last_seen = Duration::from_secs(40);
+        assert_eq!(index.packets_of_flow(&flow), vec![1]);
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).expect("write index");
+        let loaded = PacketIndex::read_from(&mut buf.as_slice()).expect("read index");
+        assert_eq!(loaded.file_size(), 1000);
+        assert_eq!(loaded.file_mtime(), index.file_mtime());
+        assert_eq!(loaded.header_hash, 0x1234);
+        assert_eq!(loaded.sections(), index.sections());
+        assert_eq!(loaded.entries(), index.entries());
+        assert_eq!(loaded.flow(flow_id), index.flow(flow_id));
+        assert_eq!(loaded.by_time, index.by_time);
+        // truncated file
+        assert!(PacketIndex::read_from(&mut &buf[..buf.len() - 1]).is_err());
+
+        assert_eq!(
+            index_path("/tmp/capture.pcap"),
+            std::path::PathBuf::from("/tmp/capture.pcap.idx")
+        );
+    }
+
+    #[test]
+    fn index_open_stale() {
+        let src = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../pako-pcap/assets/legacy_le_us.pcap"
+        );
+        let dir = std::env::temp_dir().join(format!("pako-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let path = dir.join("capture.pcap");
+        std::fs::copy(src, &path).expect("copy capture");
+
+        let capture = IndexedCapture::open(&path).expect("index capture");
+        assert_eq!(capture.index().len(), 4);
+        let mtime = capture.index().file_mtime();
+        assert_ne!(mtime, 0);
+        assert!(index_path(&path).exists());
+        drop(capture);
+
+        // same size and modification time, but different content
+        let mut data = std::fs::read(&path).expect("read capture");
+        data[24] ^= 0xff;
+        std::fs::write(&path, &data).expect("write capture");
+        let file = File::options().write(true).open(&path).expect("open");
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_nanos(mtime))
+            .expect("set mtime");
+        drop(file);
+        let stale = PacketIndex::load(index_path(&path)).expect("load index");
+        assert_eq!(stale.file_mtime(), mtime);
+        let capture = IndexedCapture::open(&path).expect("rebuild index");
+        assert_ne!(capture.index().header_hash, stale.header_hash);
+        assert!(IndexedCapture::new(capture.capture().clone(), stale).is_err());
+        drop(capture);
+
+        std::fs::remove_dir_all(&dir).expect("remove dir");
+    }
+}
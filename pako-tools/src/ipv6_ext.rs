@@ -0,0 +1,126 @@
+//!
+//! Walking the extension headers of IPv6 packets (RFC 8200)
+//!
+
+use crate::error::{Error, Layer};
+
+const IPV6_NH_HOPOPT: u8 = 0;
+const IPV6_NH_ROUTING: u8 = 43;
+const IPV6_NH_FRAGMENT: u8 = 44;
+const IPV6_NH_ESP: u8 = 50;
+const IPV6_NH_AH: u8 = 51;
+const IPV6_NH_DSTOPTS: u8 = 60;
+const IPV6_NH_MOBILITY: u8 = 135;
+/// Host Identity Protocol (RFC 7401)
+const IPV6_NH_HIP: u8 = 139;
+/// Shim6 Protocol (RFC 5533)
+const IPV6_NH_SHIM6: u8 = 140;
+/// Experimental and testing (RFC 3692)
+const IPV6_NH_EXPERIMENTAL1: u8 = 253;
+const IPV6_NH_EXPERIMENTAL2: u8 = 254;
+
+/// Iterator over the extension headers of an IPv6 packet
+///
+/// Each item is the type of the header, and its data (including the next header and
+/// length fields). Only the boundaries of the headers are checked, their content is not
+/// parsed.
+///
+/// Iteration stops on the first header which is not an extension header, or on an ESP
+/// header (the rest of the packet is encrypted), which is not returned. `next_header` and
+/// `data` then return the protocol and data following the last returned header. Fragment
+/// headers are returned like other headers: callers must check the fragment offset, since
+/// the headers following a Fragment header are only present in the first fragment.
+///
+/// After an error (truncated or malformed header), iteration stops, and `next_header` and
+/// `data` refer to the invalid header.
+#[derive(Clone, Debug)]
+pub struct Ipv6ExtensionWalker<'a> {
+    next_header: u8,
+    data: &'a [u8],
+    done: bool,
+}
+
+impl<'a> Ipv6ExtensionWalker<'a> {
+    /// Create a walker over `data`, which starts with a header of type `next_header`
+    pub fn new(next_header: u8, data: &'a [u8]) -> Self {
+        Ipv6ExtensionWalker {
+            next_header,
+            data,
+            done: false,
+        }
+    }
+
+    /// Return true if `next_header` is the type of an IPv6 extension header
+    pub fn is_extension(next_header: u8) -> bool {
+        matches!(
+            next_header,
+            IPV6_NH_HOPOPT
+                | IPV6_NH_ROUTING
+                | IPV6_NH_FRAGMENT
+                | IPV6_NH_ESP
+                | IPV6_NH_AH
+                | IPV6_NH_DSTOPTS
+                | IPV6_NH_MOBILITY
+                | IPV6_NH_HIP
+                | IPV6_NH_SHIM6
+                | IPV6_NH_EXPERIMENTAL1
+                | IPV6_NH_EXPERIMENTAL2
+        )
+    }
+
+    /// Type of the header following the last returned header
+    pub fn next_header(&self) -> u8 {
+        self.next_header
+    }
+
+    /// Data following the last returned header
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Fragment offset (in bytes) of a Fragment header returned by the iterator
+    pub fn fragment_offset(header: &[u8]) -> u16 {
+        u16::from_be_bytes([header[2], header[3]]) & 0xfff8
+    }
+
+    fn header_len(&self) -> Result<usize, Error> {
+        if self.data.len() < 8 {
+            return Err(Error::truncated(Layer::Network, "IPv6 extension header"));
+        }
+        let len = match self.next_header {
+            IPV6_NH_FRAGMENT => 8,
+            // length is in 4-octet units, minus 2
+            IPV6_NH_AH if self.data[1] == 0 => {
+                return Err(Error::malformed(Layer::Network, "IPv6 AH"))
+            }
+            IPV6_NH_AH => (usize::from(self.data[1]) + 2) * 4,
+            _ => (usize::from(self.data[1]) + 1) * 8,
+        };
+        if self.data.len() < len {
+            return Err(Error::truncated(Layer::Network, "IPv6 extension header"));
+        }
+        Ok(len)
+    }
+}
+
+impl<'a> Iterator for Ipv6ExtensionWalker<'a> {
+    type Item = Result<(u8, &'a [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.next_header == IPV6_NH_ESP || !Self::is_extension(self.next_header) {
+            return None;
+        }
+        let len = match self.header_len() {
+            Ok(len) => len,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        let (header, rem) = self.data.split_at(len);
+        let header_type = self.next_header;
+        self.next_header = header[0];
+        self.data = rem;
+        Some(Ok((header_type, header)))
+    }
+}
@@ -5,6 +5,8 @@
 extern crate log;
 
 mod analyzer;
+#[cfg(feature = "async")]
+mod async_engine;
 mod block_engine;
 mod config;
 mod context;
@@ -14,10 +16,17 @@ mod engine;
 mod error;
 mod five_tuple;
 mod flow;
+mod index;
+mod ipv6_ext;
+mod mapped_capture;
+mod mmap_engine;
 mod packet;
+mod packet_iterator;
 mod three_tuple;
 
 pub use analyzer::*;
+#[cfg(feature = "async")]
+pub use async_engine::*;
 pub use block_engine::*;
 pub use config::Config;
 pub use context::*;
@@ -27,6 +36,11 @@ pub use engine::*;
 pub use error::*;
 pub use five_tuple::*;
 pub use flow::*;
+pub use index::*;
+pub use ipv6_ext::*;
+pub use mapped_capture::*;
+pub use mmap_engine::*;
 pub use packet::*;
+pub use packet_iterator::*;
 pub use pcap_parser;
 pub use three_tuple::ThreeTuple;
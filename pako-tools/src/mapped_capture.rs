@@ -0,0 +1,355 @@
+use std::{fs::File, ops::Range, path::Path, sync::Arc};
+
+use memmap2::Mmap;
+use pako_pcap::{BigEndian, ByteOrder, Endianness, LittleEndian, PCAP_MAGIC_MODIFIED};
+use pcap_parser::{
+    nom,
+    pcapng::{parse_block_be, parse_block_le},
+    Block, LegacyPcapBlock, Linktype, PcapBlockOwned, PcapError, PcapHeader,
+};
+
+use crate::error::Error;
+
+/// Magic of the pcap-ng Section Header Block (identical in both byte orders)
+const SHB_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+/// Magic of the modified pcap format, in a big-endian file read as little-endian
+const MODIFIED_MAGIC_BE: u32 = PCAP_MAGIC_MODIFIED.swap_bytes();
+
+type FrameParser = fn(&[u8]) -> nom::IResult<&[u8], LegacyPcapBlock, PcapError<&[u8]>>;
+
+/// A read-only memory mapping of a pcap/pcap-ng file
+///
+/// The mapping is reference-counted: clones are cheap, and can be sent to other threads.
+/// Data borrowed from the mapping stays valid as long as one clone is alive, so blocks and
+/// packets returned by `blocks()` can be used without copying.
+///
+/// The file must not be modified (for ex. truncated) while it is mapped.
+#[derive(Clone)]
+pub struct MappedCapture {
+    map: Arc<Mmap>,
+}
+
+impl MappedCapture {
+    /// Map the file at `path` in memory
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Self::from_file(&file)
+    }
+
+    /// Map `file` in memory
+    pub fn from_file(file: &File) -> Result<Self, Error> {
+        // SAFETY: the mapping is read-only, and the capture file is not expected to be
+        // modified during the analysis
+        let map = unsafe { Mmap::map(file)? };
+        Ok(MappedCapture { map: Arc::new(map) })
+    }
+
+    /// Content of the file
+    pub fn data(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Size of the file, in bytes
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Return the position of `data` in the mapping, or `None` if `data` does not borrow it
+    pub fn range_of(&self, data: &[u8]) -> Option<Range<usize>> {
+        let base = self.map.as_ptr() as usize;
+        let start = (data.as_ptr() as usize).checked_sub(base)?;
+        let end = start.checked_add(data.len())?;
+        if end <= self.map.len() {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+
+    /// Return the data at `range`, or `None` if `range` is out of bounds
+    pub fn get(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.map.get(range)
+    }
+
+    /// Iterate over the blocks of the file
+    pub fn blocks(&self) -> MappedBlocks<'_> {
+        MappedBlocks::new(self.data())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Format {
+    /// File header not read yet
+    Unknown,
+    Legacy(FrameParser),
+    PcapNg {
+        big_endian: bool,
+    },
+}
+
+/// Iterator over the blocks of a pcap/pcap-ng file loaded in memory
+///
+/// Each item contains the offset of the block in the file, and the block. Blocks borrow the
+/// input data, nothing is copied. File format and byte order (including byte order changes
+/// between pcap-ng sections) are handled transparently.
+///
+/// Iteration stops after a parsing error, or on a truncated block at the end of the input.
+pub struct MappedBlocks<'a> {
+    data: &'a [u8],
+    rem: &'a [u8],
+    format: Format,
+    done: bool,
+}
+
+impl<'a> MappedBlocks<'a> {
+    /// Create an iterator over the blocks of `data`, which must start with a file header
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::with_format(data, Format::Unknown)
+    }
+
+    /// Create an iterator over the blocks of `data`, in file format `format`
+    pub(crate) fn with_format(data: &'a [u8], format: Format) -> Self {
+        MappedBlocks {
+            data,
+            rem: data,
+            format,
+            done: false,
+        }
+    }
+
+    /// File format, as read from the last file or section header
+    pub(crate) fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Offset of the next block in the input
+    pub fn offset(&self) -> usize {
+        self.data.len() - self.rem.len()
+    }
+
+    /// Continue iteration at `offset`, which must be the offset of a block
+    ///
+    /// Format and byte order are not changed, except when seeking to offset 0, which reads
+    /// the file header again.
+    pub fn seek(&mut self, offset: usize) {
+        self.rem = self.data.get(offset..).unwrap_or_default();
+        self.done = false;
+        if offset == 0 {
+            self.format = Format::Unknown;
+        }
+    }
+}
+
+fn parse_ng(
+    i: &[u8],
+    big_endian: bool,
+) -> nom::IResult<&[u8], PcapBlockOwned<'_>, PcapError<&[u8]>> {
+    let res = if big_endian {
+        parse_block_be(i)
+    } else {
+        parse_block_le(i)
+    };
+    res.map(|(rem, block)| (rem, PcapBlockOwned::NG(block)))
+}
+
+/// Read the pcap file header, including the header of the modified pcap format
+///
+/// pcap-parser does not support the modified format, which is read by the pako-pcap parser.
+fn parse_header(i: &[u8]) -> nom::IResult<&[u8], PcapHeader, PcapError<&[u8]>> {
+    let (rem, header) = match pako_pcap::pcap_header::<PcapError<&[u8]>>(i) {
+        Ok((rem, header)) if header.is_modified() => (rem, header),
+        _ => return pcap_parser::parse_pcap_header(i),
+    };
+    // pcap-parser stores the magic as read in little-endian
+    let magic_number = match header.endianness {
+        Endianness::Big => MODIFIED_MAGIC_BE,
+        Endianness::Little => PCAP_MAGIC_MODIFIED,
+    };
+    let header = PcapHeader {
+        magic_number,
+        version_major: header.version_major,
+        version_minor: header.version_minor,
+        thiszone: header.thiszone,
+        sigfigs: header.sigfigs,
+        snaplen: header.snap_len,
+        network: Linktype(header.network as i32),
+    };
+    Ok((rem, header))
+}
+
+/// Read a record of the modified pcap format, with byte order `B`
+///
+/// The additional fields of the record (interface index, protocol and packet type) are
+/// dropped.
+fn parse_frame_modified<B: ByteOrder>(
+    i: &[u8],
+) -> nom::IResult<&[u8], LegacyPcapBlock<'_>, PcapError<&[u8]>> {
+    let (rem, packet) = pako_pcap::legacy_packet::<B, PcapError<&[u8]>>(i, true)?;
+    let block = LegacyPcapBlock {
+        ts_sec: packet.ts_sec,
+        ts_usec: packet.ts_frac,
+        caplen: packet.captured_len,
+        origlen: packet.original_len,
+        data: packet.data,
+    };
+    Ok((rem, block))
+}
+
+fn frame_parser(header: &PcapHeader) -> FrameParser {
+    match header.magic_number {
+        PCAP_MAGIC_MODIFIED => parse_frame_modified::<LittleEndian>,
+        MODIFIED_MAGIC_BE => parse_frame_modified::<BigEndian>,
+        _ if header.is_bigendian() => pcap_parser::parse_pcap_frame_be,
+        _ => pcap_parser::parse_pcap_frame,
+    }
+}
+
+impl<'a> Iterator for MappedBlocks<'a> {
+    type Item = Result<(usize, PcapBlockOwned<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.rem.is_empty() {
+            return None;
+        }
+        let offset = self.offset();
+        let res = match self.format {
+            Format::Unknown if self.rem.starts_with(&SHB_MAGIC) => parse_ng(self.rem, false),
+            Format::Unknown => parse_header(self.rem)
+                .map(|(rem, header)| (rem, PcapBlockOwned::LegacyHeader(header))),
+            Format::Legacy(parse_frame) => {
+                parse_frame(self.rem).map(|(rem, b)| (rem, PcapBlockOwned::Legacy(b)))
+            }
+            Format::PcapNg { big_endian } => parse_ng(self.rem, big_endian),
+        };
+        match res {
+            Ok((rem, block)) => {
+                self.format = match block {
+                    PcapBlockOwned::LegacyHeader(ref header) => {
+                        Format::Legacy(frame_parser(header))
+                    }
+                    PcapBlockOwned::NG(Block::SectionHeader(ref shb)) => Format::PcapNg {
+                        big_endian: shb.big_endian(),
+                    },
+                    _ => self.format,
+                };
+                self.rem = rem;
+                Some(Ok((offset, block)))
+            }
+            Err(nom::Err::Incomplete(_))
+            | Err(nom::Err::Error(PcapError::Incomplete(_)))
+            | Err(nom::Err::Failure(PcapError::Incomplete(_))) => {
+                warn!("Could not read complete data block (offset={})", offset);
+                warn!("Hint: the input file may be truncated.");
+                self.done = true;
+                None
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                self.done = true;
+                Some(Err(Error::Pcap(e.to_owned_vec())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write};
+
+    use pcap_parser::PcapBlockOwned;
+
+    use super::MappedCapture;
+    use crate::{Config, Error, MmapEngine, Packet, ParseContext, PcapAnalyzer};
+
+    const ASSETS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../pako-pcap/assets/");
+
+    #[derive(Default)]
+    struct PacketCounter {
+        lengths: Vec<usize>,
+        first_ts: Option<(u64, u32)>,
+    }
+
+    impl PcapAnalyzer for PacketCounter {
+        fn handle_packet(&mut self, packet: &Packet, _ctx: &ParseContext) -> Result<(), Error> {
+            self.lengths.push(packet.caplen as usize);
+            if self.first_ts.is_none() {
+                self.first_ts = Some((packet.ts.secs(), packet.ts.subsec_micros()));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mapped_capture_range_of() {
+        let path = std::env::temp_dir().join(format!("pako-mmap-{}.bin", std::process::id()));
+        File::create(&path)
+            .and_then(|mut f| f.write_all(&[0u8, 1, 2, 3, 4, 5, 6, 7]))
+            .expect("write file");
+        let capture = MappedCapture::open(&path).expect("map file");
+        assert_eq!(capture.len(), 8);
+        let data = &capture.data()[2..5];
+        assert_eq!(capture.range_of(data), Some(2..5));
+        assert_eq!(capture.get(2..5), Some(&[2u8, 3, 4][..]));
+        // data which does not borrow the mapping
+        let other = vec![2u8, 3, 4];
+        assert_eq!(capture.range_of(&other), None);
+        // clones share the mapping
+        let clone = capture.clone();
+        assert_eq!(clone.range_of(data), Some(2..5));
+        drop((capture, clone));
+        std::fs::remove_file(&path).expect("remove file");
+    }
+
+    #[test]
+    fn mapped_blocks_legacy_and_pcapng() {
+        for (file, num_blocks) in [
+            ("legacy_le_us.pcap", 5),
+            ("legacy_be_ns.pcap", 5),
+            ("legacy_modified.pcap", 5),
+            ("blocks_le.pcapng", 12),
+            ("blocks_be.pcapng", 12),
+        ] {
+            let capture = MappedCapture::open(format!("{ASSETS}{file}")).expect("map file");
+            let blocks = capture
+                .blocks()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|e| panic!("{file}: {e:?}"));
+            assert_eq!(blocks.len(), num_blocks, "{file}");
+            assert_eq!(blocks[0].0, 0);
+            if file.ends_with(".pcap") {
+                let caplens: Vec<_> = blocks[1..]
+                    .iter()
+                    .map(|(_, block)| match block {
+                        PcapBlockOwned::Legacy(b) => b.caplen,
+                        _ => panic!("{file}: unexpected block"),
+                    })
+                    .collect();
+                assert_eq!(caplens, [74, 74, 66, 66], "{file}");
+            }
+        }
+    }
+
+    #[test]
+    fn mmap_engine_legacy_and_pcapng() {
+        for (file, num_packets) in [
+            ("legacy_le_us.pcap", 4),
+            ("legacy_be_ns.pcap", 4),
+            ("legacy_modified.pcap", 4),
+            ("blocks_le.pcapng", 5),
+        ] {
+            let mut engine = MmapEngine::new(PacketCounter::default(), &Config::default());
+            engine
+                .run_file(format!("{ASSETS}{file}"))
+                .unwrap_or_else(|e| panic!("{file}: {e:?}"));
+            let counter = engine.into_data_analyzer();
+            assert_eq!(counter.lengths.len(), num_packets, "{file}");
+            if file.ends_with(".pcap") {
+                assert_eq!(counter.lengths, [74, 74, 66, 66], "{file}");
+                assert_eq!(counter.first_ts, Some((1658321070, 679827)), "{file}");
+            }
+        }
+    }
+}
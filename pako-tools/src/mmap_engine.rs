@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use crate::{
+    analyzer::PcapAnalyzer, block_engine::BlockAnalyzer, config::Config, context::*,
+    data_engine::PcapDataAnalyzer, error::Error, mapped_capture::MappedCapture,
+};
+
+/// pcap/pcap-ng data analyzer engine, reading from a memory-mapped file
+///
+/// `MmapEngine` decodes packets like `PcapDataEngine`, but does not copy data to a
+/// read buffer: packets borrow the mapping, which stays valid for the entire run.
+/// As a consequence, `before_refill` is never called, and analyzers can keep (or send to
+/// other threads) references to packet data, using the `MappedCapture` received in
+/// `PcapAnalyzer::set_mapped_capture`.
+///
+/// ## example
+///
+/// ```no_run
+/// use pako_tools::{Config, Error, MmapEngine, Packet, ParseContext, PcapAnalyzer};
+/// #[derive(Default)]
+/// pub struct ExampleAnalyzer {
+///     packet_count: usize,
+/// }
+///
+/// impl PcapAnalyzer for ExampleAnalyzer {
+///     fn handle_packet(&mut self, packet: &Packet, ctx: &ParseContext) -> Result<(), Error> {
+///         self.packet_count += 1;
+///         Ok(())
+///     }
+/// }
+///
+/// let config = Config::default();
+/// let analyzer = ExampleAnalyzer::default();
+/// let mut engine = MmapEngine::new(analyzer, &config);
+/// let res = engine.run_file("capture.pcapng");
+/// ```
+pub struct MmapEngine<A: PcapAnalyzer> {
+    analyzer: PcapDataAnalyzer<A>,
+}
+
+impl<A: PcapAnalyzer> MmapEngine<A> {
+    pub fn new(data_analyzer: A, config: &Config) -> Self {
+        let analyzer = PcapDataAnalyzer::new(data_analyzer, config);
+        MmapEngine { analyzer }
+    }
+
+    pub fn data_analyzer(&self) -> &A {
+        &self.analyzer.data_analyzer
+    }
+
+    pub fn data_analyzer_mut(&mut self) -> &mut A {
+        &mut self.analyzer.data_analyzer
+    }
+
+    pub fn into_data_analyzer(self) -> A {
+        self.analyzer.data_analyzer
+    }
+
+    /// Return the number of packets skipped because they could not be decoded
+    pub fn num_errors(&self) -> usize {
+        self.analyzer.num_errors
+    }
+
+    /// Map the file at `path` in memory, and analyze it
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let capture = MappedCapture::open(path)?;
+        self.run(&capture)
+    }
+
+    /// Main function: read all blocks of `capture` and call analyzer for each Packet
+    pub fn run(&mut self, capture: &MappedCapture) -> Result<(), Error> {
+        self.analyzer.data_analyzer.set_mapped_capture(capture);
+        self.analyzer.init()?;
+        let mut ctx = ParseBlockContext::default();
+
+        for item in capture.blocks() {
+            let (_offset, block) = item.map_err(|e| {
+                error!(
+                    "error while reading: {:?} (block_index={})",
+                    e, ctx.block_index
+                );
+                e
+            })?;
+            self.analyzer.handle_block(&block, &ctx)?;
+            ctx.block_index += 1;
+        }
+
+        self.analyzer.teardown();
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::io::Read;
+
+use pcap_parser::{data::PacketData, traits::PcapReaderIterator, Linktype, PcapError};
+
+use crate::{
+    config::Config, context::ParseContext, data_engine::PacketDecoder, duration::Duration,
+    error::Error, packet::Packet,
+};
+
+/// Default size of the read buffer
+const DEFAULT_CAPACITY: usize = 128 * 1024;
+
+/// Layer of the data of an `OwnedPacket`
+#[derive(Clone, Copy, Debug)]
+enum DataLayer {
+    L2,
+    L3(u16),
+    L4(u8),
+    Unsupported,
+}
+
+/// A `Packet` owning its data
+///
+/// Use `packet()` to get a `Packet` borrowing from this object.
+#[derive(Clone, Debug)]
+pub struct OwnedPacket {
+    pub interface: u32,
+    pub ts: Duration,
+    pub link_type: Linktype,
+    pub caplen: u32,
+    pub origlen: u32,
+    pub pcap_index: usize,
+    layer: DataLayer,
+    data: Vec<u8>,
+}
+
+impl OwnedPacket {
+    /// Return a `Packet` borrowing data from this object
+    pub fn packet(&self) -> Packet<'_> {
+        let data = match self.layer {
+            DataLayer::L2 => PacketData::L2(&self.data),
+            DataLayer::L3(ethertype) => PacketData::L3(ethertype, &self.data),
+            DataLayer::L4(proto) => PacketData::L4(proto, &self.data),
+            DataLayer::Unsupported => PacketData::Unsupported(&self.data),
+        };
+        Packet {
+            interface: self.interface,
+            ts: self.ts,
+            link_type: self.link_type,
+            data,
+            caplen: self.caplen,
+            origlen: self.origlen,
+            pcap_index: self.pcap_index,
+        }
+    }
+
+    /// Raw packet data (starting at the layer indicated by `packet().data`)
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl<'a> From<&Packet<'a>> for OwnedPacket {
+    fn from(packet: &Packet<'a>) -> Self {
+        let (layer, data) = match packet.data {
+            PacketData::L2(data) => (DataLayer::L2, data),
+            PacketData::L3(ethertype, data) => (DataLayer::L3(ethertype), data),
+            PacketData::L4(proto, data) => (DataLayer::L4(proto), data),
+            PacketData::Unsupported(data) => (DataLayer::Unsupported, data),
+        };
+        OwnedPacket {
+            interface: packet.interface,
+            ts: packet.ts,
+            link_type: packet.link_type,
+            caplen: packet.caplen,
+            origlen: packet.origlen,
+            pcap_index: packet.pcap_index,
+            layer,
+            data: data.to_vec(),
+        }
+    }
+}
+
+/// Iterator over the packets of a pcap/pcap-ng input
+///
+/// `PacketIterator` reads blocks from any `Read`, and decodes packets like `PcapDataEngine`
+/// (file format, interfaces and timestamp resolution are handled transparently).
+/// Each item contains a copy of the packet, and the parsing context.
+///
+/// An error on a packet (for ex. a packet referring to an unknown interface) is returned
+/// as an item, and iteration can continue. Iteration stops after a parsing error on the
+/// file structure.
+///
+/// ## example
+///
+/// ```
+/// use pako_tools::PacketIterator;
+///
+/// // use a cursor as an example, any `Read` can be used
+/// use std::io::Cursor;
+/// let input = Cursor::new(vec![1, 2, 3, 4, 5]);
+/// if let Ok(iter) = PacketIterator::new(input) {
+///     for item in iter {
+///         match item {
+///             Ok((packet, ctx)) => println!("{}: {} bytes", ctx.pcap_index, packet.caplen),
+///             Err(e) => println!("error: {}", e),
+///         }
+///     }
+/// }
+/// ```
+pub struct PacketIterator<'r> {
+    reader: Box<dyn PcapReaderIterator + 'r>,
+    decoder: PacketDecoder,
+    block_index: usize,
+    last_incomplete_index: usize,
+    done: bool,
+}
+
+impl<'r> PacketIterator<'r> {
+    /// Create a new iterator, with the default buffer size
+    pub fn new<R: Read + 'r>(reader: R) -> Result<Self, Error> {
+        Self::with_capacity(reader, DEFAULT_CAPACITY)
+    }
+
+    /// Create a new iterator, using `buffer_initial_capacity` from configuration for the buffer size
+    pub fn with_config<R: Read + 'r>(reader: R, config: &Config) -> Result<Self, Error> {
+        let capacity = config
+            .get_usize("buffer_initial_capacity")
+            .unwrap_or(DEFAULT_CAPACITY);
+        Self::with_capacity(reader, capacity)
+    }
+
+    /// Create a new iterator, with a buffer of `capacity` bytes
+    ///
+    /// The buffer must be large enough to contain the largest block of the input.
+    pub fn with_capacity<R: Read + 'r>(reader: R, capacity: usize) -> Result<Self, Error> {
+        let reader = pcap_parser::create_reader(capacity, reader)?;
+        Ok(PacketIterator {
+            reader,
+            decoder: PacketDecoder::default(),
+            block_index: 0,
+            last_incomplete_index: 0,
+            done: false,
+        })
+    }
+
+    /// Parsing context of the last decoded packet
+    pub fn ctx(&self) -> &ParseContext {
+        self.decoder.ctx()
+    }
+}
+
+impl<'r> Iterator for PacketIterator<'r> {
+    type Item = Result<(OwnedPacket, ParseContext), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.reader.next() {
+                Ok((offset, block)) => {
+                    let res = self
+                        .decoder
+                        .decode(&block)
+                        .map(|p| p.map(|p| OwnedPacket::from(&p)));
+                    self.block_index += 1;
+                    self.reader.consume_noshift(offset);
+                    match res {
+                        Ok(Some(packet)) => return Some(Ok((packet, self.decoder.ctx().clone()))),
+                        Ok(None) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                Err(PcapError::Eof) => self.done = true,
+                Err(PcapError::Incomplete(_)) => {
+                    if self.last_incomplete_index == self.block_index
+                        && self.reader.reader_exhausted()
+                    {
+                        warn!(
+                            "Could not read complete data block (block_index={})",
+                            self.block_index
+                        );
+                        self.done = true;
+                        continue;
+                    }
+                    self.last_incomplete_index = self.block_index;
+                    // refill the buffer
+                    if let Err(e) = self.reader.refill() {
+                        self.done = true;
+                        return Some(Err(Error::Pcap(e.to_owned_vec())));
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(Error::Pcap(e.to_owned_vec())));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use pcap_parser::{data::PacketData, Linktype};
+
+    use super::{OwnedPacket, PacketIterator};
+    use crate::{
+        duration::Duration, packet::Packet, Config, Error, ParseContext, PcapAnalyzer,
+        PcapDataEngine, PcapEngine,
+    };
+
+    const ASSETS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../pako-pcap/assets/");
+
+    /// Record packets and contexts, as formatted strings
+    #[derive(Default)]
+    struct PacketRecorder {
+        packets: Vec<(String, Duration, Duration, usize)>,
+    }
+
+    impl PcapAnalyzer for PacketRecorder {
+        fn handle_packet(&mut self, packet: &Packet, ctx: &ParseContext) -> Result<(), Error> {
+            self.packets.push((
+                format!("{:?}", OwnedPacket::from(packet)),
+                ctx.first_packet_ts,
+                ctx.rel_ts,
+                ctx.pcap_index,
+            ));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn owned_packet_roundtrip() {
+        let data = [0x45u8, 0, 0, 20];
+        let packet = Packet {
+            interface: 1,
+            ts: Duration::from_nanos_parts(10, 20_123),
+            link_type: Linktype(101),
+            data: PacketData::L3(0x0800, &data),
+            caplen: 4,
+            origlen: 4,
+            pcap_index: 3,
+        };
+        let owned = OwnedPacket::from(&packet);
+        let p = owned.packet();
+        assert_eq!(p.pcap_index, 3);
+        assert_eq!(p.ts, Duration::from_nanos_parts(10, 20_123));
+        match p.data {
+            PacketData::L3(0x0800, d) => assert_eq!(d, &data),
+            _ => panic!("unexpected packet data"),
+        }
+    }
+
+    #[test]
+    fn packet_iterator_assets() {
+        // file, number of packets, interfaces, and fraction of second of the first packet
+        let assets = [
+            ("legacy_le_us.pcap", 4, vec![0], 679_827_000),
+            ("legacy_be_ns.pcap", 4, vec![0], 679_827_007),
+            ("blocks_le.pcapng", 5, vec![0], 679_827_000),
+            ("blocks_be.pcapng", 5, vec![0], 679_827_000),
+            ("test002_le.pcapng", 0, vec![], 0),
+            ("test002_be.pcapng", 0, vec![], 0),
+        ];
+        let config = Config::default();
+        for (name, count, interfaces, first_nanos) in assets {
+            let path = format!("{}{}", ASSETS, name);
+            let mut engine = PcapDataEngine::new(PacketRecorder::default(), &config);
+            engine
+                .run(&mut File::open(&path).expect("open"))
+                .expect("run engine");
+            let expected = engine.into_data_analyzer().packets;
+
+            // small buffer, to test refills
+            let file = File::open(&path).expect("open");
+            let iter = PacketIterator::with_capacity(file, 256).expect("iterator");
+            let mut recorder = PacketRecorder::default();
+            let mut packets = Vec::new();
+            for item in iter {
+                let (packet, ctx) = item.expect("packet");
+                recorder.handle_packet(&packet.packet(), &ctx).unwrap();
+                packets.push(packet);
+            }
+            assert_eq!(recorder.packets, expected, "{}", name);
+            assert_eq!(packets.len(), count, "{}", name);
+            let mut ifaces = packets.iter().map(|p| p.interface).collect::<Vec<_>>();
+            ifaces.dedup();
+            assert_eq!(ifaces, interfaces, "{}", name);
+            if let Some(p) = packets.first() {
+                assert_eq!(p.ts.subsec_nanos(), first_nanos, "{}", name);
+                assert_eq!(p.pcap_index, 1, "{}", name);
+            }
+        }
+    }
+}